@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::atlas::SpriteRegion;
+use crate::json::Json;
+use crate::math::float;
+use crate::texture::TextureError;
+
+///Frame duration used by grid sheets and frames without a duration.
+pub const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(100);
+
+///Shortest frame, keeps zero length frames from stalling update().
+const MIN_FRAME_DURATION: Duration = Duration::from_millis(1);
+
+///Fastest playback speed, keeps the frames stepped per update bounded.
+const MAX_SPEED: f32 = 1000.0;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Sprite Sheets
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Frames sliced out of a texture or sprite region, in sheet order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteSheet {
+    pub frames: Vec<SpriteRegion>,
+    pub durations: Vec<Duration>,
+    ///Clips defined by Aseprite frame tags, keyed by tag name.
+    pub tags: HashMap<String, AnimationClip>,
+}
+
+impl SpriteSheet {
+    ///Slice region into a grid of equally sized frames, numbered left to right and top to bottom.
+    ///Every frame lasts DEFAULT_FRAME_DURATION.
+    pub fn grid(region: SpriteRegion, columns: u32, rows: u32) -> Self {
+        let (u0, v0, u1, v1) = region.uv_rect;
+        let mut frames = Vec::with_capacity((columns * rows) as usize);
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let left = column as f32 / columns as f32;
+                let right = (column + 1) as f32 / columns as f32;
+                let top = row as f32 / rows as f32;
+                let bottom = (row + 1) as f32 / rows as f32;
+
+                frames.push(SpriteRegion {
+                    texture: region.texture,
+                    uv_rect: (
+                        float::lerp(u0, u1, left),
+                        float::lerp(v1, v0, bottom),
+                        float::lerp(u0, u1, right),
+                        float::lerp(v1, v0, top),
+                    ),
+                });
+            }
+        }
+
+        Self {
+            durations: vec![DEFAULT_FRAME_DURATION; frames.len()],
+            frames,
+            tags: HashMap::new(),
+        }
+    }
+
+    ///Slice region using a JSON frame list as exported by Aseprite or TexturePacker, in either the
+    ///hash or the array layout. Frame rectangles are in pixels of the image described by
+    ///meta.size. Frame tags become clips, trimmed frames are drawn at their trimmed size.
+    pub fn from_json(region: SpriteRegion, json: &str) -> Result<Self, TextureError> {
+        let malformed = TextureError::Malformed("Malformed sprite sheet JSON");
+        let Some(json) = Json::parse(json) else {
+            return Err(malformed);
+        };
+
+        let frame_list: Vec<&Json> = match json.get("frames") {
+            Some(Json::Array(frames)) => frames.iter().collect(),
+            Some(Json::Object(frames)) => frames.iter().map(|(_, frame)| frame).collect(),
+            _ => return Err(malformed),
+        };
+
+        let size = json.get("meta").and_then(|meta| meta.get("size"));
+        let (Some(width), Some(height)) = (
+            size.and_then(|size| number(size, "w")),
+            size.and_then(|size| number(size, "h")),
+        ) else {
+            return Err(malformed);
+        };
+        if width <= 0.0 || height <= 0.0 {
+            return Err(TextureError::InvalidDimensions {
+                width: width as i64,
+                height: height as i64,
+            });
+        }
+
+        let (u0, v0, u1, v1) = region.uv_rect;
+        let mut frames = Vec::with_capacity(frame_list.len());
+        let mut durations = Vec::with_capacity(frame_list.len());
+
+        for frame in frame_list {
+            if frame.get("rotated") == Some(&Json::Bool(true)) {
+                return Err(TextureError::Unsupported(String::from(
+                    "Rotated sprite sheet frames are not supported",
+                )));
+            }
+
+            let rect = frame.get("frame");
+            let (Some(x), Some(y), Some(w), Some(h)) = (
+                rect.and_then(|rect| number(rect, "x")),
+                rect.and_then(|rect| number(rect, "y")),
+                rect.and_then(|rect| number(rect, "w")),
+                rect.and_then(|rect| number(rect, "h")),
+            ) else {
+                return Err(malformed);
+            };
+
+            frames.push(SpriteRegion {
+                texture: region.texture,
+                uv_rect: (
+                    float::lerp(u0, u1, (x / width) as f32),
+                    float::lerp(v1, v0, ((y + h) / height) as f32),
+                    float::lerp(u0, u1, ((x + w) / width) as f32),
+                    float::lerp(v1, v0, (y / height) as f32),
+                ),
+            });
+            let duration = number(frame, "duration")
+                .and_then(|milliseconds| Duration::try_from_secs_f64(milliseconds / 1000.0).ok());
+            durations.push(duration.unwrap_or(DEFAULT_FRAME_DURATION));
+        }
+
+        let mut sheet = Self {
+            frames,
+            durations,
+            tags: HashMap::new(),
+        };
+
+        let tags = json.get("meta").and_then(|meta| meta.get("frameTags"));
+        for tag in tags.and_then(Json::as_array).unwrap_or_default() {
+            let (Some(name), Some(from), Some(to)) = (
+                tag.get("name").and_then(Json::as_str),
+                number(tag, "from"),
+                number(tag, "to"),
+            ) else {
+                return Err(malformed);
+            };
+            let (from, to) = (from as usize, to as usize);
+            if from > to || to >= sheet.frames.len() {
+                return Err(TextureError::Malformed("Sprite sheet tag out of range"));
+            }
+
+            let direction = tag.get("direction").and_then(Json::as_str);
+            let mode = match direction {
+                Some("pingpong" | "pingpong_reverse") => PlaybackMode::PingPong,
+                _ => PlaybackMode::Loop,
+            };
+            let mut clip = sheet.clip(from, to, mode);
+            if matches!(direction, Some("reverse" | "pingpong_reverse")) {
+                clip.frames.reverse();
+                clip.durations.reverse();
+            }
+            sheet.tags.insert(String::from(name), clip);
+        }
+
+        Ok(sheet)
+    }
+
+    ///Clip playing frames first to last, inclusive. Panics if last is out of range.
+    pub fn clip(&self, first: usize, last: usize, mode: PlaybackMode) -> AnimationClip {
+        AnimationClip {
+            frames: self.frames[first..=last].to_vec(),
+            durations: self.durations[first..=last].to_vec(),
+            mode,
+            events: Vec::new(),
+        }
+    }
+
+    ///Clip defined by a frame tag, None if the sheet has no tag with that name.
+    pub fn tag(&self, name: &str) -> Option<AnimationClip> {
+        self.tags.get(name).cloned()
+    }
+}
+
+fn number(json: &Json, key: &str) -> Option<f64> {
+    json.get(key).and_then(Json::as_f64)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Animation
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///What happens when an animation reaches its last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackMode {
+    ///Start over from the first frame.
+    Loop,
+    ///Play backwards to the first frame, then forwards again.
+    PingPong,
+    ///Stop on the last frame.
+    Once,
+}
+
+///Frames of an animation with their durations, shared by any number of Animation players.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationClip {
+    pub frames: Vec<SpriteRegion>,
+    pub durations: Vec<Duration>,
+    pub mode: PlaybackMode,
+    ///Events reported by Animation::events() when the frame at the index is shown.
+    pub events: Vec<(usize, String)>,
+}
+
+impl AnimationClip {
+    ///Clip where every frame lasts frame_duration.
+    pub fn new(frames: Vec<SpriteRegion>, frame_duration: Duration, mode: PlaybackMode) -> Self {
+        Self {
+            durations: vec![frame_duration; frames.len()],
+            frames,
+            mode,
+            events: Vec::new(),
+        }
+    }
+
+    ///Report event when frame is shown.
+    pub fn add_event(&mut self, frame: usize, event: &str) {
+        self.events.push((frame, String::from(event)));
+    }
+
+    ///Total length of one pass through the frames.
+    pub fn duration(&self) -> Duration {
+        self.durations.iter().sum()
+    }
+}
+
+///Plays an AnimationClip. Call update() every frame and draw the current frame with
+///System::animation().
+#[derive(Debug, Clone)]
+pub struct Animation {
+    clip: AnimationClip,
+    frame: usize,
+    elapsed: Duration,
+    speed: f32,
+    forward: bool,
+    playing: bool,
+    finished: bool,
+    started: bool,
+    events: Vec<String>,
+}
+
+impl Animation {
+    ///Start playing clip from its first frame at normal speed.
+    pub fn new(clip: AnimationClip) -> Self {
+        Self {
+            clip,
+            frame: 0,
+            elapsed: Duration::ZERO,
+            speed: 1.0,
+            forward: true,
+            playing: true,
+            finished: false,
+            started: false,
+            events: Vec::new(),
+        }
+    }
+
+    ///Advance the animation by dt scaled by the playback speed. Events of every frame shown
+    ///during this update are available from events() until the next update.
+    pub fn update(&mut self, dt: Duration) {
+        self.events.clear();
+        if self.clip.frames.is_empty() {
+            return;
+        }
+
+        if !self.started {
+            self.started = true;
+            self.fire_events();
+        }
+
+        if !self.playing || self.finished {
+            return;
+        }
+
+        //Seconds rather than Duration::mul_f32, which panics when the result doesn't fit.
+        let scaled = Duration::try_from_secs_f64(dt.as_secs_f64() * self.speed as f64);
+        self.elapsed = self.elapsed.saturating_add(scaled.unwrap_or(Duration::MAX));
+        loop {
+            let duration = self.clip.durations[self.frame].max(MIN_FRAME_DURATION);
+            if self.elapsed < duration {
+                break;
+            }
+            self.elapsed -= duration;
+
+            if !self.advance() {
+                self.finished = true;
+                self.elapsed = Duration::ZERO;
+                break;
+            }
+            self.fire_events();
+        }
+    }
+
+    ///Step to the next frame, returns false once a Once clip is past its last frame.
+    fn advance(&mut self) -> bool {
+        let last = self.clip.frames.len() - 1;
+
+        match self.clip.mode {
+            PlaybackMode::Loop => {
+                self.frame = if self.frame == last {
+                    0
+                } else {
+                    self.frame + 1
+                }
+            }
+            PlaybackMode::Once => {
+                if self.frame == last {
+                    return false;
+                }
+                self.frame += 1;
+            }
+            PlaybackMode::PingPong => {
+                if last == 0 {
+                    return true;
+                }
+                if self.forward && self.frame == last {
+                    self.forward = false;
+                } else if !self.forward && self.frame == 0 {
+                    self.forward = true;
+                }
+                if self.forward {
+                    self.frame += 1;
+                } else {
+                    self.frame -= 1;
+                }
+            }
+        }
+        true
+    }
+
+    fn fire_events(&mut self) {
+        for (frame, event) in &self.clip.events {
+            if *frame == self.frame {
+                self.events.push(event.clone());
+            }
+        }
+    }
+
+    ///Events of the frames shown during the last update().
+    pub fn events(&self) -> &[String] {
+        &self.events
+    }
+
+    ///Region of the current frame.
+    pub fn region(&self) -> SpriteRegion {
+        match self.clip.frames.get(self.frame) {
+            Some(region) => *region,
+            None => SpriteRegion::full(0),
+        }
+    }
+
+    ///Index of the current frame in the clip.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    ///Jump to frame, its events are reported on the next update().
+    pub fn set_frame(&mut self, frame: usize) {
+        self.frame = frame.min(self.clip.frames.len().saturating_sub(1));
+        self.elapsed = Duration::ZERO;
+        self.finished = false;
+        self.started = false;
+    }
+
+    ///Playback speed multiplier, 1.0 is normal speed. Negative and NaN speeds are treated as 0,
+    ///speeds are capped at 1000.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = match speed.is_nan() {
+            true => 0.0,
+            false => speed.clamp(0.0, MAX_SPEED),
+        };
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing && !self.finished
+    }
+
+    ///True once a Once clip has shown its last frame for its full duration.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    ///Start over from the first frame, playing forwards.
+    pub fn restart(&mut self) {
+        self.forward = true;
+        self.set_frame(0);
+    }
+
+    ///Switch to another clip and restart, does nothing if clip is already playing. Use this to
+    ///change state without restarting the current animation every frame.
+    pub fn set_clip(&mut self, clip: &AnimationClip) {
+        if self.clip != *clip {
+            self.clip = clip.clone();
+            self.restart();
+        }
+    }
+
+    pub fn clip(&self) -> &AnimationClip {
+        &self.clip
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use crate::image::Image;
+use crate::system::gl32::GLuint;
+use crate::texture::TextureError;
+
+///UV rectangle covering a whole texture.
+pub const FULL_UV_RECT: (f32, f32, f32, f32) = (0.0, 0.0, 1.0, 1.0);
+
+///Named part of a texture drawn with System::sprite_region(). uv_rect is (u0, v0, u1, v1), (u0, v0)
+///maps to the bottom left corner of the sprite and (u1, v1) to the top right corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteRegion {
+    pub texture: GLuint,
+    pub uv_rect: (f32, f32, f32, f32),
+}
+
+impl SpriteRegion {
+    ///Region covering the whole texture.
+    pub fn full(texture: GLuint) -> Self {
+        Self {
+            texture,
+            uv_rect: FULL_UV_RECT,
+        }
+    }
+}
+
+///Placement of a packed image inside an atlas page. x and y are the top left corner of the image
+///in pixels from the top left corner of the page, extrusion not included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub uv_rect: (f32, f32, f32, f32),
+}
+
+///Images packed into RGBA8 pages by AtlasBuilder. Upload it with System::upload_atlas.
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    pub pages: Vec<Image>,
+    pub regions: HashMap<String, AtlasRegion>,
+}
+
+///Packs images into as few atlas pages as possible. Images are placed with a skyline bottom left
+///packer, tallest first.
+pub struct AtlasBuilder {
+    page_width: u32,
+    page_height: u32,
+    padding: u32,
+    extrude: u32,
+    images: Vec<(String, Image)>,
+}
+
+impl AtlasBuilder {
+    ///Pages are at most page_width by page_height pixels. padding is the number of transparent
+    ///pixels left between images, extrude repeats the edge pixels of every image outwards so
+    ///filtering doesn't blend in neighbouring images.
+    pub fn new(page_width: u32, page_height: u32, padding: u32, extrude: u32) -> Self {
+        Self {
+            page_width,
+            page_height,
+            padding,
+            extrude,
+            images: Vec::new(),
+        }
+    }
+
+    ///Add an image under name, replacing any image with the same name. Only the full size level
+    ///of the first layer is packed.
+    pub fn add(&mut self, name: &str, image: Image) {
+        self.images.retain(|(existing, _)| existing != name);
+        self.images.push((String::from(name), image));
+    }
+
+    ///Load and add an image file, key to the image is the file stem name without extension.
+    pub fn add_file(&mut self, path: &str) -> Result<(), TextureError> {
+        let Some(name) = std::path::Path::new(path)
+            .file_stem()
+            .and_then(|name| name.to_str())
+        else {
+            return Err(TextureError::Io(std::io::ErrorKind::InvalidInput));
+        };
+
+        let image = Image::load(path)?;
+        self.add(name, image);
+        Ok(())
+    }
+
+    ///Pack all added images. Fails if an image doesn't fit on an empty page.
+    pub fn build(mut self) -> Result<Atlas, TextureError> {
+        //Tallest first, then widest, names keep the layout the same between runs.
+        self.images.sort_by(|(a_name, a), (b_name, b)| {
+            b.height
+                .cmp(&a.height)
+                .then(b.width.cmp(&a.width))
+                .then(a_name.cmp(b_name))
+        });
+
+        //Padding is only needed between images, the skylines get room for one trailing padding
+        //at the right and bottom edges of the page.
+        let border = self.extrude * 2 + self.padding;
+        let mut skylines: Vec<Skyline> = Vec::new();
+        let mut placements = Vec::with_capacity(self.images.len());
+
+        for (name, image) in &self.images {
+            if image.width == 0 || image.height == 0 {
+                return Err(TextureError::InvalidDimensions {
+                    width: image.width as i64,
+                    height: image.height as i64,
+                });
+            }
+            if !image.is_complete() {
+                return Err(TextureError::Malformed("Image pixel data is too short"));
+            }
+
+            let width = image.width + border;
+            let height = image.height + border;
+
+            let mut placement = None;
+            for (page, skyline) in skylines.iter_mut().enumerate() {
+                if let Some((x, y)) = skyline.insert(width, height) {
+                    placement = Some((page, x, y));
+                    break;
+                }
+            }
+
+            let placement = match placement {
+                Some(placement) => placement,
+                None => {
+                    let mut skyline = Skyline::new(
+                        self.page_width + self.padding,
+                        self.page_height + self.padding,
+                    );
+                    let Some((x, y)) = skyline.insert(width, height) else {
+                        return Err(TextureError::Unsupported(format!(
+                            "Image '{name}' doesn't fit on a {}x{} atlas page",
+                            self.page_width, self.page_height
+                        )));
+                    };
+                    skylines.push(skyline);
+                    (skylines.len() - 1, x, y)
+                }
+            };
+            placements.push(placement);
+        }
+
+        //Pages are cropped to the area that was used.
+        let mut page_sizes = vec![(0, 0); skylines.len()];
+        for ((page, x, y), (_, image)) in placements.iter().zip(&self.images) {
+            let (width, height) = &mut page_sizes[*page];
+            *width = (*width).max(x + image.width + self.extrude * 2);
+            *height = (*height).max(y + image.height + self.extrude * 2);
+        }
+
+        let mut pages: Vec<Image> = page_sizes
+            .iter()
+            .map(|&(width, height)| {
+                Image::new(width, height, vec![0; width as usize * height as usize * 4])
+            })
+            .collect();
+        let mut regions = HashMap::new();
+
+        for ((page, x, y), (name, image)) in placements.into_iter().zip(self.images) {
+            let x = x + self.extrude;
+            let y = y + self.extrude;
+            blit_extruded(&mut pages[page], &image, x, y, self.extrude);
+
+            //Pages are uploaded bottom row first, v grows upwards from the bottom of the page.
+            let page_width = pages[page].width as f32;
+            let page_height = pages[page].height as f32;
+            let uv_rect = (
+                x as f32 / page_width,
+                1.0 - (y + image.height) as f32 / page_height,
+                (x + image.width) as f32 / page_width,
+                1.0 - y as f32 / page_height,
+            );
+
+            regions.insert(
+                name,
+                AtlasRegion {
+                    page,
+                    x,
+                    y,
+                    width: image.width,
+                    height: image.height,
+                    uv_rect,
+                },
+            );
+        }
+
+        Ok(Atlas { pages, regions })
+    }
+}
+
+///Copy the full size level of image into page with its top left corner at (x, y), repeating the
+///edge pixels extrude times around it.
+fn blit_extruded(page: &mut Image, image: &Image, x: u32, y: u32, extrude: u32) {
+    //Block compressed images are stored bottom row first when drawn on their own, flip them so
+    //they look the same in the atlas.
+    let compressed = image.format.is_compressed();
+    let decoded;
+    let pixels = if compressed {
+        decoded = image.decompress();
+        decoded.mip_level(0).unwrap().2
+    } else {
+        image.mip_level(0).unwrap().2
+    };
+
+    let width = image.width as i64;
+    let height = image.height as i64;
+    let extrude = extrude as i64;
+    let page_width = page.width as usize;
+
+    for dy in -extrude..height + extrude {
+        let mut source_y = dy.clamp(0, height - 1);
+        if compressed {
+            source_y = height - 1 - source_y;
+        }
+        let row = (y as i64 + dy) as usize * page_width;
+
+        for dx in -extrude..width + extrude {
+            let source_x = dx.clamp(0, width - 1);
+            let source = (source_y * width + source_x) as usize * 4;
+            let target = (row + (x as i64 + dx) as usize) * 4;
+            page.pixels[target..target + 4].copy_from_slice(&pixels[source..source + 4]);
+        }
+    }
+}
+
+///Skyline of a page, every node is a horizontal segment (x, y, width) with everything below y
+///already taken. y grows downwards.
+struct Skyline {
+    width: u32,
+    height: u32,
+    nodes: Vec<(u32, u32, u32)>,
+}
+
+impl Skyline {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            nodes: vec![(0, 0, width)],
+        }
+    }
+
+    ///Place a width by height rectangle as high up as possible, the narrowest segment wins ties.
+    ///Returns the top left corner or None if the rectangle doesn't fit.
+    fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let mut best: Option<(usize, u32, u32)> = None;
+
+        for index in 0..self.nodes.len() {
+            let Some(y) = self.fit(index, width, height) else {
+                continue;
+            };
+            let node_width = self.nodes[index].2;
+            if best.is_none_or(|(_, best_y, best_width)| {
+                y < best_y || (y == best_y && node_width < best_width)
+            }) {
+                best = Some((index, y, node_width));
+            }
+        }
+
+        let (index, y, _) = best?;
+        let x = self.nodes[index].0;
+        self.nodes.insert(index, (x, y + height, width));
+
+        //Shrink or remove the segments now covered by the new one.
+        let right = x + width;
+        while index + 1 < self.nodes.len() {
+            let (next_x, next_y, next_width) = self.nodes[index + 1];
+            if next_x >= right {
+                break;
+            }
+
+            let next_right = next_x + next_width;
+            if next_right <= right {
+                self.nodes.remove(index + 1);
+            } else {
+                self.nodes[index + 1] = (right, next_y, next_right - right);
+                break;
+            }
+        }
+
+        //Merge neighbours at the same height.
+        let mut i = 0;
+        while i + 1 < self.nodes.len() {
+            if self.nodes[i].1 == self.nodes[i + 1].1 {
+                self.nodes[i].2 += self.nodes[i + 1].2;
+                self.nodes.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+
+        Some((x, y))
+    }
+
+    ///Lowest y a rectangle starting at the left edge of node index can be placed at.
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.nodes[index].0;
+        if x + width > self.width {
+            return None;
+        }
+
+        let mut y = 0;
+        let mut remaining = width;
+        for &(_, node_y, node_width) in &self.nodes[index..] {
+            if remaining == 0 {
+                break;
+            }
+            y = y.max(node_y);
+            if y + height > self.height {
+                return None;
+            }
+            remaining = remaining.saturating_sub(node_width);
+        }
+
+        Some(y)
+    }
+}
@@ -1,29 +1,46 @@
 #![allow(dead_code)]
 
+use crate::mixer::{read_sound, Mixer, SoundHandle, MIX_CHANNELS, MIX_SAMPLE_RATE};
+use crate::platform::AudioBackend;
+use crate::stream::read_stream;
 use crate::system::audio32::*;
 use crate::system::win32::*;
 
-use std::collections::HashMap;
 use std::ffi::c_void;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
 
-use std::fs::File;
-use std::io::Read;
+///Output buffers XAudio2 has finished playing, counted by OnBufferEnd.
+static BUFFERS_PLAYED: AtomicU32 = AtomicU32::new(0);
 
 #[allow(non_snake_case)]
 mod callbacks {
     use super::*;
 
-    pub fn OnStreamEnd(_this: *const IXAudio2EngineCallback) {}
-    pub fn OnVoiceProcessingPassEnd(_this: *const IXAudio2EngineCallback) {}
-    pub fn OnVoiceProcessingPassStart(
+    pub extern "system" fn OnVoiceProcessingPassStart(
         _this: *const IXAudio2EngineCallback,
         _samplesRequired: UINT32,
     ) {
     }
-    pub fn OnBufferEnd(_this: *const IXAudio2EngineCallback) {}
-    pub fn OnBufferStart(_this: *const IXAudio2EngineCallback, _pBufferContext: *mut c_void) {}
-    pub fn OnLoopEnd(_this: *const IXAudio2EngineCallback, _pBufferContext: *mut c_void) {}
-    pub fn OnVoiceError(
+    pub extern "system" fn OnVoiceProcessingPassEnd(_this: *const IXAudio2EngineCallback) {}
+    pub extern "system" fn OnStreamEnd(_this: *const IXAudio2EngineCallback) {}
+    pub extern "system" fn OnBufferStart(
+        _this: *const IXAudio2EngineCallback,
+        _pBufferContext: *mut c_void,
+    ) {
+    }
+    pub extern "system" fn OnBufferEnd(
+        _this: *const IXAudio2EngineCallback,
+        _pBufferContext: *mut c_void,
+    ) {
+        BUFFERS_PLAYED.fetch_add(1, Ordering::Release);
+    }
+    pub extern "system" fn OnLoopEnd(
+        _this: *const IXAudio2EngineCallback,
+        _pBufferContext: *mut c_void,
+    ) {
+    }
+    pub extern "system" fn OnVoiceError(
         _this: *const IXAudio2EngineCallback,
         _pBufferContext: *mut c_void,
         _error: HRESULT,
@@ -34,45 +51,37 @@ mod callbacks {
 use self::callbacks::*;
 static ENGINE_CALLBACKS: IXAudio2EngineCallbackStruct = IXAudio2EngineCallbackStruct {
     lpVtable: IXAudio2EngineCallbackVtbl {
-        OnStreamEnd,
-        OnVoiceProcessingPassEnd,
         OnVoiceProcessingPassStart,
-        OnBufferEnd,
+        OnVoiceProcessingPassEnd,
+        OnStreamEnd,
         OnBufferStart,
+        OnBufferEnd,
         OnLoopEnd,
         OnVoiceError,
     },
 };
 
-#[derive(PartialEq)]
-pub enum VoiceType {
-    OneShot,
-    Loop,
-}
-
-pub struct AudioDataVec {
-    pub data: Vec<u8>,
-    pub size: UINT32,
-    pub format: WAVEFORMATEXTENSIBLE,
-}
+///Frames in one buffer submitted to XAudio2, one frame of a 60 fps game.
+const OUTPUT_BUFFER_FRAMES: usize = MIX_SAMPLE_RATE as usize / 60;
 
-#[derive(Debug)]
-pub struct AudioBuffer {
-    audio_buffer: XAUDIO2_BUFFER,
-    _raw_buffer: Vec<u8>,
-}
+///Buffers queued ahead of playback. Frames that take longer than all of them together to update
+///leave a gap in the audio.
+const OUTPUT_BUFFERS: usize = 4;
 
+///XAudio2 backend. Sounds are mixed by a Mixer on the main thread and the mixed stream is played
+///through a single source voice, fed a ring of buffers every frame.
 pub struct Audio {
     pub device: *const IXAudio2Struct,
     pub master_voice: *const IXAudio2MasteringVoiceStruct,
     pub format: WAVEFORMATEX,
     pub engine_callbacks: *const IXAudio2EngineCallbackStruct,
 
-    pub loops: Vec<*const IXAudio2SourceVoiceStruct>,
-    pub one_shots: Vec<*const IXAudio2SourceVoiceStruct>,
-    pub current_one_shot: u8,
+    pub output: *const IXAudio2SourceVoiceStruct,
+    output_buffers: Vec<Vec<f32>>,
+    next_buffer: usize,
+    buffers_submitted: u32,
 
-    pub audio_data: HashMap<String, AudioBuffer>,
+    pub mixer: Mixer,
 }
 
 impl std::fmt::Debug for Audio {
@@ -87,13 +96,15 @@ impl std::fmt::Debug for Audio {
 
 impl Audio {
     pub fn new(one_shot_voices: u8, loop_voices: u8) -> Box<Audio> {
-        let mut audio = Box::new(Audio::initialize());
-        audio.create_source_voices(one_shot_voices, VoiceType::OneShot);
-        audio.create_source_voices(loop_voices, VoiceType::Loop);
-        audio.start_one_shots();
+        let mut audio = Box::new(Audio::initialize(Mixer::new(
+            MIX_SAMPLE_RATE,
+            one_shot_voices,
+            loop_voices,
+        )));
+        audio.create_output_voice();
         audio
     }
-    pub fn initialize() -> Audio {
+    pub fn initialize(mixer: Mixer) -> Audio {
         unsafe {
             let mut device: IXAudio2 = std::ptr::null_mut();
             let mut master_voice: IXAudio2MasteringVoice = std::ptr::null_mut();
@@ -120,13 +131,14 @@ impl Audio {
 
             let engine_callbacks = &ENGINE_CALLBACKS;
 
+            let sample_size = std::mem::size_of::<f32>() as u16;
             let format: WAVEFORMATEX = WAVEFORMATEX {
-                wFormatTag: 1,
-                nChannels: 2,
-                nSamplesPerSec: 44100,
-                nAvgBytesPerSec: 176400,
-                nBlockAlign: 4,
-                wBitsPerSample: 16,
+                wFormatTag: WAVE_FORMAT_IEEE_FLOAT,
+                nChannels: MIX_CHANNELS as u16,
+                nSamplesPerSec: mixer.sample_rate(),
+                nAvgBytesPerSec: mixer.sample_rate() * MIX_CHANNELS as u32 * sample_size as u32,
+                nBlockAlign: MIX_CHANNELS as u16 * sample_size,
+                wBitsPerSample: sample_size * 8,
                 cbSize: 0,
             };
 
@@ -136,11 +148,15 @@ impl Audio {
                 format,
                 engine_callbacks,
 
-                loops: Vec::new(),
-                one_shots: Vec::new(),
-                current_one_shot: 0,
+                output: std::ptr::null(),
+                output_buffers: vec![
+                    vec![0.0; OUTPUT_BUFFER_FRAMES * MIX_CHANNELS];
+                    OUTPUT_BUFFERS
+                ],
+                next_buffer: 0,
+                buffers_submitted: 0,
 
-                audio_data: HashMap::new(),
+                mixer,
             }
         }
     }
@@ -149,69 +165,66 @@ impl Audio {
         unsafe { &*self.device }
     }
 
-    pub fn get_buffer(&self, sound_name: &str) -> *const XAUDIO2_BUFFER {
-        let sound = self.audio_data.get(sound_name);
-        let ptr = &sound.unwrap().audio_buffer;
-        ptr as *const XAUDIO2_BUFFER
-    }
-
-    pub fn create_source_voices(&mut self, voices: u8, voice_type: VoiceType) {
-        for _ in 0..voices {
-            let mut source_voice: IXAudio2SourceVoice = std::ptr::null_mut();
-
-            unsafe {
-                let hr = ((*(*self.device).lpVtable).CreateSourceVoice)(
-                    self.device,
-                    &mut source_voice as *const IXAudio2SourceVoice,
-                    &self.format,
-                    0,
-                    XAUDIO2_DEFAULT_FREQ_RATIO,
-                    &self.engine_callbacks as *const _ as *const *mut c_void,
-                    std::ptr::null_mut(),
-                    std::ptr::null_mut(),
-                );
-                failed(hr, "XAudio2 Source Voice Creation Failed");
-            }
-            if voice_type == VoiceType::OneShot {
-                self.one_shots
-                    .push(source_voice as *const IXAudio2SourceVoiceStruct);
-            } else {
-                self.loops
-                    .push(source_voice as *const IXAudio2SourceVoiceStruct);
-            }
+    pub fn create_output_voice(&mut self) {
+        let mut source_voice: IXAudio2SourceVoice = std::ptr::null_mut();
+
+        unsafe {
+            let hr = ((*(*self.device).lpVtable).CreateSourceVoice)(
+                self.device,
+                &mut source_voice as *const IXAudio2SourceVoice,
+                &self.format,
+                0,
+                XAUDIO2_DEFAULT_FREQ_RATIO,
+                &self.engine_callbacks as *const _ as *const *mut c_void,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            failed(hr, "XAudio2 Source Voice Creation Failed");
+
+            self.output = source_voice as *const IXAudio2SourceVoiceStruct;
+            let hr = (*self.output).start();
+            failed(hr, "Couldn't start source voice");
         }
     }
 
-    pub fn load_audio(&mut self, filename: &str) {
-        let extension = std::path::Path::new(&filename).extension().unwrap();
-        if extension.to_ascii_lowercase() != "wav" {
-            eprintln!("Couldn't load: {filename} - Unsupported file format");
+    ///Mix and queue buffers until OUTPUT_BUFFERS are waiting to be played. Buffers are reused in
+    ///submission order, so a buffer is only refilled once OnBufferEnd reports XAudio2 is done
+    ///with it. Streams decode their next frames here as their buffers play out.
+    pub fn submit_mixed(&mut self) {
+        if self.output.is_null() {
             return;
         }
 
-        let audio_data = buf_read_file(filename);
-        if audio_data.data.as_ptr().is_null() {
-            eprintln!("Failed to load audio data: {filename}");
-            return;
-        }
-        let key = std::path::Path::new(&filename)
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap();
         unsafe {
-            let mut audio_buffer: XAUDIO2_BUFFER = std::mem::zeroed();
-
-            audio_buffer.AudioBytes = audio_data.size;
-            audio_buffer.pAudioData = audio_data.data.as_ptr();
-            audio_buffer.Flags = XAUDIO_END_OF_STREAM;
+            let voice = &*self.output;
+            let played = BUFFERS_PLAYED.load(Ordering::Acquire);
+            let mut queued = self.buffers_submitted.wrapping_sub(played) as usize;
+
+            while queued < OUTPUT_BUFFERS {
+                let buffer = &mut self.output_buffers[self.next_buffer];
+                self.mixer.mix(buffer);
+
+                let mut audio_buffer: XAUDIO2_BUFFER = std::mem::zeroed();
+                audio_buffer.AudioBytes = std::mem::size_of_val(buffer.as_slice()) as UINT32;
+                audio_buffer.pAudioData = buffer.as_ptr() as *const u8;
+                voice.submit(&audio_buffer, std::ptr::null_mut());
+
+                self.next_buffer = (self.next_buffer + 1) % OUTPUT_BUFFERS;
+                self.buffers_submitted = self.buffers_submitted.wrapping_add(1);
+                queued += 1;
+            }
+        }
+    }
 
-            let audio = AudioBuffer {
-                audio_buffer,
-                _raw_buffer: audio_data.data,
-            };
+    pub fn load_audio(&mut self, filename: &str) {
+        if let Some((name, sound)) = read_sound(filename) {
+            self.mixer.add_sound(&name, sound);
+        }
+    }
 
-            self.audio_data.insert(String::from(key), audio);
+    pub fn load_stream(&mut self, filename: &str) {
+        if let Some((name, stream)) = read_stream(filename) {
+            self.mixer.add_stream(&name, stream);
         }
     }
 
@@ -240,77 +253,96 @@ impl Audio {
             self.load_audio(&file_path);
         }
     }
+}
 
-    pub fn start_one_shots(&self) {
-        for i in 0..self.one_shots.len() {
-            unsafe {
-                let hr = (*(self.one_shots[i])).start();
-                failed(hr, "Couldn't start source voice");
-            }
-        }
+impl AudioBackend for Audio {
+    fn load_audio(&mut self, path: &str) {
+        Audio::load_audio(self, path);
     }
 
-    pub fn one_shot(&mut self, audio: &str, level: f32) {
-        let audio_buffer = self.get_buffer(audio);
-        unsafe {
-            if self.current_one_shot as usize >= self.one_shots.len() {
-                self.current_one_shot = 0;
-            }
-            let voice = &(*(*self.one_shots)[self.current_one_shot as usize]);
-            voice.stop();
-            voice.flush();
-            voice.set_volume(level);
-            voice.submit(audio_buffer, std::ptr::null_mut());
-            voice.start();
-            self.current_one_shot += 1;
-        }
+    fn load_audio_dir(&mut self, path: &str) {
+        self.load_assets(path);
     }
 
-    pub fn submit_loop(&mut self, audio: &str, channel: u8, level: f32) {
-        if channel as usize >= self.loops.len() {
-            println!("Channel {} does not exist", channel);
-            return;
-        }
-        let audio_buffer = self.get_buffer(audio);
-        unsafe {
-            let audio_buffer = &*audio_buffer;
-            let audio_buffer = XAUDIO2_BUFFER {
-                Flags: audio_buffer.Flags,
-                AudioBytes: audio_buffer.AudioBytes,
-                pAudioData: audio_buffer.pAudioData,
-                PlayBegin: audio_buffer.PlayBegin,
-                PlayLength: audio_buffer.PlayLength,
-                LoopBegin: audio_buffer.PlayBegin,
-                LoopLength: audio_buffer.PlayLength,
-                LoopCount: XAUDIO2_LOOP_INFINITE,
-                pContext: audio_buffer.pContext,
-            };
+    fn play_one_shot(&mut self, audio: &str, level: f32) {
+        self.mixer.play_one_shot(audio, level, 0.0);
+    }
 
-            let voice = &(*(*self.loops)[channel as usize]);
-            voice.stop();
-            voice.flush();
-            voice.set_volume(level);
-            voice.submit(&audio_buffer as *const XAUDIO2_BUFFER, std::ptr::null_mut());
-            voice.start();
-        }
+    fn play_loop(&mut self, audio: &str, channel: u8, level: f32) {
+        self.mixer.play_loop(audio, channel, level, 0.0);
     }
 
-    pub fn stop_loop(&mut self, channel: u8) {
-        if channel as usize >= self.loops.len() {
-            println!("Channel {} does not exist", channel);
-            return;
-        }
-        unsafe {
-            let voice = &(*(*self.loops)[channel as usize]);
-            voice.stop();
-            voice.flush();
-        }
+    fn stop_loop(&mut self, channel: u8) {
+        self.mixer.stop_loop(channel);
     }
 
-    pub fn set_master_volume(&self, value: f32) {
-        unsafe {
-            (*self.master_voice).set_volume(value);
-        }
+    fn play(&mut self, audio: &str, level: f32, priority: u8) -> SoundHandle {
+        self.mixer.play(audio, level, 0.0, priority)
+    }
+
+    fn stop_sound(&mut self, handle: SoundHandle) {
+        self.mixer.stop_sound(handle);
+    }
+
+    fn pause_sound(&mut self, handle: SoundHandle) {
+        self.mixer.pause_sound(handle);
+    }
+
+    fn resume_sound(&mut self, handle: SoundHandle) {
+        self.mixer.resume_sound(handle);
+    }
+
+    fn set_sound_volume(&mut self, handle: SoundHandle, level: f32) {
+        self.mixer.set_sound_volume(handle, level);
+    }
+
+    fn set_sound_pitch(&mut self, handle: SoundHandle, pitch: f32) {
+        self.mixer.set_sound_pitch(handle, pitch);
+    }
+
+    fn set_sound_pan(&mut self, handle: SoundHandle, pan: f32) {
+        self.mixer.set_sound_pan(handle, pan);
+    }
+
+    fn is_sound_playing(&self, handle: SoundHandle) -> bool {
+        self.mixer.is_sound_playing(handle)
+    }
+
+    fn sound_position(&self, handle: SoundHandle) -> Option<Duration> {
+        self.mixer.sound_position(handle)
+    }
+
+    fn load_stream(&mut self, path: &str) {
+        Audio::load_stream(self, path);
+    }
+
+    fn play_stream(&mut self, stream: &str, level: f32, looping: bool) {
+        self.mixer.play_stream(stream, level, looping);
+    }
+
+    fn pause_stream(&mut self, stream: &str) {
+        self.mixer.pause_stream(stream);
+    }
+
+    fn stop_stream(&mut self, stream: &str) {
+        self.mixer.stop_stream(stream);
+    }
+
+    fn seek_stream(&mut self, stream: &str, position: Duration) {
+        self.mixer.seek_stream(stream, position);
+    }
+
+    fn set_stream_loop_points(&mut self, stream: &str, start: Duration, end: Option<Duration>) {
+        self.mixer.set_stream_loop_points(stream, start, end);
+    }
+
+    fn set_master_volume(&mut self, level: f32) {
+        self.mixer.set_master_volume(level);
+    }
+
+    ///XAudio2 pulls buffers at its own pace, the queue is topped up whatever dt is.
+    fn update(&mut self, _dt: Duration) {
+        self.submit_mixed();
     }
 }
 
@@ -352,59 +384,3 @@ pub fn failed(value: i32, error: &str) {
         panic!();
     }
 }
-
-pub fn buf_read_file(filename: &str) -> AudioDataVec {
-    let mut file = File::open(filename).unwrap();
-
-    let chunk_type = read_chunk(&mut file);
-    if chunk_type != 1179011410 {
-        panic!("First Chunk Invalid");
-    }
-
-    let _chunk_data_size = read_chunk(&mut file);
-    let file_format = read_chunk(&mut file);
-    if file_format != 1163280727 {
-        panic!("Wrong File Format");
-    }
-
-    let chunk_type = read_chunk(&mut file);
-    if chunk_type != 544501094 {
-        panic!("Bad 'fmt ' subchunk");
-    }
-
-    let chunk_data_size = read_chunk(&mut file);
-    let format = read_wav_format(&mut file, chunk_data_size);
-    let chunk_type = read_chunk(&mut file);
-    if chunk_type != 1635017060 {
-        panic!("Bad Subchunk");
-    }
-
-    let size = read_chunk(&mut file);
-    let mut data = Vec::with_capacity(chunk_data_size as usize);
-    file.read_to_end(&mut data).unwrap();
-
-    AudioDataVec { data, size, format }
-}
-
-pub fn read_wav_format(file: &mut File, size: u32) -> WAVEFORMATEXTENSIBLE {
-    let vaules: Vec<_> = file
-        .by_ref()
-        .bytes()
-        .take(size as usize)
-        .map(|x| x.unwrap())
-        .collect();
-    unsafe {
-        let format: WAVEFORMATEXTENSIBLE = std::ptr::read(vaules.as_ptr() as *const _);
-        format
-    }
-}
-
-pub fn read_chunk(file: &mut File) -> u32 {
-    let values: Vec<_> = file
-        .by_ref()
-        .bytes()
-        .take(4)
-        .map(|x| x.unwrap() as u32)
-        .collect();
-    values[3] << 24 | values[2] << 16 | values[1] << 8 | values[0]
-}
@@ -0,0 +1,853 @@
+use crate::image::ImageFormat;
+
+///Interpolation weights for BC6H and BC7 indices with 2, 3 and 4 bits.
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+///Two subset partitions, bit i is the subset of pixel i.
+#[rustfmt::skip]
+const PARTITIONS_2: [u32; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+///Three subset partitions, bits 2i and 2i + 1 are the subset of pixel i.
+#[rustfmt::skip]
+const PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+///Anchor pixel of the second subset of two subset partitions. The anchor of the first subset is
+///always pixel 0.
+#[rustfmt::skip]
+const ANCHORS_2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15,  2,  8,  2,  2,  8,  8, 15,  2,  8,  2,  2,  8,  8,  2,  2,
+    15, 15,  6,  8,  2,  8, 15, 15,  2,  8,  2,  2,  2, 15, 15,  6,
+     6,  2,  6,  8, 15, 15,  2,  2, 15, 15, 15, 15, 15,  2,  2, 15,
+];
+
+///Anchor pixels of the second and third subset of three subset partitions.
+#[rustfmt::skip]
+const ANCHORS_3: [[usize; 64]; 2] = [
+    [
+         3,  3, 15, 15,  8,  3, 15, 15,  8,  8,  6,  6,  6,  5,  3,  3,
+         3,  3,  8, 15,  3,  3,  6, 10,  5,  8,  8,  6,  8,  5, 15, 15,
+         8, 15,  3,  5,  6, 10,  8, 15, 15,  3, 15,  5, 15, 15, 15, 15,
+         3, 15,  5,  5,  5,  8,  5, 10,  5, 10,  8, 13, 15, 12,  3,  3,
+    ],
+    [
+        15,  8,  8,  3, 15, 15,  3,  8, 15, 15, 15, 15, 15, 15, 15,  8,
+        15,  8, 15,  3, 15,  8, 15,  8,  3, 15,  6, 10, 15, 15, 10,  8,
+        15,  3, 15, 10, 10,  8,  9, 10,  6, 15,  8, 15,  3,  6,  6,  8,
+        15,  3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,  3, 15, 15,  8,
+    ],
+];
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Decoding
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Decode a single mip level of block compressed data to RGBA8. Pixels are written in the order
+///the blocks are stored, BC6H is clamped to the 0 to 1 range and signed channels to 0 like they
+///would be when sampled and written to an 8bit render target.
+pub fn decode_bcn(format: ImageFormat, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    let width = width.max(1) as usize;
+    let height = height.max(1) as usize;
+    let block_size = format.level_size(1, 1);
+    let blocks_x = width.div_ceil(4);
+
+    let mut pixels = vec![0; width * height * 4];
+
+    for (index, block) in data.chunks_exact(block_size).enumerate() {
+        let decoded = match format {
+            ImageFormat::Rgba8 => unreachable!(),
+            ImageFormat::Bc1 => decode_bc1(block, true),
+            ImageFormat::Bc2 => {
+                let mut decoded = decode_bc1(&block[8..16], false);
+                let alpha = u64::from_le_bytes(block[0..8].try_into().unwrap());
+                for (i, pixel) in decoded.iter_mut().enumerate() {
+                    pixel[3] = ((alpha >> (i * 4)) & 0x0f) as u8 * 17;
+                }
+                decoded
+            }
+            ImageFormat::Bc3 => {
+                let mut decoded = decode_bc1(&block[8..16], false);
+                let alpha = decode_bc4(&block[0..8], false);
+                for (pixel, alpha) in decoded.iter_mut().zip(alpha) {
+                    pixel[3] = alpha;
+                }
+                decoded
+            }
+            ImageFormat::Bc4 | ImageFormat::Bc4Signed => {
+                let red = decode_bc4(block, format == ImageFormat::Bc4Signed);
+                red.map(|red| [red, 0, 0, 255])
+            }
+            ImageFormat::Bc5 | ImageFormat::Bc5Signed => {
+                let signed = format == ImageFormat::Bc5Signed;
+                let red = decode_bc4(&block[0..8], signed);
+                let green = decode_bc4(&block[8..16], signed);
+                let mut decoded = [[0, 0, 0, 255]; 16];
+                for (i, pixel) in decoded.iter_mut().enumerate() {
+                    pixel[0] = red[i];
+                    pixel[1] = green[i];
+                }
+                decoded
+            }
+            ImageFormat::Bc6h | ImageFormat::Bc6hSigned => {
+                decode_bc6h(block, format == ImageFormat::Bc6hSigned)
+            }
+            ImageFormat::Bc7 => decode_bc7(block),
+        };
+
+        let block_x = (index % blocks_x) * 4;
+        let block_y = (index / blocks_x) * 4;
+
+        //Blocks on the right and bottom edge may hang over the image.
+        for (i, rgba) in decoded.iter().enumerate() {
+            let x = block_x + i % 4;
+            let y = block_y + i / 4;
+            if x < width && y < height {
+                let start = (y * width + x) * 4;
+                pixels[start..start + 4].copy_from_slice(rgba);
+            }
+        }
+    }
+
+    pixels
+}
+
+///Decode a BC1 color block. Blocks of BC2 and BC3 always use four colors, only BC1 blocks with
+///the first color not greater than the second have a transparent black entry.
+fn decode_bc1(block: &[u8], punch_through: bool) -> [[u8; 4]; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+
+    let a = rgb565(color0);
+    let b = rgb565(color1);
+    let mix = |weight_a: u32, weight_b: u32| {
+        let total = weight_a + weight_b;
+        [0, 1, 2].map(|channel| {
+            ((a[channel] as u32 * weight_a + b[channel] as u32 * weight_b + total / 2) / total)
+                as u8
+        })
+    };
+
+    let palette = if color0 > color1 || !punch_through {
+        let [r2, g2, b2] = mix(2, 1);
+        let [r3, g3, b3] = mix(1, 2);
+        [
+            [a[0], a[1], a[2], 255],
+            [b[0], b[1], b[2], 255],
+            [r2, g2, b2, 255],
+            [r3, g3, b3, 255],
+        ]
+    } else {
+        let [r2, g2, b2] = mix(1, 1);
+        [
+            [a[0], a[1], a[2], 255],
+            [b[0], b[1], b[2], 255],
+            [r2, g2, b2, 255],
+            [0, 0, 0, 0],
+        ]
+    };
+
+    std::array::from_fn(|i| palette[((indices >> (i * 2)) & 0x03) as usize])
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 0x1f) as u8;
+    let g = ((color >> 5) & 0x3f) as u8;
+    let b = (color & 0x1f) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+///Decode a BC4 block, also used for BC3 alpha and both BC5 channels. Signed values below zero
+///decode as 0.
+fn decode_bc4(block: &[u8], signed: bool) -> [u8; 16] {
+    let (value0, value1, max) = if signed {
+        //-128 is treated as -127 so zero sits in the middle of the range.
+        (
+            (block[0] as i8).max(-127) as f32,
+            (block[1] as i8).max(-127) as f32,
+            127.0,
+        )
+    } else {
+        (block[0] as f32, block[1] as f32, 255.0)
+    };
+
+    let mut palette = [value0, value1, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    if value0 > value1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i) as f32 * value0 + i as f32 * value1) / 7.0;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i) as f32 * value0 + i as f32 * value1) / 5.0;
+        }
+        palette[6] = if signed { -max } else { 0.0 };
+        palette[7] = max;
+    }
+
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+
+    std::array::from_fn(|i| {
+        let value = palette[((indices >> (i * 3)) & 0x07) as usize];
+        (value.max(0.0) * 255.0 / max).round() as u8
+    })
+}
+
+///Reads a 128bit block from the least significant bit up.
+struct BlockBits {
+    bits: u128,
+    position: u32,
+}
+
+impl BlockBits {
+    fn new(block: &[u8]) -> Self {
+        Self {
+            bits: u128::from_le_bytes(block.try_into().unwrap()),
+            position: 0,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = (self.bits >> self.position) as u32 & ((1u64 << count) - 1) as u32;
+        self.position += count;
+        value
+    }
+}
+
+///Interpolate between two endpoints with a weight out of 64.
+fn interpolate(a: u32, b: u32, weight: u32) -> u32 {
+    ((64 - weight) * a + weight * b + 32) >> 6
+}
+
+fn weights(index_bits: u32) -> &'static [u32] {
+    match index_bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    }
+}
+
+fn subset(subsets: usize, partition: usize, pixel: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => (PARTITIONS_2[partition] >> pixel) as usize & 1,
+        _ => (PARTITIONS_3[partition] >> (pixel * 2)) as usize & 3,
+    }
+}
+
+///Anchor pixels store their index with one bit less, the dropped bit is always zero.
+fn is_anchor(subsets: usize, partition: usize, pixel: usize) -> bool {
+    pixel == 0
+        || match subsets {
+            1 => false,
+            2 => ANCHORS_2[partition] == pixel,
+            _ => ANCHORS_3[0][partition] == pixel || ANCHORS_3[1][partition] == pixel,
+        }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// BC7
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    ///Every endpoint has its own p-bit, the shared lowest bit of all its channels.
+    endpoint_pbits: bool,
+    ///Both endpoints of a subset share a p-bit.
+    shared_pbits: bool,
+    index_bits: u32,
+    ///Bits of the separate alpha indices, 0 if color and alpha share indices.
+    alpha_index_bits: u32,
+}
+
+#[rustfmt::skip]
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, alpha_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, alpha_index_bits: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, alpha_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, alpha_index_bits: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, alpha_index_bits: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, alpha_index_bits: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, alpha_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, alpha_index_bits: 0 },
+];
+
+fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    //The mode is the number of zero bits before the first set bit, blocks without one are invalid
+    //and decode as transparent black.
+    let mode_number = block[0].trailing_zeros();
+    if mode_number >= 8 {
+        return [[0; 4]; 16];
+    }
+
+    let mut bits = BlockBits::new(block);
+    bits.read(mode_number + 1);
+    let mode = &BC7_MODES[mode_number as usize];
+
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    //Endpoints are stored channel by channel, two per subset.
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = bits.read(mode.alpha_bits);
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let mut pbits = [0; 6];
+        if mode.endpoint_pbits {
+            for pbit in pbits.iter_mut().take(endpoint_count) {
+                *pbit = bits.read(1);
+            }
+        } else {
+            for subset in 0..mode.subsets {
+                let pbit = bits.read(1);
+                pbits[subset * 2] = pbit;
+                pbits[subset * 2 + 1] = pbit;
+            }
+        }
+
+        for (endpoint, pbit) in endpoints.iter_mut().zip(pbits) {
+            for channel in endpoint.iter_mut() {
+                *channel = (*channel << 1) | pbit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    //Expand to 8 bits by repeating the high bits in the low bits.
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for (channel, value) in endpoint.iter_mut().enumerate() {
+            let precision = if channel < 3 { color_bits } else { alpha_bits };
+            *value = if precision == 0 {
+                255
+            } else {
+                let shifted = *value << (8 - precision);
+                shifted | (shifted >> precision)
+            };
+        }
+    }
+
+    let mut indices = [0; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let anchor = is_anchor(mode.subsets, partition, pixel) as u32;
+        *index = bits.read(mode.index_bits - anchor);
+    }
+
+    let mut alpha_indices = indices;
+    if mode.alpha_index_bits > 0 {
+        for (pixel, index) in alpha_indices.iter_mut().enumerate() {
+            *index = bits.read(mode.alpha_index_bits - (pixel == 0) as u32);
+        }
+    }
+
+    let mut color_weights = weights(mode.index_bits);
+    let mut alpha_weights = if mode.alpha_index_bits > 0 {
+        weights(mode.alpha_index_bits)
+    } else {
+        color_weights
+    };
+
+    //The index selection bit swaps which indices are used for color and which for alpha.
+    if index_selection == 1 {
+        std::mem::swap(&mut indices, &mut alpha_indices);
+        std::mem::swap(&mut color_weights, &mut alpha_weights);
+    }
+
+    std::array::from_fn(|pixel| {
+        let subset = subset(mode.subsets, partition, pixel);
+        let a = endpoints[subset * 2];
+        let b = endpoints[subset * 2 + 1];
+
+        let color_weight = color_weights[indices[pixel] as usize];
+        let alpha_weight = alpha_weights[alpha_indices[pixel] as usize];
+        let mut rgba = [
+            interpolate(a[0], b[0], color_weight) as u8,
+            interpolate(a[1], b[1], color_weight) as u8,
+            interpolate(a[2], b[2], color_weight) as u8,
+            interpolate(a[3], b[3], alpha_weight) as u8,
+        ];
+
+        //Rotation swaps alpha with one of the color channels.
+        if rotation > 0 {
+            rgba.swap(rotation as usize - 1, 3);
+        }
+        rgba
+    })
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// BC6H
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+//Endpoint fields of BC6H blocks, w and x are the endpoints of the first subset, y and z of the
+//second. PARTITION is the partition number of two subset modes.
+const RW: usize = 0;
+const GW: usize = 1;
+const BW: usize = 2;
+const RX: usize = 3;
+const GX: usize = 4;
+const BX: usize = 5;
+const RY: usize = 6;
+const GY: usize = 7;
+const BY: usize = 8;
+const RZ: usize = 9;
+const GZ: usize = 10;
+const BZ: usize = 11;
+const PARTITION: usize = 12;
+
+struct Bc6hMode {
+    subsets: usize,
+    ///Endpoints other than the first are stored as deltas from it.
+    transformed: bool,
+    ///Bits of the first endpoint.
+    endpoint_bits: u32,
+    ///Bits of the other endpoints per channel.
+    delta_bits: [u32; 3],
+    ///Fields after the mode bits in the order they are stored as field, first bit, last bit. The
+    ///bits are read from last to first, so (RW, 9, 0) is bit 0 of rw up to bit 9.
+    layout: &'static [(usize, u32, u32)],
+}
+
+#[rustfmt::skip]
+const BC6H_MODE_1: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 10,
+    delta_bits: [5, 5, 5],
+    layout: &[
+        (GY, 4, 4), (BY, 4, 4), (BZ, 4, 4), (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 4, 0),
+        (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1),
+        (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3), (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_2: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 7,
+    delta_bits: [6, 6, 6],
+    layout: &[
+        (GY, 5, 5), (GZ, 4, 4), (GZ, 5, 5), (RW, 6, 0), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4),
+        (GW, 6, 0), (BY, 5, 5), (BZ, 2, 2), (GY, 4, 4), (BW, 6, 0), (BZ, 3, 3), (BZ, 5, 5),
+        (BZ, 4, 4), (RX, 5, 0), (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0),
+        (RY, 5, 0), (RZ, 5, 0), (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_3: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [5, 4, 4],
+    layout: &[
+        (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 4, 0), (RW, 10, 10), (GY, 3, 0), (GX, 3, 0),
+        (GW, 10, 10), (BZ, 0, 0), (GZ, 3, 0), (BX, 3, 0), (BW, 10, 10), (BZ, 1, 1), (BY, 3, 0),
+        (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3), (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_4: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [4, 5, 4],
+    layout: &[
+        (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 10), (GZ, 4, 4), (GY, 3, 0),
+        (GX, 4, 0), (GW, 10, 10), (GZ, 3, 0), (BX, 3, 0), (BW, 10, 10), (BZ, 1, 1), (BY, 3, 0),
+        (RY, 3, 0), (BZ, 0, 0), (BZ, 2, 2), (RZ, 3, 0), (GY, 4, 4), (BZ, 3, 3), (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_5: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [4, 4, 5],
+    layout: &[
+        (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 10), (BY, 4, 4), (GY, 3, 0),
+        (GX, 3, 0), (GW, 10, 10), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BW, 10, 10), (BY, 3, 0),
+        (RY, 3, 0), (BZ, 1, 1), (BZ, 2, 2), (RZ, 3, 0), (BZ, 4, 4), (BZ, 3, 3), (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_6: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 9,
+    delta_bits: [5, 5, 5],
+    layout: &[
+        (RW, 8, 0), (BY, 4, 4), (GW, 8, 0), (GY, 4, 4), (BW, 8, 0), (BZ, 4, 4), (RX, 4, 0),
+        (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1),
+        (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3), (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_7: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 8,
+    delta_bits: [6, 5, 5],
+    layout: &[
+        (RW, 7, 0), (GZ, 4, 4), (BY, 4, 4), (GW, 7, 0), (BZ, 2, 2), (GY, 4, 4), (BW, 7, 0),
+        (BZ, 3, 3), (BZ, 4, 4), (RX, 5, 0), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0),
+        (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 5, 0), (RZ, 5, 0), (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_8: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 8,
+    delta_bits: [5, 6, 5],
+    layout: &[
+        (RW, 7, 0), (BZ, 0, 0), (BY, 4, 4), (GW, 7, 0), (GY, 5, 5), (GY, 4, 4), (BW, 7, 0),
+        (GZ, 5, 5), (BZ, 4, 4), (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0),
+        (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3),
+        (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_9: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: true,
+    endpoint_bits: 8,
+    delta_bits: [5, 5, 6],
+    layout: &[
+        (RW, 7, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 7, 0), (BY, 5, 5), (GY, 4, 4), (BW, 7, 0),
+        (BZ, 5, 5), (BZ, 4, 4), (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0),
+        (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3),
+        (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_10: Bc6hMode = Bc6hMode {
+    subsets: 2,
+    transformed: false,
+    endpoint_bits: 6,
+    delta_bits: [6, 6, 6],
+    layout: &[
+        (RW, 5, 0), (GZ, 4, 4), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 5, 0), (GY, 5, 5),
+        (BY, 5, 5), (BZ, 2, 2), (GY, 4, 4), (BW, 5, 0), (GZ, 5, 5), (BZ, 3, 3), (BZ, 5, 5),
+        (BZ, 4, 4), (RX, 5, 0), (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0),
+        (RY, 5, 0), (RZ, 5, 0), (PARTITION, 4, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_11: Bc6hMode = Bc6hMode {
+    subsets: 1,
+    transformed: false,
+    endpoint_bits: 10,
+    delta_bits: [10, 10, 10],
+    layout: &[
+        (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 9, 0), (GX, 9, 0), (BX, 9, 0),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_12: Bc6hMode = Bc6hMode {
+    subsets: 1,
+    transformed: true,
+    endpoint_bits: 11,
+    delta_bits: [9, 9, 9],
+    layout: &[
+        (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 8, 0), (RW, 10, 10), (GX, 8, 0), (GW, 10, 10),
+        (BX, 8, 0), (BW, 10, 10),
+    ],
+};
+
+//Modes 13 and 14 store the high bits of the first endpoint reversed.
+#[rustfmt::skip]
+const BC6H_MODE_13: Bc6hMode = Bc6hMode {
+    subsets: 1,
+    transformed: true,
+    endpoint_bits: 12,
+    delta_bits: [8, 8, 8],
+    layout: &[
+        (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 7, 0), (RW, 10, 11), (GX, 7, 0), (GW, 10, 11),
+        (BX, 7, 0), (BW, 10, 11),
+    ],
+};
+
+#[rustfmt::skip]
+const BC6H_MODE_14: Bc6hMode = Bc6hMode {
+    subsets: 1,
+    transformed: true,
+    endpoint_bits: 16,
+    delta_bits: [4, 4, 4],
+    layout: &[
+        (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 15), (GX, 3, 0), (GW, 10, 15),
+        (BX, 3, 0), (BW, 10, 15),
+    ],
+};
+
+fn decode_bc6h(block: &[u8], signed: bool) -> [[u8; 4]; 16] {
+    let mut bits = BlockBits::new(block);
+
+    //Modes 1 and 2 have 2 mode bits, the others 5.
+    let mut mode_bits = bits.read(2);
+    if mode_bits > 1 {
+        mode_bits |= bits.read(3) << 2;
+    }
+
+    let mode = match mode_bits {
+        0b00000 => &BC6H_MODE_1,
+        0b00001 => &BC6H_MODE_2,
+        0b00010 => &BC6H_MODE_3,
+        0b00110 => &BC6H_MODE_4,
+        0b01010 => &BC6H_MODE_5,
+        0b01110 => &BC6H_MODE_6,
+        0b10010 => &BC6H_MODE_7,
+        0b10110 => &BC6H_MODE_8,
+        0b11010 => &BC6H_MODE_9,
+        0b11110 => &BC6H_MODE_10,
+        0b00011 => &BC6H_MODE_11,
+        0b00111 => &BC6H_MODE_12,
+        0b01011 => &BC6H_MODE_13,
+        0b01111 => &BC6H_MODE_14,
+        //Reserved modes decode as black.
+        _ => return [[0, 0, 0, 255]; 16],
+    };
+
+    let mut fields = [0u32; 13];
+    for &(field, first, last) in mode.layout {
+        if first >= last {
+            for bit in last..=first {
+                fields[field] |= bits.read(1) << bit;
+            }
+        } else {
+            for bit in (first..=last).rev() {
+                fields[field] |= bits.read(1) << bit;
+            }
+        }
+    }
+    let partition = fields[PARTITION] as usize;
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0i32; 3]; 4];
+    for (channel, &delta_bits) in mode.delta_bits.iter().enumerate() {
+        let first = fields[channel] as i32;
+        endpoints[0][channel] = if signed {
+            sign_extend(first, mode.endpoint_bits)
+        } else {
+            first
+        };
+
+        for endpoint in 1..endpoint_count {
+            let mut value = fields[endpoint * 3 + channel] as i32;
+            if mode.transformed || signed {
+                value = sign_extend(value, delta_bits);
+            }
+            if mode.transformed {
+                value = (first + value) & ((1 << mode.endpoint_bits) - 1);
+                if signed {
+                    value = sign_extend(value, mode.endpoint_bits);
+                }
+            }
+            endpoints[endpoint][channel] = value;
+        }
+    }
+
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        for value in endpoint.iter_mut() {
+            *value = unquantize(*value, mode.endpoint_bits, signed);
+        }
+    }
+
+    let index_bits = if mode.subsets == 1 { 4 } else { 3 };
+    let weights = weights(index_bits);
+
+    std::array::from_fn(|pixel| {
+        let anchor = is_anchor(mode.subsets, partition, pixel) as u32;
+        let weight = weights[bits.read(index_bits - anchor) as usize] as i32;
+        let subset = subset(mode.subsets, partition, pixel);
+        let a = endpoints[subset * 2];
+        let b = endpoints[subset * 2 + 1];
+
+        let [r, g, b] = [0, 1, 2].map(|channel| {
+            let value = ((64 - weight) * a[channel] + weight * b[channel] + 32) >> 6;
+            let half = if signed {
+                //Negative values are stored as sign and magnitude.
+                if value < 0 {
+                    0x8000 | ((-value * 31) >> 5) as u16
+                } else {
+                    ((value * 31) >> 5) as u16
+                }
+            } else {
+                ((value * 31) >> 6) as u16
+            };
+            (half_to_f32(half).clamp(0.0, 1.0) * 255.0).round() as u8
+        });
+        [r, g, b, 255]
+    })
+}
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+///Scale an endpoint to the full 16bit range before interpolation.
+fn unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if signed {
+        if bits >= 16 {
+            return value;
+        }
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7fff
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        if value < 0 {
+            -unquantized
+        } else {
+            unquantized
+        }
+    } else if bits >= 15 || value == 0 {
+        value
+    } else if value == (1 << bits) - 1 {
+        0xffff
+    } else {
+        ((value << 16) + 0x8000) >> bits
+    }
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    #[test]
+    fn decodes_bc1_four_and_three_color_blocks() {
+        let block = [0x00, 0xf8, 0x1f, 0x00, 0b1110_0100, 0, 0, 0];
+        let pixels = decode_bcn(ImageFormat::Bc1, 4, 4, &block);
+        assert_eq!(pixels[0..4], RED);
+        assert_eq!(pixels[4..8], [0, 0, 255, 255]);
+        assert_eq!(pixels[8..12], [170, 0, 85, 255]);
+        assert_eq!(pixels[12..16], [85, 0, 170, 255]);
+
+        //The first color not above the second switches to three colors and transparent black.
+        let block = [0x1f, 0x00, 0x00, 0xf8, 0b1110_0100, 0, 0, 0];
+        let pixels = decode_bcn(ImageFormat::Bc1, 4, 4, &block);
+        assert_eq!(pixels[8..12], [128, 0, 128, 255]);
+        assert_eq!(pixels[12..16], [0, 0, 0, 0]);
+
+        //BC2 and BC3 color blocks always use four colors.
+        let mut bc2 = [0xff; 8].to_vec();
+        bc2.extend_from_slice(&block);
+        assert_eq!(
+            decode_bcn(ImageFormat::Bc2, 4, 4, &bc2)[12..16],
+            [170, 0, 85, 255]
+        );
+    }
+
+    #[test]
+    fn decodes_bc4_endpoints_and_signed_clamp() {
+        let pixels = decode_bcn(ImageFormat::Bc4, 4, 4, &[255, 0, 0x08, 0, 0, 0, 0, 0]);
+        assert_eq!(pixels[0..8], [255, 0, 0, 255, 0, 0, 0, 255]);
+
+        //-128 reads as -127 and negative values clamp to 0.
+        let pixels = decode_bcn(
+            ImageFormat::Bc4Signed,
+            4,
+            4,
+            &[0x7f, 0x80, 0x08, 0, 0, 0, 0, 0],
+        );
+        assert_eq!(pixels[0..8], [255, 0, 0, 255, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_bc7_mode_6_and_invalid_blocks() {
+        //Mode 6 with every endpoint, p bit and index set is opaque white.
+        let mut block = [0xff; 16];
+        block[0] = 0xc0;
+        assert_eq!(decode_bcn(ImageFormat::Bc7, 4, 4, &block), [255; 64]);
+
+        //No mode bit at all is reserved and decodes as transparent black.
+        assert_eq!(decode_bcn(ImageFormat::Bc7, 4, 4, &[0; 16]), [0; 64]);
+    }
+
+    #[test]
+    fn clips_edge_blocks_and_leaves_missing_blocks_empty() {
+        let red_block = [0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0];
+        let pixels = decode_bcn(ImageFormat::Bc1, 5, 3, &red_block.repeat(2));
+        assert_eq!(pixels, RED.repeat(15));
+
+        let pixels = decode_bcn(ImageFormat::Bc1, 5, 3, &red_block);
+        assert_eq!(pixels.len(), 60);
+        assert_eq!(pixels[16..20], [0; 4]);
+        assert_eq!(pixels[0..16], RED.repeat(4));
+    }
+}
@@ -0,0 +1,445 @@
+use crate::image::Image;
+use crate::texture::TextureError;
+
+const BMP_FILE_HEADER_SIZE: usize = 14;
+
+const BMP_CORE_HEADER: u32 = 12;
+const BMP_INFO_HEADER: u32 = 40;
+const BMP_V2_HEADER: u32 = 52;
+const BMP_V3_HEADER: u32 = 56;
+const BMP_V4_HEADER: u32 = 108;
+const BMP_V5_HEADER: u32 = 124;
+
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+///Largest width * height accepted, guards against absurd allocations from corrupt headers.
+const BMP_MAX_PIXELS: u64 = 1 << 28;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Decoding
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Decode a 1, 4, 8, 16, 24 or 32bit BMP file, uncompressed, BITFIELDS or RLE4/RLE8 compressed,
+///with any header version from BITMAPCOREHEADER to BITMAPV5HEADER. Pixels skipped by RLE deltas
+///are transparent black.
+pub fn decode_bmp(file: &[u8]) -> Result<Image, TextureError> {
+    if file.len() < 2 || &file[0..2] != b"BM" {
+        return Err(TextureError::InvalidSignature);
+    }
+
+    let data_offset = read_u32(file, 10)? as usize;
+    let header_size = read_u32(file, BMP_FILE_HEADER_SIZE)?;
+
+    let (width, height, bit_depth, compression) = match header_size {
+        BMP_CORE_HEADER => (
+            read_u16(file, 18)? as i32,
+            read_u16(file, 20)? as i16 as i32,
+            read_u16(file, 24)?,
+            BI_RGB,
+        ),
+        BMP_INFO_HEADER | BMP_V2_HEADER | BMP_V3_HEADER | BMP_V4_HEADER | BMP_V5_HEADER => (
+            read_u32(file, 18)? as i32,
+            read_u32(file, 22)? as i32,
+            read_u16(file, 28)?,
+            read_u32(file, 30)?,
+        ),
+        _ => {
+            return Err(TextureError::Unsupported(format!(
+                "Unsupported BMP header size {header_size}"
+            )))
+        }
+    };
+
+    if width <= 0
+        || height == 0
+        || height == i32::MIN
+        || width as u64 * height.unsigned_abs() as u64 > BMP_MAX_PIXELS
+    {
+        return Err(TextureError::InvalidDimensions {
+            width: width as i64,
+            height: height as i64,
+        });
+    }
+
+    if !matches!(bit_depth, 1 | 4 | 8 | 16 | 24 | 32) {
+        return Err(TextureError::Unsupported(format!(
+            "Unsupported BMP bit depth {bit_depth}"
+        )));
+    }
+
+    let valid_compression = match compression {
+        BI_RGB => true,
+        BI_RLE8 => bit_depth == 8,
+        BI_RLE4 => bit_depth == 4,
+        BI_BITFIELDS | BI_ALPHABITFIELDS => bit_depth == 16 || bit_depth == 32,
+        _ => false,
+    };
+    if !valid_compression {
+        return Err(TextureError::Unsupported(format!(
+            "Unsupported BMP compression {compression} for bit depth {bit_depth}"
+        )));
+    }
+
+    //BITMAPINFOHEADER stores the masks right after the header, later versions inside it.
+    let mask_offset = BMP_FILE_HEADER_SIZE + BMP_INFO_HEADER as usize;
+    let extra_masks = match (header_size, compression) {
+        (BMP_INFO_HEADER, BI_BITFIELDS) => 12,
+        (BMP_INFO_HEADER, BI_ALPHABITFIELDS) => 16,
+        _ => 0,
+    };
+
+    let masks = match (compression, bit_depth) {
+        (BI_BITFIELDS | BI_ALPHABITFIELDS, _) => {
+            let has_alpha = header_size >= BMP_V3_HEADER || compression == BI_ALPHABITFIELDS;
+            [
+                read_u32(file, mask_offset)?,
+                read_u32(file, mask_offset + 4)?,
+                read_u32(file, mask_offset + 8)?,
+                if has_alpha {
+                    read_u32(file, mask_offset + 12)?
+                } else {
+                    0
+                },
+            ]
+        }
+        (_, 16) => [0x7c00, 0x03e0, 0x001f, 0],
+        _ => [0x00ff0000, 0x0000ff00, 0x000000ff, 0xff000000],
+    };
+    let masks = masks.map(ColorMask::new);
+
+    let palette = if bit_depth <= 8 {
+        let entry_size = if header_size == BMP_CORE_HEADER { 3 } else { 4 };
+        let colors_used = if header_size == BMP_CORE_HEADER {
+            0
+        } else {
+            read_u32(file, 46)? as usize
+        };
+        let count = match colors_used {
+            0 => 1 << bit_depth,
+            count => count.min(256),
+        };
+
+        let start = BMP_FILE_HEADER_SIZE + header_size as usize + extra_masks;
+        let Some(entries) = file.get(start..start + count * entry_size) else {
+            return Err(TextureError::Malformed("Malformed palette data"));
+        };
+
+        let mut palette: Vec<[u8; 4]> = entries
+            .chunks_exact(entry_size)
+            .map(|bgr| [bgr[2], bgr[1], bgr[0], 255])
+            .collect();
+        //Indices past the end of the palette decode as opaque black.
+        palette.resize(256, [0, 0, 0, 255]);
+        palette
+    } else {
+        Vec::new()
+    };
+
+    let Some(data) = file.get(data_offset..) else {
+        return Err(TextureError::Malformed("Pixel data offset out of bounds"));
+    };
+
+    let width = width as usize;
+    let rows = height.unsigned_abs() as usize;
+    let top_down = height < 0;
+    let bit_depth = bit_depth as usize;
+    let row_bytes = (width * bit_depth).div_ceil(8);
+    let stride = (width * bit_depth).div_ceil(32) * 4;
+
+    if compression == BI_RLE8 || compression == BI_RLE4 {
+        //A run or absolute command moves at most 255 pixels per two bytes and a delta at most 255
+        //rows per four bytes, data shorter than that can't reach the last row or column.
+        if width > data.len() / 2 * 255 || rows - 1 > data.len() / 4 * 255 + data.len() / 2 {
+            return Err(TextureError::UnexpectedEof);
+        }
+    } else if data.len() < stride * (rows - 1) + row_bytes {
+        //The padding after the last row is often left out.
+        return Err(TextureError::UnexpectedEof);
+    }
+
+    let mut pixels = vec![0; width * rows * 4];
+
+    //Rows are stored bottom up unless the height is negative.
+    let image_row = |row: usize| if top_down { row } else { rows - 1 - row };
+
+    if compression == BI_RLE8 || compression == BI_RLE4 {
+        let indices = decode_rle(data, width, rows, compression == BI_RLE4)?;
+        for (row, row_indices) in indices.chunks_exact(width).enumerate() {
+            let start = image_row(row) * width * 4;
+            for (pixel, index) in pixels[start..start + width * 4]
+                .chunks_exact_mut(4)
+                .zip(row_indices)
+            {
+                if let Some(index) = index {
+                    pixel.copy_from_slice(&palette[*index as usize]);
+                }
+            }
+        }
+        return Ok(Image::new(width as u32, rows as u32, pixels));
+    }
+
+    for row in 0..rows {
+        let line = &data[row * stride..row * stride + row_bytes];
+        let start = image_row(row) * width * 4;
+
+        for (x, pixel) in pixels[start..start + width * 4]
+            .chunks_exact_mut(4)
+            .enumerate()
+        {
+            let rgba = match bit_depth {
+                1 | 4 | 8 => {
+                    let bit = x * bit_depth;
+                    let shift = 8 - bit_depth - bit % 8;
+                    let index = (line[bit / 8] >> shift) & ((1u16 << bit_depth) - 1) as u8;
+                    palette[index as usize]
+                }
+                24 => [line[x * 3 + 2], line[x * 3 + 1], line[x * 3], 255],
+                16 => {
+                    let value = u16::from_le_bytes([line[x * 2], line[x * 2 + 1]]) as u32;
+                    masks.map(|mask| mask.extract(value))
+                }
+                _ => {
+                    let value = u32::from_le_bytes(line[x * 4..x * 4 + 4].try_into().unwrap());
+                    masks.map(|mask| mask.extract(value))
+                }
+            };
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    //Plenty of writers leave the alpha byte of 32bit files at zero, treat those as opaque.
+    if bit_depth == 32 && pixels.chunks_exact(4).all(|pixel| pixel[3] == 0) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+    }
+
+    Ok(Image::new(width as u32, rows as u32, pixels))
+}
+
+///Single channel bit mask. A channel without a mask decodes as 255.
+#[derive(Clone, Copy)]
+pub(crate) struct ColorMask {
+    mask: u32,
+    shift: u32,
+    max: u32,
+}
+
+impl ColorMask {
+    pub(crate) fn new(mask: u32) -> Self {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        Self {
+            mask,
+            shift,
+            max: mask >> shift,
+        }
+    }
+
+    pub(crate) fn extract(&self, value: u32) -> u8 {
+        if self.mask == 0 {
+            return 255;
+        }
+        (((value & self.mask) >> self.shift) as u64 * 255 / self.max as u64) as u8
+    }
+}
+
+///Expand RLE4 or RLE8 data to palette indices, rows in file order. Pixels that aren't written are
+///None.
+fn decode_rle(
+    data: &[u8],
+    width: usize,
+    rows: usize,
+    rle4: bool,
+) -> Result<Vec<Option<u8>>, TextureError> {
+    let mut indices = vec![None; width * rows];
+    let mut position = 0;
+    let mut x = 0;
+    let mut y = 0;
+
+    let mut set = |x: usize, y: usize, index: u8| {
+        if x < width && y < rows {
+            indices[y * width + x] = Some(index);
+        }
+    };
+    let nibble = |byte: u8, i: usize| {
+        if i.is_multiple_of(2) {
+            byte >> 4
+        } else {
+            byte & 0x0f
+        }
+    };
+
+    //Files that end without an end of bitmap marker are accepted.
+    while position + 2 <= data.len() {
+        let count = data[position] as usize;
+        let value = data[position + 1];
+        position += 2;
+
+        if count > 0 {
+            for i in 0..count {
+                set(x, y, if rle4 { nibble(value, i) } else { value });
+                x += 1;
+            }
+            continue;
+        }
+
+        match value {
+            0 => {
+                x = 0;
+                y += 1;
+            }
+            1 => break,
+            2 => {
+                let Some(delta) = data.get(position..position + 2) else {
+                    return Err(TextureError::Malformed("Malformed RLE data"));
+                };
+                x += delta[0] as usize;
+                y += delta[1] as usize;
+                position += 2;
+            }
+            count => {
+                let count = count as usize;
+                let size = if rle4 { count.div_ceil(2) } else { count };
+                let Some(run) = data.get(position..position + size) else {
+                    return Err(TextureError::Malformed("Malformed RLE data"));
+                };
+                for i in 0..count {
+                    set(x, y, if rle4 { nibble(run[i / 2], i) } else { run[i] });
+                    x += 1;
+                }
+                //Absolute runs are padded to a 16bit boundary.
+                position += size + size % 2;
+            }
+        }
+    }
+
+    Ok(indices)
+}
+
+fn read_u16(file: &[u8], offset: usize) -> Result<u16, TextureError> {
+    match file.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(TextureError::UnexpectedEof),
+    }
+}
+
+fn read_u32(file: &[u8], offset: usize) -> Result<u32, TextureError> {
+    match file.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes(bytes.try_into().unwrap())),
+        None => Err(TextureError::UnexpectedEof),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///BITMAPINFOHEADER file with the given palette and pixel data.
+    fn bmp(
+        width: i32,
+        height: i32,
+        bit_depth: u16,
+        compression: u32,
+        palette: &[[u8; 4]],
+        data: &[u8],
+    ) -> Vec<u8> {
+        let data_offset = BMP_FILE_HEADER_SIZE + BMP_INFO_HEADER as usize + palette.len() * 4;
+        let mut file = b"BM".to_vec();
+        file.extend_from_slice(&((data_offset + data.len()) as u32).to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        file.extend_from_slice(&BMP_INFO_HEADER.to_le_bytes());
+        file.extend_from_slice(&width.to_le_bytes());
+        file.extend_from_slice(&height.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&bit_depth.to_le_bytes());
+        file.extend_from_slice(&compression.to_le_bytes());
+        file.extend_from_slice(&[0; 12]);
+        file.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        for entry in palette {
+            file.extend_from_slice(entry);
+        }
+        file.extend_from_slice(data);
+        file
+    }
+
+    #[test]
+    fn decodes_bottom_up_24bit_rows() {
+        //Two rows of two BGR pixels, each padded to four bytes.
+        let data = [0, 0, 255, 0, 255, 0, 0, 0, 255, 0, 0, 255, 255, 255, 0, 0];
+        let image = decode_bmp(&bmp(2, 2, 24, BI_RGB, &[], &data)).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 2));
+        #[rustfmt::skip]
+        assert_eq!(image.pixels, [
+            0, 0, 255, 255, 255, 255, 255, 255,
+            255, 0, 0, 255, 0, 255, 0, 255,
+        ]);
+    }
+
+    #[test]
+    fn decodes_top_down_palette_and_zero_alpha() {
+        let palette = [[0, 0, 0, 0], [255, 0, 0, 0]];
+        let image = decode_bmp(&bmp(3, -1, 1, BI_RGB, &palette, &[0b0100_0000, 0, 0, 0])).unwrap();
+        assert_eq!(image.pixels, [0, 0, 0, 255, 0, 0, 255, 255, 0, 0, 0, 255]);
+
+        //32bit files with every alpha at zero are opaque.
+        let image = decode_bmp(&bmp(1, 1, 32, BI_RGB, &[], &[1, 2, 3, 0])).unwrap();
+        assert_eq!(image.pixels, [3, 2, 1, 255]);
+    }
+
+    #[test]
+    fn decodes_rle8_runs_absolute_and_delta() {
+        let palette = [[0, 0, 0, 0], [255, 0, 0, 0], [0, 255, 0, 0]];
+        #[rustfmt::skip]
+        let data = [
+            2, 1, //Run of two blue pixels.
+            0, 2, 0, 1, //Delta one row up.
+            0, 3, 2, 1, 2, 0, //Absolute run of three, padded to 16 bits.
+            0, 1, //End of bitmap.
+        ];
+        let image = decode_bmp(&bmp(4, 2, 8, BI_RLE8, &palette, &data)).unwrap();
+
+        #[rustfmt::skip]
+        assert_eq!(image.pixels, [
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 0, 255, 0, 0, 255, 255,
+            0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_hostile_files() {
+        let file = bmp(2, 2, 24, BI_RGB, &[], &[0; 16]);
+        for length in [0, 1, 2, 10, 20, 40, 60] {
+            assert!(decode_bmp(&file[..length]).is_err(), "length {length}");
+        }
+        assert_eq!(
+            decode_bmp(&bmp(2, 2, 24, BI_RGB, &[], &[0; 12])).err(),
+            Some(TextureError::UnexpectedEof)
+        );
+        assert!(matches!(
+            decode_bmp(&bmp(1 << 20, 1 << 20, 24, BI_RGB, &[], &[])),
+            Err(TextureError::InvalidDimensions { .. })
+        ));
+
+        //Large images with almost no data fail before allocating their pixels.
+        let palette = [[0; 4]; 2];
+        assert_eq!(
+            decode_bmp(&bmp(16384, 16384, 24, BI_RGB, &[], &[0; 64])).err(),
+            Some(TextureError::UnexpectedEof)
+        );
+        assert_eq!(
+            decode_bmp(&bmp(16384, 16384, 8, BI_RLE8, &palette, &[0, 1])).err(),
+            Some(TextureError::UnexpectedEof)
+        );
+        assert_eq!(
+            decode_bmp(&bmp(4, 2, 8, BI_RLE8, &palette, &[0, 3, 1])).err(),
+            Some(TextureError::Malformed("Malformed RLE data"))
+        );
+    }
+}
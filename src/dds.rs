@@ -0,0 +1,443 @@
+use crate::bmp::ColorMask;
+use crate::image::{Image, ImageFormat};
+use crate::texture::TextureError;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const DDS_HEADER_SIZE: usize = 128;
+const DDS_DX10_HEADER_SIZE: usize = 20;
+
+//Sizes stored in the header itself, which excludes the magic.
+const DDS_HEADER_STRUCT_SIZE: u32 = 124;
+const DDS_PIXEL_FORMAT_SIZE: u32 = 32;
+
+const DDS_DXT1: u32 = 0x31545844;
+const DDS_DXT2: u32 = 0x32545844;
+const DDS_DXT3: u32 = 0x33545844;
+const DDS_DXT4: u32 = 0x34545844;
+const DDS_DXT5: u32 = 0x35545844;
+const DDS_ATI1: u32 = 0x31495441;
+const DDS_ATI2: u32 = 0x32495441;
+const DDS_BC4U: u32 = 0x55344342;
+const DDS_BC4S: u32 = 0x53344342;
+const DDS_BC5U: u32 = 0x55354342;
+const DDS_BC5S: u32 = 0x53354342;
+const DDS_DX10: u32 = 0x30315844;
+
+const DDSD_DEPTH: u32 = 0x800000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xfc00;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+const D3D10_RESOURCE_DIMENSION_TEXTURE1D: u32 = 2;
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+///Largest width * height accepted, guards against absurd allocations from corrupt headers.
+const DDS_MAX_PIXELS: u64 = 1 << 28;
+
+///How the pixel data of a DDS file is stored.
+enum DdsFormat {
+    ///Blocks that are kept as they are.
+    Compressed(ImageFormat),
+    ///Uncompressed pixels of 8 to 32 bits with a bit mask per RGBA channel, converted to RGBA8.
+    Masked { bit_count: u32, masks: [u32; 4] },
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Decoding
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Read a DDS file with a legacy or DX10 header. BC1 to BC7 blocks are kept compressed,
+///uncompressed RGB, RGBA, luminance and alpha formats are converted to RGBA8. Every mip level and
+///every layer of texture arrays and cube maps is kept, volume textures aren't supported.
+pub fn decode_dds(file: &[u8]) -> Result<Image, TextureError> {
+    if file.len() < DDS_MAGIC.len() || &file[0..4] != DDS_MAGIC {
+        return Err(TextureError::InvalidSignature);
+    }
+
+    if file.len() < DDS_HEADER_SIZE {
+        return Err(TextureError::UnexpectedEof);
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap());
+
+    if read_u32(4) != DDS_HEADER_STRUCT_SIZE || read_u32(76) != DDS_PIXEL_FORMAT_SIZE {
+        return Err(TextureError::Malformed("Invalid DDS header size"));
+    }
+
+    let flags = read_u32(8);
+    let height = read_u32(12);
+    let width = read_u32(16);
+    let depth = read_u32(24);
+    let mip_levels = read_u32(28).max(1);
+    let pixel_flags = read_u32(80);
+    let four_cc = read_u32(84);
+    let caps2 = read_u32(112);
+
+    let mut data_offset = DDS_HEADER_SIZE;
+    let mut layers = 1;
+    let mut cube_map = false;
+
+    let format = if pixel_flags & DDPF_FOURCC != 0 && four_cc == DDS_DX10 {
+        let Some(header) = file.get(DDS_HEADER_SIZE..DDS_HEADER_SIZE + DDS_DX10_HEADER_SIZE) else {
+            return Err(TextureError::UnexpectedEof);
+        };
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        data_offset += DDS_DX10_HEADER_SIZE;
+
+        let dimension = read_u32(4);
+        if dimension != D3D10_RESOURCE_DIMENSION_TEXTURE1D
+            && dimension != D3D10_RESOURCE_DIMENSION_TEXTURE2D
+        {
+            return Err(TextureError::Unsupported(format!(
+                "Unsupported DDS resource dimension {dimension}"
+            )));
+        }
+
+        layers = read_u32(12);
+        if layers == 0 {
+            return Err(TextureError::Malformed("DDS array size is zero"));
+        }
+        if read_u32(8) & D3D10_RESOURCE_MISC_TEXTURECUBE != 0 {
+            cube_map = true;
+            layers = layers.saturating_mul(6);
+        }
+
+        dxgi_format(read_u32(0))?
+    } else {
+        if caps2 & DDSCAPS2_VOLUME != 0 || (flags & DDSD_DEPTH != 0 && depth > 1) {
+            return Err(TextureError::Unsupported(String::from(
+                "DDS volume textures aren't supported",
+            )));
+        }
+
+        if caps2 & DDSCAPS2_CUBEMAP != 0 {
+            if caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+                return Err(TextureError::Unsupported(String::from(
+                    "DDS cube maps without all six faces aren't supported",
+                )));
+            }
+            cube_map = true;
+            layers = 6;
+        }
+
+        legacy_format(pixel_flags, four_cc, &file[88..108])?
+    };
+
+    if width == 0 || height == 0 || width as u64 * height as u64 > DDS_MAX_PIXELS {
+        return Err(TextureError::InvalidDimensions {
+            width: width as i64,
+            height: height as i64,
+        });
+    }
+
+    //Every level halves the size down to 1x1, a chain can't be longer than that.
+    if mip_levels > 32 - width.max(height).leading_zeros() {
+        return Err(TextureError::Malformed("Too many DDS mip levels"));
+    }
+
+    let level_size = |level: u32| {
+        let width = (width >> level).max(1) as usize;
+        let height = (height >> level).max(1) as usize;
+        match &format {
+            DdsFormat::Compressed(format) => format.level_size(width as u32, height as u32),
+            //Rows of uncompressed formats are packed without padding.
+            DdsFormat::Masked { bit_count, .. } => {
+                (width * *bit_count as usize).div_ceil(8) * height
+            }
+        }
+    };
+
+    let layer_size: usize = (0..mip_levels).map(level_size).sum();
+    let Some(data) = layer_size
+        .checked_mul(layers as usize)
+        .and_then(|size| file.get(data_offset..data_offset.checked_add(size)?))
+    else {
+        return Err(TextureError::UnexpectedEof);
+    };
+
+    let mut image = match format {
+        DdsFormat::Compressed(format) => {
+            Image::with_format(width, height, format, mip_levels, layers, data.to_vec())
+        }
+        DdsFormat::Masked { bit_count, masks } => {
+            let bytes_per_pixel = bit_count as usize / 8;
+            let masks = masks.map(ColorMask::new);
+
+            //Every pixel is its own little endian value, levels are already tightly packed.
+            let mut pixels = Vec::with_capacity(data.len() / bytes_per_pixel * 4);
+            for pixel in data.chunks_exact(bytes_per_pixel) {
+                let mut value = [0; 4];
+                value[..bytes_per_pixel].copy_from_slice(pixel);
+                let value = u32::from_le_bytes(value);
+                pixels.extend_from_slice(&masks.map(|mask| mask.extract(value)));
+            }
+
+            Image::with_format(
+                width,
+                height,
+                ImageFormat::Rgba8,
+                mip_levels,
+                layers,
+                pixels,
+            )
+        }
+    };
+    image.cube_map = cube_map;
+
+    Ok(image)
+}
+
+///Format of a file without a DX10 header. pixel_format holds the bit count and the RGBA masks.
+fn legacy_format(
+    pixel_flags: u32,
+    four_cc: u32,
+    pixel_format: &[u8],
+) -> Result<DdsFormat, TextureError> {
+    let read_u32 =
+        |offset: usize| u32::from_le_bytes(pixel_format[offset..offset + 4].try_into().unwrap());
+
+    if pixel_flags & DDPF_FOURCC != 0 {
+        //DXT2 and DXT4 store premultiplied alpha but are otherwise the same as DXT3 and DXT5.
+        let format = match four_cc {
+            DDS_DXT1 => ImageFormat::Bc1,
+            DDS_DXT2 | DDS_DXT3 => ImageFormat::Bc2,
+            DDS_DXT4 | DDS_DXT5 => ImageFormat::Bc3,
+            DDS_ATI1 | DDS_BC4U => ImageFormat::Bc4,
+            DDS_BC4S => ImageFormat::Bc4Signed,
+            DDS_ATI2 | DDS_BC5U => ImageFormat::Bc5,
+            DDS_BC5S => ImageFormat::Bc5Signed,
+            _ => {
+                let name = String::from_utf8_lossy(&four_cc.to_le_bytes()).into_owned();
+                return Err(TextureError::Unsupported(format!(
+                    "Unsupported DDS format '{name}'"
+                )));
+            }
+        };
+        return Ok(DdsFormat::Compressed(format));
+    }
+
+    let bit_count = read_u32(0);
+    let [red, green, blue, alpha] = [4, 8, 12, 16].map(read_u32);
+    let alpha = if pixel_flags & (DDPF_ALPHAPIXELS | DDPF_ALPHA) != 0 {
+        alpha
+    } else {
+        0
+    };
+
+    //Channels without a mask decode as 255, so alpha only files decode as white.
+    let masks = if pixel_flags & DDPF_RGB != 0 {
+        [red, green, blue, alpha]
+    } else if pixel_flags & DDPF_LUMINANCE != 0 {
+        [red, red, red, alpha]
+    } else if pixel_flags & DDPF_ALPHA != 0 {
+        [0, 0, 0, alpha]
+    } else {
+        return Err(TextureError::Unsupported(String::from(
+            "Unsupported DDS pixel format",
+        )));
+    };
+
+    if !matches!(bit_count, 8 | 16 | 24 | 32) {
+        return Err(TextureError::Unsupported(format!(
+            "Unsupported DDS bit count {bit_count}"
+        )));
+    }
+
+    Ok(DdsFormat::Masked { bit_count, masks })
+}
+
+///Format of a DXGI_FORMAT value from a DX10 header. sRGB formats are read like their linear
+///counterparts since textures are sampled without conversion.
+fn dxgi_format(dxgi_format: u32) -> Result<DdsFormat, TextureError> {
+    let compressed = match dxgi_format {
+        70..=72 => ImageFormat::Bc1,
+        73..=75 => ImageFormat::Bc2,
+        76..=78 => ImageFormat::Bc3,
+        79 | 80 => ImageFormat::Bc4,
+        81 => ImageFormat::Bc4Signed,
+        82 | 83 => ImageFormat::Bc5,
+        84 => ImageFormat::Bc5Signed,
+        94 | 95 => ImageFormat::Bc6h,
+        96 => ImageFormat::Bc6hSigned,
+        97..=99 => ImageFormat::Bc7,
+        _ => {
+            let (bit_count, masks) = match dxgi_format {
+                //R10G10B10A2
+                23 | 24 => (32, [0x000003ff, 0x000ffc00, 0x3ff00000, 0xc0000000]),
+                //R8G8B8A8
+                27..=29 => (32, [0x000000ff, 0x0000ff00, 0x00ff0000, 0xff000000]),
+                //B5G6R5
+                85 => (16, [0xf800, 0x07e0, 0x001f, 0]),
+                //B5G5R5A1
+                86 => (16, [0x7c00, 0x03e0, 0x001f, 0x8000]),
+                //B8G8R8A8
+                87 | 90 | 91 => (32, [0x00ff0000, 0x0000ff00, 0x000000ff, 0xff000000]),
+                //B8G8R8X8
+                88 | 92 | 93 => (32, [0x00ff0000, 0x0000ff00, 0x000000ff, 0]),
+                //B4G4R4A4
+                115 => (16, [0x0f00, 0x00f0, 0x000f, 0xf000]),
+                _ => {
+                    return Err(TextureError::Unsupported(format!(
+                        "Unsupported DXGI format {dxgi_format}"
+                    )))
+                }
+            };
+            return Ok(DdsFormat::Masked { bit_count, masks });
+        }
+    };
+
+    Ok(DdsFormat::Compressed(compressed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dds(
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        pixel_format: [u32; 7],
+        caps2: u32,
+    ) -> Vec<u8> {
+        let mut file = vec![0; DDS_HEADER_SIZE];
+        let mut write = |offset: usize, value: u32| {
+            file[offset..offset + 4].copy_from_slice(&value.to_le_bytes())
+        };
+        write(4, DDS_HEADER_STRUCT_SIZE);
+        write(12, height);
+        write(16, width);
+        write(28, mip_levels);
+        write(76, DDS_PIXEL_FORMAT_SIZE);
+        for (i, value) in pixel_format.into_iter().enumerate() {
+            write(80 + i * 4, value);
+        }
+        write(112, caps2);
+        file[0..4].copy_from_slice(DDS_MAGIC);
+        file
+    }
+
+    fn four_cc(four_cc: u32) -> [u32; 7] {
+        [DDPF_FOURCC, four_cc, 0, 0, 0, 0, 0]
+    }
+
+    fn dx10(file: &mut Vec<u8>, dxgi_format: u32, misc: u32, array_size: u32) {
+        for value in [
+            dxgi_format,
+            D3D10_RESOURCE_DIMENSION_TEXTURE2D,
+            misc,
+            array_size,
+            0,
+        ] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn keeps_blocks_and_mip_levels() {
+        let mut file = dds(8, 4, 3, four_cc(DDS_DXT1), 0);
+        let data: Vec<u8> = (0..8 * 4).map(|value| value as u8).collect();
+        file.extend_from_slice(&data);
+        let image = decode_dds(&file).unwrap();
+
+        assert_eq!(image.format, ImageFormat::Bc1);
+        assert_eq!((image.width, image.height, image.mip_levels), (8, 4, 3));
+        assert_eq!(image.pixels, data);
+        assert_eq!(image.mip_level(2), Some((2, 1, &data[24..32])));
+    }
+
+    #[test]
+    fn converts_masked_pixels() {
+        //16bit B5G6R5 with two mip levels.
+        let masks = [DDPF_RGB, 0, 16, 0xf800, 0x07e0, 0x001f, 0];
+        let mut file = dds(2, 1, 2, masks, 0);
+        file.extend_from_slice(&[0x00, 0xf8, 0xe0, 0x07, 0x1f, 0x00]);
+        let image = decode_dds(&file).unwrap();
+
+        assert_eq!(image.format, ImageFormat::Rgba8);
+        #[rustfmt::skip]
+        assert_eq!(image.pixels, [
+            255, 0, 0, 255, 0, 255, 0, 255,
+            0, 0, 255, 255,
+        ]);
+
+        //Luminance only files fill red, green and blue.
+        let mut file = dds(1, 1, 1, [DDPF_LUMINANCE, 0, 8, 0xff, 0, 0, 0], 0);
+        file.push(77);
+        assert_eq!(decode_dds(&file).unwrap().pixels, [77, 77, 77, 255]);
+    }
+
+    #[test]
+    fn reads_arrays_and_cube_maps() {
+        let mut file = dds(4, 4, 1, four_cc(DDS_DX10), 0);
+        dx10(&mut file, 98, 0, 2);
+        file.extend_from_slice(&[0x40; 32]);
+        let image = decode_dds(&file).unwrap();
+        assert_eq!(
+            (image.format, image.layers, image.cube_map),
+            (ImageFormat::Bc7, 2, false)
+        );
+        assert!(image.subresource(1, 0).is_some());
+
+        let mut file = dds(
+            4,
+            4,
+            1,
+            four_cc(DDS_DXT5),
+            DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALL_FACES,
+        );
+        file.extend_from_slice(&[0; 16 * 6]);
+        let image = decode_dds(&file).unwrap();
+        assert_eq!(
+            (image.format, image.layers, image.cube_map),
+            (ImageFormat::Bc3, 6, true)
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_and_hostile_files() {
+        let mut file = dds(4, 4, 1, four_cc(DDS_DXT1), 0);
+        file.extend_from_slice(&[0; 8]);
+        for length in 0..file.len() {
+            assert!(decode_dds(&file[..length]).is_err(), "length {length}");
+        }
+
+        assert_eq!(
+            decode_dds(&dds(4, 4, 4, four_cc(DDS_DXT1), 0)).err(),
+            Some(TextureError::Malformed("Too many DDS mip levels"))
+        );
+        assert!(matches!(
+            decode_dds(&dds(1 << 16, 1 << 16, 1, four_cc(DDS_DXT1), 0)),
+            Err(TextureError::InvalidDimensions { .. })
+        ));
+        assert!(matches!(
+            decode_dds(&dds(4, 4, 1, four_cc(DDS_DXT1), DDSCAPS2_VOLUME)),
+            Err(TextureError::Unsupported(_))
+        ));
+        assert!(matches!(
+            decode_dds(&dds(4, 4, 1, four_cc(0x20202020), 0)),
+            Err(TextureError::Unsupported(_))
+        ));
+
+        let mut file = dds(4, 4, 1, four_cc(DDS_DX10), 0);
+        dx10(&mut file, 71, 0, 0);
+        assert_eq!(
+            decode_dds(&file).err(),
+            Some(TextureError::Malformed("DDS array size is zero"))
+        );
+
+        //A huge array with no data fails on the size check instead of allocating.
+        let mut file = dds(4096, 4096, 1, four_cc(DDS_DX10), 0);
+        dx10(&mut file, 71, D3D10_RESOURCE_MISC_TEXTURECUBE, u32::MAX);
+        assert_eq!(decode_dds(&file).err(), Some(TextureError::UnexpectedEof));
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt::Display;
+use std::time::SystemTime;
+
+use crate::math::Vector;
+use crate::particle::{ColorGradient, Curve, ParticleProperties, SpawnShape};
+use crate::system::gl32::GLuint;
+
+///Reason a particle effect file couldn't be parsed. line starts at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for EffectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}: {}!", self.line, self.message)
+    }
+}
+
+impl std::error::Error for EffectError {}
+
+impl ParticleProperties {
+    ///Parse a particle effect. Every line is a `key = value` pair named after a field of
+    ///ParticleProperties, fields that aren't listed keep their default value and `#` starts a
+    ///comment. Values are written as:
+    ///
+    ///* Numbers: `lifetime = 1.5`
+    ///* Vectors: `velocity = 0 120`, missing z and w are 0
+    ///* Shapes: `shape = point`, `circle 10`, `box 40 20` or `cone 30`
+    ///* Gradients: `color = 0: 1 1 0.5 1, 0.5: 1 0.4 0 1, 1: 0.3 0 0 0`, or a single color. Alpha
+    ///  is 1 when left out.
+    ///* Curves: `size = 0: 2, 0.3: 6, 1: 0`, or a single number
+    ///* Textures: `texture = spark`, looked up by name with texture()
+    pub fn from_text(text: &str, texture: impl Fn(&str) -> GLuint) -> Result<Self, EffectError> {
+        let mut properties = ParticleProperties::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| EffectError {
+                line: index + 1,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("Expected 'key = value', found '{line}'")));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = || error(format!("Invalid value for '{key}': '{value}'"));
+
+            match key {
+                "pos" => properties.pos = vector(value).ok_or_else(invalid)?,
+                "shape" => properties.shape = shape(value).ok_or_else(invalid)?,
+                "velocity" => properties.velocity = vector(value).ok_or_else(invalid)?,
+                "velocity_var" => properties.velocity_var = vector(value).ok_or_else(invalid)?,
+                "acceleration" => properties.acceleration = vector(value).ok_or_else(invalid)?,
+                "drag" => properties.drag = number(value).ok_or_else(invalid)?,
+                "rotation" => properties.rotation = number(value).ok_or_else(invalid)?,
+                "rotation_var" => properties.rotation_var = number(value).ok_or_else(invalid)?,
+                "angular_velocity" => {
+                    properties.angular_velocity = number(value).ok_or_else(invalid)?
+                }
+                "angular_velocity_var" => {
+                    properties.angular_velocity_var = number(value).ok_or_else(invalid)?
+                }
+                "color" => properties.color = gradient(value).ok_or_else(invalid)?,
+                "size" => properties.size = curve(value).ok_or_else(invalid)?,
+                "size_var" => properties.size_var = number(value).ok_or_else(invalid)?,
+                "lifetime" => properties.lifetime = number(value).ok_or_else(invalid)?,
+                "lifetime_var" => properties.lifetime_var = number(value).ok_or_else(invalid)?,
+                "rate" => properties.rate = number(value).ok_or_else(invalid)?,
+                "burst" => properties.burst = value.parse().map_err(|_| invalid())?,
+                "max_particles" => {
+                    properties.max_particles = value.parse().map_err(|_| invalid())?
+                }
+                "texture" => properties.tex_id = Some(texture(value)),
+                _ => return Err(error(format!("Unknown key '{key}'"))),
+            }
+        }
+
+        Ok(properties)
+    }
+}
+
+fn number(value: &str) -> Option<f32> {
+    value.parse().ok().filter(|number: &f32| number.is_finite())
+}
+
+fn numbers(value: &str) -> Option<Vec<f32>> {
+    value.split_whitespace().map(number).collect()
+}
+
+fn vector(value: &str) -> Option<Vector> {
+    let numbers = numbers(value)?;
+    if numbers.is_empty() || numbers.len() > 4 {
+        return None;
+    }
+
+    let get = |i: usize| numbers.get(i).copied().unwrap_or(0.0);
+    Some(Vector::new(get(0), get(1), get(2), get(3)))
+}
+
+fn color(value: &str) -> Option<Vector> {
+    match numbers(value)?[..] {
+        [r, g, b] => Some(Vector::new(r, g, b, 1.0)),
+        [r, g, b, a] => Some(Vector::new(r, g, b, a)),
+        _ => None,
+    }
+}
+
+fn shape(value: &str) -> Option<SpawnShape> {
+    let (name, arguments) = value.split_once(' ').unwrap_or((value, ""));
+    match (name, &numbers(arguments)?[..]) {
+        ("point", []) => Some(SpawnShape::Point),
+        ("circle", &[radius]) => Some(SpawnShape::Circle { radius }),
+        ("box", &[width, height]) => Some(SpawnShape::Box { width, height }),
+        ("cone", &[spread]) => Some(SpawnShape::Cone { spread }),
+        _ => None,
+    }
+}
+
+///Comma separated `t: value` stops, or a single value without t.
+fn stops<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Vec<(f32, T)>> {
+    if !value.contains(':') {
+        return Some(vec![(0.0, parse(value)?)]);
+    }
+
+    value
+        .split(',')
+        .map(|stop| {
+            let (t, value) = stop.split_once(':')?;
+            Some((number(t.trim())?, parse(value.trim())?))
+        })
+        .collect()
+}
+
+fn gradient(value: &str) -> Option<ColorGradient> {
+    stops(value, color).map(ColorGradient::new)
+}
+
+fn curve(value: &str) -> Option<Curve> {
+    stops(value, number).map(Curve::new)
+}
+
+///Particle effect loaded from a file, reloaded by System when the file changes.
+pub struct ParticleEffect {
+    pub path: String,
+    pub properties: ParticleProperties,
+    modified: Option<SystemTime>,
+}
+
+impl ParticleEffect {
+    ///Read and parse the effect at path. Errors are reported and None is returned.
+    pub fn load(path: &str, texture: impl Fn(&str) -> GLuint) -> Option<Self> {
+        let modified = modified(path);
+        Some(Self {
+            path: String::from(path),
+            properties: read_effect(path, texture)?,
+            modified,
+        })
+    }
+
+    ///Parse the file again if it was modified since it was last read. Returns true if the
+    ///properties changed, a file that fails to parse is reported and the old properties are kept.
+    pub fn reload(&mut self, texture: impl Fn(&str) -> GLuint) -> bool {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+
+        match read_effect(&self.path, texture) {
+            Some(properties) => {
+                self.properties = properties;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn read_effect(path: &str, texture: impl Fn(&str) -> GLuint) -> Option<ParticleProperties> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => {
+            eprintln!("Loading particle effect '{path}' failed: {error}");
+            return None;
+        }
+    };
+
+    match ParticleProperties::from_text(&text, texture) {
+        Ok(properties) => Some(properties),
+        Err(error) => {
+            eprintln!("Loading particle effect '{path}' failed: {error}");
+            None
+        }
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|file| file.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<ParticleProperties, EffectError> {
+        ParticleProperties::from_text(text, |name| if name == "spark" { 7 } else { 0 })
+    }
+
+    #[test]
+    fn parses_every_kind_of_value() {
+        let properties = parse(
+            "# Sparks from a grinder\n\
+             \n\
+             pos = 10 20\n\
+             shape = cone 30   # degrees\n\
+             velocity = 0 120 0 0\n\
+             drag = 0.5\n\
+             color = 1: 0.3 0 0 0, 0: 1 1 0.5\n\
+             size = 0: 2, 0.5: 6, 1: 0\n\
+             size_var = 1\n\
+             lifetime = 1.5\n\
+             rate = 40\n\
+             burst = 12\n\
+             max_particles = 250\n\
+             texture = spark\n",
+        )
+        .unwrap();
+
+        assert_eq!(properties.pos, Vector::new(10.0, 20.0, 0.0, 0.0));
+        assert_eq!(properties.shape, SpawnShape::Cone { spread: 30.0 });
+        assert_eq!(properties.velocity, Vector::new(0.0, 120.0, 0.0, 0.0));
+        assert_eq!(properties.drag, 0.5);
+        assert_eq!(
+            properties.color,
+            ColorGradient::between(
+                Vector::new(1.0, 1.0, 0.5, 1.0),
+                Vector::new(0.3, 0.0, 0.0, 0.0)
+            )
+        );
+        assert_eq!(properties.size.sample(0.25), 4.0);
+        assert_eq!(properties.size_var, 1.0);
+        assert_eq!(properties.lifetime, 1.5);
+        assert_eq!(properties.rate, 40.0);
+        assert_eq!(properties.burst, 12);
+        assert_eq!(properties.max_particles, 250);
+        assert_eq!(properties.tex_id, Some(7));
+    }
+
+    #[test]
+    fn keeps_defaults_and_accepts_single_values() {
+        let properties = parse("color = 0 0 1\nsize = 3\nshape = box 40 20").unwrap();
+        let defaults = ParticleProperties::default();
+
+        assert_eq!(
+            properties.color,
+            ColorGradient::constant(Vector::new(0.0, 0.0, 1.0, 1.0))
+        );
+        assert_eq!(properties.size, Curve::constant(3.0));
+        assert_eq!(
+            properties.shape,
+            SpawnShape::Box {
+                width: 40.0,
+                height: 20.0
+            }
+        );
+        assert_eq!(properties.lifetime, defaults.lifetime);
+        assert_eq!(properties.max_particles, defaults.max_particles);
+        assert_eq!(parse(""), Ok(defaults));
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        let error = |text: &str| parse(text).unwrap_err();
+
+        assert_eq!(
+            error("rate = 4\nwobble = 1"),
+            EffectError {
+                line: 2,
+                message: String::from("Unknown key 'wobble'"),
+            }
+        );
+        assert_eq!(error("\n\nlifetime 2").line, 3);
+        assert_eq!(
+            error("lifetime = inf").message,
+            "Invalid value for 'lifetime': 'inf'"
+        );
+        for text in [
+            "velocity = 1 2 3 4 5",
+            "velocity =",
+            "shape = circle",
+            "shape = star 5",
+            "color = 1 1",
+            "size = 0: 1, 1",
+            "burst = -1",
+            "max_particles = 1e9",
+        ] {
+            assert_eq!(error(text).line, 1, "{text}");
+        }
+        assert_eq!(
+            error("drag = x").to_string(),
+            "Line 1: Invalid value for 'drag': 'x'!"
+        );
+    }
+}
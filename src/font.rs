@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+
+use crate::system::gl32::GLuint;
+use crate::texture::TextureError;
+
+///Glyph of a BitmapFont. x, y, width and height locate it on its page in pixels from the top left
+///corner of the page. The offsets place its top left corner relative to the pen, which sits at the
+///top of the line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+    ///How far the pen moves right after the glyph.
+    pub x_advance: f32,
+}
+
+///Glyphs packed into page images, as described by an AngelCode BMFont .fnt file. Sizes are in
+///pixels of the pages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitmapFont {
+    pub line_height: f32,
+    ///Distance from the top of a line to the baseline.
+    pub base: f32,
+    pub page_width: u32,
+    pub page_height: u32,
+    ///File names of the page images, relative to the font file.
+    pub pages: Vec<String>,
+    pub glyphs: HashMap<char, Glyph>,
+    ///Extra advance between a pair of characters.
+    pub kerning: HashMap<(char, char), f32>,
+    ///Placeholder glyph drawn for characters the font has no glyph for.
+    pub fallback: Option<Glyph>,
+}
+
+///Horizontal alignment of the lines of a block of text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+///How BitmapFont::layout_text() places a block of text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub scale: f32,
+    ///Lines wider than max_width are wrapped at spaces, words that don't fit on a line of their
+    ///own are broken between characters. None only breaks lines at '\n'.
+    pub max_width: Option<f32>,
+    ///Lines are aligned within max_width, or within the widest line without a max_width.
+    pub align: TextAlign,
+    ///Distance between lines as a multiple of the line height of the font.
+    pub line_spacing: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            max_width: None,
+            align: TextAlign::Left,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+///Glyph placed by BitmapFont::layout(). x and y are the center of the glyph quad relative to the
+///top left corner of the text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedGlyph {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub page: usize,
+    pub uv_rect: (f32, f32, f32, f32),
+}
+
+impl BitmapFont {
+    ///Read a .fnt file in the text or binary format.
+    pub fn load(path: &str) -> Result<Self, TextureError> {
+        let bytes = std::fs::read(path).map_err(|error| TextureError::Io(error.kind()))?;
+        Self::parse(&bytes)
+    }
+
+    ///Parse a .fnt file in the text or binary format.
+    pub fn parse(bytes: &[u8]) -> Result<Self, TextureError> {
+        if bytes.starts_with(b"BMF") {
+            return parse_binary(bytes);
+        }
+
+        match std::str::from_utf8(bytes) {
+            Ok(text) if text.trim_start().starts_with("info") => parse_text(text),
+            _ => Err(TextureError::UnrecognizedFormat),
+        }
+    }
+
+    ///UV rectangle of glyph on its page. Pages are uploaded bottom row first like atlas pages,
+    ///v0 is the top of the glyph so it's upright when y grows downwards like with the default
+    ///camera.
+    pub fn uv_rect(&self, glyph: &Glyph) -> (f32, f32, f32, f32) {
+        let page_width = self.page_width as f32;
+        let page_height = self.page_height as f32;
+        (
+            glyph.x as f32 / page_width,
+            1.0 - glyph.y as f32 / page_height,
+            (glyph.x + glyph.width) as f32 / page_width,
+            1.0 - (glyph.y + glyph.height) as f32 / page_height,
+        )
+    }
+
+    ///Place the glyphs of text scaled by scale, starting at the top left corner with y growing
+    ///downwards. Lines are broken at '\n', characters without a glyph are drawn with a fallback
+    ///glyph.
+    pub fn layout(&self, text: &str, scale: f32) -> Vec<PlacedGlyph> {
+        self.layout_text(
+            text,
+            &TextStyle {
+                scale,
+                ..Default::default()
+            },
+        )
+    }
+
+    ///Place the glyphs of text, wrapped and aligned as described by style, starting at the top
+    ///left corner of the block with y growing downwards.
+    pub fn layout_text(&self, text: &str, style: &TextStyle) -> Vec<PlacedGlyph> {
+        let lines = self.wrap(text, style);
+        let widths: Vec<f32> = lines
+            .iter()
+            .map(|line| self.line_width(line.trim_end(), style.scale))
+            .collect();
+        let block_width = style
+            .max_width
+            .unwrap_or_else(|| widths.iter().copied().fold(0.0, f32::max));
+
+        let mut placed = Vec::with_capacity(text.len());
+        let mut pen_y = 0.0;
+        for (line, width) in lines.into_iter().zip(widths) {
+            let mut pen_x = match style.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => (block_width - width) / 2.0,
+                TextAlign::Right => block_width - width,
+            };
+            let mut previous = None;
+
+            for character in line.chars() {
+                pen_x += self.kerning(previous, character) * style.scale;
+                previous = Some(character);
+                let Some(glyph) = self.glyph(character) else {
+                    continue;
+                };
+
+                if glyph.width > 0 && glyph.height > 0 {
+                    let width = glyph.width as f32 * style.scale;
+                    let height = glyph.height as f32 * style.scale;
+                    placed.push(PlacedGlyph {
+                        x: pen_x + glyph.x_offset * style.scale + width / 2.0,
+                        y: pen_y + glyph.y_offset * style.scale + height / 2.0,
+                        width,
+                        height,
+                        page: glyph.page,
+                        uv_rect: self.uv_rect(glyph),
+                    });
+                }
+                pen_x += glyph.x_advance * style.scale;
+            }
+            pen_y += self.line_height * style.scale * style.line_spacing;
+        }
+
+        placed
+    }
+
+    ///Width and height of text laid out with style. The width is that of the widest line without
+    ///trailing spaces, the height reaches the bottom of the last line.
+    pub fn measure_text(&self, text: &str, style: &TextStyle) -> (f32, f32) {
+        let lines = self.wrap(text, style);
+        let width = lines
+            .iter()
+            .map(|line| self.line_width(line.trim_end(), style.scale))
+            .fold(0.0, f32::max);
+        let line_height = self.line_height * style.scale;
+        let height = line_height + (lines.len() - 1) as f32 * line_height * style.line_spacing;
+        (width, height)
+    }
+
+    ///Glyph drawn for character. Characters without a glyph use the placeholder glyph of the
+    ///font, or U+FFFD or '?' if it has none. Control characters aren't drawn.
+    pub fn glyph(&self, character: char) -> Option<&Glyph> {
+        if let Some(glyph) = self.glyphs.get(&character) {
+            return Some(glyph);
+        }
+        if character.is_control() {
+            return None;
+        }
+        self.fallback
+            .as_ref()
+            .or_else(|| self.glyphs.get(&char::REPLACEMENT_CHARACTER))
+            .or_else(|| self.glyphs.get(&'?'))
+    }
+
+    fn kerning(&self, previous: Option<char>, character: char) -> f32 {
+        match previous {
+            Some(previous) => *self.kerning.get(&(previous, character)).unwrap_or(&0.0),
+            None => 0.0,
+        }
+    }
+
+    ///How far the pen moves over line, scaled by scale.
+    fn line_width(&self, line: &str, scale: f32) -> f32 {
+        let mut width = 0.0;
+        let mut previous = None;
+        for character in line.chars() {
+            width += self.advance(previous, character) * scale;
+            previous = Some(character);
+        }
+        width
+    }
+
+    fn advance(&self, previous: Option<char>, character: char) -> f32 {
+        let advance = self.glyph(character).map_or(0.0, |glyph| glyph.x_advance);
+        self.kerning(previous, character) + advance
+    }
+
+    ///Split text into lines at '\n' and wherever a line grows wider than style.max_width.
+    fn wrap<'a>(&self, text: &'a str, style: &TextStyle) -> Vec<&'a str> {
+        let mut lines = Vec::new();
+
+        for paragraph in text.split('\n') {
+            let paragraph = paragraph.strip_suffix('\r').unwrap_or(paragraph);
+            let Some(max_width) = style.max_width else {
+                lines.push(paragraph);
+                continue;
+            };
+
+            let mut start = 0;
+            let mut width = 0.0;
+            let mut previous = None;
+            let mut space = None;
+
+            for (index, character) in paragraph.char_indices() {
+                loop {
+                    let advance = self.advance(previous, character) * style.scale;
+                    //Spaces may hang past the end of a line, they are trimmed when aligning.
+                    if character == ' ' || index == start || width + advance <= max_width {
+                        width += advance;
+                        previous = Some(character);
+                        break;
+                    }
+
+                    //Break after the last space, or before this character if the line is a
+                    //single word.
+                    let end = space.unwrap_or(index);
+                    lines.push(&paragraph[start..end]);
+                    start = if end < index { end + 1 } else { index };
+                    space = None;
+                    width = self.line_width(&paragraph[start..index], style.scale);
+                    previous = paragraph[start..index].chars().next_back();
+                }
+
+                if character == ' ' {
+                    space = Some(index);
+                }
+            }
+            lines.push(&paragraph[start..]);
+        }
+
+        lines
+    }
+}
+
+///A BitmapFont with its pages uploaded as textures.
+pub struct FontTextures {
+    pub font: BitmapFont,
+    pub pages: Vec<GLuint>,
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Text Format
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+fn parse_text(text: &str) -> Result<BitmapFont, TextureError> {
+    let malformed = TextureError::Malformed("Malformed BMFont file");
+    let mut font = BitmapFont {
+        line_height: 0.0,
+        base: 0.0,
+        page_width: 0,
+        page_height: 0,
+        pages: Vec::new(),
+        glyphs: HashMap::new(),
+        kerning: HashMap::new(),
+        fallback: None,
+    };
+    let mut common = false;
+
+    for line in text.lines() {
+        let mut tokens = tokens(line);
+        let Some(tag) = tokens.next() else {
+            continue;
+        };
+        let values: HashMap<&str, &str> =
+            tokens.filter_map(|token| token.split_once('=')).collect();
+        let number = |key: &str| -> Result<i64, TextureError> {
+            values
+                .get(key)
+                .and_then(|value| value.parse().ok())
+                .ok_or(malformed.clone())
+        };
+
+        match tag {
+            "common" => {
+                common = true;
+                font.line_height = number("lineHeight")? as f32;
+                font.base = number("base")? as f32;
+                font.page_width = number("scaleW")? as u32;
+                font.page_height = number("scaleH")? as u32;
+                if values.get("packed") == Some(&"1") {
+                    return Err(packed());
+                }
+            }
+            "page" => {
+                let id = number("id")? as usize;
+                //Binary files store the page of a glyph in a byte.
+                if id > u8::MAX as usize {
+                    return Err(malformed);
+                }
+                let Some(file) = values.get("file") else {
+                    return Err(malformed);
+                };
+                if font.pages.len() <= id {
+                    font.pages.resize(id + 1, String::new());
+                }
+                font.pages[id] = String::from(file.trim_matches('"'));
+            }
+            "char" => {
+                let glyph = Glyph {
+                    page: number("page")? as usize,
+                    x: number("x")? as u32,
+                    y: number("y")? as u32,
+                    width: number("width")? as u32,
+                    height: number("height")? as u32,
+                    x_offset: number("xoffset")? as f32,
+                    y_offset: number("yoffset")? as f32,
+                    x_advance: number("xadvance")? as f32,
+                };
+                match number("id")? {
+                    PLACEHOLDER_ID => font.fallback = Some(glyph),
+                    id => {
+                        if let Some(character) = character(id) {
+                            font.glyphs.insert(character, glyph);
+                        }
+                    }
+                }
+            }
+            "kerning" => {
+                let pair = (character(number("first")?), character(number("second")?));
+                if let (Some(first), Some(second)) = pair {
+                    font.kerning
+                        .insert((first, second), number("amount")? as f32);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !common {
+        return Err(malformed);
+    }
+    validate(font)
+}
+
+///Split a line into whitespace separated tokens, keeping quoted values together.
+fn tokens(line: &str) -> impl Iterator<Item = &str> {
+    let mut rest = line.trim();
+    std::iter::from_fn(move || {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+
+        let mut quoted = false;
+        let end = rest
+            .char_indices()
+            .find(|&(_, character)| {
+                if character == '"' {
+                    quoted = !quoted;
+                }
+                character.is_whitespace() && !quoted
+            })
+            .map_or(rest.len(), |(index, _)| index);
+
+        let (token, remaining) = rest.split_at(end);
+        rest = remaining;
+        Some(token)
+    })
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Binary Format
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+const BLOCK_COMMON: u8 = 2;
+const BLOCK_PAGES: u8 = 3;
+const BLOCK_CHARS: u8 = 4;
+const BLOCK_KERNING: u8 = 5;
+
+const CHAR_SIZE: usize = 20;
+const KERNING_SIZE: usize = 10;
+
+fn parse_binary(bytes: &[u8]) -> Result<BitmapFont, TextureError> {
+    if bytes.get(3) != Some(&3) {
+        return Err(TextureError::Unsupported(String::from(
+            "Only version 3 binary BMFont files are supported",
+        )));
+    }
+
+    let mut font = BitmapFont {
+        line_height: 0.0,
+        base: 0.0,
+        page_width: 0,
+        page_height: 0,
+        pages: Vec::new(),
+        glyphs: HashMap::new(),
+        kerning: HashMap::new(),
+        fallback: None,
+    };
+    let mut common = false;
+    let mut position = 4;
+
+    while position < bytes.len() {
+        let kind = bytes[position];
+        let size = read_u32(bytes, position + 1)? as usize;
+        let Some(block) = bytes.get(position + 5..position + 5 + size) else {
+            return Err(TextureError::UnexpectedEof);
+        };
+        position += 5 + size;
+
+        match kind {
+            BLOCK_COMMON => {
+                common = true;
+                font.line_height = read_u16(block, 0)? as f32;
+                font.base = read_u16(block, 2)? as f32;
+                font.page_width = read_u16(block, 4)? as u32;
+                font.page_height = read_u16(block, 6)? as u32;
+                //Bit 7 of the bit field marks channel packed fonts.
+                if block.get(10).is_some_and(|bits| bits & 0x80 != 0) {
+                    return Err(packed());
+                }
+            }
+            BLOCK_PAGES => {
+                for name in block
+                    .split(|&byte| byte == 0)
+                    .filter(|name| !name.is_empty())
+                {
+                    font.pages.push(String::from_utf8_lossy(name).into_owned());
+                }
+            }
+            BLOCK_CHARS => {
+                for entry in block.chunks_exact(CHAR_SIZE) {
+                    let glyph = Glyph {
+                        page: entry[18] as usize,
+                        x: read_u16(entry, 4)? as u32,
+                        y: read_u16(entry, 6)? as u32,
+                        width: read_u16(entry, 8)? as u32,
+                        height: read_u16(entry, 10)? as u32,
+                        x_offset: read_u16(entry, 12)? as i16 as f32,
+                        y_offset: read_u16(entry, 14)? as i16 as f32,
+                        x_advance: read_u16(entry, 16)? as i16 as f32,
+                    };
+                    match read_u32(entry, 0)? as i32 as i64 {
+                        PLACEHOLDER_ID => font.fallback = Some(glyph),
+                        id => {
+                            if let Some(character) = character(id) {
+                                font.glyphs.insert(character, glyph);
+                            }
+                        }
+                    }
+                }
+            }
+            BLOCK_KERNING => {
+                for pair in block.chunks_exact(KERNING_SIZE) {
+                    let first = character(read_u32(pair, 0)? as i64);
+                    let second = character(read_u32(pair, 4)? as i64);
+                    if let (Some(first), Some(second)) = (first, second) {
+                        font.kerning
+                            .insert((first, second), read_u16(pair, 8)? as i16 as f32);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !common {
+        return Err(TextureError::Malformed("BMFont file has no common block"));
+    }
+    validate(font)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, TextureError> {
+    match bytes.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(TextureError::UnexpectedEof),
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, TextureError> {
+    match bytes.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes(bytes.try_into().unwrap())),
+        None => Err(TextureError::UnexpectedEof),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Shared
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Id some tools write for the placeholder glyph drawn for missing characters.
+const PLACEHOLDER_ID: i64 = -1;
+
+///Glyph ids are Unicode code points.
+fn character(id: i64) -> Option<char> {
+    u32::try_from(id).ok().and_then(char::from_u32)
+}
+
+fn packed() -> TextureError {
+    TextureError::Unsupported(String::from(
+        "Channel packed BMFont files are not supported",
+    ))
+}
+
+///Make sure every glyph lies on a page.
+fn validate(font: BitmapFont) -> Result<BitmapFont, TextureError> {
+    if font.page_width == 0 || font.page_height == 0 {
+        return Err(TextureError::InvalidDimensions {
+            width: font.page_width as i64,
+            height: font.page_height as i64,
+        });
+    }
+
+    for glyph in font.glyphs.values().chain(&font.fallback) {
+        let right = glyph.x.checked_add(glyph.width);
+        let bottom = glyph.y.checked_add(glyph.height);
+        if glyph.page >= font.pages.len()
+            || right.is_none_or(|right| right > font.page_width)
+            || bottom.is_none_or(|bottom| bottom > font.page_height)
+        {
+            return Err(TextureError::Malformed("BMFont glyph out of range"));
+        }
+    }
+    Ok(font)
+}
@@ -0,0 +1,254 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::image::Image;
+use crate::{Event, EventFunctions, System, SystemConf};
+
+///Reason a frame didn't match its golden image. The rendered frame is always written next to the
+///golden image as name_actual.png so it can be inspected or promoted to the new golden image.
+#[derive(Debug)]
+pub enum GoldenError {
+    MissingGolden {
+        golden_path: String,
+        actual_path: String,
+    },
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+        actual_path: String,
+    },
+    PixelMismatch {
+        mismatched_pixels: usize,
+        max_difference: u8,
+        actual_path: String,
+        diff_path: String,
+    },
+}
+
+impl Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GoldenError::MissingGolden {
+                golden_path,
+                actual_path,
+            } => write!(
+                f,
+                "Golden image '{golden_path}' couldn't be loaded, frame written to '{actual_path}'"
+            ),
+            GoldenError::SizeMismatch {
+                expected,
+                actual,
+                actual_path,
+            } => write!(
+                f,
+                "Frame is {}x{} but golden image is {}x{}, frame written to '{actual_path}'",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            GoldenError::PixelMismatch {
+                mismatched_pixels,
+                max_difference,
+                actual_path,
+                diff_path,
+            } => write!(
+                f,
+                "{mismatched_pixels} pixels differ from golden image (max channel difference \
+                 {max_difference}), frame written to '{actual_path}', diff written to '{diff_path}'"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+///Run game on a software rendered System for the given number of frames and capture the last one.
+pub fn render_frames(
+    game: &mut impl EventFunctions,
+    config: SystemConf,
+    frames: usize,
+    fixed_dt: Duration,
+) -> Image {
+    let mut system = System::new_software(config);
+    Event::run_frames(game, &mut system, frames, fixed_dt);
+    system.capture_frame()
+}
+
+///Compare a frame to the PNG or BMP golden image at golden_path. Channels may differ by up to
+///tolerance. On mismatch a diff image is written next to the golden image as name_diff.png.
+pub fn compare_golden(frame: &Image, golden_path: &str, tolerance: u8) -> Result<(), GoldenError> {
+    let path = Path::new(golden_path);
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("golden");
+    let output_path = |suffix: &str| {
+        path.with_file_name(format!("{stem}_{suffix}.png"))
+            .to_string_lossy()
+            .into_owned()
+    };
+    let actual_path = output_path("actual");
+
+    let Ok(expected) = Image::load(golden_path) else {
+        write_image(frame, &actual_path);
+        return Err(GoldenError::MissingGolden {
+            golden_path: String::from(golden_path),
+            actual_path,
+        });
+    };
+
+    let Some(result) = frame.compare(&expected, tolerance) else {
+        write_image(frame, &actual_path);
+        return Err(GoldenError::SizeMismatch {
+            expected: (expected.width, expected.height),
+            actual: (frame.width, frame.height),
+            actual_path,
+        });
+    };
+
+    if result.mismatched_pixels > 0 {
+        let diff_path = output_path("diff");
+        write_image(frame, &actual_path);
+        write_image(&result.diff, &diff_path);
+        return Err(GoldenError::PixelMismatch {
+            mismatched_pixels: result.mismatched_pixels,
+            max_difference: result.max_difference,
+            actual_path,
+            diff_path,
+        });
+    }
+
+    Ok(())
+}
+
+///Render frames with render_frames() and panic if the last frame doesn't match the golden image.
+pub fn assert_golden(
+    game: &mut impl EventFunctions,
+    config: SystemConf,
+    frames: usize,
+    fixed_dt: Duration,
+    golden_path: &str,
+    tolerance: u8,
+) {
+    let frame = render_frames(game, config, frames, fixed_dt);
+
+    if let Err(error) = compare_golden(&frame, golden_path, tolerance) {
+        panic!("{error}");
+    }
+}
+
+fn write_image(image: &Image, path: &str) {
+    if let Err(error) = image.save(path) {
+        eprintln!("Couldn't write '{path}': {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vector;
+
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+    ///Draws a rectangle and a 2x2 checker texture as a sprite.
+    #[derive(Default)]
+    struct RectAndSprite {
+        texture: crate::GLuint,
+    }
+
+    impl EventFunctions for RectAndSprite {
+        fn init(&mut self, system: &mut System) {
+            #[rustfmt::skip]
+            let checker = Image::new(2, 2, vec![
+                255, 0, 0, 255, 0, 255, 0, 255,
+                0, 0, 255, 255, 255, 255, 255, 255,
+            ]);
+            self.texture = system.upload_texture("checker", &checker);
+        }
+
+        fn update(&mut self, _system: &mut System, _dt: Duration) {}
+
+        fn draw(&mut self, system: &mut System) {
+            system.display_clear(0.2, 0.2, 0.2, 1.0);
+            let yellow = Vector::new(1.0, 1.0, 0.0, 1.0);
+            let white = Vector::new(1.0, 1.0, 1.0, 1.0);
+            system.rect(9.0, 9.0, 0.0, 10.0, 10.0, 0.0, yellow);
+            system.sprite(22.0, 22.0, 0.0, 12.0, 12.0, 0.0, self.texture, white);
+        }
+    }
+
+    fn config() -> SystemConf {
+        SystemConf {
+            screen_width: 32,
+            screen_height: 32,
+            rng_seed: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rect_and_sprite_match_golden_image() {
+        assert_golden(
+            &mut RectAndSprite::default(),
+            config(),
+            1,
+            Duration::from_millis(16),
+            &format!("{GOLDEN_DIR}/rect_and_sprite.png"),
+            1,
+        );
+    }
+
+    #[test]
+    fn mismatches_write_actual_and_diff_images() {
+        let directory = std::env::temp_dir().join("roze_golden_mismatch");
+        std::fs::create_dir_all(&directory).unwrap();
+        let golden_path = directory.join("frame.png");
+        let actual_path = directory.join("frame_actual.png");
+        let diff_path = directory.join("frame_diff.png");
+        for path in [&golden_path, &actual_path, &diff_path] {
+            let _ = std::fs::remove_file(path);
+        }
+        let golden = golden_path.to_str().unwrap();
+
+        let frame = Image::new(2, 1, vec![10, 20, 30, 255, 40, 50, 60, 255]);
+        let Err(GoldenError::MissingGolden {
+            actual_path: written,
+            ..
+        }) = compare_golden(&frame, golden, 0)
+        else {
+            panic!("missing golden image wasn't reported");
+        };
+        assert_eq!(written, actual_path.to_str().unwrap());
+        assert_eq!(Image::load(&written), Ok(frame.clone()));
+
+        frame.save(golden).unwrap();
+        std::fs::remove_file(&actual_path).unwrap();
+        assert!(compare_golden(&frame, golden, 0).is_ok());
+        assert!(!actual_path.exists());
+
+        let mut changed = frame.clone();
+        changed.pixels[4] = 45;
+        assert!(compare_golden(&changed, golden, 5).is_ok());
+        match compare_golden(&changed, golden, 4) {
+            Err(GoldenError::PixelMismatch {
+                mismatched_pixels: 1,
+                max_difference: 5,
+                ..
+            }) => {}
+            result => panic!("expected a pixel mismatch, got {result:?}"),
+        }
+        assert_eq!(Image::load(actual_path.to_str().unwrap()), Ok(changed));
+        let diff = Image::load(diff_path.to_str().unwrap()).unwrap();
+        assert_eq!((diff.width, diff.height), (2, 1));
+
+        let small = Image::new(1, 1, vec![0; 4]);
+        assert!(matches!(
+            compare_golden(&small, golden, 0),
+            Err(GoldenError::SizeMismatch {
+                expected: (2, 1),
+                actual: (1, 1),
+                ..
+            })
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,580 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::Duration;
+
+use crate::image::Image;
+use crate::input::Input;
+use crate::math::{Matrix, Vector};
+use crate::mixer::{read_sound, Mixer, SoundHandle, MIX_CHANNELS, MIX_SAMPLE_RATE};
+use crate::particle::Particle;
+use crate::platform::{
+    AudioBackend, AudioCall, DrawCall, Platform, RenderBackend, WindowDimension,
+};
+use crate::sprite::{sort_sprites, sprite_batches};
+use crate::statistics::BatchStats;
+use crate::stream::read_stream;
+use crate::system::gl32::GLuint;
+use crate::texture::read_texture;
+
+pub struct HeadlessPlatform {
+    dimensions: WindowDimension,
+}
+
+impl Platform for HeadlessPlatform {
+    fn create(_window_title: &str, width: i32, height: i32) -> Self {
+        Self {
+            dimensions: WindowDimension { width, height },
+        }
+    }
+
+    fn pump_events(&mut self, _input: &mut Input) -> bool {
+        true
+    }
+
+    fn poll_input(&mut self, _input: &mut Input) {}
+
+    fn set_cursor_visible(&mut self, _visible: bool) {}
+
+    fn present(&mut self) {}
+
+    fn dimensions(&self) -> WindowDimension {
+        self.dimensions
+    }
+}
+
+pub struct HeadlessRenderer {
+    texture_ids: HashMap<String, GLuint>,
+    textures: Vec<Image>,
+    fonts: HashSet<String>,
+    sprites: Vec<DrawCall>,
+    draw_calls: Vec<DrawCall>,
+    batch_stats: BatchStats,
+}
+
+impl HeadlessRenderer {
+    pub fn new() -> Self {
+        Self {
+            texture_ids: HashMap::new(),
+            textures: Vec::new(),
+            fonts: HashSet::new(),
+            sprites: Vec::new(),
+            draw_calls: Vec::new(),
+            batch_stats: BatchStats::default(),
+        }
+    }
+}
+
+impl RenderBackend for HeadlessRenderer {
+    fn load_texture(&mut self, path: &str) -> GLuint {
+        let Some((name, image)) = read_texture(path) else {
+            return 0;
+        };
+
+        self.upload_texture(&name, &image)
+    }
+
+    fn load_texture_dir(&mut self, path: &str) {
+        let directory = std::path::Path::new(path);
+
+        if let Ok(entries) = fs::read_dir(directory) {
+            for entry in entries {
+                let entry = entry.unwrap();
+                self.load_texture(entry.path().to_str().unwrap());
+            }
+        } else {
+            eprintln!("Couldn't find texture directory: '{path}'");
+        }
+    }
+
+    fn upload_texture(&mut self, name: &str, image: &Image) -> GLuint {
+        self.textures.push(image.clone());
+        let tex_id = self.textures.len() as GLuint;
+
+        self.texture_ids.insert(String::from(name), tex_id);
+        tex_id
+    }
+
+    fn get_texture(&self, name: &str) -> GLuint {
+        if let Some(tex_id) = self.texture_ids.get(name) {
+            *tex_id
+        } else {
+            eprintln!("get_texture failed: '{name}' does not exist");
+            0
+        }
+    }
+
+    fn display_clear(&mut self, color: (f32, f32, f32, f32)) {
+        self.draw_calls.push(DrawCall::Clear { color });
+    }
+
+    fn draw_rect(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        color: Vector,
+        _proj: Matrix,
+        _view: Matrix,
+    ) {
+        self.batch_stats.draw_calls += 1;
+        self.draw_calls.push(DrawCall::Rect {
+            x,
+            y,
+            z,
+            w,
+            h,
+            rot,
+            color,
+        });
+    }
+
+    fn draw_line(
+        &mut self,
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        z: f32,
+        w: f32,
+        color: Vector,
+        _proj: Matrix,
+        _view: Matrix,
+    ) {
+        self.batch_stats.draw_calls += 1;
+        self.draw_calls.push(DrawCall::Line {
+            x1,
+            y1,
+            x2,
+            y2,
+            z,
+            w,
+            color,
+        });
+    }
+
+    fn add_sprite(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        texture_id: GLuint,
+        uv_rect: (f32, f32, f32, f32),
+        color: Vector,
+        _proj: Matrix,
+        _view: Matrix,
+    ) {
+        self.sprites.push(DrawCall::Sprite {
+            x,
+            y,
+            z,
+            w,
+            h,
+            rot,
+            texture_id,
+            uv_rect,
+            color,
+        });
+    }
+
+    fn draw_sprites(&mut self) {
+        let key = |call: &DrawCall| match call {
+            DrawCall::Sprite { z, texture_id, .. } => (*z, *texture_id),
+            _ => unreachable!(),
+        };
+        sort_sprites(&mut self.sprites, key);
+
+        let batches = sprite_batches(&self.sprites, |call| key(call).1).len();
+        self.batch_stats.sprites += self.sprites.len();
+        self.batch_stats.batches += batches;
+        self.batch_stats.draw_calls += batches;
+
+        self.draw_calls.append(&mut self.sprites);
+    }
+
+    fn define_font(
+        &mut self,
+        name: &str,
+        _size: i32,
+        _weight: i32,
+        _italic: bool,
+        _underline: bool,
+        _strikeout: bool,
+        _font: &str,
+    ) {
+        self.fonts.insert(String::from(name));
+    }
+
+    fn draw_text(&mut self, text: &str, x: f32, y: f32, color: (f32, f32, f32, f32), font: &str) {
+        if !self.fonts.contains(font) {
+            eprintln!("Invalid font: {font}");
+            return;
+        }
+
+        self.batch_stats.draw_calls += 1;
+        self.draw_calls.push(DrawCall::Text {
+            text: String::from(text),
+            x,
+            y,
+            color,
+            font: String::from(font),
+        });
+    }
+
+    fn draw_particles(&mut self, particles: &[Particle], _proj: Matrix, _view: Matrix) {
+        self.batch_stats.draw_calls += sprite_batches(particles, |particle| particle.tex_id).len();
+        for particle in particles {
+            self.draw_calls.push(DrawCall::Particle {
+                pos: particle.pos,
+                size: particle.size,
+                rotation: particle.rotation,
+                color: particle.color,
+                tex_id: particle.tex_id,
+            });
+        }
+    }
+
+    fn take_batch_stats(&mut self) -> BatchStats {
+        std::mem::take(&mut self.batch_stats)
+    }
+
+    fn take_draw_calls(&mut self) -> Vec<DrawCall> {
+        std::mem::take(&mut self.draw_calls)
+    }
+}
+
+///Audio backend that mixes without an audio device. Calls are recorded and every update mixes
+///the audio of the frame time it's given, so sounds and streams keep pace with game time and
+///sound handles report the same state they would with a device.
+pub struct HeadlessAudio {
+    mixer: Mixer,
+    audio_calls: Vec<AudioCall>,
+    scratch: Vec<f32>,
+    ///Fraction of a frame of audio left over from the last update.
+    mix_remainder: f64,
+}
+
+impl HeadlessAudio {
+    pub fn new(one_shot_voices: u8, loop_voices: u8) -> Self {
+        Self {
+            mixer: Mixer::new(MIX_SAMPLE_RATE, one_shot_voices, loop_voices),
+            audio_calls: Vec::new(),
+            scratch: Vec::new(),
+            mix_remainder: 0.0,
+        }
+    }
+
+    fn sound_exists(&self, audio: &str) -> bool {
+        let exists = self.mixer.get_sound(audio).is_some();
+        if !exists {
+            eprintln!("Couldn't play: '{audio}' does not exist");
+        }
+        exists
+    }
+
+    fn stream_exists(&self, stream: &str) -> bool {
+        let exists = self.mixer.get_stream(stream).is_some();
+        if !exists {
+            eprintln!("Couldn't play: stream '{stream}' does not exist");
+        }
+        exists
+    }
+}
+
+impl AudioBackend for HeadlessAudio {
+    fn load_audio(&mut self, path: &str) {
+        if let Some((name, sound)) = read_sound(path) {
+            self.mixer.add_sound(&name, sound);
+        }
+    }
+
+    fn load_audio_dir(&mut self, path: &str) {
+        let directory = std::path::Path::new(path);
+
+        if !directory.is_dir() {
+            eprintln!("Invalid audio directory: {path}");
+            return;
+        }
+
+        for entry in fs::read_dir(directory).unwrap() {
+            let entry = entry.unwrap();
+            self.load_audio(entry.path().to_str().unwrap());
+        }
+    }
+
+    fn play_one_shot(&mut self, audio: &str, level: f32) {
+        if !self.sound_exists(audio) {
+            return;
+        }
+
+        self.mixer.play_one_shot(audio, level, 0.0);
+        self.audio_calls.push(AudioCall::OneShot {
+            audio: String::from(audio),
+            level,
+        });
+    }
+
+    fn play(&mut self, audio: &str, level: f32, priority: u8) -> SoundHandle {
+        let handle = self.mixer.play(audio, level, 0.0, priority);
+        if self.mixer.get_sound(audio).is_some() {
+            self.audio_calls.push(AudioCall::Play {
+                audio: String::from(audio),
+                level,
+                priority,
+                handle,
+            });
+        }
+        handle
+    }
+
+    fn play_loop(&mut self, audio: &str, channel: u8, level: f32) {
+        if channel >= self.mixer.loop_voices() {
+            println!("Channel {} does not exist", channel);
+            return;
+        }
+        if !self.sound_exists(audio) {
+            return;
+        }
+
+        self.mixer.play_loop(audio, channel, level, 0.0);
+        self.audio_calls.push(AudioCall::Loop {
+            audio: String::from(audio),
+            channel,
+            level,
+        });
+    }
+
+    fn stop_loop(&mut self, channel: u8) {
+        if channel >= self.mixer.loop_voices() {
+            println!("Channel {} does not exist", channel);
+            return;
+        }
+
+        self.mixer.stop_loop(channel);
+        self.audio_calls.push(AudioCall::StopLoop { channel });
+    }
+
+    fn stop_sound(&mut self, handle: SoundHandle) {
+        self.mixer.stop_sound(handle);
+        self.audio_calls.push(AudioCall::StopSound { handle });
+    }
+
+    fn pause_sound(&mut self, handle: SoundHandle) {
+        self.mixer.pause_sound(handle);
+        self.audio_calls.push(AudioCall::PauseSound { handle });
+    }
+
+    fn resume_sound(&mut self, handle: SoundHandle) {
+        self.mixer.resume_sound(handle);
+        self.audio_calls.push(AudioCall::ResumeSound { handle });
+    }
+
+    fn set_sound_volume(&mut self, handle: SoundHandle, level: f32) {
+        self.mixer.set_sound_volume(handle, level);
+        self.audio_calls
+            .push(AudioCall::SoundVolume { handle, level });
+    }
+
+    fn set_sound_pitch(&mut self, handle: SoundHandle, pitch: f32) {
+        self.mixer.set_sound_pitch(handle, pitch);
+        self.audio_calls
+            .push(AudioCall::SoundPitch { handle, pitch });
+    }
+
+    fn set_sound_pan(&mut self, handle: SoundHandle, pan: f32) {
+        self.mixer.set_sound_pan(handle, pan);
+        self.audio_calls.push(AudioCall::SoundPan { handle, pan });
+    }
+
+    fn is_sound_playing(&self, handle: SoundHandle) -> bool {
+        self.mixer.is_sound_playing(handle)
+    }
+
+    fn sound_position(&self, handle: SoundHandle) -> Option<Duration> {
+        self.mixer.sound_position(handle)
+    }
+
+    fn load_stream(&mut self, path: &str) {
+        if let Some((name, stream)) = read_stream(path) {
+            self.mixer.add_stream(&name, stream);
+        }
+    }
+
+    fn play_stream(&mut self, stream: &str, level: f32, looping: bool) {
+        if self.stream_exists(stream) {
+            self.mixer.play_stream(stream, level, looping);
+            self.audio_calls.push(AudioCall::PlayStream {
+                stream: String::from(stream),
+                level,
+                looping,
+            });
+        }
+    }
+
+    fn pause_stream(&mut self, stream: &str) {
+        if self.stream_exists(stream) {
+            self.mixer.pause_stream(stream);
+            self.audio_calls.push(AudioCall::PauseStream {
+                stream: String::from(stream),
+            });
+        }
+    }
+
+    fn stop_stream(&mut self, stream: &str) {
+        if self.stream_exists(stream) {
+            self.mixer.stop_stream(stream);
+            self.audio_calls.push(AudioCall::StopStream {
+                stream: String::from(stream),
+            });
+        }
+    }
+
+    fn seek_stream(&mut self, stream: &str, position: Duration) {
+        if self.stream_exists(stream) {
+            self.mixer.seek_stream(stream, position);
+            self.audio_calls.push(AudioCall::SeekStream {
+                stream: String::from(stream),
+                position,
+            });
+        }
+    }
+
+    fn set_stream_loop_points(&mut self, stream: &str, start: Duration, end: Option<Duration>) {
+        if self.stream_exists(stream) {
+            self.mixer.set_stream_loop_points(stream, start, end);
+            self.audio_calls.push(AudioCall::StreamLoopPoints {
+                stream: String::from(stream),
+                start,
+                end,
+            });
+        }
+    }
+
+    fn set_master_volume(&mut self, level: f32) {
+        self.mixer.set_master_volume(level);
+        self.audio_calls.push(AudioCall::MasterVolume { level });
+    }
+
+    ///Mix the audio frames that play in dt, the output is dropped.
+    fn update(&mut self, dt: Duration) {
+        let frames = dt.as_secs_f64() * self.mixer.sample_rate() as f64 + self.mix_remainder;
+        self.mix_remainder = frames.fract();
+
+        self.scratch.resize(frames as usize * MIX_CHANNELS, 0.0);
+        self.mixer.mix(&mut self.scratch);
+    }
+
+    fn take_audio_calls(&mut self) -> Vec<AudioCall> {
+        std::mem::take(&mut self.audio_calls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Event, EventFunctions, System, SystemConf};
+
+    ///Half a second of silence as an 8bit mono WAV file at 1000 Hz.
+    fn write_wav(path: &std::path::Path) {
+        let samples = [128u8; 500];
+        let mut file = Vec::from(*b"RIFF");
+        file.extend((36 + samples.len() as u32).to_le_bytes());
+        file.extend(b"WAVEfmt ");
+        file.extend(16u32.to_le_bytes());
+        file.extend([1, 0, 1, 0]);
+        file.extend(1000u32.to_le_bytes());
+        file.extend(1000u32.to_le_bytes());
+        file.extend([1, 0, 8, 0]);
+        file.extend(b"data");
+        file.extend((samples.len() as u32).to_le_bytes());
+        file.extend(samples);
+        fs::write(path, file).unwrap();
+    }
+
+    struct Game {
+        sound_path: String,
+        handle: Option<SoundHandle>,
+    }
+
+    impl EventFunctions for Game {
+        fn init(&mut self, system: &mut System) {
+            system.load_audio(&self.sound_path);
+        }
+
+        fn update(&mut self, system: &mut System, _dt: Duration) {
+            if self.handle.is_none() {
+                self.handle = Some(system.play("headless_blip", 0.5, 1));
+            }
+        }
+
+        fn draw(&mut self, system: &mut System) {
+            system.display_clear(0.0, 0.0, 0.0, 1.0);
+            system.rect(
+                10.0,
+                20.0,
+                0.0,
+                30.0,
+                40.0,
+                0.0,
+                (1.0, 0.0, 0.0, 1.0).into(),
+            );
+        }
+    }
+
+    #[test]
+    fn run_frames_records_draw_and_audio_calls() {
+        let path = std::env::temp_dir().join("headless_blip.wav");
+        write_wav(&path);
+
+        let mut system = System::new_headless(SystemConf {
+            rng_seed: 1,
+            ..Default::default()
+        });
+        let mut game = Game {
+            sound_path: String::from(path.to_str().unwrap()),
+            handle: None,
+        };
+        Event::run_frames(&mut game, &mut system, 2, Duration::from_millis(100));
+        fs::remove_file(&path).unwrap();
+
+        let frame = [
+            DrawCall::Clear {
+                color: (0.0, 0.0, 0.0, 1.0),
+            },
+            DrawCall::Rect {
+                x: 10.0,
+                y: 20.0,
+                z: 0.0,
+                w: 30.0,
+                h: 40.0,
+                rot: 0.0,
+                color: (1.0, 0.0, 0.0, 1.0).into(),
+            },
+        ];
+        assert_eq!(system.take_draw_calls(), [frame.clone(), frame].concat());
+
+        let handle = game.handle.unwrap();
+        assert_eq!(
+            system.take_audio_calls(),
+            [AudioCall::Play {
+                audio: String::from("headless_blip"),
+                level: 0.5,
+                priority: 1,
+                handle,
+            }]
+        );
+
+        //Audio advances with the frame time, two frames of 100ms into the half second sound.
+        assert!(system.is_sound_playing(handle));
+        let position = system.sound_position(handle).unwrap();
+        assert!((position.as_secs_f64() - 0.2).abs() < 0.001, "{position:?}");
+
+        Event::run_frames(&mut game, &mut system, 4, Duration::from_millis(100));
+        assert!(!system.is_sound_playing(handle));
+    }
+}
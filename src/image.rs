@@ -0,0 +1,399 @@
+use std::ffi::OsStr;
+use std::fs;
+
+use crate::bcn::decode_bcn;
+use crate::bmp::decode_bmp;
+use crate::dds::decode_dds;
+use crate::png::{decode_png, encode_png};
+use crate::qoi::decode_qoi;
+use crate::texture::TextureError;
+use crate::tga::decode_tga;
+
+const BMP_HEADER_SIZE: usize = 54;
+
+///Extensions of the image formats that can be decoded, compared case insensitively.
+const IMAGE_EXTENSIONS: [&str; 5] = ["bmp", "png", "tga", "qoi", "dds"];
+
+pub fn is_image_extension(extension: &OsStr) -> bool {
+    IMAGE_EXTENSIONS
+        .iter()
+        .any(|format| extension.eq_ignore_ascii_case(format))
+}
+
+///Layout of the pixel data in an Image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    ///8bit per channel RGBA with the top row first.
+    Rgba8,
+    ///4x4 blocks as stored in a DXT1 DDS file.
+    Bc1,
+    ///4x4 blocks as stored in a DXT3 DDS file.
+    Bc2,
+    ///4x4 blocks as stored in a DXT5 DDS file.
+    Bc3,
+    ///4x4 blocks with a single unsigned channel, sampled as red.
+    Bc4,
+    ///4x4 blocks with a single signed channel, sampled as red.
+    Bc4Signed,
+    ///4x4 blocks with two unsigned channels, sampled as red and green.
+    Bc5,
+    ///4x4 blocks with two signed channels, sampled as red and green.
+    Bc5Signed,
+    ///4x4 blocks of unsigned half float RGB.
+    Bc6h,
+    ///4x4 blocks of signed half float RGB.
+    Bc6hSigned,
+    ///4x4 blocks of RGBA with a mode chosen per block.
+    Bc7,
+}
+
+impl ImageFormat {
+    ///Size in bytes of a single mip level with the given dimensions.
+    pub fn level_size(&self, width: u32, height: u32) -> usize {
+        let width = width.max(1) as usize;
+        let height = height.max(1) as usize;
+        let blocks = width.div_ceil(4) * height.div_ceil(4);
+
+        match self {
+            ImageFormat::Rgba8 => width * height * 4,
+            ImageFormat::Bc1 | ImageFormat::Bc4 | ImageFormat::Bc4Signed => blocks * 8,
+            _ => blocks * 16,
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        *self != ImageFormat::Rgba8
+    }
+}
+
+///Decoded image kept on the CPU. Upload it with System::upload_texture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub format: ImageFormat,
+    ///Number of mip levels in every layer, only images loaded from DDS files have more than one.
+    pub mip_levels: u32,
+    ///Number of array layers, cube maps count each of their six faces as a layer.
+    pub layers: u32,
+    ///The layers are cube map faces in +X, -X, +Y, -Y, +Z, -Z order, six per cube.
+    pub cube_map: bool,
+    ///Pixel data of every layer one after another, each with all of its mip levels starting with
+    ///the full size level.
+    pub pixels: Vec<u8>,
+}
+
+///Result of comparing two images of the same size.
+pub struct ImageDiff {
+    ///Number of pixels where at least one channel differs by more than the tolerance.
+    pub mismatched_pixels: usize,
+    ///Largest difference found in any channel.
+    pub max_difference: u8,
+    ///Mismatched pixels in red over a dimmed copy of the expected image.
+    pub diff: Image,
+}
+
+impl Image {
+    ///Create an RGBA8 image.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        Image::with_format(width, height, ImageFormat::Rgba8, 1, 1, pixels)
+    }
+
+    pub fn with_format(
+        width: u32,
+        height: u32,
+        format: ImageFormat,
+        mip_levels: u32,
+        layers: u32,
+        pixels: Vec<u8>,
+    ) -> Self {
+        let image = Self {
+            width,
+            height,
+            format,
+            mip_levels,
+            layers,
+            cube_map: false,
+            pixels,
+        };
+        debug_assert_eq!(
+            image.pixels.len(),
+            image.layer_size(image.mip_levels) * layers as usize
+        );
+        image
+    }
+
+    ///Dimensions and data of a mip level of the first layer, None if the image doesn't have that
+    ///level.
+    pub fn mip_level(&self, level: u32) -> Option<(u32, u32, &[u8])> {
+        self.subresource(0, level)
+    }
+
+    ///Dimensions and data of a mip level of a layer, None if the image doesn't have that layer or
+    ///level or its pixels are too short to hold it.
+    pub fn subresource(&self, layer: u32, level: u32) -> Option<(u32, u32, &[u8])> {
+        if layer >= self.layers || level >= self.mip_levels {
+            return None;
+        }
+
+        let start = self.layer_size(self.mip_levels) * layer as usize + self.layer_size(level);
+        let width = (self.width >> level).max(1);
+        let height = (self.height >> level).max(1);
+        let size = self.format.level_size(width, height);
+
+        Some((width, height, self.pixels.get(start..start + size)?))
+    }
+
+    ///Whether pixels holds every mip level of every layer. Images built by hand with too little
+    ///data are rejected by uploads instead of reading past the end.
+    pub fn is_complete(&self) -> bool {
+        self.pixels.len() >= self.layer_size(self.mip_levels) * self.layers as usize
+    }
+
+    ///Decode block compressed pixel data on the CPU. Returns an RGBA8 image with the same mip
+    ///levels and layers, rows are kept in the order the blocks are stored in.
+    pub fn decompress(&self) -> Image {
+        if !self.format.is_compressed() {
+            return self.clone();
+        }
+
+        let mut pixels = Vec::with_capacity(
+            self.width as usize * self.height as usize * 4 * self.layers as usize,
+        );
+        for layer in 0..self.layers {
+            for level in 0..self.mip_levels {
+                //Levels missing from short pixel data decode as transparent black.
+                let width = (self.width >> level).max(1);
+                let height = (self.height >> level).max(1);
+                match self.subresource(layer, level) {
+                    Some((_, _, data)) => {
+                        pixels.extend_from_slice(&decode_bcn(self.format, width, height, data))
+                    }
+                    None => pixels.resize(pixels.len() + width as usize * height as usize * 4, 0),
+                }
+            }
+        }
+
+        let mut image = Image::with_format(
+            self.width,
+            self.height,
+            ImageFormat::Rgba8,
+            self.mip_levels,
+            self.layers,
+            pixels,
+        );
+        image.cube_map = self.cube_map;
+        image
+    }
+
+    ///Size in bytes of the first levels mip levels of a single layer.
+    fn layer_size(&self, levels: u32) -> usize {
+        (0..levels)
+            .map(|level| {
+                self.format
+                    .level_size(self.width >> level, self.height >> level)
+            })
+            .sum()
+    }
+
+    ///Load a BMP, PNG, TGA, QOI or DDS file.
+    pub fn load(path: &str) -> Result<Image, TextureError> {
+        let extension = std::path::Path::new(&path).extension().unwrap_or_default();
+
+        if !is_image_extension(extension) {
+            return Err(TextureError::UnrecognizedFormat);
+        }
+
+        match fs::read(path) {
+            Ok(file) => Image::decode(&file, extension),
+            Err(error) => Err(TextureError::Io(error.kind())),
+        }
+    }
+
+    ///Decode file contents in the format given by a file extension.
+    pub fn decode(file: &[u8], extension: &OsStr) -> Result<Image, TextureError> {
+        let extension = extension.to_ascii_lowercase();
+
+        match extension.to_str() {
+            Some("bmp") => decode_bmp(file),
+            Some("png") => decode_png(file),
+            Some("tga") => decode_tga(file),
+            Some("qoi") => decode_qoi(file),
+            Some("dds") => decode_dds(file),
+            _ => Err(TextureError::UnrecognizedFormat),
+        }
+    }
+
+    ///Save an RGBA8 image as PNG or 32bit BMP depending on the extension of path. Only the full
+    ///size level of the first layer is saved.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        if self.format != ImageFormat::Rgba8 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Saving image '{path}' failed: Only RGBA8 images can be saved!"),
+            ));
+        }
+
+        let Some((width, height, pixels)) = self.mip_level(0) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Saving image '{path}' failed: Pixel data is too short!"),
+            ));
+        };
+
+        if self.mip_levels > 1 || self.layers > 1 {
+            return Image::new(width, height, pixels.to_vec()).save(path);
+        }
+
+        let extension = std::path::Path::new(&path).extension();
+
+        if extension.is_some_and(|extension| extension.eq_ignore_ascii_case("bmp")) {
+            fs::write(path, self.encode_bmp())
+        } else if extension.is_some_and(|extension| extension.eq_ignore_ascii_case("png")) {
+            fs::write(path, encode_png(self))
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Saving image '{path}' failed: Unrecognized format!"),
+            ))
+        }
+    }
+
+    ///Compare the full size level of the first layer against an expected RGBA8 image of the same
+    ///size. Returns None if the sizes differ, either image isn't RGBA8 or has too little data.
+    pub fn compare(&self, expected: &Image, tolerance: u8) -> Option<ImageDiff> {
+        if self.width != expected.width
+            || self.height != expected.height
+            || self.format != ImageFormat::Rgba8
+            || expected.format != ImageFormat::Rgba8
+        {
+            return None;
+        }
+
+        let mut mismatched_pixels = 0;
+        let mut max_difference = 0;
+        let mut diff = Vec::with_capacity(self.width as usize * self.height as usize * 4);
+
+        let (_, _, actual) = self.mip_level(0)?;
+        let (_, _, expected) = expected.mip_level(0)?;
+
+        for (actual, expected) in actual.chunks_exact(4).zip(expected.chunks_exact(4)) {
+            let difference = actual
+                .iter()
+                .zip(expected)
+                .map(|(a, e)| a.abs_diff(*e))
+                .max()
+                .unwrap_or(0);
+            max_difference = max_difference.max(difference);
+
+            if difference > tolerance {
+                mismatched_pixels += 1;
+                diff.extend_from_slice(&[255, 0, 0, 255]);
+            } else {
+                diff.extend_from_slice(&[expected[0] / 4, expected[1] / 4, expected[2] / 4, 255]);
+            }
+        }
+
+        Some(ImageDiff {
+            mismatched_pixels,
+            max_difference,
+            diff: Image::new(self.width, self.height, diff),
+        })
+    }
+
+    fn encode_bmp(&self) -> Vec<u8> {
+        let image_size = self.pixels.len() as u32;
+        let file_size = BMP_HEADER_SIZE as u32 + image_size;
+
+        let mut file = Vec::with_capacity(file_size as usize);
+        file.extend_from_slice(b"BM");
+        file.extend_from_slice(&file_size.to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&(BMP_HEADER_SIZE as u32).to_le_bytes());
+        file.extend_from_slice(&40u32.to_le_bytes());
+        file.extend_from_slice(&(self.width as i32).to_le_bytes());
+        file.extend_from_slice(&(self.height as i32).to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&32u16.to_le_bytes());
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&image_size.to_le_bytes());
+        file.extend_from_slice(&[0; 16]);
+
+        let stride = (self.width as usize * 4).max(1);
+        for row in self.pixels.chunks_exact(stride).rev() {
+            for rgba in row.chunks_exact(4) {
+                file.extend_from_slice(&[rgba[2], rgba[1], rgba[0], rgba[3]]);
+            }
+        }
+
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_levels_and_layers() {
+        let pixels: Vec<u8> = (0..(16 + 4) * 2).map(|value| value as u8).collect();
+        let image = Image::with_format(2, 2, ImageFormat::Rgba8, 2, 2, pixels);
+
+        assert!(image.is_complete());
+        assert_eq!(image.mip_level(1), Some((1, 1, &[16, 17, 18, 19][..])));
+        assert_eq!(image.subresource(1, 1), Some((1, 1, &[36, 37, 38, 39][..])));
+        assert_eq!(image.subresource(2, 0), None);
+        assert_eq!(image.mip_level(2), None);
+    }
+
+    #[test]
+    fn short_pixels_are_rejected_instead_of_panicking() {
+        let mut image = Image::new(2, 2, vec![255; 16]);
+        image.pixels.truncate(12);
+
+        assert!(!image.is_complete());
+        assert_eq!(image.mip_level(0), None);
+        assert!(image.compare(&Image::new(2, 2, vec![255; 16]), 0).is_none());
+        assert!(Image::new(2, 2, vec![255; 16]).compare(&image, 0).is_none());
+        let path = std::env::temp_dir().join("short_pixels.png");
+        assert!(image.save(path.to_str().unwrap()).is_err());
+
+        let mut compressed = Image::with_format(4, 4, ImageFormat::Bc1, 1, 1, vec![0; 8]);
+        compressed.pixels.clear();
+        assert_eq!(compressed.decompress().pixels, [0; 64]);
+    }
+
+    #[test]
+    fn decodes_by_extension() {
+        let image = Image::new(2, 1, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+
+        assert_eq!(
+            Image::decode(&image.encode_bmp(), OsStr::new("BMP")),
+            Ok(image.clone())
+        );
+        assert_eq!(
+            Image::decode(&encode_png(&image), OsStr::new("png")),
+            Ok(image.clone())
+        );
+        assert_eq!(
+            Image::decode(&encode_png(&image), OsStr::new("gif")),
+            Err(TextureError::UnrecognizedFormat)
+        );
+        assert_eq!(
+            Image::load("missing.txt"),
+            Err(TextureError::UnrecognizedFormat)
+        );
+    }
+
+    #[test]
+    fn compare_counts_pixels_past_the_tolerance() {
+        let expected = Image::new(2, 1, vec![100, 100, 100, 255, 100, 100, 100, 255]);
+        let actual = Image::new(2, 1, vec![103, 100, 100, 255, 100, 90, 100, 255]);
+
+        let diff = actual.compare(&expected, 3).unwrap();
+        assert_eq!(diff.mismatched_pixels, 1);
+        assert_eq!(diff.max_difference, 10);
+        assert_eq!((diff.diff.width, diff.diff.height), (2, 1));
+        assert!(actual.compare(&Image::new(1, 2, vec![0; 8]), 3).is_none());
+    }
+}
@@ -6,6 +6,12 @@ pub struct Input {
     pub keyboard: Keyboard,
 }
 
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Input {
     pub fn new() -> Self {
         Input {
@@ -20,15 +26,43 @@ impl Input {
         }
     }
 
-    pub fn poll(&mut self, hwnd: HWND) {
-        self.poll_controllers();
-        self.poll_mouse_position(hwnd)
-    }
-
     pub fn advance(&mut self) {
-        self.set_controllers();
         self.mouse.advance_input();
         self.keyboard.advance_input();
+        for controller in &mut self.controllers {
+            controller.advance_input();
+        }
+    }
+
+    ///Capture the current device state for input recording.
+    pub fn snapshot(&self) -> InputSnapshot {
+        InputSnapshot {
+            keys: self.keyboard.current_frame,
+            mouse_x: self.mouse.position.x,
+            mouse_y: self.mouse.position.y,
+            mouse_buttons: self.mouse.current_frame,
+            wheel_delta: self.mouse.wheel_delta,
+            wheel_h_delta: self.mouse.wheel_h_delta,
+            controllers: [0, 1, 2, 3].map(|i| self.controllers[i].current_frame.Gamepad.clone()),
+        }
+    }
+
+    ///Replace the current device state with a recorded one. Previous frame state is kept so
+    ///pressed and released still work.
+    pub fn apply_snapshot(&mut self, snapshot: &InputSnapshot) {
+        self.keyboard.current_frame = snapshot.keys;
+        self.mouse.position.x = snapshot.mouse_x;
+        self.mouse.position.y = snapshot.mouse_y;
+        self.mouse.current_frame = snapshot.mouse_buttons;
+        self.mouse.wheel_delta = snapshot.wheel_delta;
+        self.mouse.wheel_h_delta = snapshot.wheel_h_delta;
+
+        for (controller, gamepad) in self.controllers.iter_mut().zip(&snapshot.controllers) {
+            controller.process_input(&XINPUT_STATE {
+                dwPacketNumber: 0,
+                Gamepad: gamepad.clone(),
+            });
+        }
     }
 
     pub fn process_messages(&mut self, message: u32, wparam: WPARAM) -> LPARAM {
@@ -107,25 +141,9 @@ impl Input {
         }
     }
 
-    pub fn poll_controllers(&mut self) {
-        for controller_index in 0..1 {
-            let controller_state = XINPUT_STATE::default();
-
-            unsafe {
-                if XInputGetState(controller_index, &controller_state) == ERROR_SUCCESS {
-                    self.controllers[controller_index as usize].process_input(&controller_state);
-                    //println!("controller state: {:?}", &controller_state);
-                } else {
-                }
-            }
-        }
-    }
-
-    pub fn poll_mouse_position(&mut self, hwnd: HWND) {
-        unsafe {
-            GetCursorPos(&self.mouse.position);
-            ScreenToClient(hwnd, &self.mouse.position);
-        }
+    pub fn set_mouse_position(&mut self, x: i32, y: i32) {
+        self.mouse.position.x = x;
+        self.mouse.position.y = y;
     }
 
     pub fn set_mouse_state(&mut self, button: u8) {
@@ -151,17 +169,18 @@ impl Input {
     pub fn release_key_state(&mut self, index: usize) {
         self.keyboard.current_frame[index] = false;
     }
+}
 
-    pub fn set_controllers(&self) {
-        for controller_index in 0..XUSER_MAX_COUNT {
-            unsafe {
-                XInputSetState(
-                    controller_index,
-                    &self.controllers[controller_index as usize].vibration,
-                );
-            }
-        }
-    }
+///Device state seen by a single frame.
+#[derive(Clone)]
+pub struct InputSnapshot {
+    pub keys: [bool; 256],
+    pub mouse_x: i32,
+    pub mouse_y: i32,
+    pub mouse_buttons: u8,
+    pub wheel_delta: i16,
+    pub wheel_h_delta: i16,
+    pub controllers: [XINPUT_GAMEPAD; XUSER_MAX_COUNT as usize],
 }
 
 #[allow(non_camel_case_types)]
@@ -403,16 +422,25 @@ impl Controller {
             vibration: XINPUT_VIBRATION::default(),
         }
     }
+    ///Set the current state; the previous frame is only moved forward by
+    ///[`Controller::advance_input`], so polling twice in a frame keeps edges.
     pub fn process_input(&mut self, controller_state: &XINPUT_STATE) {
-        std::mem::swap(&mut self.current_frame, &mut self.prev_frame);
         self.current_frame = controller_state.clone();
     }
 
+    pub fn advance_input(&mut self) {
+        self.prev_frame = self.current_frame.clone();
+    }
+
     pub fn set_vibration(&mut self, left_speed: u16, right_speed: u16) {
         self.vibration.wLeftMotorSpeed = left_speed;
         self.vibration.wRightMotorSpeed = right_speed;
     }
 
+    pub fn get_vibration(&self) -> &XINPUT_VIBRATION {
+        &self.vibration
+    }
+
     pub fn pressed(&self, gamepad: GAMEPAD) -> bool {
         self.current_frame.Gamepad.wButtons & gamepad as u16 != 0
             && self.prev_frame.Gamepad.wButtons & gamepad as u16 == 0
@@ -479,3 +507,32 @@ impl Controller {
         self.current_frame.Gamepad.sThumbRY
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(buttons: u16) -> XINPUT_STATE {
+        let mut state = XINPUT_STATE::default();
+        state.Gamepad.wButtons = buttons;
+        state
+    }
+
+    #[test]
+    fn gamepad_edges_follow_input_advance() {
+        let mut input = Input::new();
+        input.controllers[0].process_input(&state(GAMEPAD::A as u16));
+        //Polling again within the same frame must not eat the press.
+        input.controllers[0].process_input(&state(GAMEPAD::A as u16));
+        assert!(input.controllers[0].pressed(GAMEPAD::A));
+
+        input.advance();
+        input.controllers[0].process_input(&state(GAMEPAD::A as u16));
+        assert!(!input.controllers[0].pressed(GAMEPAD::A));
+        assert!(input.controllers[0].held(GAMEPAD::A));
+
+        input.advance();
+        input.controllers[0].process_input(&state(0));
+        assert!(input.controllers[0].released(GAMEPAD::A));
+    }
+}
@@ -4,17 +4,37 @@
 //! This is the 0.1 release and is subject to major api changes.
 //!
 //! #### Current Features:
-//! * Texture loading system that supports 32bit rgba BMP and DDS files
-//! * Audio loading system that supports WAV files
+//! * Texture loading system that supports BMP, PNG, TGA, QOI and DDS files
+//! * BC1 to BC7 compressed DDS textures, cube maps and texture arrays with a CPU fallback decoder
+//! * Audio loading system that supports WAV files with 8 to 32bit PCM or float samples, any sample
+//!   rate and up to 8 channels
+//! * Zero dependency Ogg Vorbis decoding for music, fully decoded or streamed a piece at a time
 //! * Audio playback system for playing one-shots and loops
+//! * Sound handles to stop, pause, pitch, pan and track playing sounds, with voice priorities so
+//!   important sounds aren't cut off
+//! * Music streaming with play, pause, seek and gapless looping between loop points
+//! * Software mixer with per voice volume and pan, resampling and master volume, testable without
+//!   an audio device
 //! * Text rendering
+//! * BMFont bitmap fonts drawn as sprites with the camera, color, scale and depth
+//! * TrueType fonts loaded from .ttf files and rasterized with anti-aliasing into glyph atlases
+//! * Text measurement, word wrapping, alignment, line spacing and fallback glyphs for Unicode text
 //! * Input system for Mouse, Keyboard and Xinput Gamepads
 //! * Random number generation system
 //! * Textured Sprite and Primitives system
+//! * Batched sprite rendering grouped by texture with batch and draw call statistics
+//! * Runtime texture atlas packing with padding and edge extrusion
+//! * Sprite sheets from grids or Aseprite JSON and animation clips with events
 //! * Basic Orthographic Camera system
 //! * Barebones Math library
 //! * Textured particle system
+//! * Particle emitters with their own pools, spawn shapes, gravity, drag, color gradients and size
+//!   curves, drawn in batches
+//! * Emitter handles updated by the engine with pause, stop and drain, bursts and live counts
+//! * Particle effects described in text files and reloaded when they change on disk
 //! * Basic fps, frametime and performance statistics
+//! * Optional fixed timestep game loop with interpolation alpha
+//! * Input recording and deterministic replay
 //!
 //! ### Entry point:
 //! * The [System] struct defines most of the important functions.
@@ -22,12 +42,54 @@
 //!
 //! * [EventFunctions] must be implemented to start a project.
 //!
-//! * Check out [ParticleProperties] to define a particle.
+//! * Check out [ParticleProperties] to define a particle. Run a whole effect from one set of
+//!   properties with [System::create_emitter], or own a [ParticleEmitter] and drive it with
+//!   [System::update_emitter] and [System::draw_emitter]. Designers can tweak effects without
+//!   recompiling in files loaded with [System::load_particle_effect], see
+//!   [ParticleProperties::from_text] for the format.
+//!
+//! * [Image::load] decodes textures on the CPU without a GL context, upload them with
+//!   [System::upload_texture].
+//!
+//! * Pack many small textures into a few atlas pages with [AtlasBuilder] or
+//!   [System::load_atlas_dir] and draw them with [System::sprite_region].
+//!
+//! * Load a BMFont with [System::load_bitmap_font] and draw it with [System::text] or
+//!   [System::bitmap_text]. Pass a .ttf file to [System::define_font] to ship a font with the game
+//!   instead of relying on installed fonts, or rasterize one yourself with [TrueTypeFont].
+//!   Wrap and align text with [System::styled_text] and size UI around it with
+//!   [System::measure_text].
+//!
+//! * Slice a [SpriteSheet] into frames, play an [AnimationClip] with an [Animation] and draw it
+//!   with [System::animation].
+//!
+//! * Implement [Platform], [RenderBackend] and [AudioBackend] and pass them to
+//!   [System::with_backends] to run on something other than win32.
+//!
+//! * Use [System::new_headless] with [Event::run_frames] to drive a game without a window, GPU or
+//!   audio device. Draw and audio calls are recorded and can be read back with
+//!   [System::take_draw_calls] and [System::take_audio_calls].
+//!
+//! * Sounds are mixed by a [Mixer] that any backend can pull interleaved stereo frames from, it
+//!   runs without an audio device so mixing can be tested anywhere. [Sound::load] decodes audio
+//!   files into the mix format. [VorbisStream] decodes long Ogg Vorbis music on demand instead of
+//!   all at once. [AudioStream] plays it through a small ring of buffers that are decoded as they
+//!   play out.
+//!
+//! * Use [System::new_software] to draw into a [SoftwareRenderer] framebuffer instead, read it
+//!   back with [System::capture_frame].
+//!
+//! * [assert_golden] renders a game for a number of frames with the software renderer and
+//!   compares the last frame to a golden PNG or BMP image.
+//!
+//! * Call [System::start_recording] in init to record input, and [System::start_replay] to play
+//!   a recording back with the same frame times and random numbers.
 //!
 //! #### Getting started:
 //! Displays a 600x600 window with red background.
 //!
-//! ```
+//! ```no_run
+//! # use roze::prelude::*;
 //! pub struct Game {}
 //!
 //! impl EventFunctions for Game {
@@ -38,6 +100,9 @@
 //!     }
 //! }
 //!
+//! # #[cfg(not(windows))]
+//! # fn main() {}
+//! # #[cfg(windows)]
 //! fn main() {
 //!     let system = System::new(600, 600, "Game Title");
 //!     let game = Game {};
@@ -49,7 +114,8 @@
 //! Displays a window with a spinning textured sprite. An audio clip plays when the left mouse
 //! button is clicked.
 //!
-//! ```
+//! ```no_run
+//! # use roze::prelude::*;
 //! pub struct Game {
 //!     rotation: f32,
 //! }
@@ -86,6 +152,9 @@
 //!     }
 //! }
 //!
+//! # #[cfg(not(windows))]
+//! # fn main() {}
+//! # #[cfg(windows)]
 //! fn main() {
 //!     let system = System::new(800, 800, "Sprites, Textures and Audio");
 //!     let game = Game { rotation: 0.0 };
@@ -97,7 +166,8 @@
 //! Define an untextured colored particle effect that moves out from the center of the screen. Also
 //! define a font and display the current fps in the top right of the screen.
 //!
-//! ```
+//! ```no_run
+//! # use roze::prelude::*;
 //! pub struct Game {
 //!     particle_prop: ParticleProperties,
 //! }
@@ -105,16 +175,15 @@
 //! impl Game {
 //!     pub fn new() -> Self {
 //!         let particle_prop = ParticleProperties {
-//!             pos: (0.0, 0.0, 0.0).into(),
-//!             velocity: (0.0, 0.0, 0.0).into(),
 //!             velocity_var: (150.0, 150.0, 0.0).into(),
-//!             color_begin: (1.0, 0.0, 0.0, 1.0).into(),
-//!             color_end: (0.0, 0.0, 1.0, 0.0).into(),
-//!             size_begin: 15.0,
-//!             size_end: 25.0,
+//!             color: ColorGradient::between(
+//!                 (1.0, 0.0, 0.0, 1.0).into(),
+//!                 (0.0, 0.0, 1.0, 0.0).into(),
+//!             ),
+//!             size: Curve::linear(15.0, 25.0),
 //!             size_var: 10.0,
 //!             lifetime: 5.0,
-//!             tex_id: None,
+//!             ..Default::default()
 //!         };
 //!         Self { particle_prop }
 //!     }
@@ -153,12 +222,15 @@
 //!             &format!("fps: {}", system.get_fps()),
 //!             system.screen_width() - 100.0,
 //!             20.0,
-//!             (1.0, 1.0, 1.0, 1.0),
+//!             (1.0, 1.0, 1.0, 1.0).into(),
 //!             "myFont",
 //!         );
 //!     }
 //! }
 //!
+//! # #[cfg(not(windows))]
+//! # fn main() {}
+//! # #[cfg(windows)]
 //! fn main() {
 //!     let config = SystemConf {
 //!         window_title: "Particles".into(),
@@ -170,6 +242,7 @@
 //!         rng_seed: 1,
 //!         rng_pool_size: 1_000_000,
 //!         lock_fps: false,
+//!         ..Default::default()
 //!     };
 //!     let system = System::new_ex(config);
 //!     let game = Game::new();
@@ -181,51 +254,129 @@
 
 #![allow(clippy::too_many_arguments)]
 
+mod animation;
+mod atlas;
+#[cfg(windows)]
 mod audio;
+mod bcn;
+mod bmp;
 mod camera;
+mod dds;
+mod effect;
+mod font;
+mod golden;
+mod headless;
+mod image;
 mod input;
+mod json;
 mod math;
+mod mixer;
+mod ogg;
+mod particle;
+mod platform;
+mod png;
+mod qoi;
 mod rand;
+#[cfg(windows)]
 mod renderer;
+mod replay;
+#[cfg(windows)]
 mod shader;
+mod software;
 mod sprite;
 mod statistics;
+mod stream;
 mod system;
 mod texture;
+mod tga;
+mod truetype;
+mod vorbis;
+mod wav;
+#[cfg(windows)]
 mod window;
 
+use atlas::FULL_UV_RECT;
+#[cfg(windows)]
 use audio::Audio;
 use camera::OrthoCam;
-use input::{Input, GAMEPAD, KEYCODE, MOUSECODE};
+use effect::ParticleEffect;
+use font::FontTextures;
+use headless::{HeadlessAudio, HeadlessPlatform, HeadlessRenderer};
+use input::{GAMEPAD, KEYCODE, MOUSECODE};
 use math::*;
 use rand::RngEngine;
+#[cfg(windows)]
 use renderer::Renderer;
-use sprite::{ParticleProperties, Weight};
+use replay::{InputRecorder, InputReplay};
+use sprite::Weight;
 use statistics::Stats;
 use system::gl32::GLuint;
-use system::win32::*;
-use window::{Window, WindowResources};
-
-use std::time::{Duration, Instant, UNIX_EPOCH};
+use texture::read_texture;
+#[cfg(windows)]
+use window::Window;
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+pub use animation::{Animation, AnimationClip, PlaybackMode, SpriteSheet, DEFAULT_FRAME_DURATION};
+pub use atlas::{Atlas, AtlasBuilder, AtlasRegion, SpriteRegion};
+pub use bmp::decode_bmp;
+pub use dds::decode_dds;
+pub use effect::EffectError;
+pub use font::{BitmapFont, Glyph, PlacedGlyph, TextAlign, TextStyle};
+pub use golden::{assert_golden, compare_golden, render_frames, GoldenError};
+pub use image::{Image, ImageDiff, ImageFormat};
+pub use input::Input;
+pub use mixer::{AudioError, Mixer, Sound, SoundHandle, MIX_CHANNELS, MIX_SAMPLE_RATE};
+pub use particle::{
+    ColorGradient, Curve, EmitterId, Particle, ParticleEmitter, ParticleProperties, SpawnShape,
+};
+pub use platform::{AudioBackend, AudioCall, DrawCall, Platform, RenderBackend, WindowDimension};
+pub use png::decode_png;
+pub use qoi::decode_qoi;
+pub use software::SoftwareRenderer;
+pub use statistics::BatchStats;
+pub use stream::{AudioStream, StreamState};
+pub use texture::TextureError;
+pub use tga::decode_tga;
+pub use truetype::TrueTypeFont;
+pub use vorbis::{decode_ogg, VorbisStream};
+pub use wav::decode_wav;
 
 pub mod prelude {
+    pub use crate::font::{TextAlign, TextStyle};
     pub use crate::input::{GAMEPAD, KEYCODE, MOUSECODE};
     pub use crate::math::*;
-    pub use crate::sprite::{ParticleProperties, Weight};
+    pub use crate::particle::{ColorGradient, Curve, ParticleProperties, SpawnShape};
+    pub use crate::sprite::Weight;
     pub use crate::{Event, EventFunctions, System, SystemConf};
     pub use std::time::Duration;
 }
 
 pub struct System {
-    window: Box<window::Window>,
-    audio: Box<audio::Audio>,
-    input: Box<input::Input>,
-    resources: &'static mut WindowResources,
-    renderer: Box<Renderer>,
+    platform: Box<dyn Platform>,
+    renderer: Box<dyn RenderBackend>,
+    audio: Box<dyn AudioBackend>,
+    input: Box<Input>,
+    particle: Box<ParticleEmitter>,
+    ///Ordered by id so emitters update and draw in creation order, which keeps replays in step.
+    emitters: BTreeMap<EmitterId, ParticleEmitter>,
+    next_emitter_id: u64,
+    particle_effects: HashMap<String, ParticleEffect>,
+    effect_reload_timer: Duration,
     rng: Box<RngEngine>,
     cam: Box<OrthoCam>,
     stats: Box<Stats>,
+    sprite_regions: HashMap<String, SpriteRegion>,
+    fonts: HashMap<String, FontTextures>,
+    running: bool,
+    show_cursor: bool,
+    hide_cursor: bool,
     lock_fps: bool,
+    fixed_timestep: Option<Duration>,
+    accumulator: Duration,
+    recorder: Option<InputRecorder>,
+    replay: Option<InputReplay>,
 }
 
 pub struct SystemConf {
@@ -238,89 +389,163 @@ pub struct SystemConf {
     pub rng_seed: u64,
     pub rng_pool_size: usize,
     pub lock_fps: bool,
+    ///Call update with this fixed dt, as many times per frame as needed to catch up with real time.
+    ///None calls update once per frame with the measured frame time, a zero step is treated as
+    ///None.
+    pub fixed_timestep: Option<Duration>,
 }
 
-impl System {
-    ///Create new instance of system with default settings.
-    pub fn new(width: i32, height: i32, window_title: &str) -> Self {
-        let window = Box::new(Window::create_window(window_title, width, height));
-        let audio = Audio::new(8, 8);
-        let mut input = Box::new(Input::new());
-        let mut renderer = Renderer::new(window.device_context, 1000);
-
+impl Default for SystemConf {
+    ///The settings of System::new() in an 800 by 800 window, seeded from the current time.
+    fn default() -> Self {
         let seed = std::time::SystemTime::now()
-            .duration_since(UNIX_EPOCH)
+            .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        let rng = Box::new(RngEngine::new(seed, 1_000_000));
 
-        let cam = Box::new(OrthoCam::new(
-            0.0,
-            width as f32,
-            height as f32,
-            0.0,
-            0.1,
-            100.0,
-        ));
+        Self {
+            window_title: String::new(),
+            screen_width: 800,
+            screen_height: 800,
+            audio_one_shot_channels: 8,
+            audio_loop_channels: 8,
+            particle_pool_size: 1000,
+            rng_seed: seed,
+            rng_pool_size: 1_000_000,
+            lock_fps: true,
+            fixed_timestep: None,
+        }
+    }
+}
 
-        let stats = Box::new(Stats::new());
+///Most fixed updates run in a single frame. Time beyond this is dropped so a slow update can't
+///fall further and further behind.
+const MAX_FIXED_UPDATES: u32 = 8;
 
-        unsafe {
-            let mut resources = &mut *window.window_resources;
-            resources.input = &mut *input;
-            resources.renderer = &mut *renderer;
-
-            System {
-                window,
-                audio,
-                input,
-                resources,
-                renderer,
-                rng,
-                cam,
-                stats,
-                lock_fps: true,
-            }
-        }
+///Transparent pixels between images and edge pixels repeated around them in atlases built by
+///load_atlas_dir().
+const ATLAS_PADDING: u32 = 2;
+const ATLAS_EXTRUDE: u32 = 1;
+
+///How often particle effect files are checked for changes.
+const EFFECT_RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+impl System {
+    ///Create new instance of system with default settings.
+    #[cfg(windows)]
+    pub fn new(width: i32, height: i32, window_title: &str) -> Self {
+        System::new_ex(SystemConf {
+            window_title: window_title.into(),
+            screen_width: width,
+            screen_height: height,
+            ..SystemConf::default()
+        })
     }
     ///Create new instance of System using SystemConf struct.
+    #[cfg(windows)]
     pub fn new_ex(config: SystemConf) -> Self {
         let window = Box::new(Window::create_window(
             &config.window_title,
             config.screen_width,
             config.screen_height,
         ));
+        let renderer = Renderer::new(window.device_context);
         let audio = Audio::new(config.audio_one_shot_channels, config.audio_loop_channels);
-        let mut input = Box::new(Input::new());
-        let mut renderer = Renderer::new(window.device_context, config.particle_pool_size);
+
+        System::with_backends(config, window, renderer, audio)
+    }
+    ///Create new instance of System without a window, GPU or audio device. Textures are only read
+    ///into memory and draw calls are recorded instead of executed. Audio calls are recorded and
+    ///mixed without being played.
+    pub fn new_headless(config: SystemConf) -> Self {
+        let platform = Box::new(HeadlessPlatform::create(
+            &config.window_title,
+            config.screen_width,
+            config.screen_height,
+        ));
+        let renderer = Box::new(HeadlessRenderer::new());
+        let audio = Box::new(HeadlessAudio::new(
+            config.audio_one_shot_channels,
+            config.audio_loop_channels,
+        ));
+
+        System::with_backends(config, platform, renderer, audio)
+    }
+    ///Create new instance of System without a window or GPU that draws into a software
+    ///framebuffer. Read the finished frame back with capture_frame(), audio calls are recorded.
+    pub fn new_software(config: SystemConf) -> Self {
+        let platform = Box::new(HeadlessPlatform::create(
+            &config.window_title,
+            config.screen_width,
+            config.screen_height,
+        ));
+        let renderer = Box::new(SoftwareRenderer::new(
+            config.screen_width,
+            config.screen_height,
+        ));
+        let audio = Box::new(HeadlessAudio::new(
+            config.audio_one_shot_channels,
+            config.audio_loop_channels,
+        ));
+
+        System::with_backends(config, platform, renderer, audio)
+    }
+    ///Create new instance of System on top of the provided platform, renderer and audio backends.
+    ///Window title and size in config are ignored, the platform has already created its window.
+    pub fn with_backends(
+        config: SystemConf,
+        platform: Box<dyn Platform>,
+        renderer: Box<dyn RenderBackend>,
+        audio: Box<dyn AudioBackend>,
+    ) -> Self {
+        //A zero step would never use up the accumulator and hang the first frame.
+        let fixed_timestep = config.fixed_timestep.filter(|step| !step.is_zero());
+        if config.fixed_timestep.is_some() && fixed_timestep.is_none() {
+            eprintln!(
+                "Invalid fixed timestep: step can't be zero, updating once per frame instead"
+            );
+        }
+
+        let input = Box::new(Input::new());
+        let particle = Box::new(ParticleEmitter::new(ParticleProperties {
+            max_particles: config.particle_pool_size,
+            ..Default::default()
+        }));
         let rng = Box::new(RngEngine::new(config.rng_seed, config.rng_pool_size));
+        let dimensions = platform.dimensions();
         let cam = Box::new(OrthoCam::new(
             0.0,
-            config.screen_width as f32,
-            config.screen_height as f32,
+            dimensions.width as f32,
+            dimensions.height as f32,
             0.0,
             0.1,
             100.0,
         ));
         let stats = Box::new(Stats::new());
-        let lock_fps = config.lock_fps;
-
-        unsafe {
-            let mut resources = &mut *window.window_resources;
-            resources.input = &mut *input;
-            resources.renderer = &mut *renderer;
-
-            System {
-                window,
-                audio,
-                input,
-                resources,
-                renderer,
-                rng,
-                cam,
-                stats,
-                lock_fps,
-            }
+
+        System {
+            platform,
+            renderer,
+            audio,
+            input,
+            particle,
+            emitters: BTreeMap::new(),
+            next_emitter_id: 0,
+            particle_effects: HashMap::new(),
+            effect_reload_timer: Duration::ZERO,
+            rng,
+            cam,
+            stats,
+            sprite_regions: HashMap::new(),
+            fonts: HashMap::new(),
+            running: true,
+            show_cursor: false,
+            hide_cursor: false,
+            lock_fps: config.lock_fps,
+            fixed_timestep,
+            accumulator: Duration::ZERO,
+            recorder: None,
+            replay: None,
         }
     }
 
@@ -331,19 +556,111 @@ impl System {
     ///Load all supported textures in provided directory, keys to textures are their file stem names
     ///without the extension.
     pub fn load_texture_dir(&mut self, path: &str) {
-        self.renderer
-            .texture
-            .load_directory(&self.renderer.gl, path);
+        self.renderer.load_texture_dir(path);
     }
 
     ///Load single texture from path, key to texture is the file stem name without extension.
     pub fn load_texture(&mut self, texture_path: &str) -> GLuint {
-        self.renderer.texture.load(&self.renderer.gl, texture_path)
+        self.renderer.load_texture(texture_path)
+    }
+
+    ///Upload an image decoded or generated on the CPU as a texture, key to texture is name.
+    pub fn upload_texture(&mut self, name: &str, image: &Image) -> GLuint {
+        self.renderer.upload_texture(name, image)
     }
 
     ///Get texture id using texture key.
     pub fn get_texture(&self, tname: &str) -> GLuint {
-        self.renderer.texture.get(tname)
+        self.renderer.get_texture(tname)
+    }
+
+    ///Upload the pages of an atlas as textures keyed name_0, name_1 and so on. Every packed image
+    ///becomes a sprite region keyed by its name in the atlas.
+    pub fn upload_atlas(&mut self, name: &str, atlas: &Atlas) {
+        let page_ids: Vec<GLuint> = atlas
+            .pages
+            .iter()
+            .enumerate()
+            .map(|(i, page)| self.renderer.upload_texture(&format!("{name}_{i}"), page))
+            .collect();
+
+        for (region_name, region) in &atlas.regions {
+            self.sprite_regions.insert(
+                region_name.clone(),
+                SpriteRegion {
+                    texture: page_ids[region.page],
+                    uv_rect: region.uv_rect,
+                },
+            );
+        }
+    }
+
+    ///Pack all supported textures in provided directory into page_size by page_size atlas pages
+    ///and upload them with upload_atlas(). Keys to sprite regions are the file stem names.
+    pub fn load_atlas_dir(&mut self, name: &str, path: &str, page_size: u32) {
+        let Ok(entries) = std::fs::read_dir(path) else {
+            eprintln!("Couldn't find texture directory: '{path}'");
+            return;
+        };
+
+        let mut builder = AtlasBuilder::new(page_size, page_size, ATLAS_PADDING, ATLAS_EXTRUDE);
+        for entry in entries {
+            let entry = entry.unwrap();
+            if let Some((image_name, image)) = read_texture(entry.path().to_str().unwrap()) {
+                builder.add(&image_name, image);
+            }
+        }
+
+        match builder.build() {
+            Ok(atlas) => self.upload_atlas(name, &atlas),
+            Err(error) => eprintln!("Building atlas '{name}' failed: {error}"),
+        }
+    }
+
+    ///Get sprite region using the key of an image packed with upload_atlas() or load_atlas_dir().
+    pub fn get_sprite_region(&self, name: &str) -> SpriteRegion {
+        if let Some(region) = self.sprite_regions.get(name) {
+            *region
+        } else {
+            eprintln!("get_sprite_region failed: '{name}' does not exist");
+            SpriteRegion::full(0)
+        }
+    }
+
+    ///Load a JSON sprite sheet exported by Aseprite or TexturePacker together with the image
+    ///named in meta.image, relative to the JSON file. Errors are reported and None is returned.
+    pub fn load_sprite_sheet(&mut self, path: &str) -> Option<SpriteSheet> {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(error) => {
+                eprintln!("Loading sprite sheet '{path}' failed: {error}");
+                return None;
+            }
+        };
+
+        let image = json::Json::parse(&json).and_then(|json| {
+            json.get("meta")
+                .and_then(|meta| meta.get("image"))
+                .and_then(|image| image.as_str().map(String::from))
+        });
+        let Some(image) = image else {
+            eprintln!("Loading sprite sheet '{path}' failed: Missing meta.image!");
+            return None;
+        };
+
+        let image_path = std::path::Path::new(path).with_file_name(image);
+        let texture = self.load_texture(image_path.to_str().unwrap());
+        if texture == 0 {
+            return None;
+        }
+
+        match SpriteSheet::from_json(SpriteRegion::full(texture), &json) {
+            Ok(sheet) => Some(sheet),
+            Err(error) => {
+                eprintln!("Loading sprite sheet '{path}' failed: {error}");
+                None
+            }
+        }
     }
 
     ///////////////////////////////////////////////////////////////////////////////////////////////////
@@ -353,7 +670,7 @@ impl System {
     ///Load all supported audio files in provided directory, keys to audio files are their file stem
     ///names without extension.
     pub fn load_audio_dir(&mut self, path: &str) {
-        self.audio.load_assets(path);
+        self.audio.load_audio_dir(path);
     }
 
     ///Load single audio file from path, key to audio file is the file stem name without extension.
@@ -363,13 +680,59 @@ impl System {
 
     ///Play audio file using its key. File will be played as a one shot sample.
     pub fn play_one_shot(&mut self, audio: &str, level: f32) {
-        self.audio.one_shot(audio, level);
+        self.audio.play_one_shot(audio, level);
+    }
+
+    ///Play audio file using its key once and get a handle to control it while it plays. When
+    ///every one shot channel is busy the oldest sound of the lowest priority is stopped to make
+    ///room, sounds of a higher priority than this one are never stopped for it. Sounds played with
+    ///play_one_shot() have priority 0.
+    pub fn play(&mut self, audio: &str, level: f32, priority: u8) -> SoundHandle {
+        self.audio.play(audio, level, priority)
+    }
+
+    ///Stop sound, does nothing once it has finished like the rest of the sound handle functions.
+    pub fn stop_sound(&mut self, handle: SoundHandle) {
+        self.audio.stop_sound(handle);
+    }
+
+    ///Pause sound, it keeps its channel until it's resumed or stopped.
+    pub fn pause_sound(&mut self, handle: SoundHandle) {
+        self.audio.pause_sound(handle);
+    }
+
+    pub fn resume_sound(&mut self, handle: SoundHandle) {
+        self.audio.resume_sound(handle);
+    }
+
+    pub fn set_sound_volume(&mut self, handle: SoundHandle, level: f32) {
+        self.audio.set_sound_volume(handle, level);
+    }
+
+    ///Set frequency ratio of sound, 2 plays it an octave higher and twice as fast.
+    pub fn set_sound_pitch(&mut self, handle: SoundHandle, pitch: f32) {
+        self.audio.set_sound_pitch(handle, pitch);
+    }
+
+    ///Pan sound from -1 for left to 1 for right.
+    pub fn set_sound_pan(&mut self, handle: SoundHandle, pan: f32) {
+        self.audio.set_sound_pan(handle, pan);
+    }
+
+    ///False once sound has finished, was stopped or had its channel taken, and while it's paused.
+    pub fn is_sound_playing(&self, handle: SoundHandle) -> bool {
+        self.audio.is_sound_playing(handle)
+    }
+
+    ///Position in sound, None once it has finished.
+    pub fn sound_position(&self, handle: SoundHandle) -> Option<Duration> {
+        self.audio.sound_position(handle)
     }
 
     ///Play audio file using its key. File will be played as a looping sample on selected channel
     ///until stop_loop() is called on that channel.
     pub fn play_loop(&mut self, audio: &str, channel: u8, level: f32) {
-        self.audio.submit_loop(audio, channel, level);
+        self.audio.play_loop(audio, channel, level);
     }
 
     ///Stop loop on selected channel.
@@ -377,6 +740,39 @@ impl System {
         self.audio.stop_loop(channel);
     }
 
+    ///Open audio file from path for streaming, key to the stream is the file stem name without
+    ///extension. Streams decode a piece at a time while playing, use them for long music tracks.
+    pub fn load_stream(&mut self, path: &str) {
+        self.audio.load_stream(path);
+    }
+
+    ///Play stream using its key from its current position. Looping streams loop between their
+    ///loop points without a gap, other streams stop and rewind at their end.
+    pub fn play_stream(&mut self, stream: &str, level: f32, looping: bool) {
+        self.audio.play_stream(stream, level, looping);
+    }
+
+    ///Pause stream, playing it again continues where it was paused.
+    pub fn pause_stream(&mut self, stream: &str) {
+        self.audio.pause_stream(stream);
+    }
+
+    ///Stop stream and rewind it to the beginning.
+    pub fn stop_stream(&mut self, stream: &str) {
+        self.audio.stop_stream(stream);
+    }
+
+    ///Move stream to position, a playing stream keeps playing from there.
+    pub fn seek_stream(&mut self, stream: &str, position: Duration) {
+        self.audio.seek_stream(stream, position);
+    }
+
+    ///Loop stream from end back to start, end None loops at the end of the stream. Lets music
+    ///with an intro loop only its main part.
+    pub fn set_stream_loop_points(&mut self, stream: &str, start: Duration, end: Option<Duration>) {
+        self.audio.set_stream_loop_points(stream, start, end);
+    }
+
     ///Set master volume of audio system.
     pub fn set_master_volume(&mut self, level: f32) {
         self.audio.set_master_volume(level);
@@ -395,16 +791,14 @@ impl System {
     pub fn rect(&mut self, x: f32, y: f32, z: f32, w: f32, h: f32, rot: f32, color: Vector) {
         let (proj, view) = self.cam.get_proj_view();
         self.renderer
-            .rect
-            .draw_rect(x, y, z, w, h, rot, color, proj, view, &self.renderer.gl)
+            .draw_rect(x, y, z, w, h, rot, color, proj, view);
     }
 
     ///Draw line primitive between points (x1,y1) and (x2,y2) with a given thickness of w.
     pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, z: f32, w: f32, color: Vector) {
         let (proj, view) = self.cam.get_proj_view();
         self.renderer
-            .rect
-            .draw_line(x1, y1, x2, y2, z, w, color, proj, view, &self.renderer.gl)
+            .draw_line(x1, y1, x2, y2, z, w, color, proj, view);
     }
 
     ///Draw textured sprite. Use get_texture() to assign appropriate texture value.
@@ -420,13 +814,67 @@ impl System {
         color: Vector,
     ) {
         let (proj, view) = self.cam.get_proj_view();
-        self.renderer
-            .sprite
-            .add_sprite(x, y, z, w, h, rot, texture_id, color, proj, view);
+        self.renderer.add_sprite(
+            x,
+            y,
+            z,
+            w,
+            h,
+            rot,
+            texture_id,
+            FULL_UV_RECT,
+            color,
+            proj,
+            view,
+        );
+    }
+
+    ///Draw part of a texture as a sprite. Use get_sprite_region() to look up regions packed into
+    ///an atlas, sprites sharing an atlas page share a texture.
+    pub fn sprite_region(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        region: SpriteRegion,
+        color: Vector,
+    ) {
+        let (proj, view) = self.cam.get_proj_view();
+        self.renderer.add_sprite(
+            x,
+            y,
+            z,
+            w,
+            h,
+            rot,
+            region.texture,
+            region.uv_rect,
+            color,
+            proj,
+            view,
+        );
+    }
+
+    ///Draw the current frame of an animation as a sprite.
+    pub fn animation(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        animation: &Animation,
+        color: Vector,
+    ) {
+        self.sprite_region(x, y, z, w, h, rot, animation.region(), color);
     }
 
     fn draw_sprites(&mut self) {
-        self.renderer.sprite.draw_sprites(&self.renderer.gl);
+        self.renderer.draw_sprites();
     }
 
     ///////////////////////////////////////////////////////////////////////////////////////////////////
@@ -434,22 +882,153 @@ impl System {
     ///////////////////////////////////////////////////////////////////////////////////////////////////
 
     ///Draw text string at coordinate. A font must be defined first! Pass defined font name to
-    ///font parameter.
+    ///font parameter. Fonts loaded with load_bitmap_font() are drawn with bitmap_text() at depth
+    ///0 and their own size.
     pub fn text(&mut self, text: &str, x: f32, y: f32, color: Vector, font: &str) {
+        if self.fonts.contains_key(font) {
+            self.bitmap_text(text, x, y, 0.0, 1.0, color, font);
+            return;
+        }
+
         let ndc_x = (x / self.screen_width()) * 2.0 - 1.0;
         let ndc_y = (y / self.screen_height()) * 2.0 - 1.0;
-        self.renderer.text.draw_text(
+        self.renderer.draw_text(
             text,
             ndc_x,
             -ndc_y,
             (color.x, color.y, color.z, color.w),
             font,
-            &self.renderer.gl,
         );
     }
 
+    ///Draw text with a font loaded by load_bitmap_font() as sprites through the camera. (x, y) is
+    ///the top left corner of the first line, lines are broken at '\n' and glyphs are scaled by
+    ///scale.
+    pub fn bitmap_text(
+        &mut self,
+        text: &str,
+        x: f32,
+        y: f32,
+        z: f32,
+        scale: f32,
+        color: Vector,
+        font: &str,
+    ) {
+        let style = TextStyle {
+            scale,
+            ..Default::default()
+        };
+        self.styled_text(text, x, y, z, &style, color, font);
+    }
+
+    ///Draw text with a font loaded by load_bitmap_font() or defined from a .ttf file, wrapped and
+    ///aligned as described by style. (x, y) is the top left corner of the text block.
+    pub fn styled_text(
+        &mut self,
+        text: &str,
+        x: f32,
+        y: f32,
+        z: f32,
+        style: &TextStyle,
+        color: Vector,
+        font: &str,
+    ) {
+        let Some(font) = self.fonts.get(font) else {
+            eprintln!("Invalid font: {font}");
+            return;
+        };
+
+        let (proj, view) = self.cam.get_proj_view();
+        for glyph in font.font.layout_text(text, style) {
+            self.renderer.add_sprite(
+                x + glyph.x,
+                y + glyph.y,
+                z,
+                glyph.width,
+                glyph.height,
+                0.0,
+                font.pages[glyph.page],
+                glyph.uv_rect,
+                color,
+                proj,
+                view,
+            );
+        }
+    }
+
+    ///Width and height of text drawn with styled_text(). Only fonts drawn as sprites, loaded with
+    ///load_bitmap_font() or defined from a .ttf file, can be measured.
+    pub fn measure_text(&self, text: &str, style: &TextStyle, font: &str) -> (f32, f32) {
+        match self.fonts.get(font) {
+            Some(font) => font.font.measure_text(text, style),
+            None => {
+                eprintln!("Invalid font: {font}");
+                (0.0, 0.0)
+            }
+        }
+    }
+
+    ///Load an AngelCode BMFont .fnt file in the text or binary format together with its page
+    ///images, relative to the .fnt file. Key to the font is the file stem name without extension.
+    pub fn load_bitmap_font(&mut self, path: &str) {
+        let Some(name) = std::path::Path::new(path)
+            .file_stem()
+            .and_then(|name| name.to_str())
+        else {
+            eprintln!("Loading font '{path}' failed: Couldn't resolve font name!");
+            return;
+        };
+
+        let font = match BitmapFont::load(path) {
+            Ok(font) => font,
+            Err(error) => {
+                eprintln!("Loading font '{path}' failed: {error}");
+                return;
+            }
+        };
+
+        let mut pages = Vec::with_capacity(font.pages.len());
+        for page in &font.pages {
+            let page_path = std::path::Path::new(path).with_file_name(page);
+            match Image::load(page_path.to_str().unwrap()) {
+                Ok(image) => pages.push(image),
+                Err(error) => {
+                    eprintln!(
+                        "Loading font page '{}' failed: {error}",
+                        page_path.display()
+                    );
+                    return;
+                }
+            }
+        }
+        self.upload_bitmap_font(name, font, &pages);
+    }
+
+    ///Upload the page images of a font as textures keyed name_0, name_1 and so on, and make the
+    ///font available to text() and bitmap_text() under name.
+    pub fn upload_bitmap_font(&mut self, name: &str, font: BitmapFont, pages: &[Image]) {
+        if pages.len() < font.pages.len() {
+            eprintln!(
+                "Uploading font '{name}' failed: {} pages expected, {} given!",
+                font.pages.len(),
+                pages.len()
+            );
+            return;
+        }
+
+        let pages = pages
+            .iter()
+            .enumerate()
+            .map(|(i, page)| self.renderer.upload_texture(&format!("{name}_{i}"), page))
+            .collect();
+        self.fonts
+            .insert(String::from(name), FontTextures { font, pages });
+    }
+
     ///Define a font to be draw with text() later. Will find closest match if system can't find
-    ///font that matches exact specification.
+    ///font that matches exact specification. If font is the path of a .ttf file its glyphs are
+    ///rasterized size pixels high into an atlas and drawn like a bitmap font, weight, italic,
+    ///underline and strikeout are ignored.
     pub fn define_font(
         &mut self,
         name: &str,
@@ -460,6 +1039,11 @@ impl System {
         strikeout: bool,
         font: &str,
     ) {
+        if font.to_ascii_lowercase().ends_with(".ttf") {
+            self.define_truetype_font(name, size.unsigned_abs() as f32, font);
+            return;
+        }
+
         let weight_value = match weight {
             Weight::DONTCARE => 0,
             Weight::THIN => 100,
@@ -472,39 +1056,225 @@ impl System {
             Weight::EXTRABOLD => 800,
             Weight::HEAVY => 900,
         };
-        self.renderer.text.define_font(
-            name,
-            size,
-            weight_value,
-            italic,
-            underline,
-            strikeout,
-            font,
-            self.window.device_context,
-        );
+        self.renderer
+            .define_font(name, size, weight_value, italic, underline, strikeout, font);
+    }
+
+    fn define_truetype_font(&mut self, name: &str, pixel_height: f32, path: &str) {
+        let rasterized = TrueTypeFont::load(path)
+            .and_then(|font| font.rasterize(pixel_height, truetype::default_characters()));
+        match rasterized {
+            Ok((font, pages)) => self.upload_bitmap_font(name, font, &pages),
+            Err(error) => eprintln!("Loading font '{path}' failed: {error}"),
+        }
     }
 
     ///////////////////////////////////////////////////////////////////////////////////////////////////
     // Particles
     ///////////////////////////////////////////////////////////////////////////////////////////////////
 
-    ///After defining your ParticleProperties struct call to render your particle.
+    ///After defining your ParticleProperties struct call to emit one particle into the shared
+    ///pool of SystemConf::particle_pool_size particles. rate, burst and max_particles are ignored.
+    ///When the pool is full the particle is dropped, live particles are never replaced.
     pub fn particle_emit(&mut self, properties: &ParticleProperties) {
-        self.renderer.particle.emit(properties, &mut self.rng);
+        self.particle.emit_with(properties, &mut self.rng);
+    }
+
+    ///Advance an emitter you own by dt, spawning its burst and rate particles into its own pool.
+    ///Call it from update().
+    pub fn update_emitter(&mut self, emitter: &mut ParticleEmitter, dt: Duration) {
+        emitter.update(dt, &mut self.rng);
+    }
+
+    ///Draw the live particles of an emitter you own. Call it from draw().
+    pub fn draw_emitter(&mut self, emitter: &ParticleEmitter) {
+        let (proj, view) = self.cam.get_proj_view();
+        self.renderer
+            .draw_particles(emitter.particles(), proj, view);
+    }
+
+    ///Create an emitter that is updated and drawn every frame until it's removed, or until it's
+    ///stopped and its last particle dies.
+    pub fn create_emitter(&mut self, properties: ParticleProperties) -> EmitterId {
+        let id = EmitterId::new(self.next_emitter_id);
+        self.next_emitter_id += 1;
+        self.emitters.insert(id, ParticleEmitter::new(properties));
+        id
+    }
+
+    pub fn move_emitter(&mut self, id: EmitterId, pos: Vector) {
+        if let Some(emitter) = self.emitter_or_log(id) {
+            emitter.set_position(pos);
+        }
+    }
+
+    ///Freeze an emitter and its particles, they are still drawn.
+    pub fn pause_emitter(&mut self, id: EmitterId) {
+        if let Some(emitter) = self.emitter_or_log(id) {
+            emitter.pause();
+        }
+    }
+
+    pub fn resume_emitter(&mut self, id: EmitterId) {
+        if let Some(emitter) = self.emitter_or_log(id) {
+            emitter.play();
+        }
+    }
+
+    ///Stop spawning particles. The emitter is removed once its live particles have died.
+    pub fn stop_emitter(&mut self, id: EmitterId) {
+        if let Some(emitter) = self.emitter_or_log(id) {
+            emitter.stop();
+        }
+    }
+
+    ///Remove an emitter and its particles right away.
+    pub fn remove_emitter(&mut self, id: EmitterId) {
+        self.emitters.remove(&id);
+    }
+
+    ///Spawn count particles at once, even if the emitter is paused or stopped.
+    pub fn emitter_burst(&mut self, id: EmitterId, count: usize) {
+        if let Some(emitter) = self.emitters.get_mut(&id) {
+            emitter.emit(count, &mut self.rng);
+        } else {
+            eprintln!("Invalid emitter: {id:?}");
+        }
+    }
+
+    ///Number of live particles of an emitter, 0 once it has been removed.
+    pub fn emitter_particle_count(&self, id: EmitterId) -> usize {
+        self.emitters
+            .get(&id)
+            .map_or(0, |emitter| emitter.particles().len())
+    }
+
+    ///None once the emitter has been removed.
+    pub fn get_emitter(&self, id: EmitterId) -> Option<&ParticleEmitter> {
+        self.emitters.get(&id)
+    }
+
+    ///Change the properties of a live emitter, None once it has been removed.
+    pub fn get_emitter_mut(&mut self, id: EmitterId) -> Option<&mut ParticleEmitter> {
+        self.emitters.get_mut(&id)
+    }
+
+    ///Load all particle effects with the .particle extension in provided directory, keys to
+    ///effects are their file stem names without extension.
+    pub fn load_particle_effect_dir(&mut self, path: &str) {
+        let Ok(entries) = std::fs::read_dir(path) else {
+            eprintln!("Couldn't find particle effect directory: '{path}'");
+            return;
+        };
+
+        for entry in entries {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "particle")
+            {
+                self.load_particle_effect(path.to_str().unwrap());
+            }
+        }
+    }
+
+    ///Load a particle effect written in the format described by ParticleProperties::from_text(),
+    ///key to the effect is the file stem name without extension. The file is reloaded whenever
+    ///it changes on disk, emitters created with create_effect_emitter() pick up the changes.
+    pub fn load_particle_effect(&mut self, path: &str) {
+        let Some(name) = std::path::Path::new(path)
+            .file_stem()
+            .and_then(|name| name.to_str())
+        else {
+            eprintln!("Loading particle effect '{path}' failed: Couldn't resolve effect name!");
+            return;
+        };
+
+        let renderer = &self.renderer;
+        if let Some(effect) = ParticleEffect::load(path, |texture| renderer.get_texture(texture)) {
+            self.particle_effects.insert(String::from(name), effect);
+        }
+    }
+
+    ///Get the properties of a loaded particle effect using its key, to use with particle_emit()
+    ///or create_emitter().
+    pub fn get_particle_effect(&self, name: &str) -> ParticleProperties {
+        if let Some(effect) = self.particle_effects.get(name) {
+            effect.properties.clone()
+        } else {
+            eprintln!("get_particle_effect failed: '{name}' does not exist");
+            ParticleProperties::default()
+        }
+    }
+
+    ///Create an emitter at pos from a loaded particle effect. Its properties are replaced when the
+    ///effect file is reloaded, pos is kept.
+    pub fn create_effect_emitter(&mut self, name: &str, pos: Vector) -> EmitterId {
+        let properties = ParticleProperties {
+            pos,
+            ..self.get_particle_effect(name)
+        };
+        let id = self.create_emitter(properties);
+        if let Some(emitter) = self.emitters.get_mut(&id) {
+            emitter.effect = Some(String::from(name));
+        }
+        id
+    }
+
+    ///Check the loaded particle effects for changes every EFFECT_RELOAD_INTERVAL.
+    fn reload_particle_effects(&mut self, dt: Duration) {
+        self.effect_reload_timer += dt;
+        if self.effect_reload_timer < EFFECT_RELOAD_INTERVAL {
+            return;
+        }
+        self.effect_reload_timer = Duration::ZERO;
+
+        let renderer = &self.renderer;
+        for (name, effect) in &mut self.particle_effects {
+            if !effect.reload(|texture| renderer.get_texture(texture)) {
+                continue;
+            }
+
+            for emitter in self.emitters.values_mut() {
+                if emitter.effect.as_ref() == Some(name) {
+                    emitter.properties = ParticleProperties {
+                        pos: emitter.properties.pos,
+                        ..effect.properties.clone()
+                    };
+                }
+            }
+        }
+    }
+
+    fn emitter_or_log(&mut self, id: EmitterId) -> Option<&mut ParticleEmitter> {
+        let emitter = self.emitters.get_mut(&id);
+        if emitter.is_none() {
+            eprintln!("Invalid emitter: {id:?}");
+        }
+        emitter
     }
 
     fn particle_update(&mut self, dt: Duration) {
-        self.renderer.particle.update(dt);
+        self.particle.update(dt, &mut self.rng);
+        for emitter in self.emitters.values_mut() {
+            emitter.update(dt, &mut self.rng);
+        }
+        self.emitters.retain(|_, emitter| !emitter.is_finished());
     }
 
     fn particle_draw(&mut self) {
         let (proj, view) = self.cam.get_proj_view();
 
-        self.renderer.particle.draw(proj, view, &self.renderer.gl);
+        self.renderer
+            .draw_particles(self.particle.particles(), proj, view);
+        for emitter in self.emitters.values() {
+            self.renderer
+                .draw_particles(emitter.particles(), proj, view);
+        }
     }
 
     fn particle_active(&self) -> bool {
-        self.renderer.particle.active
+        !self.particle.particles().is_empty() || !self.emitters.is_empty()
     }
 
     ///////////////////////////////////////////////////////////////////////////////////////////////////
@@ -657,35 +1427,116 @@ impl System {
     // System Helpers
     ///////////////////////////////////////////////////////////////////////////////////////////////////
 
+    ///Capture what has been drawn so far this frame. Returns an empty image if the renderer can't
+    ///read back its framebuffer.
+    pub fn capture_frame(&self) -> Image {
+        self.renderer.capture_frame().unwrap_or_else(|| {
+            eprintln!("capture_frame failed: Renderer can't read back its framebuffer");
+            Image::new(0, 0, Vec::new())
+        })
+    }
+
+    ///Take draw calls recorded since the last call. Always empty unless the system is headless.
+    pub fn take_draw_calls(&mut self) -> Vec<DrawCall> {
+        self.renderer.take_draw_calls()
+    }
+
+    ///Take audio calls recorded since the last call. Always empty unless the system is headless.
+    pub fn take_audio_calls(&mut self) -> Vec<AudioCall> {
+        self.audio.take_audio_calls()
+    }
+
+    ///Start recording input and frame times. Restarts the random number sequence from the current
+    ///seed so a replay sees the same numbers. Call it in init to record the whole game.
+    pub fn start_recording(&mut self) {
+        let seed = self.rng.seed();
+        self.rng.reseed(seed);
+        self.recorder = Some(InputRecorder::new(seed));
+    }
+
+    ///Stop recording and write everything recorded since start_recording() to path.
+    pub fn stop_recording(&mut self, path: &str) -> std::io::Result<()> {
+        let Some(recorder) = self.recorder.take() else {
+            return Err(std::io::Error::other(format!(
+                "Saving recording '{path}' failed: Not recording!"
+            )));
+        };
+
+        recorder.save(path)
+    }
+
+    ///Play back a recording made with start_recording(). Input from devices is ignored and update
+    ///gets the recorded dt until the recording runs out. Call it in init so the game starts from
+    ///the same state it was recorded from.
+    pub fn start_replay(&mut self, path: &str) -> bool {
+        let Some(replay) = InputReplay::load(path) else {
+            return false;
+        };
+
+        self.rng.reseed(replay.seed());
+        self.replay = Some(replay);
+        true
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    fn poll_input(&mut self, dt: Duration) -> Duration {
+        if let Some(replay) = self.replay.as_mut() {
+            if let Some((recorded_dt, snapshot)) = replay.next_frame() {
+                self.input.apply_snapshot(snapshot);
+                return recorded_dt;
+            }
+            self.replay = None;
+        }
+
+        self.platform.poll_input(&mut self.input);
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(dt, &self.input.snapshot());
+        }
+        dt
+    }
+
     ///End system event loop.
     pub fn quit(&mut self) {
-        self.resources.running = false;
+        self.running = false;
+    }
+
+    ///How far between the last and the next fixed update the current frame is, from 0.0 to 1.0.
+    ///Use it in draw to interpolate between previous and current state. Always 1.0 without a fixed
+    ///timestep.
+    pub fn interpolation_alpha(&self) -> f32 {
+        match self.fixed_timestep {
+            Some(step) => (self.accumulator.as_secs_f64() / step.as_secs_f64()) as f32,
+            None => 1.0,
+        }
     }
 
     pub fn screen_width(&self) -> f32 {
-        self.window.dimensions.width as f32
+        self.platform.dimensions().width as f32
     }
 
     pub fn screen_height(&self) -> f32 {
-        self.window.dimensions.height as f32
+        self.platform.dimensions().height as f32
     }
 
     pub fn show_cursor(&mut self) {
-        self.resources.show_cursor = true;
+        self.show_cursor = true;
     }
 
     pub fn hide_cursor(&mut self) {
-        self.resources.hide_cursor = true;
+        self.hide_cursor = true;
     }
 
     fn set_cursor_state(&mut self) {
-        if self.resources.show_cursor {
-            unsafe { while ShowCursor(1) < 0 {} }
-            self.resources.show_cursor = false;
+        if self.show_cursor {
+            self.platform.set_cursor_visible(true);
+            self.show_cursor = false;
         }
-        if self.resources.hide_cursor {
-            unsafe { while ShowCursor(0) >= 0 {} }
-            self.resources.hide_cursor = false;
+        if self.hide_cursor {
+            self.platform.set_cursor_visible(false);
+            self.hide_cursor = false;
         }
     }
 
@@ -729,6 +1580,11 @@ impl System {
     pub fn get_particle_time(&self) -> usize {
         self.stats.get_particle_time()
     }
+
+    ///Sprite, batch and draw call counts of the last frame.
+    pub fn get_batch_stats(&self) -> BatchStats {
+        self.stats.get_batch_stats()
+    }
 }
 
 pub trait EventFunctions {
@@ -741,50 +1597,164 @@ pub struct Event {}
 
 impl Event {
     pub fn run(mut game: impl EventFunctions, mut system: System) {
-        unsafe {
-            let mut message = MSG::default();
-            let mut ticks = Instant::now();
-            let frametime = if system.lock_fps { 16u64 } else { 0u64 };
+        let mut ticks = Instant::now();
+        let frametime = if system.lock_fps { 16u64 } else { 0u64 };
 
-            game.init(&mut system);
+        game.init(&mut system);
 
-            while system.resources.running {
-                while PeekMessageA(&mut message, 0, 0, 0, PM_REMOVE) > 0 {
-                    if message.message == WM_QUIT {
-                        system.resources.running = false;
-                    }
+        while system.running {
+            if !system.platform.pump_events(&mut system.input) {
+                system.running = false;
+            }
 
-                    TranslateMessage(&message);
-                    DispatchMessageA(&message);
-                }
+            while (Instant::now() - ticks) < Duration::from_millis(frametime) {}
+            let dt = Instant::now() - ticks;
+            ticks = Instant::now();
+
+            Event::frame(&mut game, &mut system, dt);
+        }
+    }
+
+    ///Run init and then at most the given number of frames, passing fixed_dt to update instead of
+    ///the measured frame time. Stops early if quit() is called. Meant for headless systems.
+    pub fn run_frames(
+        game: &mut impl EventFunctions,
+        system: &mut System,
+        frames: usize,
+        fixed_dt: Duration,
+    ) {
+        game.init(system);
+
+        for _ in 0..frames {
+            if !system.running {
+                break;
+            }
+            if !system.platform.pump_events(&mut system.input) {
+                system.running = false;
+            }
 
-                while (Instant::now() - ticks) < Duration::from_millis(frametime) {}
-                let dt = Instant::now() - ticks;
-                ticks = Instant::now();
+            Event::frame(game, system, fixed_dt);
+        }
+    }
 
-                system.stats.calculate_frametime(dt);
+    fn frame(game: &mut impl EventFunctions, system: &mut System, dt: Duration) {
+        let dt = system.poll_input(dt);
+        system.stats.calculate_frametime(dt);
+        system.reload_particle_effects(dt);
 
-                let update_time = Instant::now();
-                system.set_cursor_state();
-                system.input.poll(system.window.window_handle);
-                game.update(&mut system, dt);
-                system.stats.update_time(update_time);
+        let update_time = Instant::now();
+        system.set_cursor_state();
+        let updated = match system.fixed_timestep {
+            Some(step) => Event::fixed_update(game, system, dt, step),
+            None => {
+                game.update(system, dt);
+                true
+            }
+        };
+        system.stats.update_time(update_time);
 
-                let draw_time = Instant::now();
-                game.draw(&mut system);
-                system.draw_sprites();
-                system.stats.draw_time(draw_time);
+        let draw_time = Instant::now();
+        game.draw(system);
+        system.draw_sprites();
+        system.stats.draw_time(draw_time);
 
-                let particle_time = Instant::now();
-                if system.particle_active() {
-                    system.particle_update(dt);
-                    system.particle_draw();
-                }
-                system.stats.particle_time(particle_time);
+        let particle_time = Instant::now();
+        if system.particle_active() {
+            if system.fixed_timestep.is_none() {
+                system.particle_update(dt);
+            }
+            system.particle_draw();
+        }
+        system.stats.particle_time(particle_time);
+        system.stats.batch_stats(system.renderer.take_batch_stats());
+        system.audio.update(dt);
 
+        //Keep input edges until an update has seen them.
+        if updated {
+            system.input.advance();
+        }
+        system.platform.present();
+    }
+
+    ///Run as many fixed steps as have accumulated, returns false if no update ran this frame.
+    fn fixed_update(
+        game: &mut impl EventFunctions,
+        system: &mut System,
+        dt: Duration,
+        step: Duration,
+    ) -> bool {
+        system.accumulator = (system.accumulator + dt).min(step * MAX_FIXED_UPDATES);
+
+        let mut updated = false;
+        while system.accumulator >= step {
+            //Only the first update of a frame sees pressed and released edges.
+            if updated {
                 system.input.advance();
-                system.renderer.display_frame();
             }
+
+            game.update(system, step);
+            if system.particle_active() {
+                system.particle_update(step);
+            }
+
+            system.accumulator -= step;
+            updated = true;
+        }
+
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Counts updates and the time they were given.
+    #[derive(Default)]
+    struct Counter {
+        updates: u32,
+        elapsed: Duration,
+    }
+
+    impl EventFunctions for Counter {
+        fn init(&mut self, _system: &mut System) {}
+
+        fn update(&mut self, _system: &mut System, dt: Duration) {
+            self.updates += 1;
+            self.elapsed += dt;
         }
+
+        fn draw(&mut self, _system: &mut System) {}
+    }
+
+    fn headless(fixed_timestep: Option<Duration>) -> System {
+        System::new_headless(SystemConf {
+            rng_seed: 1,
+            fixed_timestep,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn fixed_timestep_catches_up_with_frame_time() {
+        let mut system = headless(Some(Duration::from_millis(10)));
+        let mut game = Counter::default();
+        Event::run_frames(&mut game, &mut system, 3, Duration::from_millis(25));
+
+        //75ms of frames run 7 steps of 10ms, the last 5ms wait for the next frame.
+        assert_eq!(game.updates, 7);
+        assert_eq!(game.elapsed, Duration::from_millis(70));
+        assert!((system.interpolation_alpha() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn zero_fixed_timestep_updates_once_per_frame() {
+        let mut system = headless(Some(Duration::ZERO));
+        let mut game = Counter::default();
+        Event::run_frames(&mut game, &mut system, 3, Duration::from_millis(16));
+
+        assert_eq!(game.updates, 3);
+        assert_eq!(game.elapsed, Duration::from_millis(48));
+        assert_eq!(system.interpolation_alpha(), 1.0);
     }
 }
@@ -0,0 +1,641 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::stream::{AudioStream, StreamState};
+use crate::vorbis::decode_ogg;
+use crate::wav::decode_wav;
+
+///Sample rate the engine mixes at.
+pub const MIX_SAMPLE_RATE: u32 = 44100;
+
+///Channels of the mixed stream, interleaved left then right.
+pub const MIX_CHANNELS: usize = 2;
+
+///Extensions of the audio formats that can be decoded, compared case insensitively.
+const AUDIO_EXTENSIONS: [&str; 2] = ["wav", "ogg"];
+
+pub fn is_audio_extension(extension: &OsStr) -> bool {
+    AUDIO_EXTENSIONS
+        .iter()
+        .any(|format| extension.eq_ignore_ascii_case(format))
+}
+
+///Reason an audio file couldn't be read or decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioError {
+    Io(std::io::ErrorKind),
+    UnrecognizedFormat,
+    InvalidSignature,
+    UnexpectedEof,
+    ///The file is valid but uses a feature that isn't supported.
+    Unsupported(String),
+    ///The file is corrupt.
+    Malformed(&'static str),
+}
+
+impl Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioError::Io(std::io::ErrorKind::NotFound) => write!(f, "File not found!"),
+            AudioError::Io(kind) => write!(f, "Couldn't read file: {kind}!"),
+            AudioError::UnrecognizedFormat => write!(f, "Unsupported file format!"),
+            AudioError::InvalidSignature => write!(f, "Malformed header data!"),
+            AudioError::UnexpectedEof => write!(f, "Unexpected end of file!"),
+            AudioError::Unsupported(message) => write!(f, "{message}!"),
+            AudioError::Malformed(message) => write!(f, "{message}!"),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+///Load an audio file. Returns the audio name, the file stem without the extension, and the sound.
+///Errors are reported and None is returned.
+pub fn read_sound(path: &str) -> Option<(String, Sound)> {
+    let Some(filename) = std::path::Path::new(&path).file_stem() else {
+        eprintln!("Loading audio '{path}' failed: Couldn't resolve audio name!");
+        return None;
+    };
+
+    match Sound::load(path) {
+        Ok(sound) => Some((String::from(filename.to_str().unwrap()), sound)),
+        Err(error) => {
+            eprintln!("Loading audio '{path}' failed: {error}");
+            None
+        }
+    }
+}
+
+///Decoded audio as interleaved f32 samples between -1 and 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sound {
+    pub sample_rate: u32,
+    ///1 for mono or 2 for stereo.
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl Sound {
+    ///Load a WAV or Ogg Vorbis file.
+    pub fn load(path: &str) -> Result<Sound, AudioError> {
+        let extension = std::path::Path::new(&path).extension().unwrap_or_default();
+
+        if !is_audio_extension(extension) {
+            return Err(AudioError::UnrecognizedFormat);
+        }
+
+        match std::fs::read(path) {
+            Ok(file) => Sound::decode(&file, extension),
+            Err(error) => Err(AudioError::Io(error.kind())),
+        }
+    }
+
+    ///Decode file contents in the format given by a file extension.
+    pub fn decode(file: &[u8], extension: &OsStr) -> Result<Sound, AudioError> {
+        let extension = extension.to_ascii_lowercase();
+
+        match extension.to_str() {
+            Some("wav") => decode_wav(file),
+            Some("ogg") => decode_ogg(file),
+            _ => Err(AudioError::UnrecognizedFormat),
+        }
+    }
+
+    ///Number of samples per channel.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    ///Left and right sample of frame, silence past the end.
+    fn frame(&self, frame: usize) -> (f32, f32) {
+        match self.channels {
+            1 => {
+                let sample = self.samples.get(frame).copied().unwrap_or(0.0);
+                (sample, sample)
+            }
+            channels => {
+                let start = frame * channels as usize;
+                match self.samples.get(start..start + 2) {
+                    Some(samples) => (samples[0], samples[1]),
+                    None => (0.0, 0.0),
+                }
+            }
+        }
+    }
+}
+
+///Frequency ratios a voice can play at, the range XAudio2 allows.
+const MIN_PITCH: f32 = 1.0 / 1024.0;
+const MAX_PITCH: f32 = 1024.0;
+
+///Handle to a sound played by Mixer::play or System::play. Handles stay valid after the sound has
+///finished, they just no longer refer to a playing sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SoundHandle(u64);
+
+///Sound playing on a voice of the Mixer.
+#[derive(Debug, Clone)]
+struct Voice {
+    sound: Rc<Sound>,
+    handle: SoundHandle,
+    ///Voices of lower priority are taken over first when every voice is busy.
+    priority: u8,
+    ///Position in frames of the sound, fractional between frames when resampling.
+    position: f64,
+    volume: f32,
+    pan: f32,
+    ///Frequency ratio, 2 plays an octave higher and twice as fast.
+    pitch: f32,
+    looping: bool,
+    paused: bool,
+}
+
+impl Voice {
+    fn new(sound: Rc<Sound>, handle: SoundHandle, volume: f32, pan: f32, looping: bool) -> Self {
+        Self {
+            sound,
+            handle,
+            priority: 0,
+            position: 0.0,
+            volume,
+            pan: pan.clamp(-1.0, 1.0),
+            pitch: 1.0,
+            looping,
+            paused: false,
+        }
+    }
+
+    ///Add the voice to out, returns false once a one shot has played to the end.
+    fn mix(&mut self, out: &mut [f32], sample_rate: u32) -> bool {
+        let frames = self.sound.frames();
+        if frames == 0 {
+            return false;
+        }
+        if self.paused {
+            return true;
+        }
+
+        let step = self.sound.sample_rate as f64 / sample_rate as f64 * self.pitch as f64;
+        //Balance rather than constant power, a centered voice plays at its full volume.
+        let left_gain = self.volume * (1.0 - self.pan).min(1.0);
+        let right_gain = self.volume * (1.0 + self.pan).min(1.0);
+
+        for frame in out.chunks_exact_mut(MIX_CHANNELS) {
+            if self.position >= frames as f64 {
+                if !self.looping {
+                    return false;
+                }
+                self.position %= frames as f64;
+            }
+
+            //Linear interpolation between the frames around the position.
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            let next = if self.looping && index + 1 == frames {
+                0
+            } else {
+                index + 1
+            };
+            let (left_a, right_a) = self.sound.frame(index);
+            let (left_b, right_b) = self.sound.frame(next);
+
+            frame[0] += (left_a + (left_b - left_a) * fraction) * left_gain;
+            frame[1] += (right_a + (right_b - right_a) * fraction) * right_gain;
+            self.position += step;
+        }
+        //A one shot that reached its end is done now rather than on the next mix.
+        self.looping || self.position < frames as f64
+    }
+}
+
+///Mixes the playing sounds into one interleaved stereo stream at the output sample rate. Audio
+///backends pull mixed frames with mix() and only need to play them.
+#[derive(Debug)]
+pub struct Mixer {
+    sample_rate: u32,
+    master_volume: f32,
+    sounds: HashMap<String, Rc<Sound>>,
+    one_shots: Vec<Option<Voice>>,
+    loops: Vec<Option<Voice>>,
+    streams: HashMap<String, AudioStream>,
+    next_handle: u64,
+    scratch: Vec<f32>,
+}
+
+impl Mixer {
+    ///Mixer producing sample_rate frames per second, one shots play on one_shot_voices voices and
+    ///loops play on loop_voices channels.
+    pub fn new(sample_rate: u32, one_shot_voices: u8, loop_voices: u8) -> Self {
+        Self {
+            sample_rate,
+            master_volume: 1.0,
+            sounds: HashMap::new(),
+            one_shots: vec![None; one_shot_voices as usize],
+            loops: vec![None; loop_voices as usize],
+            streams: HashMap::new(),
+            next_handle: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn loop_voices(&self) -> u8 {
+        self.loops.len() as u8
+    }
+
+    ///Make sound playable under name, replacing any sound with the same name.
+    pub fn add_sound(&mut self, name: &str, sound: Sound) {
+        self.sounds.insert(String::from(name), Rc::new(sound));
+    }
+
+    pub fn get_sound(&self, name: &str) -> Option<&Sound> {
+        self.sounds.get(name).map(|sound| &**sound)
+    }
+
+    ///Make stream playable under name, replacing any stream with the same name.
+    pub fn add_stream(&mut self, name: &str, stream: AudioStream) {
+        self.streams.insert(String::from(name), stream);
+    }
+
+    pub fn get_stream(&self, name: &str) -> Option<&AudioStream> {
+        self.streams.get(name)
+    }
+
+    pub fn get_stream_mut(&mut self, name: &str) -> Option<&mut AudioStream> {
+        self.streams.get_mut(name)
+    }
+
+    fn stream_or_log(&mut self, name: &str) -> Option<&mut AudioStream> {
+        let stream = self.streams.get_mut(name);
+        if stream.is_none() {
+            eprintln!("Couldn't play: stream '{name}' does not exist");
+        }
+        stream
+    }
+
+    fn sound_or_log(&self, name: &str) -> Option<Rc<Sound>> {
+        let sound = self.sounds.get(name).cloned();
+        if sound.is_none() {
+            eprintln!("Couldn't play: '{name}' does not exist");
+        }
+        sound
+    }
+
+    fn new_handle(&mut self) -> SoundHandle {
+        self.next_handle += 1;
+        SoundHandle(self.next_handle)
+    }
+
+    ///Play sound once at level, pan goes from -1 for left to 1 for right. Plays on a free one shot
+    ///voice, when every voice is busy it takes over the oldest sound of the lowest priority. Sounds
+    ///of a higher priority than the new one are never taken over, if that's all that's playing
+    ///the new sound doesn't play and its handle is never playing.
+    pub fn play(&mut self, sound: &str, level: f32, pan: f32, priority: u8) -> SoundHandle {
+        let handle = self.new_handle();
+        let Some(sound) = self.sound_or_log(sound) else {
+            return handle;
+        };
+
+        //Handles count up, so the lowest one is the oldest sound.
+        let slot = self.one_shots.iter_mut().min_by_key(|slot| match slot {
+            Some(voice) => (true, voice.priority, voice.handle),
+            None => (false, 0, handle),
+        });
+        if let Some(slot) = slot {
+            if slot.as_ref().is_none_or(|voice| voice.priority <= priority) {
+                let mut voice = Voice::new(sound, handle, level, pan, false);
+                voice.priority = priority;
+                *slot = Some(voice);
+            }
+        }
+        handle
+    }
+
+    ///Play sound once at level at the lowest priority, see play().
+    pub fn play_one_shot(&mut self, sound: &str, level: f32, pan: f32) {
+        self.play(sound, level, pan, 0);
+    }
+
+    ///Loop sound on channel at level, replacing whatever the channel was playing.
+    pub fn play_loop(&mut self, sound: &str, channel: u8, level: f32, pan: f32) {
+        if channel as usize >= self.loops.len() {
+            println!("Channel {} does not exist", channel);
+            return;
+        }
+        let Some(sound) = self.sound_or_log(sound) else {
+            return;
+        };
+        let handle = self.new_handle();
+        self.loops[channel as usize] = Some(Voice::new(sound, handle, level, pan, true));
+    }
+
+    pub fn stop_loop(&mut self, channel: u8) {
+        match self.loops.get_mut(channel as usize) {
+            Some(voice) => *voice = None,
+            None => println!("Channel {} does not exist", channel),
+        }
+    }
+
+    fn voice_mut(&mut self, handle: SoundHandle) -> Option<&mut Voice> {
+        let mut voices = self.one_shots.iter_mut().chain(&mut self.loops).flatten();
+        voices.find(|voice| voice.handle == handle)
+    }
+
+    fn voice(&self, handle: SoundHandle) -> Option<&Voice> {
+        let mut voices = self.one_shots.iter().chain(&self.loops).flatten();
+        voices.find(|voice| voice.handle == handle)
+    }
+
+    ///Stop sound and free its voice. Handles of sounds that have finished are ignored, like the
+    ///rest of the handle functions.
+    pub fn stop_sound(&mut self, handle: SoundHandle) {
+        let slots = self.one_shots.iter_mut().chain(&mut self.loops);
+        for slot in slots {
+            if slot.as_ref().is_some_and(|voice| voice.handle == handle) {
+                *slot = None;
+            }
+        }
+    }
+
+    ///Pause sound, it keeps its voice until it's resumed or stopped.
+    pub fn pause_sound(&mut self, handle: SoundHandle) {
+        if let Some(voice) = self.voice_mut(handle) {
+            voice.paused = true;
+        }
+    }
+
+    pub fn resume_sound(&mut self, handle: SoundHandle) {
+        if let Some(voice) = self.voice_mut(handle) {
+            voice.paused = false;
+        }
+    }
+
+    pub fn set_sound_volume(&mut self, handle: SoundHandle, level: f32) {
+        if let Some(voice) = self.voice_mut(handle) {
+            voice.volume = level;
+        }
+    }
+
+    ///Play sound at a frequency ratio of pitch, 2 plays it an octave higher and twice as fast and
+    ///0.5 an octave lower at half the speed.
+    pub fn set_sound_pitch(&mut self, handle: SoundHandle, pitch: f32) {
+        if let Some(voice) = self.voice_mut(handle) {
+            voice.pitch = pitch.clamp(MIN_PITCH, MAX_PITCH);
+        }
+    }
+
+    ///Pan goes from -1 for left to 1 for right.
+    pub fn set_sound_pan(&mut self, handle: SoundHandle, pan: f32) {
+        if let Some(voice) = self.voice_mut(handle) {
+            voice.pan = pan.clamp(-1.0, 1.0);
+        }
+    }
+
+    ///False once the sound has finished, was stopped or taken over, and while it's paused.
+    pub fn is_sound_playing(&self, handle: SoundHandle) -> bool {
+        self.voice(handle).is_some_and(|voice| !voice.paused)
+    }
+
+    ///Position in the sound, None once it has finished.
+    pub fn sound_position(&self, handle: SoundHandle) -> Option<Duration> {
+        let voice = self.voice(handle)?;
+        //Looping voices wrap around when they mix next.
+        let position = match voice.looping {
+            true => voice.position % voice.sound.frames().max(1) as f64,
+            false => voice.position,
+        };
+        let seconds = position / voice.sound.sample_rate as f64;
+        Some(Duration::from_secs_f64(seconds))
+    }
+
+    ///Play stream at level from its current position, looping between its loop points if
+    ///looping.
+    pub fn play_stream(&mut self, stream: &str, level: f32, looping: bool) {
+        if let Some(stream) = self.stream_or_log(stream) {
+            stream.play(level, looping);
+        }
+    }
+
+    pub fn pause_stream(&mut self, stream: &str) {
+        if let Some(stream) = self.stream_or_log(stream) {
+            stream.pause();
+        }
+    }
+
+    ///Stop stream and rewind it to the beginning.
+    pub fn stop_stream(&mut self, stream: &str) {
+        if let Some(stream) = self.stream_or_log(stream) {
+            stream.stop();
+        }
+    }
+
+    pub fn seek_stream(&mut self, stream: &str, position: Duration) {
+        if let Some(stream) = self.stream_or_log(stream) {
+            stream.seek(position);
+        }
+    }
+
+    pub fn set_stream_loop_points(&mut self, stream: &str, start: Duration, end: Option<Duration>) {
+        if let Some(stream) = self.stream_or_log(stream) {
+            stream.set_loop_points(start, end);
+        }
+    }
+
+    pub fn set_master_volume(&mut self, level: f32) {
+        self.master_volume = level;
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    ///Number of voices and streams currently playing, paused ones don't count.
+    pub fn active_voices(&self) -> usize {
+        let voices = self.one_shots.iter().chain(&self.loops).flatten();
+        let voices = voices.filter(|voice| !voice.paused);
+        let streams = self.streams.values();
+        let streams = streams.filter(|stream| stream.state() == StreamState::Playing);
+        voices.count() + streams.count()
+    }
+
+    ///Fill out with interleaved stereo frames of every playing voice and stream, scaled by the
+    ///master volume and clipped to -1 and 1. Voices advance by out.len() / 2 frames and streams
+    ///decode the frames they need.
+    pub fn mix(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+        for slot in self.one_shots.iter_mut().chain(&mut self.loops) {
+            if let Some(voice) = slot {
+                if !voice.mix(out, self.sample_rate) {
+                    *slot = None;
+                }
+            }
+        }
+        for (name, stream) in &mut self.streams {
+            if let Err(error) = stream.mix(out, self.sample_rate) {
+                eprintln!("Streaming '{name}' failed: {error}");
+                stream.stop();
+            }
+        }
+
+        for sample in out.iter_mut() {
+            *sample = (*sample * self.master_volume).clamp(-1.0, 1.0);
+        }
+    }
+
+    ///Like mix(), converted to 16-bit samples.
+    pub fn mix_i16(&mut self, out: &mut [i16]) {
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.resize(out.len(), 0.0);
+        self.mix(&mut scratch);
+
+        for (out, sample) in out.iter_mut().zip(&scratch) {
+            *out = (sample * i16::MAX as f32) as i16;
+        }
+        self.scratch = scratch;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mono(sample_rate: u32, samples: Vec<f32>) -> Sound {
+        Sound {
+            sample_rate,
+            channels: 1,
+            samples,
+        }
+    }
+
+    fn mix_frames(mixer: &mut Mixer, frames: usize) -> Vec<f32> {
+        let mut out = vec![0.0; frames * MIX_CHANNELS];
+        mixer.mix(&mut out);
+        out
+    }
+
+    #[test]
+    fn volume_and_pan_scale_each_side() {
+        let mut mixer = Mixer::new(100, 1, 0);
+        mixer.add_sound("tone", mono(100, vec![0.5; 100]));
+
+        for (pan, left, right) in [(0.0, 0.25, 0.25), (-1.0, 0.25, 0.0), (0.5, 0.125, 0.25)] {
+            mixer.play_one_shot("tone", 0.5, pan);
+            assert_eq!(mix_frames(&mut mixer, 1), [left, right], "pan {pan}");
+        }
+    }
+
+    #[test]
+    fn master_volume_scales_and_clamps() {
+        let mut mixer = Mixer::new(100, 2, 0);
+        mixer.add_sound("tone", mono(100, vec![0.5; 100]));
+        mixer.play_one_shot("tone", 1.0, 0.0);
+
+        mixer.set_master_volume(0.5);
+        assert_eq!(mix_frames(&mut mixer, 1), [0.25, 0.25]);
+
+        mixer.play_one_shot("tone", 1.0, 0.0);
+        mixer.set_master_volume(4.0);
+        assert_eq!(mix_frames(&mut mixer, 1), [1.0, 1.0]);
+
+        let mut out = [0; 2];
+        mixer.mix_i16(&mut out);
+        assert_eq!(out, [i16::MAX, i16::MAX]);
+    }
+
+    #[test]
+    fn resampling_interpolates_between_frames() {
+        let mut mixer = Mixer::new(200, 1, 0);
+        mixer.add_sound("ramp", mono(100, vec![0.0, 0.5, 1.0]));
+        mixer.play_one_shot("ramp", 1.0, 0.0);
+
+        let out = mix_frames(&mut mixer, 4);
+        let left: Vec<f32> = out.iter().step_by(2).copied().collect();
+        assert_eq!(left, [0.0, 0.25, 0.5, 0.75]);
+        assert_eq!(mixer.active_voices(), 1);
+
+        //The one shot ends in the mix that plays its last frame and its voice is freed.
+        let out = mix_frames(&mut mixer, 2);
+        assert_eq!(out, [1.0, 1.0, 0.5, 0.5]);
+        assert_eq!(mixer.active_voices(), 0);
+    }
+
+    #[test]
+    fn loops_wrap_to_the_first_frame() {
+        let mut mixer = Mixer::new(100, 0, 1);
+        mixer.add_sound("loop", mono(100, vec![0.25, 0.5, 0.75]));
+        mixer.play_loop("loop", 0, 1.0, 0.0);
+
+        let out = mix_frames(&mut mixer, 7);
+        let left: Vec<f32> = out.iter().step_by(2).copied().collect();
+        assert_eq!(left, [0.25, 0.5, 0.75, 0.25, 0.5, 0.75, 0.25]);
+
+        mixer.stop_loop(0);
+        assert_eq!(mix_frames(&mut mixer, 1), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn pitch_changes_the_step() {
+        let mut mixer = Mixer::new(100, 1, 0);
+        mixer.add_sound("ramp", mono(100, vec![0.0, 0.25, 0.5, 0.75, 1.0]));
+        let handle = mixer.play("ramp", 1.0, 0.0, 0);
+        mixer.set_sound_pitch(handle, 2.0);
+
+        let out = mix_frames(&mut mixer, 3);
+        let left: Vec<f32> = out.iter().step_by(2).copied().collect();
+        assert_eq!(left, [0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn play_takes_over_the_oldest_lowest_priority_voice() {
+        let mut mixer = Mixer::new(100, 2, 0);
+        mixer.add_sound("tone", mono(100, vec![0.5; 100]));
+
+        let music = mixer.play("tone", 1.0, 0.0, 5);
+        let step_a = mixer.play("tone", 1.0, 0.0, 0);
+        let step_b = mixer.play("tone", 1.0, 0.0, 0);
+        assert!(mixer.is_sound_playing(music));
+        assert!(!mixer.is_sound_playing(step_a));
+        assert!(mixer.is_sound_playing(step_b));
+
+        //Higher priority sounds take over lower ones but never the other way around.
+        let alarm = mixer.play("tone", 1.0, 0.0, 9);
+        assert!(mixer.is_sound_playing(alarm) && !mixer.is_sound_playing(step_b));
+        let step_c = mixer.play("tone", 1.0, 0.0, 0);
+        assert!(!mixer.is_sound_playing(step_c));
+        assert_eq!(mixer.sound_position(step_c), None);
+
+        //A stopped sound frees its voice.
+        mixer.stop_sound(music);
+        let step_d = mixer.play("tone", 1.0, 0.0, 0);
+        assert!(mixer.is_sound_playing(step_d) && mixer.is_sound_playing(alarm));
+    }
+
+    #[test]
+    fn paused_sounds_keep_their_position() {
+        let mut mixer = Mixer::new(100, 1, 0);
+        mixer.add_sound("tone", mono(100, vec![0.5; 100]));
+        let handle = mixer.play("tone", 1.0, 0.0, 0);
+        mix_frames(&mut mixer, 10);
+
+        mixer.pause_sound(handle);
+        assert!(!mixer.is_sound_playing(handle));
+        assert_eq!(mix_frames(&mut mixer, 10), [0.0; 20]);
+        assert_eq!(
+            mixer.sound_position(handle),
+            Some(Duration::from_millis(100))
+        );
+
+        mixer.resume_sound(handle);
+        assert_eq!(mix_frames(&mut mixer, 1), [0.5, 0.5]);
+    }
+}
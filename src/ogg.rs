@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+
+use crate::mixer::AudioError;
+
+const PAGE_HEADER_SIZE: usize = 27;
+
+const CONTINUED_PACKET: u8 = 0x01;
+const END_OF_STREAM: u8 = 0x04;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+///Packet of the logical stream with the granule position of its page if it's the last packet
+///that ends on that page.
+#[derive(Debug, Clone)]
+pub(crate) struct OggPacket {
+    pub data: Vec<u8>,
+    pub granule_position: Option<u64>,
+    pub last: bool,
+}
+
+///Splits the pages of an Ogg file back into the packets of its first logical stream, other
+///multiplexed streams are skipped. The reader doesn't own the file so a stream can keep the
+///file and a reader side by side.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OggReader {
+    position: usize,
+    serial: Option<u32>,
+    packets: VecDeque<OggPacket>,
+    partial: Vec<u8>,
+    ///Set after seeking, the end of a packet that started before the page is dropped.
+    discard_continued: bool,
+    finished: bool,
+}
+
+impl OggReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Start over from the first page.
+    pub fn rewind(&mut self) {
+        *self = Self {
+            serial: self.serial,
+            ..Self::default()
+        };
+    }
+
+    ///Continue reading at the page starting at position, the first packet read is the first one
+    ///that starts on that page.
+    pub fn seek_to_page(&mut self, position: usize) {
+        self.position = position;
+        self.packets.clear();
+        self.partial.clear();
+        self.discard_continued = true;
+        self.finished = false;
+    }
+
+    ///Granule position of every page of the stream that has one and the offset of the page after
+    ///it, in file order.
+    pub fn granule_positions(&self, file: &[u8]) -> Result<Vec<(u64, usize)>, AudioError> {
+        let mut positions = Vec::new();
+        let mut position = 0;
+
+        while position < file.len() {
+            let page = read_page(file, position)?;
+            position += page.size;
+
+            if self.serial.is_some_and(|serial| serial != page.serial) {
+                continue;
+            }
+            if page.granule_position != u64::MAX {
+                positions.push((page.granule_position, position));
+            }
+            if page.header_type & END_OF_STREAM != 0 {
+                break;
+            }
+        }
+        Ok(positions)
+    }
+
+    ///Next packet of the stream, None after the last one.
+    pub fn next_packet(&mut self, file: &[u8]) -> Result<Option<OggPacket>, AudioError> {
+        loop {
+            if let Some(packet) = self.packets.pop_front() {
+                return Ok(Some(packet));
+            }
+            if self.finished || self.position >= file.len() {
+                return Ok(None);
+            }
+            self.read_page(file)?;
+        }
+    }
+
+    fn read_page(&mut self, file: &[u8]) -> Result<(), AudioError> {
+        let page = read_page(file, self.position)?;
+        self.position += page.size;
+
+        if *self.serial.get_or_insert(page.serial) != page.serial {
+            return Ok(());
+        }
+
+        //A page that doesn't continue a packet ends whatever was cut off before it.
+        let continued = page.header_type & CONTINUED_PACKET != 0;
+        if !continued {
+            self.partial.clear();
+        }
+        let mut discarding = self.discard_continued && continued;
+
+        let completed = self.packets.len();
+        let mut offset = page.body;
+        for &lacing in page.segments {
+            let start = offset;
+            offset += lacing as usize;
+
+            //Rest of a packet that started before the page seeked to.
+            if discarding {
+                discarding = lacing == 255;
+                continue;
+            }
+            self.partial.extend_from_slice(&file[start..offset]);
+
+            //Segments shorter than 255 bytes end a packet.
+            if lacing < 255 {
+                self.packets.push_back(OggPacket {
+                    data: std::mem::take(&mut self.partial),
+                    granule_position: None,
+                    last: false,
+                });
+            }
+        }
+
+        self.discard_continued = discarding;
+
+        if self.packets.len() > completed {
+            let packet = self.packets.back_mut().unwrap();
+            //All bits set means no packet ends on the page.
+            if page.granule_position != u64::MAX {
+                packet.granule_position = Some(page.granule_position);
+            }
+            if page.header_type & END_OF_STREAM != 0 {
+                packet.last = true;
+            }
+        }
+        if page.header_type & END_OF_STREAM != 0 {
+            self.finished = true;
+        }
+        Ok(())
+    }
+}
+
+///Header of the page at position, the lacing values and where its body starts.
+struct OggPage<'a> {
+    header_type: u8,
+    granule_position: u64,
+    serial: u32,
+    segments: &'a [u8],
+    body: usize,
+    ///Size of the page including the header.
+    size: usize,
+}
+
+fn read_page(file: &[u8], position: usize) -> Result<OggPage<'_>, AudioError> {
+    let Some(header) = file.get(position..position + PAGE_HEADER_SIZE) else {
+        return Err(AudioError::UnexpectedEof);
+    };
+    if &header[0..4] != b"OggS" {
+        return Err(AudioError::InvalidSignature);
+    }
+    if header[4] != 0 {
+        return Err(AudioError::Unsupported(format!(
+            "Unsupported Ogg version {}",
+            header[4]
+        )));
+    }
+
+    let segment_count = header[26] as usize;
+    let body = position + PAGE_HEADER_SIZE + segment_count;
+    let Some(segments) = file.get(position + PAGE_HEADER_SIZE..body) else {
+        return Err(AudioError::UnexpectedEof);
+    };
+    let body_size: usize = segments.iter().map(|&lacing| lacing as usize).sum();
+    let Some(page) = file.get(position..body + body_size) else {
+        return Err(AudioError::UnexpectedEof);
+    };
+
+    if crc32(page) != u32::from_le_bytes(header[22..26].try_into().unwrap()) {
+        return Err(AudioError::Malformed("Ogg page checksum mismatch"));
+    }
+
+    Ok(OggPage {
+        header_type: header[5],
+        granule_position: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+        serial: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+        segments,
+        body,
+        size: page.len(),
+    })
+}
+
+///CRC-32 with polynomial 0x04c11db7 over a page with its checksum field counted as zero.
+fn crc32(page: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for (i, &byte) in page.iter().enumerate() {
+        let byte = if (22..26).contains(&i) { 0 } else { byte };
+        crc = (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize];
+    }
+    crc
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
@@ -0,0 +1,505 @@
+use std::time::Duration;
+
+use crate::math::{float, Vector};
+use crate::rand::RngEngine;
+use crate::sprite::quad_vertices;
+use crate::system::gl32::GLuint;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Gradients and Curves
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Color over the life of a particle. Stops are (t, color) pairs where t runs from 0.0 when the
+///particle is spawned to 1.0 when it dies, colors between stops are interpolated linearly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorGradient {
+    pub stops: Vec<(f32, Vector)>,
+}
+
+impl ColorGradient {
+    ///Gradient through stops in any order, they are sorted by t.
+    pub fn new(mut stops: Vec<(f32, Vector)>) -> Self {
+        stops.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Self { stops }
+    }
+
+    ///Fade from begin to end over the whole life of the particle.
+    pub fn between(begin: Vector, end: Vector) -> Self {
+        Self::new(vec![(0.0, begin), (1.0, end)])
+    }
+
+    pub fn constant(color: Vector) -> Self {
+        Self::new(vec![(0.0, color)])
+    }
+
+    ///Color at t, an empty gradient is opaque white.
+    pub fn sample(&self, t: f32) -> Vector {
+        sample_stops(&self.stops, t, Vector::lerp).unwrap_or(Vector::new(1.0, 1.0, 1.0, 1.0))
+    }
+}
+
+///Value over the life of a particle, used for its size. Points are (t, value) pairs with t from
+///0.0 to 1.0 like the stops of a ColorGradient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Curve {
+    ///Curve through points in any order, they are sorted by t.
+    pub fn new(mut points: Vec<(f32, f32)>) -> Self {
+        points.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Self { points }
+    }
+
+    ///Straight line from begin to end.
+    pub fn linear(begin: f32, end: f32) -> Self {
+        Self::new(vec![(0.0, begin), (1.0, end)])
+    }
+
+    pub fn constant(value: f32) -> Self {
+        Self::new(vec![(0.0, value)])
+    }
+
+    ///Value at t, an empty curve is 0.0.
+    pub fn sample(&self, t: f32) -> f32 {
+        sample_stops(&self.points, t, float::lerp).unwrap_or(0.0)
+    }
+}
+
+///Interpolate between the two stops around t, clamping to the first and last stop.
+fn sample_stops<T: Copy>(stops: &[(f32, T)], t: f32, lerp: impl Fn(T, T, f32) -> T) -> Option<T> {
+    let &(first_t, first) = stops.first()?;
+    if t <= first_t {
+        return Some(first);
+    }
+
+    for pair in stops.windows(2) {
+        let (t0, a) = pair[0];
+        let (t1, b) = pair[1];
+        if t <= t1 {
+            let span = t1 - t0;
+            return Some(if span > 0.0 {
+                lerp(a, b, (t - t0) / span)
+            } else {
+                b
+            });
+        }
+    }
+    stops.last().map(|&(_, value)| value)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Particles
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Area new particles are spawned in, centered on ParticleProperties::pos.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpawnShape {
+    Point,
+    ///Anywhere inside a circle.
+    Circle {
+        radius: f32,
+    },
+    ///Anywhere inside a width by height box.
+    Box {
+        width: f32,
+        height: f32,
+    },
+    ///Spawn on the emitter and turn the velocity by a random angle of up to half of spread degrees
+    ///either way, the velocity is the axis of the cone.
+    Cone {
+        spread: f32,
+    },
+}
+
+///Describes a particle effect. Fields ending in _var are the most a value is randomly changed by
+///in either direction when a particle is spawned. Angles are in degrees, times in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParticleProperties {
+    pub pos: Vector,
+    pub shape: SpawnShape,
+    pub velocity: Vector,
+    pub velocity_var: Vector,
+    ///Added to the velocity every second, use it for gravity and wind.
+    pub acceleration: Vector,
+    ///Fraction of the velocity lost per second.
+    pub drag: f32,
+    pub rotation: f32,
+    pub rotation_var: f32,
+    ///Rotation per second.
+    pub angular_velocity: f32,
+    pub angular_velocity_var: f32,
+    pub color: ColorGradient,
+    pub size: Curve,
+    ///Added to every point of the size curve.
+    pub size_var: f32,
+    pub lifetime: f32,
+    pub lifetime_var: f32,
+    ///Particles spawned per second by a ParticleEmitter.
+    pub rate: f32,
+    ///Particles spawned at once by a ParticleEmitter on its first update.
+    pub burst: usize,
+    ///Size of the particle pool of a ParticleEmitter, no particles are spawned while it's full.
+    pub max_particles: usize,
+    pub tex_id: Option<GLuint>,
+}
+
+impl Default for ParticleProperties {
+    fn default() -> Self {
+        Self {
+            pos: Vector::zeroed(),
+            shape: SpawnShape::Point,
+            velocity: Vector::zeroed(),
+            velocity_var: Vector::zeroed(),
+            acceleration: Vector::zeroed(),
+            drag: 0.0,
+            rotation: 0.0,
+            rotation_var: 180.0,
+            angular_velocity: 0.0,
+            angular_velocity_var: 0.0,
+            color: ColorGradient::constant(Vector::new(1.0, 1.0, 1.0, 1.0)),
+            size: Curve::constant(10.0),
+            size_var: 0.0,
+            lifetime: 1.0,
+            lifetime_var: 0.0,
+            rate: 0.0,
+            burst: 0,
+            max_particles: 1000,
+            tex_id: None,
+        }
+    }
+}
+
+///A live particle. color and size are updated from the gradient and curve of its properties
+///every step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub pos: Vector,
+    pub velocity: Vector,
+    pub rotation: f32,
+    pub angular_velocity: f32,
+    pub color: Vector,
+    pub size: f32,
+    pub lifetime: f32,
+    pub life_remaining: f32,
+    pub tex_id: Option<GLuint>,
+    size_offset: f32,
+    ///Index into ParticleEmitter::behaviours, None for particles of the emitter's own properties.
+    behaviour: Option<usize>,
+}
+
+impl Particle {
+    ///How far the particle is through its life, from 0.0 to 1.0.
+    pub fn age(&self) -> f32 {
+        1.0 - self.life_remaining / self.lifetime
+    }
+
+    ///World space positions of the six QUAD vertices of the particle.
+    pub fn vertices(&self) -> [[f32; 3]; 6] {
+        //quad_vertices() flips z for sprites, particles sit at pos.z.
+        quad_vertices(
+            self.pos.x,
+            self.pos.y,
+            -self.pos.z,
+            self.size,
+            self.size,
+            self.rotation,
+        )
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Emitter
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///The parts of ParticleProperties that still matter after a particle is spawned.
+#[derive(Debug, Clone, PartialEq)]
+struct Behaviour {
+    acceleration: Vector,
+    drag: f32,
+    color: ColorGradient,
+    size: Curve,
+    ///Live particles using the behaviour, its slot is reused once this drops to 0.
+    particles: usize,
+}
+
+impl Behaviour {
+    fn new(properties: &ParticleProperties) -> Self {
+        Self {
+            acceleration: properties.acceleration,
+            drag: properties.drag,
+            color: properties.color.clone(),
+            size: properties.size.clone(),
+            particles: 0,
+        }
+    }
+
+    fn matches(&self, properties: &ParticleProperties) -> bool {
+        self.acceleration == properties.acceleration
+            && self.drag == properties.drag
+            && self.color == properties.color
+            && self.size == properties.size
+    }
+
+    fn step(
+        acceleration: Vector,
+        drag: f32,
+        color: &ColorGradient,
+        size: &Curve,
+        particle: &mut Particle,
+        dt: f32,
+    ) {
+        particle.velocity += acceleration * dt;
+        particle.velocity = particle.velocity * (1.0 - drag * dt).max(0.0);
+        particle.pos += particle.velocity * dt;
+        particle.rotation += particle.angular_velocity * dt;
+
+        let age = particle.age();
+        particle.color = color.sample(age);
+        particle.size = (size.sample(age) + particle.size_offset).max(0.0);
+    }
+}
+
+///Handle to an emitter owned by System, returned by System::create_emitter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EmitterId(u64);
+
+impl EmitterId {
+    pub(crate) fn new(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+///Spawns particles described by its properties into its own pool. Emits properties.burst
+///particles on its first update and properties.rate particles per second after that.
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    pub properties: ParticleProperties,
+    particles: Vec<Particle>,
+    ///Behaviours of particles spawned with emit_with(), slots without live particles are reused.
+    behaviours: Vec<Behaviour>,
+    spawn_accumulator: f32,
+    started: bool,
+    paused: bool,
+    stopped: bool,
+    ///Name of the particle effect the properties were loaded from, they are replaced when it's
+    ///reloaded.
+    pub(crate) effect: Option<String>,
+}
+
+impl ParticleEmitter {
+    pub fn new(properties: ParticleProperties) -> Self {
+        Self {
+            //The pool grows as particles spawn, max_particles may come from an effect file.
+            particles: Vec::new(),
+            properties,
+            behaviours: Vec::new(),
+            spawn_accumulator: 0.0,
+            started: false,
+            paused: false,
+            stopped: false,
+            effect: None,
+        }
+    }
+
+    ///Freeze the emitter and its particles, they are still drawn.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn play(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    ///Stop spawning particles, the live particles finish their lives.
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    ///True once a stopped emitter has no live particles left.
+    pub fn is_finished(&self) -> bool {
+        self.stopped && self.particles.is_empty()
+    }
+
+    pub fn set_position(&mut self, pos: Vector) {
+        self.properties.pos = pos;
+    }
+
+    ///Live particles, oldest first.
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    ///Age and move the live particles by dt, then spawn new ones.
+    pub(crate) fn update(&mut self, dt: Duration, rng: &mut RngEngine) {
+        if self.paused {
+            return;
+        }
+
+        let dt = dt.as_secs_f32();
+        let properties = &self.properties;
+        let behaviours = &mut self.behaviours;
+
+        //Keep the pool in spawn order so older particles are drawn first.
+        self.particles.retain_mut(|particle| {
+            particle.life_remaining -= dt;
+            if particle.life_remaining <= 0.0 {
+                if let Some(index) = particle.behaviour {
+                    behaviours[index].particles -= 1;
+                }
+                return false;
+            }
+
+            match particle.behaviour {
+                Some(index) => {
+                    let behaviour = &behaviours[index];
+                    Behaviour::step(
+                        behaviour.acceleration,
+                        behaviour.drag,
+                        &behaviour.color,
+                        &behaviour.size,
+                        particle,
+                        dt,
+                    );
+                }
+                None => Behaviour::step(
+                    properties.acceleration,
+                    properties.drag,
+                    &properties.color,
+                    &properties.size,
+                    particle,
+                    dt,
+                ),
+            }
+            true
+        });
+        if self.stopped {
+            return;
+        }
+
+        let mut count = 0;
+        if !self.started {
+            self.started = true;
+            count += self.properties.burst;
+        }
+        self.spawn_accumulator += self.properties.rate.max(0.0) * dt;
+        let spawned = self.spawn_accumulator.floor();
+        self.spawn_accumulator -= spawned;
+        count += spawned as usize;
+
+        self.emit(count, rng);
+    }
+
+    ///Spawn count particles at once, even while paused or stopped.
+    pub(crate) fn emit(&mut self, count: usize, rng: &mut RngEngine) {
+        for _ in 0..count {
+            if self.particles.len() >= self.properties.max_particles {
+                break;
+            }
+            if let Some(particle) = spawn(&self.properties, None, rng) {
+                self.particles.push(particle);
+            }
+        }
+    }
+
+    ///Spawn one particle described by properties instead of the emitter's own properties. The
+    ///pool size of the emitter still applies.
+    pub(crate) fn emit_with(&mut self, properties: &ParticleProperties, rng: &mut RngEngine) {
+        if self.particles.len() >= self.properties.max_particles {
+            return;
+        }
+
+        //Live behaviours are bounded by the pool size, so the search stays short however long
+        //the emitter runs.
+        let matching = self
+            .behaviours
+            .iter()
+            .position(|behaviour| behaviour.particles > 0 && behaviour.matches(properties));
+        let index = match matching {
+            Some(index) => index,
+            None => {
+                let behaviour = Behaviour::new(properties);
+                match self.behaviours.iter().position(|slot| slot.particles == 0) {
+                    Some(index) => {
+                        self.behaviours[index] = behaviour;
+                        index
+                    }
+                    None => {
+                        self.behaviours.push(behaviour);
+                        self.behaviours.len() - 1
+                    }
+                }
+            }
+        };
+        if let Some(particle) = spawn(properties, Some(index), rng) {
+            self.behaviours[index].particles += 1;
+            self.particles.push(particle);
+        }
+    }
+}
+
+///New particle described by properties, None if its random lifetime came out as zero.
+fn spawn(
+    properties: &ParticleProperties,
+    behaviour: Option<usize>,
+    rng: &mut RngEngine,
+) -> Option<Particle> {
+    let mut random = || rng.get_float_range(-1.0, 1.0) as f32;
+
+    let lifetime = properties.lifetime + properties.lifetime_var * random();
+    if lifetime <= 0.0 {
+        return None;
+    }
+
+    let mut pos = properties.pos;
+    let mut velocity = properties.velocity;
+    velocity.x += properties.velocity_var.x * random();
+    velocity.y += properties.velocity_var.y * random();
+
+    match properties.shape {
+        SpawnShape::Point => {}
+        SpawnShape::Circle { radius } => {
+            //sqrt spreads the particles evenly over the area instead of bunching them up in the
+            //middle.
+            let distance = radius * ((random() + 1.0) * 0.5).sqrt();
+            let (sin, cos) = (random() * std::f32::consts::PI).sin_cos();
+            pos.x += cos * distance;
+            pos.y += sin * distance;
+        }
+        SpawnShape::Box { width, height } => {
+            pos.x += width * 0.5 * random();
+            pos.y += height * 0.5 * random();
+        }
+        SpawnShape::Cone { spread } => {
+            let (sin, cos) = (spread * 0.5 * random()).to_radians().sin_cos();
+            (velocity.x, velocity.y) = (
+                velocity.x * cos - velocity.y * sin,
+                velocity.x * sin + velocity.y * cos,
+            );
+        }
+    }
+
+    let size_offset = properties.size_var * random();
+    Some(Particle {
+        pos,
+        velocity,
+        rotation: properties.rotation + properties.rotation_var * random(),
+        angular_velocity: properties.angular_velocity + properties.angular_velocity_var * random(),
+        color: properties.color.sample(0.0),
+        size: (properties.size.sample(0.0) + size_offset).max(0.0),
+        lifetime,
+        life_remaining: lifetime,
+        tex_id: properties.tex_id,
+        size_offset,
+        behaviour,
+    })
+}
@@ -0,0 +1,311 @@
+use crate::image::Image;
+use crate::input::Input;
+use crate::math::{Matrix, Vector};
+use crate::mixer::SoundHandle;
+use crate::particle::Particle;
+use crate::statistics::BatchStats;
+use crate::system::gl32::GLuint;
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WindowDimension {
+    pub width: i32,
+    pub height: i32,
+}
+
+///Draw call recorded by the headless renderer. Text coordinates are in normalized device
+///coordinates, everything else is in the units passed to System. Sprites are recorded in the
+///order the GL renderer draws them, back to front and grouped by texture.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawCall {
+    Clear {
+        color: (f32, f32, f32, f32),
+    },
+    Rect {
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        color: Vector,
+    },
+    Line {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        z: f32,
+        w: f32,
+        color: Vector,
+    },
+    Sprite {
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        texture_id: GLuint,
+        uv_rect: (f32, f32, f32, f32),
+        color: Vector,
+    },
+    Text {
+        text: String,
+        x: f32,
+        y: f32,
+        color: (f32, f32, f32, f32),
+        font: String,
+    },
+    Particle {
+        pos: Vector,
+        size: f32,
+        rotation: f32,
+        color: Vector,
+        tex_id: Option<GLuint>,
+    },
+}
+
+///Audio call recorded by the headless audio backend.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioCall {
+    OneShot {
+        audio: String,
+        level: f32,
+    },
+    Loop {
+        audio: String,
+        channel: u8,
+        level: f32,
+    },
+    StopLoop {
+        channel: u8,
+    },
+    Play {
+        audio: String,
+        level: f32,
+        priority: u8,
+        handle: SoundHandle,
+    },
+    StopSound {
+        handle: SoundHandle,
+    },
+    PauseSound {
+        handle: SoundHandle,
+    },
+    ResumeSound {
+        handle: SoundHandle,
+    },
+    SoundVolume {
+        handle: SoundHandle,
+        level: f32,
+    },
+    SoundPitch {
+        handle: SoundHandle,
+        pitch: f32,
+    },
+    SoundPan {
+        handle: SoundHandle,
+        pan: f32,
+    },
+    PlayStream {
+        stream: String,
+        level: f32,
+        looping: bool,
+    },
+    PauseStream {
+        stream: String,
+    },
+    StopStream {
+        stream: String,
+    },
+    SeekStream {
+        stream: String,
+        position: Duration,
+    },
+    StreamLoopPoints {
+        stream: String,
+        start: Duration,
+        end: Option<Duration>,
+    },
+    MasterVolume {
+        level: f32,
+    },
+}
+
+///Window and OS event layer used by System. The win32 window implements this on Windows, other
+///backends can be plugged in through System::with_backends().
+pub trait Platform {
+    ///Create the platform window with a client area of the given size.
+    fn create(window_title: &str, width: i32, height: i32) -> Self
+    where
+        Self: Sized;
+
+    ///Drain pending OS events, forwarding mouse and keyboard messages to input. Returns false once
+    ///the window has been closed.
+    fn pump_events(&mut self, input: &mut Input) -> bool;
+
+    ///Sample device state that isn't delivered through events (mouse position, gamepads) and push
+    ///gamepad vibration back to the devices.
+    fn poll_input(&mut self, input: &mut Input);
+
+    fn set_cursor_visible(&mut self, visible: bool);
+
+    ///Present the finished frame.
+    fn present(&mut self);
+
+    fn dimensions(&self) -> WindowDimension;
+}
+
+///Drawing and texture management used by System.
+pub trait RenderBackend {
+    ///Load single texture from path, key to texture is the file stem name without extension.
+    fn load_texture(&mut self, path: &str) -> GLuint;
+
+    ///Load all supported textures in directory, keys to textures are their file stem names.
+    fn load_texture_dir(&mut self, path: &str);
+
+    ///Upload a decoded image as a texture under name, replacing any texture with the same name.
+    fn upload_texture(&mut self, name: &str, image: &Image) -> GLuint;
+
+    fn get_texture(&self, name: &str) -> GLuint;
+
+    fn display_clear(&mut self, color: (f32, f32, f32, f32));
+
+    fn draw_rect(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        color: Vector,
+        proj: Matrix,
+        view: Matrix,
+    );
+
+    fn draw_line(
+        &mut self,
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        z: f32,
+        w: f32,
+        color: Vector,
+        proj: Matrix,
+        view: Matrix,
+    );
+
+    ///Queue a sprite showing the uv_rect part of the texture, queued sprites are drawn when
+    ///draw_sprites() is called at the end of the frame. Sprites are drawn back to front, sprites
+    ///at the same depth are grouped by texture.
+    fn add_sprite(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        texture_id: GLuint,
+        uv_rect: (f32, f32, f32, f32),
+        color: Vector,
+        proj: Matrix,
+        view: Matrix,
+    );
+
+    fn draw_sprites(&mut self);
+
+    fn define_font(
+        &mut self,
+        name: &str,
+        size: i32,
+        weight: i32,
+        italic: bool,
+        underline: bool,
+        strikeout: bool,
+        font: &str,
+    );
+
+    ///Draw text at normalized device coordinates.
+    fn draw_text(&mut self, text: &str, x: f32, y: f32, color: (f32, f32, f32, f32), font: &str);
+
+    ///Draw live particles in the order given, drawn over everything drawn before them.
+    fn draw_particles(&mut self, particles: &[Particle], proj: Matrix, view: Matrix);
+
+    ///Read back the framebuffer. Returns None if the backend can't read back its framebuffer.
+    fn capture_frame(&self) -> Option<Image> {
+        None
+    }
+
+    ///Sprite, batch and draw call counts since the last call.
+    fn take_batch_stats(&mut self) -> BatchStats {
+        BatchStats::default()
+    }
+
+    ///Draw calls recorded since the last call. Only the headless renderer records calls.
+    fn take_draw_calls(&mut self) -> Vec<DrawCall> {
+        Vec::new()
+    }
+}
+
+///Audio loading and playback used by System.
+pub trait AudioBackend {
+    ///Load single audio file from path, key to audio file is the file stem name without extension.
+    fn load_audio(&mut self, path: &str);
+
+    ///Load all supported audio files in directory, keys to audio files are their file stem names.
+    fn load_audio_dir(&mut self, path: &str);
+
+    fn play_one_shot(&mut self, audio: &str, level: f32);
+
+    fn play_loop(&mut self, audio: &str, channel: u8, level: f32);
+
+    fn stop_loop(&mut self, channel: u8);
+
+    ///Play audio once and return a handle to it, see Mixer::play for how priority picks a voice.
+    fn play(&mut self, audio: &str, level: f32, priority: u8) -> SoundHandle;
+
+    fn stop_sound(&mut self, handle: SoundHandle);
+
+    fn pause_sound(&mut self, handle: SoundHandle);
+
+    fn resume_sound(&mut self, handle: SoundHandle);
+
+    fn set_sound_volume(&mut self, handle: SoundHandle, level: f32);
+
+    fn set_sound_pitch(&mut self, handle: SoundHandle, pitch: f32);
+
+    fn set_sound_pan(&mut self, handle: SoundHandle, pan: f32);
+
+    fn is_sound_playing(&self, handle: SoundHandle) -> bool;
+
+    fn sound_position(&self, handle: SoundHandle) -> Option<Duration>;
+
+    ///Open audio file from path for streaming, key to the stream is the file stem name without
+    ///extension.
+    fn load_stream(&mut self, path: &str);
+
+    fn play_stream(&mut self, stream: &str, level: f32, looping: bool);
+
+    fn pause_stream(&mut self, stream: &str);
+
+    fn stop_stream(&mut self, stream: &str);
+
+    fn seek_stream(&mut self, stream: &str, position: Duration);
+
+    fn set_stream_loop_points(&mut self, stream: &str, start: Duration, end: Option<Duration>);
+
+    fn set_master_volume(&mut self, level: f32);
+
+    ///Mix and queue audio for playback, called once every frame with the frame time.
+    fn update(&mut self, _dt: Duration) {}
+
+    ///Audio calls recorded since the last call. Only the headless audio backend records calls.
+    fn take_audio_calls(&mut self) -> Vec<AudioCall> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,888 @@
+use crate::image::Image;
+use crate::texture::TextureError;
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+const PNG_COLOR_GRAY: u8 = 0;
+const PNG_COLOR_RGB: u8 = 2;
+const PNG_COLOR_PALETTE: u8 = 3;
+const PNG_COLOR_GRAY_ALPHA: u8 = 4;
+const PNG_COLOR_RGBA: u8 = 6;
+
+///Largest width * height accepted, guards against absurd allocations from corrupt headers.
+const PNG_MAX_PIXELS: u64 = 1 << 28;
+
+///Start column, start row, column step and row step of the seven Adam7 passes.
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Decoding
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Decode a PNG file of any standard color type and bit depth, interlaced or not. 16bit channels
+///are reduced to 8bit and tRNS transparency is applied.
+pub fn decode_png(file: &[u8]) -> Result<Image, TextureError> {
+    if file.len() < PNG_SIGNATURE.len() || file[0..8] != PNG_SIGNATURE {
+        return Err(TextureError::InvalidSignature);
+    }
+
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut transparency = None;
+    let mut compressed = Vec::new();
+    let mut position = PNG_SIGNATURE.len();
+
+    loop {
+        if position + 12 > file.len() {
+            return Err(TextureError::UnexpectedEof);
+        }
+
+        let length = u32::from_be_bytes(file[position..position + 4].try_into().unwrap()) as usize;
+        let chunk_type = &file[position + 4..position + 8];
+        let data_start = position + 8;
+        let data_end = data_start + length;
+
+        if data_end + 4 > file.len() {
+            return Err(TextureError::UnexpectedEof);
+        }
+
+        let crc = u32::from_be_bytes(file[data_end..data_end + 4].try_into().unwrap());
+        if crc != crc32(&file[position + 4..data_end]) {
+            return Err(TextureError::Malformed("Chunk checksum mismatch"));
+        }
+
+        let data = &file[data_start..data_end];
+
+        match chunk_type {
+            b"IHDR" => {
+                if data.len() != 13 {
+                    return Err(TextureError::Malformed("Malformed header data"));
+                }
+                header = Some(PngHeader {
+                    width: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+                    height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+                    bit_depth: data[8],
+                    color_type: data[9],
+                    compression: data[10],
+                    filter: data[11],
+                    interlace: data[12],
+                });
+            }
+            b"PLTE" => {
+                if data.is_empty() || !data.len().is_multiple_of(3) || data.len() > 256 * 3 {
+                    return Err(TextureError::Malformed("Malformed palette data"));
+                }
+                palette = data
+                    .chunks_exact(3)
+                    .map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                    .collect();
+            }
+            b"tRNS" => transparency = Some(data),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+
+        position = data_end + 4;
+    }
+
+    let Some(header) = header else {
+        return Err(TextureError::Malformed("Missing IHDR chunk"));
+    };
+
+    if header.width == 0
+        || header.height == 0
+        || header.width as u64 * header.height as u64 > PNG_MAX_PIXELS
+    {
+        return Err(TextureError::InvalidDimensions {
+            width: header.width as i64,
+            height: header.height as i64,
+        });
+    }
+
+    if header.compression != 0 || header.filter != 0 {
+        return Err(TextureError::Unsupported(String::from(
+            "Unsupported PNG compression or filter method",
+        )));
+    }
+
+    if header.interlace > 1 {
+        return Err(TextureError::Unsupported(String::from(
+            "Unsupported PNG interlace method",
+        )));
+    }
+
+    let channels = match (header.color_type, header.bit_depth) {
+        (PNG_COLOR_GRAY, 1 | 2 | 4 | 8 | 16) => 1,
+        (PNG_COLOR_RGB, 8 | 16) => 3,
+        (PNG_COLOR_PALETTE, 1 | 2 | 4 | 8) => 1,
+        (PNG_COLOR_GRAY_ALPHA, 8 | 16) => 2,
+        (PNG_COLOR_RGBA, 8 | 16) => 4,
+        _ => {
+            return Err(TextureError::Unsupported(String::from(
+                "Unsupported PNG color type or bit depth",
+            )))
+        }
+    };
+
+    let mut format = PixelFormat {
+        color_type: header.color_type,
+        bit_depth: header.bit_depth as usize,
+        channels,
+        palette,
+        transparent: None,
+    };
+
+    if header.color_type == PNG_COLOR_PALETTE {
+        if format.palette.is_empty() {
+            return Err(TextureError::Malformed("Missing PLTE chunk"));
+        }
+        if let Some(alpha) = transparency {
+            if alpha.len() > format.palette.len() {
+                return Err(TextureError::Malformed("Malformed transparency data"));
+            }
+            for (entry, alpha) in format.palette.iter_mut().zip(alpha) {
+                entry[3] = *alpha;
+            }
+        }
+        //Indices past the end of the palette decode as opaque black.
+        format.palette.resize(256, [0, 0, 0, 255]);
+    } else if let Some(key) = transparency {
+        format.transparent = match (header.color_type, key.len()) {
+            (PNG_COLOR_GRAY, 2) => {
+                let gray = u16::from_be_bytes([key[0], key[1]]);
+                Some([gray; 3])
+            }
+            (PNG_COLOR_RGB, 6) => Some([
+                u16::from_be_bytes([key[0], key[1]]),
+                u16::from_be_bytes([key[2], key[3]]),
+                u16::from_be_bytes([key[4], key[5]]),
+            ]),
+            (PNG_COLOR_GRAY_ALPHA | PNG_COLOR_RGBA, _) => None,
+            _ => return Err(TextureError::Malformed("Malformed transparency data")),
+        };
+    }
+
+    let width = header.width as usize;
+    let height = header.height as usize;
+    let bits_per_pixel = channels * format.bit_depth;
+    let bytes_per_pixel = bits_per_pixel.div_ceil(8);
+    let passes: &[(usize, usize, usize, usize)] = if header.interlace == 0 {
+        &[(0, 0, 1, 1)]
+    } else {
+        &ADAM7_PASSES
+    };
+
+    //Width, height and stride of every pass that has pixels, passes without any aren't stored.
+    let mut layout = Vec::with_capacity(passes.len());
+    let mut total_size = 0usize;
+    for &(start_x, start_y, step_x, step_y) in passes {
+        if start_x >= width || start_y >= height {
+            continue;
+        }
+
+        let pass_width = (width - start_x).div_ceil(step_x);
+        let pass_height = (height - start_y).div_ceil(step_y);
+        let stride = pass_width
+            .checked_mul(bits_per_pixel)
+            .map(|bits| bits.div_ceil(8));
+        let pass_size = stride.and_then(|stride| (stride + 1).checked_mul(pass_height));
+        let Some(size) = pass_size.and_then(|size| total_size.checked_add(size)) else {
+            return Err(TextureError::InvalidDimensions {
+                width: header.width as i64,
+                height: header.height as i64,
+            });
+        };
+        total_size = size;
+        layout.push((start_x, start_y, step_x, step_y, pass_width, pass_height));
+    }
+
+    //Decompression stops at the size the header asks for, so a small file can't inflate to gigabytes.
+    let mut data = zlib_decompress(&compressed, total_size)?;
+
+    //Checked before allocating the image so a header can't claim more than the data holds.
+    if total_size > data.len() {
+        return Err(TextureError::Malformed("Unexpected end of image data"));
+    }
+
+    let Some(pixel_size) = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(4))
+    else {
+        return Err(TextureError::InvalidDimensions {
+            width: header.width as i64,
+            height: header.height as i64,
+        });
+    };
+    let mut pixels = vec![0; pixel_size];
+
+    let mut pass_start = 0;
+    for (start_x, start_y, step_x, step_y, pass_width, pass_height) in layout {
+        let stride = (pass_width * bits_per_pixel).div_ceil(8);
+        let pass_size = (stride + 1) * pass_height;
+
+        let pass = &mut data[pass_start..pass_start + pass_size];
+        unfilter(pass, stride, pass_height, bytes_per_pixel)?;
+
+        for (row, line) in pass.chunks_exact(stride + 1).enumerate() {
+            let y = start_y + row * step_y;
+            for column in 0..pass_width {
+                let x = start_x + column * step_x;
+                let i = (y * width + x) * 4;
+                pixels[i..i + 4].copy_from_slice(&format.rgba(&line[1..], column));
+            }
+        }
+
+        pass_start += pass_size;
+    }
+
+    Ok(Image::new(header.width, header.height, pixels))
+}
+
+struct PngHeader {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    compression: u8,
+    filter: u8,
+    interlace: u8,
+}
+
+///Converts the samples of a single unfiltered row to RGBA8.
+struct PixelFormat {
+    color_type: u8,
+    bit_depth: usize,
+    channels: usize,
+    ///RGBA palette entries with tRNS alpha applied, always 256 entries for palette images.
+    palette: Vec<[u8; 4]>,
+    ///Gray or RGB sample value from tRNS that is fully transparent.
+    transparent: Option<[u16; 3]>,
+}
+
+impl PixelFormat {
+    fn rgba(&self, line: &[u8], pixel: usize) -> [u8; 4] {
+        let sample = |channel: usize| -> u16 {
+            let index = pixel * self.channels + channel;
+            match self.bit_depth {
+                16 => u16::from_be_bytes([line[index * 2], line[index * 2 + 1]]),
+                8 => line[index] as u16,
+                _ => {
+                    let bit = index * self.bit_depth;
+                    let shift = 8 - self.bit_depth - bit % 8;
+                    ((line[bit / 8] >> shift) & ((1 << self.bit_depth) - 1)) as u16
+                }
+            }
+        };
+        let to_u8 = |value: u16| -> u8 {
+            match self.bit_depth {
+                16 => (value >> 8) as u8,
+                8 => value as u8,
+                _ => (value as u32 * 255 / ((1 << self.bit_depth) - 1)) as u8,
+            }
+        };
+
+        match self.color_type {
+            PNG_COLOR_GRAY => {
+                let gray = sample(0);
+                let alpha = if self.transparent.is_some_and(|key| key[0] == gray) {
+                    0
+                } else {
+                    255
+                };
+                let gray = to_u8(gray);
+                [gray, gray, gray, alpha]
+            }
+            PNG_COLOR_RGB => {
+                let rgb = [sample(0), sample(1), sample(2)];
+                let alpha = if self.transparent == Some(rgb) {
+                    0
+                } else {
+                    255
+                };
+                [to_u8(rgb[0]), to_u8(rgb[1]), to_u8(rgb[2]), alpha]
+            }
+            PNG_COLOR_PALETTE => self.palette[sample(0) as usize],
+            PNG_COLOR_GRAY_ALPHA => {
+                let gray = to_u8(sample(0));
+                [gray, gray, gray, to_u8(sample(1))]
+            }
+            _ => [
+                to_u8(sample(0)),
+                to_u8(sample(1)),
+                to_u8(sample(2)),
+                to_u8(sample(3)),
+            ],
+        }
+    }
+}
+
+///Reverse the per row filters in place. Each row in data is prefixed with its filter type byte.
+fn unfilter(
+    data: &mut [u8],
+    stride: usize,
+    rows: usize,
+    bytes_per_pixel: usize,
+) -> Result<(), TextureError> {
+    if data.len() < rows * (stride + 1) {
+        return Err(TextureError::Malformed("Unexpected end of image data"));
+    }
+
+    for row in 0..rows {
+        let start = row * (stride + 1);
+        let filter = data[start];
+        let (previous, current) = data.split_at_mut(start + 1);
+        let current = &mut current[..stride];
+        let previous = if row == 0 {
+            None
+        } else {
+            Some(&previous[start - stride..start])
+        };
+
+        for i in 0..stride {
+            let a = if i >= bytes_per_pixel {
+                current[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let b = previous.map_or(0, |previous| previous[i]);
+            let c = match previous {
+                Some(previous) if i >= bytes_per_pixel => previous[i - bytes_per_pixel],
+                _ => 0,
+            };
+
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(TextureError::Malformed("Invalid filter type")),
+            };
+            current[i] = current[i].wrapping_add(predictor);
+        }
+    }
+
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Inflate
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const INFLATE_TOO_LARGE: &str = "Compressed data larger than expected";
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, TextureError> {
+        while self.bit_count < count {
+            let Some(byte) = self.data.get(self.position) else {
+                return Err(TextureError::Malformed("Unexpected end of compressed data"));
+            };
+            self.bit_buffer |= (*byte as u32) << self.bit_count;
+            self.bit_count += 8;
+            self.position += 1;
+        }
+
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+///Canonical huffman code, decoded one bit at a time.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, TextureError> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left = 1i32;
+        for count in &counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(TextureError::Malformed("Invalid huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, TextureError> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(TextureError::Malformed("Invalid huffman code"))
+    }
+}
+
+///Decompress a zlib stream, the adler32 checksum is verified. Fails if the output would be larger
+///than max_size.
+pub fn zlib_decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, TextureError> {
+    if data.len() < 6 {
+        return Err(TextureError::Malformed("Unexpected end of compressed data"));
+    }
+
+    let cmf = data[0];
+    let flags = data[1];
+    if cmf & 0x0f != 8
+        || !((cmf as u16) << 8 | flags as u16).is_multiple_of(31)
+        || flags & 0x20 != 0
+    {
+        return Err(TextureError::Malformed("Malformed zlib header"));
+    }
+
+    let (output, consumed) = inflate(&data[2..], max_size)?;
+
+    let checksum_start = 2 + consumed;
+    let Some(checksum) = data.get(checksum_start..checksum_start + 4) else {
+        return Err(TextureError::Malformed("Unexpected end of compressed data"));
+    };
+    if u32::from_be_bytes(checksum.try_into().unwrap()) != adler32(&output) {
+        return Err(TextureError::Malformed("Compressed data checksum mismatch"));
+    }
+
+    Ok(output)
+}
+
+///Inflate a raw deflate stream. Returns the output and the number of bytes consumed, fails as
+///soon as the output grows past max_size.
+pub fn inflate(data: &[u8], max_size: usize) -> Result<(Vec<u8>, usize), TextureError> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+
+    loop {
+        let last = reader.bits(1)?;
+        let block_type = reader.bits(2)?;
+
+        match block_type {
+            0 => {
+                reader.align_to_byte();
+                let position = reader.position;
+                let Some(header) = data.get(position..position + 4) else {
+                    return Err(TextureError::Malformed("Unexpected end of compressed data"));
+                };
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                let inverse = u16::from_le_bytes([header[2], header[3]]);
+                if length != !inverse {
+                    return Err(TextureError::Malformed("Stored block length mismatch"));
+                }
+
+                let start = position + 4;
+                let Some(block) = data.get(start..start + length as usize) else {
+                    return Err(TextureError::Malformed("Unexpected end of compressed data"));
+                };
+                if output.len() + block.len() > max_size {
+                    return Err(TextureError::Malformed(INFLATE_TOO_LARGE));
+                }
+                output.extend_from_slice(block);
+                reader.position = start + length as usize;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[0..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..288].fill(8);
+                let literals = Huffman::new(&lengths)?;
+                let distances = Huffman::new(&[5; 30])?;
+
+                inflate_block(&mut reader, &mut output, max_size, &literals, &distances)?;
+            }
+            2 => {
+                let literal_count = reader.bits(5)? as usize + 257;
+                let distance_count = reader.bits(5)? as usize + 1;
+                let code_count = reader.bits(4)? as usize + 4;
+
+                let mut code_lengths = [0u8; 19];
+                for &index in &CODE_LENGTH_ORDER[0..code_count] {
+                    code_lengths[index] = reader.bits(3)? as u8;
+                }
+                let code_lengths = Huffman::new(&code_lengths)?;
+
+                let mut lengths = vec![0u8; literal_count + distance_count];
+                let mut index = 0;
+                while index < lengths.len() {
+                    let symbol = code_lengths.decode(&mut reader)?;
+                    let (value, repeat) = match symbol {
+                        0..=15 => (symbol as u8, 1),
+                        16 => {
+                            if index == 0 {
+                                return Err(TextureError::Malformed("Invalid code length repeat"));
+                            }
+                            (lengths[index - 1], 3 + reader.bits(2)? as usize)
+                        }
+                        17 => (0, 3 + reader.bits(3)? as usize),
+                        _ => (0, 11 + reader.bits(7)? as usize),
+                    };
+
+                    if index + repeat > lengths.len() {
+                        return Err(TextureError::Malformed("Invalid code length repeat"));
+                    }
+                    lengths[index..index + repeat].fill(value);
+                    index += repeat;
+                }
+
+                let literals = Huffman::new(&lengths[0..literal_count])?;
+                let distances = Huffman::new(&lengths[literal_count..])?;
+
+                inflate_block(&mut reader, &mut output, max_size, &literals, &distances)?;
+            }
+            _ => return Err(TextureError::Malformed("Invalid block type")),
+        }
+
+        if last == 1 {
+            break;
+        }
+    }
+
+    Ok((output, reader.position))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_size: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), TextureError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+
+        if symbol < 256 {
+            if output.len() >= max_size {
+                return Err(TextureError::Malformed(INFLATE_TOO_LARGE));
+            }
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(TextureError::Malformed("Invalid length symbol"));
+        }
+        let length =
+            LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+        let symbol = distances.decode(reader)? as usize;
+        if symbol >= DISTANCE_BASE.len() {
+            return Err(TextureError::Malformed("Invalid distance symbol"));
+        }
+        let distance =
+            DISTANCE_BASE[symbol] as usize + reader.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+
+        if distance > output.len() {
+            return Err(TextureError::Malformed("Distance too far back"));
+        }
+        if output.len() + length > max_size {
+            return Err(TextureError::Malformed(INFLATE_TOO_LARGE));
+        }
+
+        let start = output.len() - distance;
+        for i in 0..length {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Encoding
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Encode an image as an 8 bit RGBA PNG file. Image data is stored without compression.
+pub fn encode_png(image: &Image) -> Vec<u8> {
+    let stride = (image.width as usize * 4).max(1);
+    let mut raw = Vec::with_capacity((stride + 1) * image.height as usize);
+    for row in image.pixels.chunks_exact(stride) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let length = block.len() as u16;
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&image.width.to_be_bytes());
+    header.extend_from_slice(&image.height.to_be_bytes());
+    header.extend_from_slice(&[8, PNG_COLOR_RGBA, 0, 0, 0]);
+
+    let mut file = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut file, b"IHDR", &header);
+    write_chunk(&mut file, b"IDAT", &zlib);
+    write_chunk(&mut file, b"IEND", &[]);
+    file
+}
+
+fn write_chunk(file: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    file.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let crc_start = file.len();
+    file.extend_from_slice(chunk_type);
+    file.extend_from_slice(data);
+    let crc = crc32(&file[crc_start..]);
+    file.extend_from_slice(&crc.to_be_bytes());
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Checksums
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        *entry = c;
+    }
+
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc = table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zlib_stored(raw: &[u8]) -> Vec<u8> {
+        let mut zlib = vec![0x78, 0x01, 1];
+        zlib.extend_from_slice(&(raw.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(raw.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(raw);
+        zlib.extend_from_slice(&adler32(raw).to_be_bytes());
+        zlib
+    }
+
+    ///8 bit grayscale file from already filtered rows.
+    fn gray_png(width: u32, height: u32, interlace: u8, raw: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, PNG_COLOR_GRAY, 0, 0, interlace]);
+
+        let mut file = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut file, b"IHDR", &header);
+        write_chunk(&mut file, b"IDAT", &zlib_stored(raw));
+        write_chunk(&mut file, b"IEND", &[]);
+        file
+    }
+
+    fn gray_values(image: &Image) -> Vec<u8> {
+        image
+            .pixels
+            .chunks_exact(4)
+            .map(|pixel| {
+                assert_eq!(pixel[0..3], [pixel[0]; 3]);
+                assert_eq!(pixel[3], 255);
+                pixel[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encoded_images_decode_unchanged() {
+        let pixels: Vec<u8> = (0..24).map(|value| value * 10).collect();
+        let image = Image::new(3, 2, pixels.clone());
+        let decoded = decode_png(&encode_png(&image)).unwrap();
+
+        assert_eq!((decoded.width, decoded.height), (3, 2));
+        assert_eq!(decoded.pixels, pixels);
+    }
+
+    #[test]
+    fn reverses_every_filter() {
+        #[rustfmt::skip]
+        let raw = [
+            1, 10, 5, 5, //Sub
+            2, 1, 1, 1, //Up
+            3, 5, 5, 5, //Average
+            4, 0, 0, 0, //Paeth
+        ];
+        let image = decode_png(&gray_png(3, 4, 0, &raw)).unwrap();
+        assert_eq!(
+            gray_values(&image),
+            [10, 15, 20, 11, 16, 21, 10, 18, 24, 10, 18, 24]
+        );
+    }
+
+    #[test]
+    fn places_adam7_passes() {
+        //A 2x2 image only has pixels in passes 1, 6 and 7.
+        let raw = [0, 1, 0, 2, 0, 3, 4];
+        let image = decode_png(&gray_png(2, 2, 1, &raw)).unwrap();
+        assert_eq!(gray_values(&image), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_hostile_files() {
+        let file = encode_png(&Image::new(2, 2, vec![7; 16]));
+        for length in 0..file.len() - 12 {
+            assert!(decode_png(&file[..length]).is_err(), "length {length}");
+        }
+
+        //More data than the header asks for is a zlib bomb, fail instead of inflating all of it.
+        assert_eq!(
+            decode_png(&gray_png(1, 1, 0, &[0; 4096])).err(),
+            Some(TextureError::Malformed(INFLATE_TOO_LARGE))
+        );
+        assert_eq!(
+            decode_png(&gray_png(3, 2, 0, &[0; 6])).err(),
+            Some(TextureError::Malformed("Unexpected end of image data"))
+        );
+        assert!(decode_png(&gray_png(2, 1, 0, &[9, 1, 2])).is_err());
+
+        //A fixed huffman block with one literal followed by a hundred 258 byte copies at distance 1.
+        let mut bits = vec![true, true, false];
+        let mut push_code = |code: u32, length: u32| {
+            bits.extend((0..length).rev().map(|bit| code >> bit & 1 != 0));
+        };
+        push_code(0b0011_0000, 8);
+        for _ in 0..100 {
+            push_code(0b1100_0101, 8);
+            push_code(0, 5);
+        }
+        push_code(0, 7);
+        let mut bomb = vec![0; bits.len().div_ceil(8)];
+        for (index, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+            bomb[index / 8] |= 1 << (index % 8);
+        }
+        assert_eq!(
+            inflate(&bomb, 25_801).map(|(output, _)| output.len()),
+            Ok(25_801)
+        );
+        assert_eq!(
+            inflate(&bomb, 1024).err(),
+            Some(TextureError::Malformed(INFLATE_TOO_LARGE))
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use crate::image::Image;
+use crate::texture::TextureError;
+
+const QOI_MAGIC: &[u8; 4] = b"qoif";
+const QOI_HEADER_SIZE: usize = 14;
+const QOI_END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+const QOI_OP_INDEX: u8 = 0x00;
+const QOI_OP_DIFF: u8 = 0x40;
+const QOI_OP_LUMA: u8 = 0x80;
+const QOI_OP_RUN: u8 = 0xc0;
+const QOI_OP_RGB: u8 = 0xfe;
+const QOI_OP_RGBA: u8 = 0xff;
+const QOI_MASK: u8 = 0xc0;
+
+///Largest width * height accepted, guards against absurd allocations from corrupt headers.
+const QOI_MAX_PIXELS: u64 = 1 << 28;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Decoding
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Decode a QOI file with 3 or 4 channels.
+pub fn decode_qoi(file: &[u8]) -> Result<Image, TextureError> {
+    if file.len() < QOI_HEADER_SIZE || &file[0..4] != QOI_MAGIC {
+        return Err(TextureError::InvalidSignature);
+    }
+
+    let width = u32::from_be_bytes(file[4..8].try_into().unwrap());
+    let height = u32::from_be_bytes(file[8..12].try_into().unwrap());
+    let channels = file[12];
+
+    if width == 0 || height == 0 || width as u64 * height as u64 > QOI_MAX_PIXELS {
+        return Err(TextureError::InvalidDimensions {
+            width: width as i64,
+            height: height as i64,
+        });
+    }
+
+    if channels != 3 && channels != 4 {
+        return Err(TextureError::Unsupported(String::from(
+            "Unsupported QOI channel count",
+        )));
+    }
+
+    //A single QOI_OP_RUN byte is the most pixels one byte of data can hold.
+    let pixel_count = width as usize * height as usize;
+    let data_size = file
+        .len()
+        .saturating_sub(QOI_HEADER_SIZE + QOI_END_MARKER.len());
+    if pixel_count > data_size * 62 {
+        return Err(TextureError::UnexpectedEof);
+    }
+
+    let mut pixels = Vec::with_capacity(pixel_count * 4);
+    let mut index = [[0u8; 4]; 64];
+    let mut pixel = [0, 0, 0, 255];
+    let mut position = QOI_HEADER_SIZE;
+    let mut run = 0;
+
+    let end = file.len().saturating_sub(QOI_END_MARKER.len());
+    let byte = |position: usize| -> Result<u8, TextureError> {
+        if position < end {
+            Ok(file[position])
+        } else {
+            Err(TextureError::UnexpectedEof)
+        }
+    };
+
+    while pixels.len() < pixel_count * 4 {
+        if run > 0 {
+            run -= 1;
+        } else {
+            let op = byte(position)?;
+            position += 1;
+
+            match op {
+                QOI_OP_RGB => {
+                    pixel[0] = byte(position)?;
+                    pixel[1] = byte(position + 1)?;
+                    pixel[2] = byte(position + 2)?;
+                    position += 3;
+                }
+                QOI_OP_RGBA => {
+                    pixel[0] = byte(position)?;
+                    pixel[1] = byte(position + 1)?;
+                    pixel[2] = byte(position + 2)?;
+                    pixel[3] = byte(position + 3)?;
+                    position += 4;
+                }
+                _ => match op & QOI_MASK {
+                    QOI_OP_INDEX => pixel = index[op as usize],
+                    QOI_OP_DIFF => {
+                        pixel[0] = pixel[0].wrapping_add((op >> 4) & 0x03).wrapping_sub(2);
+                        pixel[1] = pixel[1].wrapping_add((op >> 2) & 0x03).wrapping_sub(2);
+                        pixel[2] = pixel[2].wrapping_add(op & 0x03).wrapping_sub(2);
+                    }
+                    QOI_OP_LUMA => {
+                        let second = byte(position)?;
+                        position += 1;
+                        let green = (op & 0x3f).wrapping_sub(32);
+                        pixel[0] = pixel[0]
+                            .wrapping_add(green)
+                            .wrapping_add(second >> 4)
+                            .wrapping_sub(8);
+                        pixel[1] = pixel[1].wrapping_add(green);
+                        pixel[2] = pixel[2]
+                            .wrapping_add(green)
+                            .wrapping_add(second & 0x0f)
+                            .wrapping_sub(8);
+                    }
+                    QOI_OP_RUN => run = op & 0x3f,
+                    _ => unreachable!(),
+                },
+            }
+
+            index[qoi_hash(pixel)] = pixel;
+        }
+
+        pixels.extend_from_slice(&pixel);
+    }
+
+    Ok(Image::new(width, height, pixels))
+}
+
+fn qoi_hash(pixel: [u8; 4]) -> usize {
+    let [r, g, b, a] = pixel.map(|channel| channel as usize);
+    (r * 3 + g * 5 + b * 7 + a * 11) % 64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qoi(width: u32, height: u32, channels: u8, ops: &[u8]) -> Vec<u8> {
+        let mut file = QOI_MAGIC.to_vec();
+        file.extend_from_slice(&width.to_be_bytes());
+        file.extend_from_slice(&height.to_be_bytes());
+        file.extend_from_slice(&[channels, 0]);
+        file.extend_from_slice(ops);
+        file.extend_from_slice(&QOI_END_MARKER);
+        file
+    }
+
+    #[test]
+    fn decodes_every_op() {
+        #[rustfmt::skip]
+        let ops = [
+            QOI_OP_RGB, 10, 20, 30,
+            QOI_OP_DIFF | 3 << 4 | 2 << 2 | 1, //+1, 0, -1
+            9, //Index of the first pixel.
+            QOI_OP_LUMA | 37, 8 << 4 | 6, //Green +5, red +5, blue +3
+        ];
+        let image = decode_qoi(&qoi(2, 2, 3, &ops)).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(image.pixels, [
+            10, 20, 30, 255, 11, 20, 29, 255,
+            10, 20, 30, 255, 15, 25, 33, 255,
+        ]);
+
+        let ops = [QOI_OP_RGBA, 1, 2, 3, 4, QOI_OP_RUN | 1];
+        let image = decode_qoi(&qoi(3, 1, 4, &ops)).unwrap();
+        assert_eq!(image.pixels, [1, 2, 3, 4].repeat(3));
+    }
+
+    #[test]
+    fn rejects_truncated_and_hostile_files() {
+        let file = qoi(2, 1, 4, &[QOI_OP_RGB, 1, 2, 3, QOI_OP_RUN]);
+        for length in 0..file.len() - QOI_END_MARKER.len() {
+            assert!(decode_qoi(&file[..length]).is_err(), "length {length}");
+        }
+        assert!(matches!(
+            decode_qoi(&qoi(1 << 16, 1 << 16, 4, &[])),
+            Err(TextureError::InvalidDimensions { .. })
+        ));
+        assert!(matches!(
+            decode_qoi(&qoi(1, 1, 2, &[QOI_OP_RUN])),
+            Err(TextureError::Unsupported(_))
+        ));
+
+        //A header asking for far more pixels than the data could hold fails before allocating.
+        assert_eq!(
+            decode_qoi(&qoi(16384, 16384, 4, &[QOI_OP_RUN | 61; 8])).err(),
+            Some(TextureError::UnexpectedEof)
+        );
+        assert_eq!(
+            decode_qoi(&qoi(8, 62, 4, &[QOI_OP_RUN | 61; 7])).err(),
+            Some(TextureError::UnexpectedEof)
+        );
+    }
+}
@@ -39,6 +39,15 @@ impl RngEngine {
         low + (high - low) * num
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    ///Restart the sequence from seed, keeping the table size.
+    pub fn reseed(&mut self, seed: u64) {
+        self.regenerate_uniform(seed, self.table.len());
+    }
+
     fn regenerate_uniform(&mut self, seed: u64, size: usize) {
         self.table = Self::pseudo_uniform(seed as f64, size as f64);
         self.position = 0;
@@ -1,7 +1,12 @@
+use crate::image::Image;
+use crate::math::{Matrix, Vector};
+use crate::particle::Particle;
+use crate::platform::RenderBackend;
 use crate::shader::*;
-use crate::sprite::ParticleSystem;
+use crate::sprite::ParticleBuilder;
 use crate::sprite::TextBuilder;
 use crate::sprite::{RectBuilder, SpriteBuilder};
+use crate::statistics::BatchStats;
 use crate::system::gl32::*;
 use crate::system::win32::*;
 use crate::texture::Texture;
@@ -17,9 +22,10 @@ pub struct Renderer {
     pub rect: RectBuilder,
     pub sprite: SpriteBuilder,
     pub text: TextBuilder,
-    pub particle: ParticleSystem,
+    pub particle: ParticleBuilder,
     pub shader: Shader,
     pub texture: Texture,
+    batch_stats: BatchStats,
 }
 
 #[allow(non_snake_case)]
@@ -377,6 +383,27 @@ impl GL {
             open_gl_error("glTexParameteri");
         }
     }
+    pub fn ReadPixels(
+        &self,
+        x: GLint,
+        y: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        type_: GLenum,
+        data: *mut c_void,
+    ) {
+        unsafe {
+            glReadPixels(x, y, width, height, format, type_, data);
+            open_gl_error("glReadPixels");
+        }
+    }
+    pub fn GetIntegerv(&self, pname: GLenum, data: *mut GLint) {
+        unsafe {
+            glGetIntegerv(pname, data);
+            open_gl_error("glGetIntegerv");
+        }
+    }
     pub fn BlendFunc(&self, sfactor: GLenum, dfactor: GLenum) {
         unsafe {
             glBlendFunc(sfactor, dfactor);
@@ -393,7 +420,7 @@ impl GL {
 
 #[allow(dead_code)]
 impl Renderer {
-    pub fn new(device_context: HDC, pool_size: usize) -> Box<Self> {
+    pub fn new(device_context: HDC) -> Box<Self> {
         unsafe {
             let pfd = crate::system::gl32::PFD;
             let pixel_format = ChoosePixelFormat(device_context, &pfd);
@@ -453,12 +480,12 @@ impl Renderer {
                 "particle_shader",
             );
 
-            let texture = Texture::new();
+            let texture = Texture::new(&gl);
 
             let rect = RectBuilder::new(shader.get("line_shader"), &gl);
             let sprite = SpriteBuilder::new(shader.get("sprite_shader"), &gl);
             let text = TextBuilder::new();
-            let particle = ParticleSystem::new(pool_size, shader.get("particle_shader"), &gl);
+            let particle = ParticleBuilder::new(shader.get("particle_shader"), &gl);
 
             Box::new(Self {
                 device_context,
@@ -470,6 +497,7 @@ impl Renderer {
                 particle,
                 shader,
                 texture,
+                batch_stats: BatchStats::default(),
             })
         }
     }
@@ -488,16 +516,154 @@ impl Renderer {
         true
     }
 
-    pub fn display_frame(&self) -> bool {
-        unsafe {
-            SwapBuffers(self.device_context);
-            DwmFlush();
-        }
+    pub fn begin_drawing(&self) -> bool {
         true
     }
+}
 
-    pub fn begin_drawing(&self) -> bool {
-        true
+impl RenderBackend for Renderer {
+    fn load_texture(&mut self, path: &str) -> GLuint {
+        self.texture.load(&self.gl, path)
+    }
+
+    fn load_texture_dir(&mut self, path: &str) {
+        self.texture.load_directory(&self.gl, path);
+    }
+
+    fn upload_texture(&mut self, name: &str, image: &Image) -> GLuint {
+        self.texture.upload(&self.gl, name, image)
+    }
+
+    fn get_texture(&self, name: &str) -> GLuint {
+        self.texture.get(name)
+    }
+
+    fn display_clear(&mut self, color: (f32, f32, f32, f32)) {
+        Renderer::display_clear(self, color);
+    }
+
+    fn draw_rect(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        color: Vector,
+        proj: Matrix,
+        view: Matrix,
+    ) {
+        self.batch_stats.draw_calls += 1;
+        self.rect
+            .draw_rect(x, y, z, w, h, rot, color, proj, view, &self.gl);
+    }
+
+    fn draw_line(
+        &mut self,
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        z: f32,
+        w: f32,
+        color: Vector,
+        proj: Matrix,
+        view: Matrix,
+    ) {
+        self.batch_stats.draw_calls += 1;
+        self.rect
+            .draw_line(x1, y1, x2, y2, z, w, color, proj, view, &self.gl);
+    }
+
+    fn add_sprite(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        texture_id: GLuint,
+        uv_rect: (f32, f32, f32, f32),
+        color: Vector,
+        proj: Matrix,
+        view: Matrix,
+    ) {
+        self.sprite
+            .add_sprite(x, y, z, w, h, rot, texture_id, uv_rect, color, proj, view);
+    }
+
+    fn draw_sprites(&mut self) {
+        let (sprites, batches) = self.sprite.draw_sprites(&self.gl);
+        self.batch_stats.sprites += sprites;
+        self.batch_stats.batches += batches;
+        self.batch_stats.draw_calls += batches;
+    }
+
+    fn define_font(
+        &mut self,
+        name: &str,
+        size: i32,
+        weight: i32,
+        italic: bool,
+        underline: bool,
+        strikeout: bool,
+        font: &str,
+    ) {
+        self.text.define_font(
+            name,
+            size,
+            weight,
+            italic,
+            underline,
+            strikeout,
+            font,
+            self.device_context,
+        );
+    }
+
+    fn draw_text(&mut self, text: &str, x: f32, y: f32, color: (f32, f32, f32, f32), font: &str) {
+        self.batch_stats.draw_calls += 1;
+        self.text.draw_text(text, x, y, color, font, &self.gl);
+    }
+
+    fn draw_particles(&mut self, particles: &[Particle], proj: Matrix, view: Matrix) {
+        self.batch_stats.draw_calls += self
+            .particle
+            .draw_particles(particles, proj, view, &self.gl);
+    }
+
+    fn take_batch_stats(&mut self) -> BatchStats {
+        std::mem::take(&mut self.batch_stats)
+    }
+
+    fn capture_frame(&self) -> Option<Image> {
+        let mut viewport = [0 as GLint; 4];
+        self.gl.GetIntegerv(GL_VIEWPORT, viewport.as_mut_ptr());
+        let (width, height) = (viewport[2].max(0) as usize, viewport[3].max(0) as usize);
+
+        let mut pixels = vec![0u8; width * height * 4];
+        self.gl.ReadPixels(
+            viewport[0],
+            viewport[1],
+            width as GLsizei,
+            height as GLsizei,
+            GL_RGBA,
+            GL_UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut c_void,
+        );
+
+        //GL reads the bottom row first.
+        let stride = (width * 4).max(1);
+        let pixels = pixels
+            .chunks_exact(stride)
+            .rev()
+            .flatten()
+            .copied()
+            .collect();
+
+        Some(Image::new(width as u32, height as u32, pixels))
     }
 }
 
@@ -540,17 +706,17 @@ fn _to_wchar(str: &str) -> *const u16 {
 
 pub const SPRITE_SHADER_VERT: &str = r#"
 #version 330 core
-layout(location = 0) in vec3 vertexPosition_modelspace;
+layout(location = 0) in vec3 vertexPosition_worldspace;
 layout(location = 1) in vec2 vertexUV;
+layout(location = 2) in vec4 vertexColor;
 out vec2 UV;
 out vec4 tint;
-uniform mat4 MVP;
-uniform vec4 COLOR;
+uniform mat4 VP;
 
 void main(){
-  gl_Position = MVP * vec4(vertexPosition_modelspace, 1);
+  gl_Position = VP * vec4(vertexPosition_worldspace, 1);
   UV = vertexUV;
-  tint = COLOR;
+  tint = vertexColor;
 }
 "#;
 
@@ -592,19 +758,19 @@ void main() {
 
 pub const PARTICLE_SHADER_VERT: &str = r#"
 #version 330 core
-layout(location = 0) in vec3 vertexPos;
+layout(location = 0) in vec3 vertexPosition_worldspace;
 layout(location = 1) in vec2 vertexUV;
+layout(location = 2) in vec4 vertexColor;
 
 out vec2 UV;
 out vec4 tint;
 
-uniform mat4 MVP;
-uniform vec4 COLOR;
+uniform mat4 VP;
 
 void main() {
-    gl_Position = MVP * vec4(vertexPos, 1);
+    gl_Position = VP * vec4(vertexPosition_worldspace, 1);
     UV = vertexUV;
-    tint = COLOR;
+    tint = vertexColor;
 }
 "#;
 
@@ -0,0 +1,370 @@
+use std::fs;
+use std::time::Duration;
+
+use crate::input::InputSnapshot;
+use crate::system::win32::XINPUT_GAMEPAD;
+
+const REPLAY_MAGIC: &[u8; 4] = b"RZIN";
+const REPLAY_VERSION: u8 = 1;
+const REPLAY_HEADER_SIZE: usize = 17;
+
+//Each frame starts with its dt in nanoseconds as a varint and a mask of the sections that changed
+//since the previous frame. Only changed sections follow the mask.
+const CHANGED_KEYBOARD: u8 = 1;
+const CHANGED_MOUSE: u8 = 2;
+const CHANGED_CONTROLLER: u8 = 4;
+
+const KEYBOARD_SIZE: usize = 32;
+const MOUSE_SIZE: usize = 13;
+const CONTROLLER_SIZE: usize = 12;
+
+///Records the input state and dt of every frame.
+pub struct InputRecorder {
+    seed: u64,
+    frame_count: u32,
+    data: Vec<u8>,
+    previous: Option<InputSnapshot>,
+}
+
+impl InputRecorder {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            frame_count: 0,
+            data: Vec::new(),
+            previous: None,
+        }
+    }
+
+    pub fn record(&mut self, dt: Duration, snapshot: &InputSnapshot) {
+        write_varint(&mut self.data, dt.as_nanos() as u64);
+
+        let mask_position = self.data.len();
+        self.data.push(0);
+        let mut mask = 0;
+
+        let previous = self.previous.as_ref();
+
+        let keys = encode_keys(&snapshot.keys);
+        if previous.is_none_or(|previous| encode_keys(&previous.keys) != keys) {
+            mask |= CHANGED_KEYBOARD;
+            self.data.extend_from_slice(&keys);
+        }
+
+        let mouse = encode_mouse(snapshot);
+        if previous.is_none_or(|previous| encode_mouse(previous) != mouse) {
+            mask |= CHANGED_MOUSE;
+            self.data.extend_from_slice(&mouse);
+        }
+
+        for (index, gamepad) in snapshot.controllers.iter().enumerate() {
+            let encoded = encode_gamepad(gamepad);
+            if previous
+                .is_none_or(|previous| encode_gamepad(&previous.controllers[index]) != encoded)
+            {
+                mask |= CHANGED_CONTROLLER << index;
+                self.data.extend_from_slice(&encoded);
+            }
+        }
+
+        self.data[mask_position] = mask;
+        self.frame_count += 1;
+        self.previous = Some(snapshot.clone());
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut file = Vec::with_capacity(REPLAY_HEADER_SIZE + self.data.len());
+        file.extend_from_slice(REPLAY_MAGIC);
+        file.push(REPLAY_VERSION);
+        file.extend_from_slice(&self.seed.to_le_bytes());
+        file.extend_from_slice(&self.frame_count.to_le_bytes());
+        file.extend_from_slice(&self.data);
+
+        fs::write(path, file)
+    }
+}
+
+///Plays back a recording made by InputRecorder one frame at a time.
+pub struct InputReplay {
+    seed: u64,
+    frames_left: u32,
+    data: Vec<u8>,
+    position: usize,
+    current: InputSnapshot,
+}
+
+impl InputReplay {
+    pub fn load(path: &str) -> Option<Self> {
+        let Ok(file) = fs::read(path) else {
+            eprintln!("Loading replay '{path}' failed: File not found!");
+            return None;
+        };
+
+        if file.len() < REPLAY_HEADER_SIZE || &file[0..4] != REPLAY_MAGIC {
+            eprintln!("Loading replay '{path}' failed: Malformed header data!");
+            return None;
+        }
+
+        if file[4] != REPLAY_VERSION {
+            eprintln!(
+                "Loading replay '{path}' failed: Unsupported version {}!",
+                file[4]
+            );
+            return None;
+        }
+
+        let seed = u64::from_le_bytes(file[5..13].try_into().unwrap());
+        let frames_left = u32::from_le_bytes(file[13..17].try_into().unwrap());
+
+        Some(Self {
+            seed,
+            frames_left,
+            data: file[REPLAY_HEADER_SIZE..].to_vec(),
+            position: 0,
+            current: InputSnapshot {
+                keys: [false; 256],
+                mouse_x: 0,
+                mouse_y: 0,
+                mouse_buttons: 0,
+                wheel_delta: 0,
+                wheel_h_delta: 0,
+                controllers: [0, 1, 2, 3].map(|_| decode_gamepad(&[0; CONTROLLER_SIZE])),
+            },
+        })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    ///Advance to the next recorded frame. Returns None once the recording has run out.
+    pub fn next_frame(&mut self) -> Option<(Duration, &InputSnapshot)> {
+        if self.frames_left == 0 {
+            return None;
+        }
+        self.frames_left -= 1;
+
+        let frame = self.read_frame();
+        if frame.is_none() {
+            eprintln!("Replay ended early: Malformed frame data!");
+            self.frames_left = 0;
+        }
+
+        frame.map(|dt| (dt, &self.current))
+    }
+
+    fn read_frame(&mut self) -> Option<Duration> {
+        let dt = Duration::from_nanos(read_varint(&self.data, &mut self.position)?);
+        let mask = *self.data.get(self.position)?;
+        self.position += 1;
+
+        if mask & CHANGED_KEYBOARD != 0 {
+            let keys = self.read(KEYBOARD_SIZE)?;
+            let mut current = [false; 256];
+            for (index, down) in current.iter_mut().enumerate() {
+                *down = keys[index / 8] & (1 << (index % 8)) != 0;
+            }
+            self.current.keys = current;
+        }
+
+        if mask & CHANGED_MOUSE != 0 {
+            let mouse = self.read(MOUSE_SIZE)?;
+            let x = i32::from_le_bytes(mouse[0..4].try_into().unwrap());
+            let y = i32::from_le_bytes(mouse[4..8].try_into().unwrap());
+            let buttons = mouse[8];
+            let wheel = i16::from_le_bytes([mouse[9], mouse[10]]);
+            let wheel_h = i16::from_le_bytes([mouse[11], mouse[12]]);
+
+            self.current.mouse_x = x;
+            self.current.mouse_y = y;
+            self.current.mouse_buttons = buttons;
+            self.current.wheel_delta = wheel;
+            self.current.wheel_h_delta = wheel_h;
+        }
+
+        for index in 0..self.current.controllers.len() {
+            if mask & (CHANGED_CONTROLLER << index) != 0 {
+                let gamepad = decode_gamepad(self.read(CONTROLLER_SIZE)?);
+                self.current.controllers[index] = gamepad;
+            }
+        }
+
+        Some(dt)
+    }
+
+    fn read(&mut self, size: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.position..self.position + size)?;
+        self.position += size;
+        Some(bytes)
+    }
+}
+
+fn encode_keys(keys: &[bool; 256]) -> [u8; KEYBOARD_SIZE] {
+    let mut encoded = [0; KEYBOARD_SIZE];
+    for (index, _) in keys.iter().enumerate().filter(|(_, down)| **down) {
+        encoded[index / 8] |= 1 << (index % 8);
+    }
+    encoded
+}
+
+fn encode_mouse(snapshot: &InputSnapshot) -> [u8; MOUSE_SIZE] {
+    let mut encoded = [0; MOUSE_SIZE];
+    encoded[0..4].copy_from_slice(&snapshot.mouse_x.to_le_bytes());
+    encoded[4..8].copy_from_slice(&snapshot.mouse_y.to_le_bytes());
+    encoded[8] = snapshot.mouse_buttons;
+    encoded[9..11].copy_from_slice(&snapshot.wheel_delta.to_le_bytes());
+    encoded[11..13].copy_from_slice(&snapshot.wheel_h_delta.to_le_bytes());
+    encoded
+}
+
+fn encode_gamepad(gamepad: &XINPUT_GAMEPAD) -> [u8; CONTROLLER_SIZE] {
+    let mut encoded = [0; CONTROLLER_SIZE];
+    encoded[0..2].copy_from_slice(&gamepad.wButtons.to_le_bytes());
+    encoded[2] = gamepad.bLeftTrigger;
+    encoded[3] = gamepad.bRightTrigger;
+    encoded[4..6].copy_from_slice(&gamepad.sThumbLX.to_le_bytes());
+    encoded[6..8].copy_from_slice(&gamepad.sThumbLY.to_le_bytes());
+    encoded[8..10].copy_from_slice(&gamepad.sThumbRX.to_le_bytes());
+    encoded[10..12].copy_from_slice(&gamepad.sThumbRY.to_le_bytes());
+    encoded
+}
+
+fn decode_gamepad(encoded: &[u8]) -> XINPUT_GAMEPAD {
+    XINPUT_GAMEPAD {
+        wButtons: u16::from_le_bytes([encoded[0], encoded[1]]),
+        bLeftTrigger: encoded[2],
+        bRightTrigger: encoded[3],
+        sThumbLX: i16::from_le_bytes([encoded[4], encoded[5]]),
+        sThumbLY: i16::from_le_bytes([encoded[6], encoded[7]]),
+        sThumbRX: i16::from_le_bytes([encoded[8], encoded[9]]),
+        sThumbRY: i16::from_le_bytes([encoded[10], encoded[11]]),
+    }
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        data.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+
+    loop {
+        let byte = *data.get(*position)?;
+        *position += 1;
+
+        if shift >= 64 {
+            return None;
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Input;
+    use crate::rand::RngEngine;
+
+    fn snapshot(frame: usize) -> InputSnapshot {
+        let mut snapshot = Input::new().snapshot();
+        snapshot.keys[65 + frame % 3] = true;
+        snapshot.mouse_x = frame as i32 * 7 - 10;
+        snapshot.mouse_y = 300;
+        snapshot.mouse_buttons = (frame % 2) as u8;
+        snapshot.wheel_delta = if frame == 2 { -120 } else { 0 };
+        snapshot.controllers[frame % 4].wButtons = 0x1000;
+        snapshot.controllers[1].sThumbLX = -32768;
+        snapshot
+    }
+
+    #[test]
+    fn recording_round_trips_through_a_file() {
+        let seed = 0x1234_5678_9abc;
+        let mut recorder = InputRecorder::new(seed);
+        let frames: Vec<_> = (0..6)
+            .map(|frame| {
+                (
+                    Duration::from_micros(16_667 + frame as u64),
+                    snapshot(frame),
+                )
+            })
+            .collect();
+        for (dt, snapshot) in &frames {
+            recorder.record(*dt, snapshot);
+        }
+        //Unchanged frames only store their dt and an empty mask.
+        recorder.record(Duration::from_millis(5), &frames[5].1);
+
+        let path = std::env::temp_dir().join("replay_round_trip.rzin");
+        let path = path.to_str().unwrap();
+        recorder.save(path).unwrap();
+        let mut replay = InputReplay::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(replay.seed(), seed);
+        let mut recorded_rng = RngEngine::new(seed, 1000);
+        let mut replayed_rng = RngEngine::new(replay.seed(), 1000);
+        for _ in 0..100 {
+            assert_eq!(
+                recorded_rng.get_int_range(0, 1000),
+                replayed_rng.get_int_range(0, 1000)
+            );
+        }
+
+        let expected = frames.iter().cloned().chain(std::iter::once((
+            Duration::from_millis(5),
+            frames[5].1.clone(),
+        )));
+        for (dt, snapshot) in expected {
+            let (replayed_dt, replayed) = replay.next_frame().unwrap();
+            assert_eq!(replayed_dt, dt);
+            assert_eq!(replayed.keys, snapshot.keys);
+            assert_eq!(encode_mouse(replayed), encode_mouse(&snapshot));
+            for (replayed, recorded) in replayed.controllers.iter().zip(&snapshot.controllers) {
+                assert_eq!(encode_gamepad(replayed), encode_gamepad(recorded));
+            }
+        }
+        assert!(replay.next_frame().is_none());
+    }
+
+    #[test]
+    fn replayed_gamepad_edges_match_live_polling() {
+        let mut recorder = InputRecorder::new(1);
+        let mut live = Input::new();
+        let mut live_pressed = Vec::new();
+        for frame in 0..6 {
+            let mut state = crate::system::win32::XINPUT_STATE::default();
+            state.Gamepad.wButtons = if frame % 3 == 1 { 0x1000 } else { 0 };
+            live.controllers[0].process_input(&state);
+            recorder.record(Duration::from_millis(16), &live.snapshot());
+            live_pressed.push(live.controllers[0].pressed(crate::input::GAMEPAD::A));
+            live.advance();
+        }
+
+        let path = std::env::temp_dir().join("replay_gamepad_edges.rzin");
+        let path = path.to_str().unwrap();
+        recorder.save(path).unwrap();
+        let mut replay = InputReplay::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let mut replayed = Input::new();
+        let mut replayed_pressed = Vec::new();
+        while let Some((_, snapshot)) = replay.next_frame() {
+            replayed.apply_snapshot(snapshot);
+            replayed_pressed.push(replayed.controllers[0].pressed(crate::input::GAMEPAD::A));
+            replayed.advance();
+        }
+
+        assert_eq!(replayed_pressed, live_pressed);
+        assert_eq!(replayed_pressed, [false, true, false, false, true, false]);
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use crate::atlas::FULL_UV_RECT;
+use crate::bcn::decode_bcn;
+use crate::image::Image;
+use crate::math::{float, Matrix, Vector};
+use crate::particle::Particle;
+use crate::platform::RenderBackend;
+use crate::sprite::{
+    line_model, quad_model, quad_vertices, sort_sprites, sprite_batches, QUAD, QUAD_UV,
+};
+use crate::statistics::BatchStats;
+use crate::system::gl32::GLuint;
+use crate::texture::read_texture;
+
+struct SoftwareTexture {
+    width: i32,
+    height: i32,
+    ///RGBA texels, row 0 is at v = 0.
+    texels: Vec<u8>,
+}
+
+impl SoftwareTexture {
+    ///Bilinear sample with repeat wrapping, matches GL_LINEAR and GL_REPEAT.
+    fn sample(&self, u: f32, v: f32) -> Vector {
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        let c00 = self.texel(x0 as i32, y0 as i32);
+        let c10 = self.texel(x0 as i32 + 1, y0 as i32);
+        let c01 = self.texel(x0 as i32, y0 as i32 + 1);
+        let c11 = self.texel(x0 as i32 + 1, y0 as i32 + 1);
+
+        let top = Vector::lerp(c00, c10, fx);
+        let bottom = Vector::lerp(c01, c11, fx);
+        Vector::lerp(top, bottom, fy)
+    }
+
+    fn texel(&self, x: i32, y: i32) -> Vector {
+        let x = x.rem_euclid(self.width);
+        let y = y.rem_euclid(self.height);
+        let i = ((y * self.width + x) * 4) as usize;
+        Vector::new(
+            self.texels[i] as f32 / 255.0,
+            self.texels[i + 1] as f32 / 255.0,
+            self.texels[i + 2] as f32 / 255.0,
+            self.texels[i + 3] as f32 / 255.0,
+        )
+    }
+}
+
+struct SoftwareSprite {
+    ///World space vertices, drawn with view_projection like the batched GL sprites.
+    positions: [[f32; 3]; 6],
+    view_projection: Matrix,
+    z: f32,
+    color: Vector,
+    texture_id: GLuint,
+    uv_rect: (f32, f32, f32, f32),
+}
+
+///Screen space vertex, x and y in pixels from the top left corner and z in depth range 0..1.
+#[derive(Clone, Copy)]
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    inv_w: f32,
+    u: f32,
+    v: f32,
+}
+
+///Pure software renderer drawing into an RGBA8 framebuffer. Mirrors the GL renderer: quads are
+///blended with SRC_ALPHA/ONE_MINUS_SRC_ALPHA and depth tested with LEQUAL. Supports BMP, PNG,
+///TGA and QOI textures, text isn't drawn.
+pub struct SoftwareRenderer {
+    width: i32,
+    height: i32,
+    color_buffer: Vec<u8>,
+    depth_buffer: Vec<f32>,
+    texture_ids: HashMap<String, GLuint>,
+    textures: Vec<SoftwareTexture>,
+    fonts: HashSet<String>,
+    sprites: Vec<SoftwareSprite>,
+    batch_stats: BatchStats,
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: i32, height: i32) -> Self {
+        let pixel_count = (width.max(0) * height.max(0)) as usize;
+
+        Self {
+            width,
+            height,
+            color_buffer: vec![0; pixel_count * 4],
+            depth_buffer: vec![1.0; pixel_count],
+            texture_ids: HashMap::new(),
+            textures: Vec::new(),
+            fonts: HashSet::new(),
+            sprites: Vec::new(),
+            batch_stats: BatchStats::default(),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    ///RGBA8 pixels of the framebuffer, top row first.
+    pub fn pixels(&self) -> &[u8] {
+        &self.color_buffer
+    }
+
+    fn draw_quad(
+        &mut self,
+        mvp: Matrix,
+        color: Vector,
+        texture_id: Option<GLuint>,
+        uv_rect: (f32, f32, f32, f32),
+    ) {
+        let mut positions = [[0.0; 3]; 6];
+        for (i, position) in positions.iter_mut().enumerate() {
+            position.copy_from_slice(&QUAD[i * 3..i * 3 + 3]);
+        }
+        self.draw_vertices(mvp, positions, color, texture_id, uv_rect);
+    }
+
+    ///Draw the two triangles of a quad from positions transformed by mvp.
+    fn draw_vertices(
+        &mut self,
+        mvp: Matrix,
+        positions: [[f32; 3]; 6],
+        color: Vector,
+        texture_id: Option<GLuint>,
+        uv_rect: (f32, f32, f32, f32),
+    ) {
+        let (u0, v0, u1, v1) = uv_rect;
+        let mut vertices = [ScreenVertex {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            inv_w: 0.0,
+            u: 0.0,
+            v: 0.0,
+        }; 6];
+
+        for (i, vertex) in vertices.iter_mut().enumerate() {
+            let [x, y, z] = positions[i];
+            let position = Vector::new(x, y, z, 1.0);
+            let clip = Matrix::mxv(mvp, position);
+
+            if clip.w <= 0.0 {
+                return;
+            }
+
+            let inv_w = 1.0 / clip.w;
+            *vertex = ScreenVertex {
+                x: (clip.x * inv_w + 1.0) * 0.5 * self.width as f32,
+                y: (1.0 - clip.y * inv_w) * 0.5 * self.height as f32,
+                z: (clip.z * inv_w + 1.0) * 0.5,
+                inv_w,
+                u: float::lerp(u0, u1, QUAD_UV[i * 2]),
+                v: float::lerp(v0, v1, QUAD_UV[i * 2 + 1]),
+            };
+        }
+
+        //Sampling an unbound texture in GL returns opaque black.
+        let (texture, color) = match texture_id {
+            Some(id) => match (id as usize).checked_sub(1) {
+                Some(index) if index < self.textures.len() => (Some(index), color),
+                _ => (None, Vector::new(0.0, 0.0, 0.0, color.w)),
+            },
+            None => (None, color),
+        };
+
+        self.rasterize_triangle([vertices[0], vertices[1], vertices[2]], color, texture);
+        self.rasterize_triangle([vertices[3], vertices[4], vertices[5]], color, texture);
+    }
+
+    fn rasterize_triangle(
+        &mut self,
+        mut v: [ScreenVertex; 3],
+        color: Vector,
+        texture: Option<usize>,
+    ) {
+        let mut area = edge(&v[0], &v[1], v[2].x, v[2].y);
+        if area == 0.0 {
+            return;
+        }
+        if area < 0.0 {
+            v.swap(1, 2);
+            area = -area;
+        }
+
+        let min_x = (v[0].x.min(v[1].x).min(v[2].x).floor() as i32).max(0);
+        let min_y = (v[0].y.min(v[1].y).min(v[2].y).floor() as i32).max(0);
+        let max_x = (v[0].x.max(v[1].x).max(v[2].x).ceil() as i32).min(self.width);
+        let max_y = (v[0].y.max(v[1].y).max(v[2].y).ceil() as i32).min(self.height);
+
+        let top_left = [
+            is_top_left(&v[1], &v[2]),
+            is_top_left(&v[2], &v[0]),
+            is_top_left(&v[0], &v[1]),
+        ];
+
+        for py in min_y..max_y {
+            for px in min_x..max_x {
+                let cx = px as f32 + 0.5;
+                let cy = py as f32 + 0.5;
+                let weights = [
+                    edge(&v[1], &v[2], cx, cy),
+                    edge(&v[2], &v[0], cx, cy),
+                    edge(&v[0], &v[1], cx, cy),
+                ];
+
+                let inside = weights
+                    .iter()
+                    .zip(top_left)
+                    .all(|(&w, top_left)| w > 0.0 || (w == 0.0 && top_left));
+                if !inside {
+                    continue;
+                }
+
+                let b = [weights[0] / area, weights[1] / area, weights[2] / area];
+                let z = b[0] * v[0].z + b[1] * v[1].z + b[2] * v[2].z;
+                if !(0.0..=1.0).contains(&z) {
+                    continue;
+                }
+
+                let index = (py * self.width + px) as usize;
+                if z > self.depth_buffer[index] {
+                    continue;
+                }
+                self.depth_buffer[index] = z;
+
+                let source = match texture {
+                    Some(texture) => {
+                        let inv_w = b[0] * v[0].inv_w + b[1] * v[1].inv_w + b[2] * v[2].inv_w;
+                        let u = (b[0] * v[0].u * v[0].inv_w
+                            + b[1] * v[1].u * v[1].inv_w
+                            + b[2] * v[2].u * v[2].inv_w)
+                            / inv_w;
+                        let t = (b[0] * v[0].v * v[0].inv_w
+                            + b[1] * v[1].v * v[1].inv_w
+                            + b[2] * v[2].v * v[2].inv_w)
+                            / inv_w;
+                        let texel = self.textures[texture].sample(u, t);
+                        Vector::new(
+                            texel.x * color.x,
+                            texel.y * color.y,
+                            texel.z * color.z,
+                            texel.w * color.w,
+                        )
+                    }
+                    None => color,
+                };
+
+                self.blend(index, source);
+            }
+        }
+    }
+
+    ///SRC_ALPHA, ONE_MINUS_SRC_ALPHA blending, applied to all four channels like GL.
+    fn blend(&mut self, index: usize, source: Vector) {
+        let pixel = &mut self.color_buffer[index * 4..index * 4 + 4];
+        let alpha = source.w.clamp(0.0, 1.0);
+        let source = [source.x, source.y, source.z, source.w];
+
+        for (channel, value) in pixel.iter_mut().zip(source) {
+            let destination = *channel as f32 / 255.0;
+            let blended = value.clamp(0.0, 1.0) * alpha + destination * (1.0 - alpha);
+            *channel = to_u8(blended);
+        }
+    }
+}
+
+fn edge(a: &ScreenVertex, b: &ScreenVertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+///Top-left fill rule for clockwise triangles in y down screen space, keeps pixels on an edge shared
+///by two triangles from being drawn twice.
+fn is_top_left(a: &ScreenVertex, b: &ScreenVertex) -> bool {
+    let dx = b.x - a.x;
+    let dy = b.y - a.y;
+    (dy == 0.0 && dx > 0.0) || dy < 0.0
+}
+
+fn to_u8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+}
+
+impl RenderBackend for SoftwareRenderer {
+    fn load_texture(&mut self, path: &str) -> GLuint {
+        let Some((name, image)) = read_texture(path) else {
+            return 0;
+        };
+
+        self.upload_texture(&name, &image)
+    }
+
+    fn load_texture_dir(&mut self, path: &str) {
+        let directory = std::path::Path::new(path);
+
+        if let Ok(entries) = fs::read_dir(directory) {
+            for entry in entries {
+                let entry = entry.unwrap();
+                self.load_texture(entry.path().to_str().unwrap());
+            }
+        } else {
+            eprintln!("Couldn't find texture directory: '{path}'");
+        }
+    }
+
+    fn upload_texture(&mut self, name: &str, image: &Image) -> GLuint {
+        if image.width == 0 || image.height == 0 {
+            eprintln!("Uploading texture '{name}' failed: Image is empty!");
+            return 0;
+        }
+
+        let Some((width, height, pixels)) = image.mip_level(0) else {
+            eprintln!("Uploading texture '{name}' failed: Pixel data is too short!");
+            return 0;
+        };
+
+        //Block compressed images are decoded on the CPU and kept in stored row order like GL
+        //uploads them, RGBA8 texel row 0 is at v = 0, the bottom row of the image like in GL.
+        let texels = if image.format.is_compressed() {
+            decode_bcn(image.format, width, height, pixels)
+        } else {
+            let stride = width as usize * 4;
+            let mut texels = Vec::with_capacity(stride * height as usize);
+            for row in pixels.chunks_exact(stride).rev() {
+                texels.extend_from_slice(row);
+            }
+            texels
+        };
+
+        self.textures.push(SoftwareTexture {
+            width: image.width as i32,
+            height: image.height as i32,
+            texels,
+        });
+        let tex_id = self.textures.len() as GLuint;
+
+        self.texture_ids.insert(String::from(name), tex_id);
+        tex_id
+    }
+
+    fn get_texture(&self, name: &str) -> GLuint {
+        if let Some(tex_id) = self.texture_ids.get(name) {
+            *tex_id
+        } else {
+            eprintln!("get_texture failed: '{name}' does not exist");
+            0
+        }
+    }
+
+    fn display_clear(&mut self, color: (f32, f32, f32, f32)) {
+        let clear = [
+            to_u8(color.0),
+            to_u8(color.1),
+            to_u8(color.2),
+            to_u8(color.3),
+        ];
+
+        for pixel in self.color_buffer.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&clear);
+        }
+        self.depth_buffer.fill(1.0);
+    }
+
+    fn draw_rect(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        color: Vector,
+        proj: Matrix,
+        view: Matrix,
+    ) {
+        let mvp = Matrix::create_mvp(proj, view, quad_model(x, y, z, w, h, rot));
+        self.batch_stats.draw_calls += 1;
+        self.draw_quad(mvp, color, None, FULL_UV_RECT);
+    }
+
+    fn draw_line(
+        &mut self,
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        z: f32,
+        w: f32,
+        color: Vector,
+        proj: Matrix,
+        view: Matrix,
+    ) {
+        let mvp = Matrix::create_mvp(proj, view, line_model(x1, y1, x2, y2, z, w));
+        self.batch_stats.draw_calls += 1;
+        self.draw_quad(mvp, color, None, FULL_UV_RECT);
+    }
+
+    fn add_sprite(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+        h: f32,
+        rot: f32,
+        texture_id: GLuint,
+        uv_rect: (f32, f32, f32, f32),
+        color: Vector,
+        proj: Matrix,
+        view: Matrix,
+    ) {
+        self.sprites.push(SoftwareSprite {
+            positions: quad_vertices(x, y, z, w, h, rot),
+            view_projection: Matrix::mxm(proj, view),
+            z,
+            color,
+            texture_id,
+            uv_rect,
+        });
+    }
+
+    fn draw_sprites(&mut self) {
+        let mut sprites = std::mem::take(&mut self.sprites);
+
+        //Same order and batches as the GL renderer, each batch counts as one draw call.
+        sort_sprites(&mut sprites, |sprite| (sprite.z, sprite.texture_id));
+        let batches = sprite_batches(&sprites, |sprite| {
+            (sprite.texture_id, sprite.view_projection)
+        })
+        .len();
+        self.batch_stats.sprites += sprites.len();
+        self.batch_stats.batches += batches;
+        self.batch_stats.draw_calls += batches;
+
+        for sprite in &sprites {
+            self.draw_vertices(
+                sprite.view_projection,
+                sprite.positions,
+                sprite.color,
+                Some(sprite.texture_id),
+                sprite.uv_rect,
+            );
+        }
+    }
+
+    fn define_font(
+        &mut self,
+        name: &str,
+        _size: i32,
+        _weight: i32,
+        _italic: bool,
+        _underline: bool,
+        _strikeout: bool,
+        _font: &str,
+    ) {
+        self.fonts.insert(String::from(name));
+    }
+
+    fn draw_text(
+        &mut self,
+        _text: &str,
+        _x: f32,
+        _y: f32,
+        _color: (f32, f32, f32, f32),
+        font: &str,
+    ) {
+        if !self.fonts.contains(font) {
+            eprintln!("Invalid font: {font}");
+        }
+    }
+
+    fn draw_particles(&mut self, particles: &[Particle], proj: Matrix, view: Matrix) {
+        self.batch_stats.draw_calls += sprite_batches(particles, |particle| particle.tex_id).len();
+        let view_projection = Matrix::mxm(proj, view);
+        for particle in particles {
+            self.draw_vertices(
+                view_projection,
+                particle.vertices(),
+                particle.color,
+                particle.tex_id,
+                FULL_UV_RECT,
+            );
+        }
+    }
+
+    fn take_batch_stats(&mut self) -> BatchStats {
+        std::mem::take(&mut self.batch_stats)
+    }
+
+    fn capture_frame(&self) -> Option<Image> {
+        Some(Image::new(
+            self.width.max(0) as u32,
+            self.height.max(0) as u32,
+            self.color_buffer.clone(),
+        ))
+    }
+}
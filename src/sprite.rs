@@ -1,10 +1,17 @@
 #![allow(clippy::too_many_arguments)]
 
-use std::{collections::HashMap, ffi::c_void, mem::size_of, time::Duration};
+use crate::{
+    math::{Matrix, Vector},
+    system::gl32::{GLfloat, GLuint},
+};
 
+#[cfg(windows)]
+use std::{collections::HashMap, ffi::c_void, mem::size_of};
+
+#[cfg(windows)]
 use crate::{
-    math::{float, Matrix, Vector},
-    rand::RngEngine,
+    math::float,
+    particle::Particle,
     renderer::{pcstr, GL},
     system::gl32::*,
     system::win32::*,
@@ -34,6 +41,76 @@ pub static QUAD_UV: [GLfloat; 12] = [
     0.0, 0.0,
 ];
 
+///Model matrix for a w by h quad centered on (x,y), rotated by rot degrees.
+pub fn quad_model(x: f32, y: f32, z: f32, w: f32, h: f32, rot: f32) -> Matrix {
+    let translation = Matrix::create_translation_matrix((x, y, -z).into());
+    let rotation = Matrix::create_rotation_matrix(rot);
+    let scale = Matrix::create_scaling_matrix((w, h, 1.0).into());
+    Matrix::create_trs(translation, rotation, scale)
+}
+
+///World space positions of the six QUAD vertices of a sprite, the same as transforming QUAD by
+///quad_model() without building the matrices.
+pub fn quad_vertices(x: f32, y: f32, z: f32, w: f32, h: f32, rot: f32) -> [[f32; 3]; 6] {
+    let (sin, cos) = rot.to_radians().sin_cos();
+    let mut vertices = [[0.0; 3]; 6];
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let local_x = QUAD[i * 3] * w;
+        let local_y = QUAD[i * 3 + 1] * h;
+        *vertex = [
+            x + cos * local_x + sin * local_y,
+            y - sin * local_x + cos * local_y,
+            -z,
+        ];
+    }
+    vertices
+}
+
+///Sort queued sprites into drawing order, back to front so blending works and grouped by texture
+///within the same depth. The sort is stable, sprites with equal keys keep their queue order.
+pub fn sort_sprites<T>(sprites: &mut [T], key: impl Fn(&T) -> (f32, GLuint)) {
+    sprites.sort_by(|a, b| {
+        let (a_z, a_texture) = key(a);
+        let (b_z, b_texture) = key(b);
+        b_z.total_cmp(&a_z).then(a_texture.cmp(&b_texture))
+    });
+}
+
+///Split sorted sprites into runs drawn with a single draw call. Returns the start and length of
+///every run of consecutive sprites with the same key.
+pub fn sprite_batches<T, K: PartialEq>(
+    sprites: &[T],
+    key: impl Fn(&T) -> K,
+) -> Vec<(usize, usize)> {
+    let mut batches: Vec<(usize, usize)> = Vec::new();
+    let mut previous = None;
+
+    for (i, sprite) in sprites.iter().enumerate() {
+        let current = key(sprite);
+        match batches.last_mut() {
+            Some((_, count)) if previous.as_ref() == Some(&current) => *count += 1,
+            _ => batches.push((i, 1)),
+        }
+        previous = Some(current);
+    }
+    batches
+}
+
+///Model matrix for a quad stretched from (x1,y1) to (x2,y2) with a thickness of width.
+pub fn line_model(x1: f32, y1: f32, x2: f32, y2: f32, z: f32, width: f32) -> Matrix {
+    let position = (Vector::new(x1, y1, 0.0, 0.0) + (x2, y2, 0.0).into()) / 2.0;
+    let length_vec = Vector::new(x1, y1, 0.0, 0.0) - (x2, y2, 0.0).into();
+    let length = Vector::mag(length_vec);
+    let angle = f32::atan2(length_vec.x, length_vec.y).to_degrees();
+
+    let translation = Matrix::create_translation_matrix((position.x, position.y, -z).into());
+    let rotation = Matrix::create_rotation_matrix(angle);
+    let scale = Matrix::create_scaling_matrix((width, length, 1.0).into());
+    Matrix::create_trs(translation, rotation, scale)
+}
+
+#[cfg(windows)]
 pub struct RectBuilder {
     pub shader_id: GLuint,
     pub matrix_id: GLint,
@@ -41,6 +118,7 @@ pub struct RectBuilder {
     pub vertex_id: GLuint,
 }
 
+#[cfg(windows)]
 impl RectBuilder {
     pub fn new(shader_id: GLuint, gl: &GL) -> Self {
         let matrix_id = gl.GetUniformLocation(shader_id, pcstr("MVP"));
@@ -77,10 +155,7 @@ impl RectBuilder {
         view: Matrix,
         gl: &GL,
     ) {
-        let translation = Matrix::create_translation_matrix((x, y, -z).into());
-        let rotation = Matrix::create_rotation_matrix(rot);
-        let scale = Matrix::create_scaling_matrix((w, h, 1.0).into());
-        let model = Matrix::create_trs(translation, rotation, scale);
+        let model = quad_model(x, y, z, w, h, rot);
         let mvp = Matrix::create_mvp(proj, view, model);
 
         gl.UseProgram(self.shader_id);
@@ -109,15 +184,7 @@ impl RectBuilder {
         view: Matrix,
         gl: &GL,
     ) {
-        let position = (Vector::new(x1, y1, 0.0, 0.0) + (x2, y2, 0.0).into()) / 2.0;
-        let length_vec = Vector::new(x1, y1, 0.0, 0.0) - (x2, y2, 0.0).into();
-        let length = Vector::mag(length_vec);
-        let angle = f32::atan2(length_vec.x, length_vec.y).to_degrees();
-
-        let translation = Matrix::create_translation_matrix((position.x, position.y, -z).into());
-        let rotation = Matrix::create_rotation_matrix(angle);
-        let scale = Matrix::create_scaling_matrix((width, length, 1.0).into());
-        let model = Matrix::create_trs(translation, rotation, scale);
+        let model = line_model(x1, y1, x2, y2, z, width);
         let mvp = Matrix::create_mvp(proj, view, model);
 
         gl.UseProgram(self.shader_id);
@@ -134,54 +201,48 @@ impl RectBuilder {
     }
 }
 
+///Floats per batched sprite vertex: world space position, uv and color.
+#[cfg(windows)]
+const SPRITE_VERTEX_FLOATS: usize = 9;
+
+#[cfg(windows)]
 pub struct Sprite {
-    mvp: Matrix,
-    color: Vector,
+    vertices: [[GLfloat; SPRITE_VERTEX_FLOATS]; 6],
+    z: f32,
     texture_id: GLuint,
+    ///Index into SpriteBuilder::view_projections.
+    view_projection: usize,
 }
 
+///Queues sprites for the frame and draws them in batches. Vertices are transformed to world space
+///on the CPU and streamed into a single vertex buffer, every run of sprites sharing a texture and
+///camera is drawn with one draw call.
+#[cfg(windows)]
 pub struct SpriteBuilder {
     pub shader_id: GLuint,
-    pub matrix_id: GLint,
-    pub color_id: GLint,
+    pub view_projection_id: GLint,
     pub vertex_id: GLuint,
-    pub uv_id: GLuint,
 
     sprites: Vec<Sprite>,
+    view_projections: Vec<Matrix>,
+    vertices: Vec<GLfloat>,
 }
 
+#[cfg(windows)]
 impl SpriteBuilder {
     pub fn new(shader_id: GLuint, gl: &GL) -> Self {
-        let matrix_id = gl.GetUniformLocation(shader_id, pcstr("MVP"));
-        let color_id = gl.GetUniformLocation(shader_id, pcstr("COLOR"));
+        let view_projection_id = gl.GetUniformLocation(shader_id, pcstr("VP"));
 
         let mut vertex_id = 0;
         gl.GenBuffers(1, &mut vertex_id);
-        gl.BindBuffer(GL_ARRAY_BUFFER, vertex_id);
-        gl.BufferData(
-            GL_ARRAY_BUFFER,
-            size_of::<[GLfloat; 18]>() as *const i32,
-            &QUAD as *const _ as *const c_void,
-            GL_STATIC_DRAW,
-        );
-
-        let mut uv_id = 0;
-        gl.GenBuffers(1, &mut uv_id);
-        gl.BindBuffer(GL_ARRAY_BUFFER, uv_id);
-        gl.BufferData(
-            GL_ARRAY_BUFFER,
-            size_of::<[GLfloat; 12]>() as *const i32,
-            &QUAD_UV as *const _ as *const c_void,
-            GL_STATIC_DRAW,
-        );
 
         Self {
             shader_id,
-            matrix_id,
-            color_id,
+            view_projection_id,
             vertex_id,
-            uv_id,
             sprites: Vec::new(),
+            view_projections: Vec::new(),
+            vertices: Vec::new(),
         }
     }
 
@@ -194,46 +255,104 @@ impl SpriteBuilder {
         h: f32,
         rot: f32,
         texture_id: GLuint,
+        uv_rect: (f32, f32, f32, f32),
         color: Vector,
         proj: Matrix,
         view: Matrix,
     ) {
-        let translation = Matrix::create_translation_matrix((x, y, -z).into());
-        let rotation = Matrix::create_rotation_matrix(rot);
-        let scale = Matrix::create_scaling_matrix((w, h, 1.0).into());
-        let model = Matrix::create_trs(translation, rotation, scale);
-        let mvp = Matrix::create_mvp(proj, view, model);
+        let view_projection = Matrix::mxm(proj, view);
+        if self.view_projections.last() != Some(&view_projection) {
+            self.view_projections.push(view_projection);
+        }
+
+        let (u0, v0, u1, v1) = uv_rect;
+        let positions = quad_vertices(x, y, z, w, h, rot);
+        let mut vertices = [[0.0; SPRITE_VERTEX_FLOATS]; 6];
+        for (i, vertex) in vertices.iter_mut().enumerate() {
+            let [px, py, pz] = positions[i];
+            *vertex = [
+                px,
+                py,
+                pz,
+                float::lerp(u0, u1, QUAD_UV[i * 2]),
+                float::lerp(v0, v1, QUAD_UV[i * 2 + 1]),
+                color.x,
+                color.y,
+                color.z,
+                color.w,
+            ];
+        }
 
         self.sprites.push(Sprite {
-            mvp,
-            color,
+            vertices,
+            z,
             texture_id,
+            view_projection: self.view_projections.len() - 1,
         })
     }
 
-    pub fn draw_sprites(&mut self, gl: &GL) {
+    ///Draw and clear the queued sprites, back to front and grouped by texture. Returns the number
+    ///of sprites and batches drawn.
+    pub fn draw_sprites(&mut self, gl: &GL) -> (usize, usize) {
+        if self.sprites.is_empty() {
+            self.view_projections.clear();
+            return (0, 0);
+        }
+
+        sort_sprites(&mut self.sprites, |sprite| (sprite.z, sprite.texture_id));
+        let batches = sprite_batches(&self.sprites, |sprite| {
+            (sprite.texture_id, sprite.view_projection)
+        });
+
+        self.vertices.clear();
+        for sprite in &self.sprites {
+            self.vertices.extend(sprite.vertices.iter().flatten());
+        }
+
         gl.UseProgram(self.shader_id);
 
-        gl.EnableVertexAttribArray(0);
+        //Orphan last frame's buffer and upload this frame's vertices in one go.
         gl.BindBuffer(GL_ARRAY_BUFFER, self.vertex_id);
-        gl.VertexAttribPointer(0, 3, GL_FLOAT, GL_FALSE, 0, std::ptr::null());
+        gl.BufferData(
+            GL_ARRAY_BUFFER,
+            (self.vertices.len() * size_of::<GLfloat>()) as *const i32,
+            self.vertices.as_ptr() as *const c_void,
+            GL_STREAM_DRAW,
+        );
 
+        let stride = (SPRITE_VERTEX_FLOATS * size_of::<GLfloat>()) as GLsizei;
+        let offset = |floats: usize| (floats * size_of::<GLfloat>()) as *const c_void;
+        gl.EnableVertexAttribArray(0);
+        gl.VertexAttribPointer(0, 3, GL_FLOAT, GL_FALSE, stride, offset(0));
         gl.EnableVertexAttribArray(1);
-        gl.BindBuffer(GL_ARRAY_BUFFER, self.uv_id);
-        gl.VertexAttribPointer(1, 2, GL_FLOAT, GL_FALSE, 0, std::ptr::null());
-
-        for sprite in &self.sprites {
-            gl.UniformMatrix4fv(self.matrix_id, 1, GL_FALSE, &sprite.mvp.c0.x as *const f32);
-            gl.Uniform4fv(self.color_id, 1, &sprite.color.x as *const f32);
+        gl.VertexAttribPointer(1, 2, GL_FLOAT, GL_FALSE, stride, offset(3));
+        gl.EnableVertexAttribArray(2);
+        gl.VertexAttribPointer(2, 4, GL_FLOAT, GL_FALSE, stride, offset(5));
+
+        for (start, count) in &batches {
+            let sprite = &self.sprites[*start];
+            let view_projection = &self.view_projections[sprite.view_projection];
+
+            gl.UniformMatrix4fv(
+                self.view_projection_id,
+                1,
+                GL_FALSE,
+                &view_projection.c0.x as *const f32,
+            );
             gl.BindTexture(GL_TEXTURE_2D, sprite.texture_id);
-            gl.DrawArrays(GL_TRIANGLES, 0, 6);
+            gl.DrawArrays(GL_TRIANGLES, (*start * 6) as GLint, (*count * 6) as GLsizei);
         }
 
         gl.DisableVertexAttribArray(0);
         gl.DisableVertexAttribArray(1);
+        gl.DisableVertexAttribArray(2);
         gl.BindTexture(GL_TEXTURE_2D, 0);
         gl.UseProgram(0);
+
+        let sprite_count = self.sprites.len();
         self.sprites.clear();
+        self.view_projections.clear();
+        (sprite_count, batches.len())
     }
 }
 
@@ -250,11 +369,13 @@ pub enum Weight {
     HEAVY,
 }
 
+#[cfg(windows)]
 pub struct TextBuilder {
     fonts: HashMap<String, usize>,
     index: usize,
 }
 
+#[cfg(windows)]
 impl TextBuilder {
     pub fn new() -> Self {
         Self {
@@ -292,7 +413,8 @@ impl TextBuilder {
             );
 
             SelectObject(hdc, font);
-            wglUseFontBitmapsA(hdc, 0, 255, self.index as u32 * 1000);
+            //Lists for the first 256 Unicode code points, which are the Latin-1 characters.
+            wglUseFontBitmapsW(hdc, 0, 256, self.index as u32 * 1000);
             DeleteObject(font);
         }
 
@@ -309,7 +431,11 @@ impl TextBuilder {
         gl: &GL,
     ) {
         gl.UseProgram(0);
-        let len = text.len();
+        //One list per code point up to 255, anything beyond is drawn as '?'.
+        let characters: Vec<u8> = text
+            .chars()
+            .map(|character| u8::try_from(character).unwrap_or(b'?'))
+            .collect();
 
         let font_index = match self.fonts.get(font) {
             Some(x) => x,
@@ -323,102 +449,40 @@ impl TextBuilder {
             glRasterPos3f(x, y, -1.0);
             glColor4f(color.0, color.1, color.2, color.3);
             glListBase(*font_index as u32 * 1000);
-            glCallLists(len as i32, GL_UNSIGNED_BYTE, text.as_ptr() as *const c_void);
+            glCallLists(
+                characters.len() as i32,
+                GL_UNSIGNED_BYTE,
+                characters.as_ptr() as *const c_void,
+            );
             glFlush();
         }
         gl.UseProgram(0);
     }
 }
 
-pub struct ParticleProperties {
-    pub pos: Vector,
-    pub velocity: Vector,
-    pub velocity_var: Vector,
-    pub color_begin: Vector,
-    pub color_end: Vector,
-    pub size_begin: f32,
-    pub size_end: f32,
-    pub size_var: f32,
-    pub lifetime: f32,
-    pub tex_id: Option<GLuint>,
-}
-
-#[derive(Copy, Clone)]
-pub struct Particle {
-    pub pos: Vector,
-    pub velocity: Vector,
-    pub color_begin: Vector,
-    pub color_end: Vector,
-    pub rotation: f32,
-    pub size_begin: f32,
-    pub size_end: f32,
-    pub lifetime: f32,
-    pub life_remaining: f32,
-    pub active: bool,
-    pub tex_id: Option<GLuint>,
-}
-
-impl Default for Particle {
-    fn default() -> Self {
-        let zeroed = Vector::new(0.0, 0.0, 0.0, 0.0);
-        Self {
-            pos: zeroed,
-            velocity: zeroed,
-            color_begin: zeroed,
-            color_end: zeroed,
-            rotation: 0.0,
-            size_begin: 0.0,
-            size_end: 0.0,
-            lifetime: 0.0,
-            life_remaining: 0.0,
-            active: false,
-            tex_id: None,
-        }
-    }
-}
-
-pub struct ParticleSystem {
-    particle_pool: Vec<Particle>,
-    pool_index: usize,
+///Floats per batched particle vertex, the same layout as the batched sprites.
+#[cfg(windows)]
+const PARTICLE_VERTEX_FLOATS: usize = SPRITE_VERTEX_FLOATS;
 
+///Draws particles in batches. Vertices are built on the CPU and streamed into one vertex buffer,
+///every run of particles sharing a texture is drawn with one draw call.
+#[cfg(windows)]
+pub struct ParticleBuilder {
     vertex_id: GLuint,
-    uv_id: GLuint,
     shader_id: GLuint,
-    matrix_id: GLint,
-    color_id: GLint,
+    view_projection_id: GLint,
+    vertices: Vec<GLfloat>,
 
     texture_white_id: GLuint,
-
-    pub active: bool,
 }
 
-impl ParticleSystem {
-    pub fn new(pool_size: usize, shader_id: GLuint, gl: &GL) -> Self {
-        let particle_pool = vec![Particle::default(); pool_size];
-        let pool_index = pool_size - 1;
-
-        let matrix_id = gl.GetUniformLocation(shader_id, pcstr("MVP"));
-        let color_id = gl.GetUniformLocation(shader_id, pcstr("COLOR"));
+#[cfg(windows)]
+impl ParticleBuilder {
+    pub fn new(shader_id: GLuint, gl: &GL) -> Self {
+        let view_projection_id = gl.GetUniformLocation(shader_id, pcstr("VP"));
 
         let mut vertex_id = 0;
         gl.GenBuffers(1, &mut vertex_id);
-        gl.BindBuffer(GL_ARRAY_BUFFER, vertex_id);
-        gl.BufferData(
-            GL_ARRAY_BUFFER,
-            size_of::<[GLfloat; 18]>() as *const i32,
-            &QUAD as *const _ as *const c_void,
-            GL_STATIC_DRAW,
-        );
-
-        let mut uv_id = 0;
-        gl.GenBuffers(1, &mut uv_id);
-        gl.BindBuffer(GL_ARRAY_BUFFER, uv_id);
-        gl.BufferData(
-            GL_ARRAY_BUFFER,
-            size_of::<[GLfloat; 12]>() as *const i32,
-            &QUAD_UV as *const _ as *const c_void,
-            GL_STATIC_DRAW,
-        );
 
         let texture_white_id = 0;
         gl.GenTextures(1, &texture_white_id);
@@ -441,121 +505,86 @@ impl ParticleSystem {
         );
 
         Self {
-            particle_pool,
-            pool_index,
-
             vertex_id,
-            uv_id,
             shader_id,
-            matrix_id,
-            color_id,
+            view_projection_id,
+            vertices: Vec::new(),
 
             texture_white_id,
-            active: false,
         }
     }
 
-    pub fn update(&mut self, dt: Duration) {
-        let mut inactive = true;
-        for mut particle in &mut self.particle_pool {
-            if !particle.active {
-                continue;
-            }
-
-            if particle.life_remaining <= 0.0 {
-                particle.active = false;
-                continue;
-            }
-
-            inactive = false;
-
-            particle.life_remaining -= dt.as_secs_f32();
-            particle.pos += particle.velocity * dt.as_secs_f32();
-            particle.rotation += 0.5 * dt.as_secs_f32();
+    ///Draw particles in the order given. Returns the number of batches drawn.
+    pub fn draw_particles(
+        &mut self,
+        particles: &[Particle],
+        proj: Matrix,
+        view: Matrix,
+        gl: &GL,
+    ) -> usize {
+        if particles.is_empty() {
+            return 0;
         }
 
-        if inactive {
-            self.active = false;
+        let batches = sprite_batches(particles, |particle| particle.tex_id);
+
+        self.vertices.clear();
+        for particle in particles {
+            let color = particle.color;
+            for (i, [x, y, z]) in particle.vertices().into_iter().enumerate() {
+                self.vertices.extend_from_slice(&[
+                    x,
+                    y,
+                    z,
+                    QUAD_UV[i * 2],
+                    QUAD_UV[i * 2 + 1],
+                    color.x,
+                    color.y,
+                    color.z,
+                    color.w,
+                ]);
+            }
         }
-    }
 
-    pub fn draw(&mut self, proj: Matrix, view: Matrix, gl: &GL) {
         gl.UseProgram(self.shader_id);
 
-        gl.EnableVertexAttribArray(0);
         gl.BindBuffer(GL_ARRAY_BUFFER, self.vertex_id);
-        gl.VertexAttribPointer(0, 3, GL_FLOAT, GL_FALSE, 0, std::ptr::null());
+        gl.BufferData(
+            GL_ARRAY_BUFFER,
+            (self.vertices.len() * size_of::<GLfloat>()) as *const i32,
+            self.vertices.as_ptr() as *const c_void,
+            GL_STREAM_DRAW,
+        );
 
+        let stride = (PARTICLE_VERTEX_FLOATS * size_of::<GLfloat>()) as GLsizei;
+        let offset = |floats: usize| (floats * size_of::<GLfloat>()) as *const c_void;
+        gl.EnableVertexAttribArray(0);
+        gl.VertexAttribPointer(0, 3, GL_FLOAT, GL_FALSE, stride, offset(0));
         gl.EnableVertexAttribArray(1);
-        gl.BindBuffer(GL_ARRAY_BUFFER, self.uv_id);
-        gl.VertexAttribPointer(1, 2, GL_FLOAT, GL_FALSE, 0, std::ptr::null());
+        gl.VertexAttribPointer(1, 2, GL_FLOAT, GL_FALSE, stride, offset(3));
+        gl.EnableVertexAttribArray(2);
+        gl.VertexAttribPointer(2, 4, GL_FLOAT, GL_FALSE, stride, offset(5));
 
-        for particle in &self.particle_pool {
-            if !particle.active {
-                continue;
-            }
-
-            let life = particle.life_remaining / particle.lifetime;
-            let color = Vector::lerp(particle.color_end, particle.color_begin, life);
-            let size = float::lerp(particle.size_end, particle.size_begin, life);
-
-            let translation = Matrix::create_translation_matrix(
-                (particle.pos.x, particle.pos.y, particle.pos.z).into(),
-            );
-            let rotation = Matrix::create_rotation_matrix(particle.rotation);
-            let scale = Matrix::create_scaling_matrix((size, size, 0.0).into());
-            let model = Matrix::create_trs(translation, rotation, scale);
-            let mvp = Matrix::create_mvp(proj, view, model);
-
-            gl.UniformMatrix4fv(self.matrix_id, 1, GL_FALSE, &mvp.c0.x as *const f32);
-            gl.Uniform4fv(self.color_id, 1, &color.x as *const f32);
-
-            if particle.tex_id.is_none() {
-                gl.BindTexture(GL_TEXTURE_2D, self.texture_white_id);
-            } else {
-                gl.BindTexture(GL_TEXTURE_2D, particle.tex_id.unwrap());
-            }
+        let view_projection = Matrix::mxm(proj, view);
+        gl.UniformMatrix4fv(
+            self.view_projection_id,
+            1,
+            GL_FALSE,
+            &view_projection.c0.x as *const f32,
+        );
 
-            gl.DrawArrays(GL_TRIANGLES, 0, 6);
-            gl.BindTexture(GL_TEXTURE_2D, 0);
+        for (start, count) in &batches {
+            let texture_id = particles[*start].tex_id.unwrap_or(self.texture_white_id);
+            gl.BindTexture(GL_TEXTURE_2D, texture_id);
+            gl.DrawArrays(GL_TRIANGLES, (*start * 6) as GLint, (*count * 6) as GLsizei);
         }
 
         gl.DisableVertexAttribArray(0);
         gl.DisableVertexAttribArray(1);
+        gl.DisableVertexAttribArray(2);
         gl.BindTexture(GL_TEXTURE_2D, 0);
         gl.UseProgram(0);
-    }
 
-    pub fn emit(&mut self, properties: &ParticleProperties, rng: &mut RngEngine) {
-        self.active = true;
-        let particle = &mut self.particle_pool[self.pool_index];
-
-        particle.active = true;
-        particle.pos = properties.pos;
-        particle.rotation =
-            (rng.get_float_range(-1.0, 1.0) as f32 * 2.0 * std::f32::consts::PI).to_degrees();
-
-        //velocity
-        particle.velocity = properties.velocity;
-        particle.velocity.x += properties.velocity_var.x * rng.get_float_range(-1.0, 1.0) as f32;
-        particle.velocity.y += properties.velocity_var.y * rng.get_float_range(-1.0, 1.0) as f32;
-
-        //color
-        particle.color_begin = properties.color_begin;
-        particle.color_end = properties.color_end;
-
-        particle.lifetime = properties.lifetime;
-        particle.life_remaining = properties.lifetime;
-        particle.size_begin =
-            properties.size_begin + properties.size_var * rng.get_float_range(-1.0, 1.0) as f32;
-        particle.size_end = properties.size_end;
-
-        particle.tex_id = properties.tex_id;
-
-        if self.pool_index == 0 {
-            self.pool_index = self.particle_pool.len() - 1;
-        } else {
-            self.pool_index -= 1;
-        }
+        batches.len()
     }
 }
@@ -1,5 +1,16 @@
 use std::time::{Duration, Instant};
 
+///Sprite batching and draw call counts of a frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BatchStats {
+    ///Sprites drawn.
+    pub sprites: usize,
+    ///Runs of sprites sharing a texture and camera, each drawn with a single draw call.
+    pub batches: usize,
+    ///Draw calls issued for the whole frame, including primitives, text and particles.
+    pub draw_calls: usize,
+}
+
 pub struct Stats {
     tick_index: usize,
     tick_sum: usize,
@@ -8,6 +19,7 @@ pub struct Stats {
     update_time: usize,
     draw_time: usize,
     particle_time: usize,
+    batch_stats: BatchStats,
 }
 
 impl Stats {
@@ -20,6 +32,7 @@ impl Stats {
             update_time: 0,
             draw_time: 0,
             particle_time: 0,
+            batch_stats: BatchStats::default(),
         }
     }
 
@@ -66,4 +79,12 @@ impl Stats {
     pub fn get_particle_time(&self) -> usize {
         self.particle_time
     }
+
+    pub fn batch_stats(&mut self, batch_stats: BatchStats) {
+        self.batch_stats = batch_stats;
+    }
+
+    pub fn get_batch_stats(&self) -> BatchStats {
+        self.batch_stats
+    }
 }
@@ -0,0 +1,428 @@
+use std::ffi::OsStr;
+use std::time::Duration;
+
+use crate::mixer::{is_audio_extension, AudioError, Sound, MIX_CHANNELS};
+use crate::vorbis::VorbisStream;
+
+///Frames decoded into every buffer of a stream, about a tenth of a second.
+const STREAM_BUFFER_FRAMES: usize = 4096;
+
+///Buffers of a stream, the one playing and the ones decoded ahead of it.
+const STREAM_BUFFERS: usize = 3;
+
+///Load an audio file for streaming. Returns the stream name, the file stem without the
+///extension, and the stream. Errors are reported and None is returned.
+pub fn read_stream(path: &str) -> Option<(String, AudioStream)> {
+    let Some(filename) = std::path::Path::new(&path).file_stem() else {
+        eprintln!("Loading audio stream '{path}' failed: Couldn't resolve audio name!");
+        return None;
+    };
+
+    match AudioStream::open(path) {
+        Ok(stream) => Some((String::from(filename.to_str().unwrap()), stream)),
+        Err(error) => {
+            eprintln!("Loading audio stream '{path}' failed: {error}");
+            None
+        }
+    }
+}
+
+///Where the frames of a stream come from.
+#[derive(Debug, Clone)]
+enum StreamSource {
+    Vorbis(Box<VorbisStream>),
+    ///Formats without a streaming decoder are decoded up front.
+    Decoded {
+        sound: Sound,
+        position: usize,
+    },
+}
+
+impl StreamSource {
+    fn sample_rate(&self) -> u32 {
+        match self {
+            StreamSource::Vorbis(stream) => stream.sample_rate(),
+            StreamSource::Decoded { sound, .. } => sound.sample_rate,
+        }
+    }
+
+    fn channels(&self) -> usize {
+        match self {
+            StreamSource::Vorbis(stream) => stream.channels() as usize,
+            StreamSource::Decoded { sound, .. } => sound.channels as usize,
+        }
+    }
+
+    fn frames(&self) -> u64 {
+        match self {
+            StreamSource::Vorbis(stream) => stream.frames(),
+            StreamSource::Decoded { sound, .. } => sound.frames() as u64,
+        }
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> Result<usize, AudioError> {
+        match self {
+            StreamSource::Vorbis(stream) => stream.read(out),
+            StreamSource::Decoded { sound, position } => {
+                let channels = sound.channels as usize;
+                let start = (*position * channels).min(sound.samples.len());
+                let count = out.len().min(sound.samples.len() - start) / channels * channels;
+                out[..count].copy_from_slice(&sound.samples[start..start + count]);
+                *position += count / channels;
+                Ok(count / channels)
+            }
+        }
+    }
+
+    fn seek(&mut self, frame: u64) {
+        match self {
+            StreamSource::Vorbis(stream) => stream.seek(frame),
+            StreamSource::Decoded { position, .. } => *position = frame as usize,
+        }
+    }
+}
+
+///Playback state of a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamState {
+    ///Not playing, playing again starts over from the beginning or the frame seeked to.
+    Stopped,
+    Playing,
+    ///Not playing, playing again continues where the stream was paused.
+    Paused,
+}
+
+///Decoded frames of a stream and the frame of the source the first one came from.
+#[derive(Debug, Clone, Default)]
+struct StreamBuffer {
+    samples: Vec<f32>,
+    start: u64,
+    frames: usize,
+}
+
+///Audio decoded a piece at a time while it plays, for music and ambience that would take too
+///much memory fully decoded. Frames are decoded into a small ring of buffers and a buffer is
+///refilled with the next frames as soon as it has been played. Ogg Vorbis files are decoded as
+///they play, other formats are decoded when loaded and only played through the ring.
+///
+///A looping stream jumps from its loop end back to its loop start within the ring, so the loop
+///is gapless and music with an intro can loop only its main part.
+#[derive(Debug, Clone)]
+pub struct AudioStream {
+    source: StreamSource,
+    buffers: Vec<StreamBuffer>,
+    ///Buffer that is playing.
+    current: usize,
+    ///Position in frames of the current buffer, fractional between frames when resampling.
+    position: f64,
+    ///False until the buffers are filled, after loading, seeking and stopping.
+    primed: bool,
+    ///Frame of the source the next buffer starts at.
+    decode_position: u64,
+    state: StreamState,
+    volume: f32,
+    looping: bool,
+    loop_start: u64,
+    ///Frame the loop jumps back from, the end of the source if None.
+    loop_end: Option<u64>,
+}
+
+impl AudioStream {
+    ///Open an audio file for streaming, the format is given by its extension.
+    pub fn open(path: &str) -> Result<AudioStream, AudioError> {
+        let extension = std::path::Path::new(&path).extension().unwrap_or_default();
+
+        if extension.eq_ignore_ascii_case(OsStr::new("ogg")) {
+            return Ok(AudioStream::from_vorbis(VorbisStream::open(path)?));
+        }
+        if !is_audio_extension(extension) {
+            return Err(AudioError::UnrecognizedFormat);
+        }
+        Ok(AudioStream::from_sound(Sound::load(path)?))
+    }
+
+    pub fn from_vorbis(stream: VorbisStream) -> Self {
+        Self::new(StreamSource::Vorbis(Box::new(stream)))
+    }
+
+    ///Stream of a decoded sound, plays like a stream but doesn't save memory.
+    pub fn from_sound(sound: Sound) -> Self {
+        Self::new(StreamSource::Decoded { sound, position: 0 })
+    }
+
+    fn new(source: StreamSource) -> Self {
+        Self {
+            source,
+            buffers: vec![StreamBuffer::default(); STREAM_BUFFERS],
+            current: 0,
+            position: 0.0,
+            primed: false,
+            decode_position: 0,
+            state: StreamState::Stopped,
+            volume: 1.0,
+            looping: false,
+            loop_start: 0,
+            loop_end: None,
+        }
+    }
+
+    pub fn state(&self) -> StreamState {
+        self.state
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.frames_to_duration(self.source.frames())
+    }
+
+    ///Position of the frame that plays next.
+    pub fn position(&self) -> Duration {
+        self.frames_to_duration(self.current_frame())
+    }
+
+    ///Play at level from the current position, looping between the loop points if looping.
+    ///Streams that were stopped or played to their end are back at the beginning.
+    pub fn play(&mut self, level: f32, looping: bool) {
+        if self.looping != looping {
+            //Buffers decoded ahead were decoded for the old setting.
+            self.looping = looping;
+            self.seek_frame(self.current_frame());
+        }
+        self.volume = level;
+        self.state = StreamState::Playing;
+    }
+
+    pub fn pause(&mut self) {
+        if self.state == StreamState::Playing {
+            self.state = StreamState::Paused;
+        }
+    }
+
+    ///Stop playing and rewind to the beginning.
+    pub fn stop(&mut self) {
+        self.state = StreamState::Stopped;
+        self.seek_frame(0);
+    }
+
+    ///Continue playing at position, playing streams keep playing.
+    pub fn seek(&mut self, position: Duration) {
+        let frame = (position.as_secs_f64() * self.sample_rate() as f64) as u64;
+        self.seek_frame(frame.min(self.source.frames()));
+    }
+
+    ///Loop from end back to start instead of from the end of the stream back to its beginning,
+    ///end None loops at the end of the stream. Points out of order are reported and ignored.
+    pub fn set_loop_points(&mut self, start: Duration, end: Option<Duration>) {
+        let to_frame = |time: Duration| (time.as_secs_f64() * self.sample_rate() as f64) as u64;
+        let frames = self.source.frames();
+        let start = to_frame(start);
+        let end = end.map(|end| to_frame(end).min(frames));
+
+        if start >= end.unwrap_or(frames) {
+            eprintln!("Invalid loop points: loop start has to come before the loop end");
+            return;
+        }
+        self.loop_start = start;
+        self.loop_end = end;
+        if self.looping {
+            self.seek_frame(self.current_frame());
+        }
+    }
+
+    fn current_frame(&self) -> u64 {
+        match self.primed {
+            true => self.buffers[self.current].start + self.position as u64,
+            false => self.decode_position,
+        }
+    }
+
+    fn frames_to_duration(&self, frames: u64) -> Duration {
+        Duration::from_secs_f64(frames as f64 / self.sample_rate().max(1) as f64)
+    }
+
+    fn seek_frame(&mut self, frame: u64) {
+        self.source.seek(frame);
+        self.decode_position = frame;
+        self.position = 0.0;
+        self.primed = false;
+    }
+
+    ///Decode the next frames into a buffer, jumping back to the loop start at the loop end.
+    fn fill(&mut self, index: usize) -> Result<(), AudioError> {
+        let channels = self.source.channels();
+        let buffer = &mut self.buffers[index];
+        buffer.samples.resize(STREAM_BUFFER_FRAMES * channels, 0.0);
+
+        //A loop end that falls on the end of a buffer leaves nothing to read until the jump.
+        for _ in 0..2 {
+            let end = match self.looping {
+                true => self.loop_end.unwrap_or(u64::MAX),
+                false => u64::MAX,
+            };
+            let wanted = end
+                .saturating_sub(self.decode_position)
+                .min(STREAM_BUFFER_FRAMES as u64) as usize;
+            let frames = self.source.read(&mut buffer.samples[..wanted * channels])?;
+
+            buffer.start = self.decode_position;
+            buffer.frames = frames;
+            self.decode_position += frames as u64;
+
+            if self.looping && (frames < wanted || self.decode_position >= end) {
+                self.source.seek(self.loop_start);
+                self.decode_position = self.loop_start;
+            }
+            if frames > 0 || !self.looping {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    ///Left and right sample of frame of a buffer, silence past its end.
+    fn frame(&self, index: usize, frame: usize) -> (f32, f32) {
+        let buffer = &self.buffers[index];
+        if frame >= buffer.frames {
+            return (0.0, 0.0);
+        }
+        match self.source.channels() {
+            1 => (buffer.samples[frame], buffer.samples[frame]),
+            _ => (buffer.samples[frame * 2], buffer.samples[frame * 2 + 1]),
+        }
+    }
+
+    ///Add the stream to out while it's playing, decoding the frames it needs. A stream that plays
+    ///to its end stops and rewinds.
+    pub(crate) fn mix(&mut self, out: &mut [f32], sample_rate: u32) -> Result<(), AudioError> {
+        if self.state != StreamState::Playing {
+            return Ok(());
+        }
+        if !self.primed {
+            for index in 0..STREAM_BUFFERS {
+                self.fill(index)?;
+            }
+            self.current = 0;
+            self.primed = true;
+        }
+
+        let step = self.sample_rate() as f64 / sample_rate as f64;
+        for frame in out.chunks_exact_mut(MIX_CHANNELS) {
+            //Played buffers are refilled right away and the next one takes over.
+            while self.position >= self.buffers[self.current].frames as f64 {
+                let frames = self.buffers[self.current].frames;
+                if frames == 0 {
+                    self.stop();
+                    return Ok(());
+                }
+                self.position -= frames as f64;
+                self.fill(self.current)?;
+                self.current = (self.current + 1) % STREAM_BUFFERS;
+            }
+
+            //Linear interpolation between the frames around the position, the next frame can be
+            //in the next buffer.
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            let (left_a, right_a) = self.frame(self.current, index);
+            let (left_b, right_b) = match index + 1 < self.buffers[self.current].frames {
+                true => self.frame(self.current, index + 1),
+                false => self.frame((self.current + 1) % STREAM_BUFFERS, 0),
+            };
+
+            frame[0] += (left_a + (left_b - left_a) * fraction) * self.volume;
+            frame[1] += (right_a + (right_b - right_a) * fraction) * self.volume;
+            self.position += step;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mixer::Mixer;
+
+    ///Mono sound whose frame i has the value i / frames, at a rate where a millisecond is a frame.
+    fn ramp(frames: usize) -> Sound {
+        Sound {
+            sample_rate: 1000,
+            channels: 1,
+            samples: (0..frames).map(|i| i as f32 / frames as f32).collect(),
+        }
+    }
+
+    ///Left channel of frames mixed in uneven chunks, so buffer ends don't line up with them.
+    fn mix_left(mixer: &mut Mixer, frames: usize) -> Vec<f32> {
+        let mut left = Vec::with_capacity(frames);
+        while left.len() < frames {
+            let mut out = vec![0.0; 7 * MIX_CHANNELS];
+            mixer.mix(&mut out);
+            left.extend(out.iter().step_by(MIX_CHANNELS));
+        }
+        left.truncate(frames);
+        left
+    }
+
+    #[test]
+    fn loop_points_wrap_without_a_gap() {
+        let frames = 10_000;
+        let sound = ramp(frames);
+        let mut mixer = Mixer::new(1000, 0, 0);
+        mixer.add_stream("music", AudioStream::from_sound(sound.clone()));
+        mixer.set_stream_loop_points(
+            "music",
+            Duration::from_millis(1000),
+            Some(Duration::from_millis(3000)),
+        );
+        mixer.play_stream("music", 1.0, true);
+
+        let left = mix_left(&mut mixer, 12_000);
+        for (i, sample) in left.iter().enumerate() {
+            let frame = if i < 3000 {
+                i
+            } else {
+                1000 + (i - 3000) % 2000
+            };
+            assert_eq!(*sample, sound.samples[frame], "frame {i}");
+        }
+    }
+
+    #[test]
+    fn streams_stop_and_rewind_at_the_end() {
+        let sound = ramp(5000);
+        let mut mixer = Mixer::new(1000, 0, 0);
+        mixer.add_stream("music", AudioStream::from_sound(sound.clone()));
+        mixer.play_stream("music", 1.0, false);
+
+        let left = mix_left(&mut mixer, 6000);
+        assert_eq!(left[..5000], sound.samples[..]);
+        assert!(left[5000..].iter().all(|&sample| sample == 0.0));
+
+        let stream = mixer.get_stream("music").unwrap();
+        assert_eq!(stream.state(), StreamState::Stopped);
+        assert_eq!(stream.position(), Duration::ZERO);
+    }
+
+    #[test]
+    fn pause_and_seek_continue_from_the_right_frame() {
+        let sound = ramp(10_000);
+        let mut mixer = Mixer::new(1000, 0, 0);
+        mixer.add_stream("music", AudioStream::from_sound(sound.clone()));
+        mixer.play_stream("music", 1.0, false);
+
+        mix_left(&mut mixer, 700);
+        mixer.pause_stream("music");
+        assert_eq!(mix_left(&mut mixer, 70), [0.0; 70]);
+        let stream = mixer.get_stream("music").unwrap();
+        assert_eq!(stream.position(), Duration::from_millis(700));
+
+        mixer.play_stream("music", 1.0, false);
+        assert_eq!(mix_left(&mut mixer, 5), sound.samples[700..705]);
+
+        mixer.seek_stream("music", Duration::from_millis(8000));
+        assert_eq!(mix_left(&mut mixer, 5), sound.samples[8000..8005]);
+    }
+}
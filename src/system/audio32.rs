@@ -36,6 +36,9 @@ pub const XAUDIO2_DEFAULT_SAMPLERATE: u32 = 0u32;
 pub const XAUDIO2_DEFAULT_FREQ_RATIO: f32 = 2.0;
 pub const XAUDIO_END_OF_STREAM: u32 = 0x40;
 pub const XAUDIO2_COMMIT_NOW: u32 = 0;
+pub const XAUDIO2_VOICE_NOSAMPLESPLAYED: u32 = 0x0100;
+
+pub const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
 
 pub const AUDIOCATEGORY_GAMEEFFECTS: AUDIO_STREAM_CATEGORY = 6i32;
 
@@ -364,20 +367,22 @@ pub struct IXAudio2SourceVoiceVtbl {
 
 #[derive(Debug)]
 #[repr(C)]
+///Source voice callbacks in IXAudio2VoiceCallback order, called by XAudio2 on its own thread.
 pub struct IXAudio2EngineCallbackVtbl {
-    pub OnStreamEnd: fn(*const IXAudio2EngineCallback) -> (),
-    pub OnVoiceProcessingPassEnd: fn(*const IXAudio2EngineCallback) -> (),
-    pub OnVoiceProcessingPassStart: fn(*const IXAudio2EngineCallback, UINT32) -> (),
-    pub OnBufferEnd: fn(*const IXAudio2EngineCallback) -> (),
-    pub OnBufferStart: fn(*const IXAudio2EngineCallback, *mut c_void) -> (),
-    pub OnLoopEnd: fn(*const IXAudio2EngineCallback, *mut c_void) -> (),
-    pub OnVoiceError: fn(*const IXAudio2EngineCallback, *mut c_void, HRESULT) -> (),
+    pub OnVoiceProcessingPassStart: extern "system" fn(*const IXAudio2EngineCallback, UINT32) -> (),
+    pub OnVoiceProcessingPassEnd: extern "system" fn(*const IXAudio2EngineCallback) -> (),
+    pub OnStreamEnd: extern "system" fn(*const IXAudio2EngineCallback) -> (),
+    pub OnBufferStart: extern "system" fn(*const IXAudio2EngineCallback, *mut c_void) -> (),
+    pub OnBufferEnd: extern "system" fn(*const IXAudio2EngineCallback, *mut c_void) -> (),
+    pub OnLoopEnd: extern "system" fn(*const IXAudio2EngineCallback, *mut c_void) -> (),
+    pub OnVoiceError: extern "system" fn(*const IXAudio2EngineCallback, *mut c_void, HRESULT) -> (),
 }
 
 ///////////////////////////////////////////////////////////////////////////////////////////////////
 // WIN32 XAudio2 FUNCTIONS
 ///////////////////////////////////////////////////////////////////////////////////////////////////
 
+#[cfg(windows)]
 #[link(name = "Xaudio2")]
 extern "system" {
     pub fn XAudio2Create(
@@ -3,6 +3,7 @@
 #![allow(non_snake_case)]
 #![allow(clippy::style)]
 
+#[cfg(windows)]
 use crate::{renderer::pcstr, system::win32::*};
 use std::ffi::c_void;
 
@@ -80,6 +81,7 @@ pub const GLU_VERSION: GLenum = 100800;
 
 pub const GL_ARRAY_BUFFER: GLenum = 34962;
 pub const GL_STATIC_DRAW: GLenum = 35044;
+pub const GL_STREAM_DRAW: GLenum = 35040;
 pub const GL_FLOAT: GLenum = 5126;
 pub const GL_FALSE: GLboolean = 0;
 pub const GL_TRUE: GLboolean = 1;
@@ -107,6 +109,7 @@ pub const GL_TEXTURE_MIN_FILER: GLenum = 10241u32;
 pub const GL_LINEAR_MIPMAP_LINEAR: GLenum = 9987u32;
 pub const GL_DEPTH_TEST: GLenum = 2929u32;
 pub const GL_LESS: GLenum = 513u32;
+pub const GL_VIEWPORT: GLenum = 2978u32;
 
 pub const GL_NO_ERROR: GLenum = 0u32;
 pub const GL_INVALID_ENUM: GLenum = 1280u32;
@@ -145,6 +148,15 @@ pub const GL_DEBUG_SOURCE_OTHER: GLenum = 33355u32;
 pub const GL_COMPRESSED_RGBA_S3TC_DXT1_EXT: GLenum = 33777u32;
 pub const GL_COMPRESSED_RGBA_S3TC_DXT3_EXT: GLenum = 33778u32;
 pub const GL_COMPRESSED_RGBA_S3TC_DXT5_EXT: GLenum = 33779u32;
+pub const GL_COMPRESSED_RED_RGTC1: GLenum = 36283u32;
+pub const GL_COMPRESSED_SIGNED_RED_RGTC1: GLenum = 36284u32;
+pub const GL_COMPRESSED_RG_RGTC2: GLenum = 36285u32;
+pub const GL_COMPRESSED_SIGNED_RG_RGTC2: GLenum = 36286u32;
+pub const GL_COMPRESSED_RGBA_BPTC_UNORM: GLenum = 36492u32;
+pub const GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT: GLenum = 36494u32;
+pub const GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT: GLenum = 36495u32;
+pub const GL_NUM_COMPRESSED_TEXTURE_FORMATS: GLenum = 34466u32;
+pub const GL_COMPRESSED_TEXTURE_FORMATS: GLenum = 34467u32;
 
 pub const GL_TEXTURE_COMPRESSED: GLenum = 34465u32;
 pub const GL_BLEND: GLenum = 3042u32;
@@ -260,6 +272,7 @@ pub type glDebugMessageCallback = fn(callback: *const c_void, userParam: *const
 // WIN32 FUNCTIONS
 ///////////////////////////////////////////////////////////////////////////////////////////////////
 
+#[cfg(windows)]
 #[link(name = "Gdi32")]
 extern "system" {
     pub fn ChoosePixelFormat(hdc: HDC, ppfd: *const PIXELFORMATDESCRIPTOR) -> i32;
@@ -267,11 +280,13 @@ extern "system" {
     pub fn SwapBuffers(hdc: HDC) -> bool;
 }
 
+#[cfg(windows)]
 #[link(name = "Dwmapi")]
 extern "system" {
     pub fn DwmFlush() -> HRESULT;
 }
 
+#[cfg(windows)]
 #[link(name = "Opengl32")]
 extern "system" {
     pub fn wglCreateContext(hdc: HDC) -> HGLRC;
@@ -279,6 +294,7 @@ extern "system" {
     pub fn wglDeleteContext(hglrc: HGLRC) -> bool;
     pub fn wglGetProcAddress(identifier: PCSTR) -> PROC;
     pub fn wglUseFontBitmapsA(hdc: HDC, param1: DWORD, param2: DWORD, param3: DWORD) -> bool;
+    pub fn wglUseFontBitmapsW(hdc: HDC, param1: DWORD, param2: DWORD, param3: DWORD) -> bool;
     pub fn glGetString(name: u32) -> *mut u8;
     pub fn glEnable(cap: u32) -> ();
     pub fn glHint(target: u32, mode: u32) -> ();
@@ -298,6 +314,16 @@ extern "system" {
         data: *const c_void,
     );
     pub fn glTexParameteri(target: GLenum, pname: GLenum, param: GLint);
+    pub fn glReadPixels(
+        x: GLint,
+        y: GLint,
+        width: GLsizei,
+        height: GLsizei,
+        format: GLenum,
+        type_: GLenum,
+        data: *mut c_void,
+    );
+    pub fn glGetIntegerv(pname: GLenum, data: *mut GLint);
     pub fn glBlendFunc(sfactor: GLenum, dfactor: GLenum);
     pub fn glDisable(cap: GLenum);
     pub fn glRasterPos2f(x: GLfloat, y: GLfloat);
@@ -309,6 +335,7 @@ extern "system" {
     pub fn glColor4f(red: GLfloat, green: GLfloat, blue: GLfloat, alpha: GLfloat);
 }
 
+#[cfg(windows)]
 #[link(name = "Glu32")]
 extern "system" {
     pub fn gluGetString(name: GLenum) -> *const GLubyte;
@@ -318,6 +345,7 @@ extern "system" {
 // Helpers
 ///////////////////////////////////////////////////////////////////////////////////////////////////
 
+#[cfg(windows)]
 pub fn wglGetProcAddressChecked(identifier: &str) -> PROC {
     unsafe {
         let proc = wglGetProcAddress(pcstr(identifier));
@@ -422,6 +422,7 @@ pub struct LOGFONTA {
 pub type WNDPROC =
     unsafe extern "system" fn(param0: HWND, param1: u32, param2: WPARAM, param3: LPARAM) -> LRESULT;
 
+#[cfg(windows)]
 #[link(name = "Kernel32")]
 extern "system" {
     pub fn GetModuleHandleA(lpmodulename: PCSTR) -> HINSTANCE;
@@ -453,6 +454,7 @@ extern "system" {
     ) -> BOOL;
 }
 
+#[cfg(windows)]
 #[link(name = "User32")]
 extern "system" {
     pub fn LoadCursorW(hinstance: HINSTANCE, lpcursorname: PCWSTR) -> HCURSOR;
@@ -527,6 +529,7 @@ extern "system" {
     ) -> BOOL;
 }
 
+#[cfg(windows)]
 #[link(name = "Gdi32")]
 extern "system" {
     pub fn PatBlt(hdc: HDC, x: i32, y: i32, w: i32, h: i32, rop: DWORD) -> BOOL;
@@ -579,6 +582,7 @@ extern "system" {
     pub fn CreateFontIndirectA(lplf: *const LOGFONTA) -> HFONT;
 }
 
+#[cfg(windows)]
 #[link(name = "onecore")]
 extern "system" {
     pub fn VirtualAlloc(
@@ -591,17 +595,20 @@ extern "system" {
     pub fn VirtualFree(lpAddress: LPVOID, dwSize: DWORD, dwFreeType: DWORD) -> BOOL;
 }
 
+#[cfg(windows)]
 #[link(name = "Xinput")]
 extern "system" {
     pub fn XInputGetState(dwUserIndex: DWORD, pState: *const XINPUT_STATE) -> DWORD;
     pub fn XInputSetState(dwUserIndex: DWORD, pVibration: *const XINPUT_VIBRATION) -> DWORD;
 }
 
+#[cfg(windows)]
 #[link(name = "Ole32")]
 extern "system" {
     pub fn CoInitializeEx(pvReserved: LPVOID, dwCoInit: DWORD) -> HRESULT;
 }
 
+#[cfg(windows)]
 #[link(name = "Dwmapi")]
 extern "system" {
     pub fn DwmGetWindowAttribute(
@@ -1,65 +1,64 @@
-use std::collections::HashMap;
-use std::ffi::c_void;
+use std::fmt::Display;
+#[cfg(windows)]
 use std::fs;
-use std::str::from_utf8;
 
-use crate::renderer::GL;
-use crate::system::gl32::*;
+use crate::image::Image;
+#[cfg(windows)]
+use crate::image::ImageFormat;
 
+#[cfg(windows)]
+use std::{collections::HashMap, ffi::c_void};
+
+#[cfg(windows)]
+use crate::{bcn::decode_bcn, renderer::GL, system::gl32::*};
+
+#[cfg(windows)]
 pub struct Texture {
     texture_ids: HashMap<String, GLuint>,
+    ///Compressed formats the driver can sample, others are decoded on the CPU.
+    compressed_formats: Vec<GLenum>,
 }
 
+#[cfg(windows)]
 impl Texture {
-    pub fn new() -> Self {
+    pub fn new(gl: &GL) -> Self {
+        let mut count: GLint = 0;
+        gl.GetIntegerv(GL_NUM_COMPRESSED_TEXTURE_FORMATS, &mut count);
+
+        let mut compressed_formats = vec![0 as GLint; count.max(0) as usize];
+        if !compressed_formats.is_empty() {
+            gl.GetIntegerv(
+                GL_COMPRESSED_TEXTURE_FORMATS,
+                compressed_formats.as_mut_ptr(),
+            );
+        }
+
         Self {
             texture_ids: HashMap::new(),
+            compressed_formats: compressed_formats
+                .into_iter()
+                .map(|format| format as GLenum)
+                .collect(),
         }
     }
     pub fn load(&mut self, gl: &GL, path: &str) -> GLuint {
-        let Some(extension) = std::path::Path::new(&path).extension() else {
-                eprintln!("Loading texture '{path}' failed: Couldn't parse extension!");
-                return 0;
+        let Some((name, image)) = read_texture(path) else {
+            return 0;
         };
 
-        if extension.to_ascii_lowercase() == "dds" {
-            let Some(filename) = std::path::Path::new(&path).file_stem() else {
-                eprintln!("Loading texture '{path}' failed: Couldn't resolve texture name!");
-                return 0;
-            };
-
-            let tex_id = load_dds(gl, path);
-
-            if tex_id == 0 {
-                return 0;
-            }
-
-            self.texture_ids
-                .insert(String::from(filename.to_str().unwrap()), tex_id);
-            #[cfg(debug_assertions)]
-            println!("Inserting texture: {:?} with id: {tex_id}", filename);
-            tex_id
-        } else if extension.to_ascii_lowercase() == "bmp" {
-            let Some(filename) = std::path::Path::new(&path).file_stem() else {
-                eprintln!("Loading texture '{path}' failed: Couldn't resolve texture name!");
-                return 0;
-            };
-
-            let tex_id = load_bmp(gl, path);
-
-            if tex_id == 0 {
-                return 0;
-            }
+        self.upload(gl, &name, &image)
+    }
+    pub fn upload(&mut self, gl: &GL, name: &str, image: &Image) -> GLuint {
+        let tex_id = upload_image(gl, image, &self.compressed_formats);
 
-            self.texture_ids
-                .insert(String::from(filename.to_str().unwrap()), tex_id);
-            #[cfg(debug_assertions)]
-            println!("Inserting texture: {:?} with id: {tex_id}", filename);
-            tex_id
-        } else {
-            eprintln!("Loading texture '{path}' failed: Unrecognzied format!");
-            0
+        if tex_id == 0 {
+            return 0;
         }
+
+        self.texture_ids.insert(String::from(name), tex_id);
+        #[cfg(debug_assertions)]
+        println!("Inserting texture: {:?} with id: {tex_id}", name);
+        tex_id
     }
     pub fn load_directory(&mut self, gl: &GL, path: &str) {
         let directory = std::path::Path::new(path);
@@ -84,165 +83,169 @@ impl Texture {
     }
 }
 
-const BMP_HEADER_SIZE: usize = 54;
-
-pub fn load_bmp(gl: &GL, path: &str) -> GLuint {
-    let Ok(file) = fs::read(path) else {
-        eprintln!("Loading texture '{path}' failed: File not found!");
-        return 0;
-    };
+///Reason an image file couldn't be read or decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureError {
+    Io(std::io::ErrorKind),
+    UnrecognizedFormat,
+    InvalidSignature,
+    UnexpectedEof,
+    InvalidDimensions {
+        width: i64,
+        height: i64,
+    },
+    ///The file is valid but uses a feature that isn't supported.
+    Unsupported(String),
+    ///The file is corrupt.
+    Malformed(&'static str),
+}
 
-    if file.len() < BMP_HEADER_SIZE || file[0] != b'B' || file[1] != b'M' {
-        eprintln!("Loading texture '{path}' failed: Malformed header data!");
-        return 0;
+impl Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Io(std::io::ErrorKind::NotFound) => write!(f, "File not found!"),
+            TextureError::Io(kind) => write!(f, "Couldn't read file: {kind}!"),
+            TextureError::UnrecognizedFormat => write!(f, "Unrecognized format!"),
+            TextureError::InvalidSignature => write!(f, "Malformed header data!"),
+            TextureError::UnexpectedEof => write!(f, "Unexpected end of file!"),
+            TextureError::InvalidDimensions { width, height } => {
+                write!(f, "Invalid image dimensions {width}x{height}!")
+            }
+            TextureError::Unsupported(message) => write!(f, "{message}!"),
+            TextureError::Malformed(message) => write!(f, "{message}!"),
+        }
     }
+}
 
-    let mut data_position = i32::from_le_bytes([file[10], file[11], file[12], file[13]]);
-    let mut _image_size = i32::from_le_bytes([file[34], file[35], file[36], file[37]]);
-    let width = i32::from_le_bytes([file[18], file[19], file[20], file[21]]);
-    let height = i32::from_le_bytes([file[22], file[23], file[24], file[25]]);
+impl std::error::Error for TextureError {}
 
-    if _image_size == 0 {
-        _image_size = width * height * 3;
-    }
+///Load an image file for use as a texture. Returns the texture name, the file stem without the
+///extension, and the image. Errors are reported and None is returned.
+pub fn read_texture(path: &str) -> Option<(String, Image)> {
+    let Some(filename) = std::path::Path::new(&path).file_stem() else {
+        eprintln!("Loading texture '{path}' failed: Couldn't resolve texture name!");
+        return None;
+    };
 
-    if data_position == 0 {
-        data_position = BMP_HEADER_SIZE as i32;
+    match Image::load(path) {
+        Ok(image) => Some((String::from(filename.to_str().unwrap()), image)),
+        Err(error) => {
+            eprintln!("Loading texture '{path}' failed: {error}");
+            None
+        }
     }
-
-    let texture = file[data_position as usize..file.len()].to_vec();
-
-    let texture_id: GLuint = 0;
-    gl.GenTextures(1, &texture_id);
-    gl.BindTexture(GL_TEXTURE_2D, texture_id);
-
-    gl.TexImage2D(
-        GL_TEXTURE_2D,
-        0,
-        GL_RGBA,
-        width,
-        height,
-        0,
-        GL_BGRA,
-        GL_UNSIGNED_BYTE,
-        &texture[0] as *const _ as *const c_void,
-    );
-    gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as i32);
-    gl.TexParameteri(
-        GL_TEXTURE_2D,
-        GL_TEXTURE_MIN_FILER,
-        GL_LINEAR_MIPMAP_LINEAR as i32,
-    );
-    gl.GenerateMipmap(GL_TEXTURE_2D);
-    gl.BindTexture(GL_TEXTURE_2D, 0);
-
-    texture_id
 }
 
-const DDS_BUFFER_START: usize = 128;
-const DDS_DXT1: u32 = 0x31545844;
-const DDS_DXT3: u32 = 0x33545844;
-const DDS_DXT5: u32 = 0x35545844;
-
-pub fn load_dds(gl: &GL, path: &str) -> GLuint {
-    let Ok(file) = fs::read(path) else {
-        eprintln!("Loading texture '{path}' failed: File not found!");
+///Upload an image as a new texture. RGBA8 images are flipped to bottom row first and get mipmaps
+///generated, block compressed images are uploaded as stored with their own mip levels. Formats
+///missing from compressed_formats are decoded on the CPU first. Only the first layer of texture
+///arrays and the first face of cube maps is uploaded.
+#[cfg(windows)]
+pub fn upload_image(gl: &GL, image: &Image, compressed_formats: &[GLenum]) -> GLuint {
+    if image.width == 0 || image.height == 0 {
+        eprintln!("Uploading texture failed: Image is empty!");
         return 0;
-    };
-
-    let filecode = if file.len() > 4 {
-        from_utf8(&file[0..4]).unwrap()
-    } else {
-        eprintln!("Loading texture '{path}' failed: Malformed data!");
-        return 0;
-    };
+    }
 
-    if filecode != "DDS " {
-        eprintln!("Loading texture '{path}' failed: Malformed header data!");
+    if !image.is_complete() {
+        eprintln!("Uploading texture failed: Pixel data is too short!");
         return 0;
     }
 
-    let height = u32::from_le_bytes([file[12], file[13], file[14], file[15]]);
-    let width = u32::from_le_bytes([file[16], file[17], file[18], file[19]]);
-    let linear_size = u32::from_le_bytes([file[20], file[21], file[22], file[23]]);
-    let mut mipmap_count = u32::from_le_bytes([file[28], file[29], file[30], file[31]]);
-    let four_cc = u32::from_le_bytes([file[84], file[85], file[86], file[87]]);
-
-    let _buf_size = if mipmap_count > 1 {
-        linear_size * 2
-    } else {
-        linear_size
-    };
-
-    let texture = file[DDS_BUFFER_START..file.len()].to_vec();
-
-    let format = match four_cc {
-        DDS_DXT1 => GL_COMPRESSED_RGBA_S3TC_DXT1_EXT,
-        DDS_DXT3 => GL_COMPRESSED_RGBA_S3TC_DXT3_EXT,
-        DDS_DXT5 => GL_COMPRESSED_RGBA_S3TC_DXT5_EXT,
-        _ => {
-            eprintln!("Loading texture '{path}' failed: Unsupported DDS format!");
-            return 0;
-        }
-    };
-
     let texture_id: GLuint = 0;
     gl.GenTextures(1, &texture_id);
-
     gl.BindTexture(GL_TEXTURE_2D, texture_id);
-    gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_BASE_LEVEL, 0);
-    gl.TexParameteri(
-        GL_TEXTURE_2D,
-        GL_TEXTURE_MAX_LEVEL,
-        (mipmap_count - 1) as i32,
-    );
-    gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as i32);
-    gl.TexParameteri(
-        GL_TEXTURE_2D,
-        GL_TEXTURE_MIN_FILTER,
-        GL_LINEAR_MIPMAP_LINEAR as i32,
-    );
-    gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_REPEAT as i32);
-    gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_REPEAT as i32);
-
-    let block_size = if format == GL_COMPRESSED_RGBA_S3TC_DXT1_EXT {
-        8
-    } else {
-        16
-    };
 
-    let mut offset = 0;
-    let mut level = 0;
-    let mut size;
-    let mut w = width;
-    let mut h = height;
+    let format = match image.format {
+        ImageFormat::Rgba8 => None,
+        ImageFormat::Bc1 => Some(GL_COMPRESSED_RGBA_S3TC_DXT1_EXT),
+        ImageFormat::Bc2 => Some(GL_COMPRESSED_RGBA_S3TC_DXT3_EXT),
+        ImageFormat::Bc3 => Some(GL_COMPRESSED_RGBA_S3TC_DXT5_EXT),
+        ImageFormat::Bc4 => Some(GL_COMPRESSED_RED_RGTC1),
+        ImageFormat::Bc4Signed => Some(GL_COMPRESSED_SIGNED_RED_RGTC1),
+        ImageFormat::Bc5 => Some(GL_COMPRESSED_RG_RGTC2),
+        ImageFormat::Bc5Signed => Some(GL_COMPRESSED_SIGNED_RG_RGTC2),
+        ImageFormat::Bc6h => Some(GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT),
+        ImageFormat::Bc6hSigned => Some(GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT),
+        ImageFormat::Bc7 => Some(GL_COMPRESSED_RGBA_BPTC_UNORM),
+    };
 
-    while level < mipmap_count {
-        if w == 0 || h == 0 {
-            mipmap_count -= 1;
-            continue;
+    if let Some(format) = format {
+        gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_BASE_LEVEL, 0);
+        gl.TexParameteri(
+            GL_TEXTURE_2D,
+            GL_TEXTURE_MAX_LEVEL,
+            (image.mip_levels - 1) as i32,
+        );
+        gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as i32);
+        gl.TexParameteri(
+            GL_TEXTURE_2D,
+            GL_TEXTURE_MIN_FILTER,
+            GL_LINEAR_MIPMAP_LINEAR as i32,
+        );
+        gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_REPEAT as i32);
+        gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_REPEAT as i32);
+
+        let supported = compressed_formats.contains(&format);
+        for level in 0..image.mip_levels {
+            let (width, height, data) = image.mip_level(level).unwrap();
+            if supported {
+                gl.CompressedTexImage2D(
+                    GL_TEXTURE_2D,
+                    level as i32,
+                    format,
+                    width as i32,
+                    height as i32,
+                    0,
+                    data.len() as i32,
+                    data.as_ptr() as *const c_void,
+                );
+            } else {
+                //Decoded rows stay in stored order to match the compressed upload.
+                let pixels = decode_bcn(image.format, width, height, data);
+                gl.TexImage2D(
+                    GL_TEXTURE_2D,
+                    level as i32,
+                    GL_RGBA,
+                    width as i32,
+                    height as i32,
+                    0,
+                    GL_RGBA,
+                    GL_UNSIGNED_BYTE,
+                    pixels.as_ptr() as *const c_void,
+                );
+            }
+        }
+    } else {
+        let stride = image.width as usize * 4;
+        let mut texture = Vec::with_capacity(stride * image.height as usize);
+        for row in image.pixels[..stride * image.height as usize]
+            .chunks_exact(stride)
+            .rev()
+        {
+            texture.extend_from_slice(row);
         }
-        size = ((w + 3) / 4) * ((h + 3) / 4) * block_size;
-        gl.CompressedTexImage2D(
+
+        gl.TexImage2D(
             GL_TEXTURE_2D,
-            level as i32,
-            format,
-            w as i32,
-            h as i32,
             0,
-            size as i32,
-            &texture[offset] as *const _ as *const c_void,
+            GL_RGBA,
+            image.width as i32,
+            image.height as i32,
+            0,
+            GL_RGBA,
+            GL_UNSIGNED_BYTE,
+            texture.as_ptr() as *const c_void,
+        );
+        gl.TexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as i32);
+        gl.TexParameteri(
+            GL_TEXTURE_2D,
+            GL_TEXTURE_MIN_FILER,
+            GL_LINEAR_MIPMAP_LINEAR as i32,
         );
-        offset += size as usize;
-        w /= 2;
-        h /= 2;
-        level += 1;
+        gl.GenerateMipmap(GL_TEXTURE_2D);
     }
-    gl.TexParameteri(
-        GL_TEXTURE_2D,
-        GL_TEXTURE_MAX_LEVEL,
-        (mipmap_count - 1) as i32,
-    );
+
     gl.BindTexture(GL_TEXTURE_2D, 0);
 
     texture_id
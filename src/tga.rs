@@ -0,0 +1,322 @@
+use crate::image::Image;
+use crate::texture::TextureError;
+
+const TGA_HEADER_SIZE: usize = 18;
+
+const TGA_COLOR_MAPPED: u8 = 1;
+const TGA_TRUE_COLOR: u8 = 2;
+const TGA_GRAYSCALE: u8 = 3;
+const TGA_RLE: u8 = 8;
+
+const TGA_RIGHT_TO_LEFT: u8 = 0x10;
+const TGA_TOP_TO_BOTTOM: u8 = 0x20;
+
+///Largest width * height accepted, guards against absurd allocations from corrupt headers.
+const TGA_MAX_PIXELS: usize = 1 << 28;
+
+///Most pixels one RLE packet expands to.
+const TGA_MAX_RUN: usize = 128;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Decoding
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Decode an uncompressed or RLE compressed TGA file. Supports color mapped, 8bit grayscale, 16bit
+///grayscale with alpha and 16, 24 and 32bit true color images with any origin.
+pub fn decode_tga(file: &[u8]) -> Result<Image, TextureError> {
+    if file.len() < TGA_HEADER_SIZE {
+        return Err(TextureError::UnexpectedEof);
+    }
+
+    let id_length = file[0] as usize;
+    let color_map_type = file[1];
+    let image_type = file[2];
+    let color_map_first = u16::from_le_bytes([file[3], file[4]]) as usize;
+    let color_map_length = u16::from_le_bytes([file[5], file[6]]) as usize;
+    let color_map_depth = file[7];
+    let width = u16::from_le_bytes([file[12], file[13]]) as usize;
+    let height = u16::from_le_bytes([file[14], file[15]]) as usize;
+    let pixel_depth = file[16];
+    let descriptor = file[17];
+    let has_alpha = descriptor & 0x0f != 0;
+
+    if width == 0 || height == 0 || width * height > TGA_MAX_PIXELS {
+        return Err(TextureError::InvalidDimensions {
+            width: width as i64,
+            height: height as i64,
+        });
+    }
+
+    let base_type = image_type & !TGA_RLE;
+    let valid_depth = match base_type {
+        TGA_COLOR_MAPPED => color_map_type == 1 && (pixel_depth == 8 || pixel_depth == 16),
+        TGA_TRUE_COLOR => matches!(pixel_depth, 15 | 16 | 24 | 32),
+        TGA_GRAYSCALE => pixel_depth == 8 || pixel_depth == 16,
+        _ => {
+            return Err(TextureError::Unsupported(String::from(
+                "Unsupported TGA image type",
+            )))
+        }
+    };
+    if !valid_depth {
+        return Err(TextureError::Unsupported(String::from(
+            "Unsupported TGA pixel depth",
+        )));
+    }
+
+    let mut position = TGA_HEADER_SIZE + id_length;
+
+    //A color map may be present even when the image doesn't use it, it has to be skipped.
+    let mut palette = Vec::new();
+    if color_map_type == 1 {
+        let entry_size = (color_map_depth as usize).div_ceil(8);
+        if !matches!(color_map_depth, 15 | 16 | 24 | 32) {
+            return Err(TextureError::Unsupported(String::from(
+                "Unsupported TGA color map depth",
+            )));
+        }
+
+        let size = color_map_length * entry_size;
+        let Some(entries) = file.get(position..position + size) else {
+            return Err(TextureError::UnexpectedEof);
+        };
+        palette = entries
+            .chunks_exact(entry_size)
+            .map(|entry| true_color(entry, has_alpha))
+            .collect();
+        position += size;
+    }
+
+    let bytes_per_pixel = (pixel_depth as usize).div_ceil(8);
+    let pixel_count = width * height;
+
+    let data = if image_type & TGA_RLE != 0 {
+        decode_rle(
+            &file[position.min(file.len())..],
+            pixel_count,
+            bytes_per_pixel,
+        )?
+    } else {
+        let size = pixel_count * bytes_per_pixel;
+        let Some(data) = file.get(position..position + size) else {
+            return Err(TextureError::UnexpectedEof);
+        };
+        data.to_vec()
+    };
+
+    let mut pixels = vec![0; pixel_count * 4];
+
+    for (i, pixel) in data.chunks_exact(bytes_per_pixel).enumerate() {
+        let rgba = match base_type {
+            TGA_COLOR_MAPPED => {
+                let index = match bytes_per_pixel {
+                    1 => pixel[0] as usize,
+                    _ => u16::from_le_bytes([pixel[0], pixel[1]]) as usize,
+                };
+                match index
+                    .checked_sub(color_map_first)
+                    .and_then(|index| palette.get(index))
+                {
+                    Some(rgba) => *rgba,
+                    None => return Err(TextureError::Malformed("Color map index out of range")),
+                }
+            }
+            TGA_GRAYSCALE => {
+                let alpha = if bytes_per_pixel == 2 { pixel[1] } else { 255 };
+                [pixel[0], pixel[0], pixel[0], alpha]
+            }
+            _ => true_color(pixel, has_alpha),
+        };
+
+        //Rows are stored bottom up and left to right unless the descriptor says otherwise.
+        let mut x = i % width;
+        let mut y = i / width;
+        if descriptor & TGA_RIGHT_TO_LEFT != 0 {
+            x = width - 1 - x;
+        }
+        if descriptor & TGA_TOP_TO_BOTTOM == 0 {
+            y = height - 1 - y;
+        }
+
+        let start = (y * width + x) * 4;
+        pixels[start..start + 4].copy_from_slice(&rgba);
+    }
+
+    //Some writers leave the alpha of 32bit files at zero without declaring alpha bits, treat those
+    //as opaque.
+    if pixel_depth == 32 && !has_alpha && pixels.chunks_exact(4).all(|pixel| pixel[3] == 0) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+    }
+
+    Ok(Image::new(width as u32, height as u32, pixels))
+}
+
+///Convert a 15/16bit ARRRRRGGGGGBBBBB, 24bit BGR or 32bit BGRA value to RGBA.
+fn true_color(pixel: &[u8], has_alpha: bool) -> [u8; 4] {
+    match pixel.len() {
+        2 => {
+            let value = u16::from_le_bytes([pixel[0], pixel[1]]);
+            let channel = |shift: u16| (((value >> shift) & 0x1f) as u32 * 255 / 31) as u8;
+            let alpha = if !has_alpha || value & 0x8000 != 0 {
+                255
+            } else {
+                0
+            };
+            [channel(10), channel(5), channel(0), alpha]
+        }
+        3 => [pixel[2], pixel[1], pixel[0], 255],
+        _ => [pixel[2], pixel[1], pixel[0], pixel[3]],
+    }
+}
+
+///Expand RLE packets to raw pixel data. Packets may run across rows.
+fn decode_rle(
+    data: &[u8],
+    pixel_count: usize,
+    bytes_per_pixel: usize,
+) -> Result<Vec<u8>, TextureError> {
+    //Every packet takes at least a header byte and one pixel, files too short to cover the
+    //image even with the longest runs are rejected before anything is allocated.
+    if pixel_count > data.len() / (1 + bytes_per_pixel) * TGA_MAX_RUN {
+        return Err(TextureError::UnexpectedEof);
+    }
+
+    let size = pixel_count * bytes_per_pixel;
+    let mut output = Vec::new();
+    let mut position = 0;
+
+    while output.len() < size {
+        let Some(&packet) = data.get(position) else {
+            return Err(TextureError::UnexpectedEof);
+        };
+        position += 1;
+
+        let count = (packet & 0x7f) as usize + 1;
+        let run_size = if packet & 0x80 != 0 {
+            bytes_per_pixel
+        } else {
+            count * bytes_per_pixel
+        };
+
+        let Some(run) = data.get(position..position + run_size) else {
+            return Err(TextureError::UnexpectedEof);
+        };
+        position += run_size;
+
+        if packet & 0x80 != 0 {
+            for _ in 0..count {
+                output.extend_from_slice(run);
+            }
+        } else {
+            output.extend_from_slice(run);
+        }
+    }
+
+    output.truncate(size);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tga(
+        image_type: u8,
+        palette: &[u8],
+        size: (u16, u16),
+        depth: u8,
+        descriptor: u8,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut file = vec![0, !palette.is_empty() as u8, image_type, 0, 0];
+        file.extend_from_slice(&(palette.len() as u16 / 3).to_le_bytes());
+        file.push(if palette.is_empty() { 0 } else { 24 });
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&size.0.to_le_bytes());
+        file.extend_from_slice(&size.1.to_le_bytes());
+        file.extend_from_slice(&[depth, descriptor]);
+        file.extend_from_slice(palette);
+        file.extend_from_slice(data);
+        file
+    }
+
+    #[test]
+    fn decodes_bottom_up_true_color() {
+        let data = [0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255];
+        let image = decode_tga(&tga(TGA_TRUE_COLOR, &[], (2, 2), 24, 0, &data)).unwrap();
+
+        #[rustfmt::skip]
+        assert_eq!(image.pixels, [
+            0, 0, 255, 255, 255, 255, 255, 255,
+            255, 0, 0, 255, 0, 255, 0, 255,
+        ]);
+    }
+
+    #[test]
+    fn decodes_rle_runs_across_rows() {
+        //A run of three and a raw packet of one, top down with alpha bits.
+        let data = [0x82, 1, 2, 3, 4, 0x00, 5, 6, 7, 8];
+        let image = decode_tga(&tga(
+            TGA_TRUE_COLOR | TGA_RLE,
+            &[],
+            (2, 2),
+            32,
+            TGA_TOP_TO_BOTTOM | 8,
+            &data,
+        ))
+        .unwrap();
+
+        assert_eq!(image.pixels[0..12], [3, 2, 1, 4].repeat(3));
+        assert_eq!(image.pixels[12..16], [7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn decodes_right_to_left_color_map_and_grayscale() {
+        let palette = [255, 0, 0, 0, 0, 255];
+        let image = decode_tga(&tga(
+            TGA_COLOR_MAPPED,
+            &palette,
+            (2, 1),
+            8,
+            TGA_RIGHT_TO_LEFT,
+            &[0, 1],
+        ))
+        .unwrap();
+        assert_eq!(image.pixels, [255, 0, 0, 255, 0, 0, 255, 255]);
+
+        let image = decode_tga(&tga(TGA_GRAYSCALE, &[], (1, 1), 16, 8, &[90, 128])).unwrap();
+        assert_eq!(image.pixels, [90, 90, 90, 128]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_hostile_files() {
+        let file = tga(TGA_TRUE_COLOR, &[], (2, 2), 24, 0, &[0; 12]);
+        for length in 0..file.len() {
+            assert!(decode_tga(&file[..length]).is_err(), "length {length}");
+        }
+        assert_eq!(
+            decode_tga(&tga(TGA_COLOR_MAPPED, &[0; 3], (1, 1), 8, 0, &[1])).err(),
+            Some(TextureError::Malformed("Color map index out of range"))
+        );
+        assert!(matches!(
+            decode_tga(&tga(TGA_TRUE_COLOR, &[], (0, 4), 24, 0, &[])),
+            Err(TextureError::InvalidDimensions { .. })
+        ));
+
+        //RLE data that couldn't cover the image even with the longest runs fails up front.
+        assert_eq!(
+            decode_tga(&tga(
+                TGA_TRUE_COLOR | TGA_RLE,
+                &[],
+                (16384, 16384),
+                32,
+                0,
+                &[0xff; 10]
+            ))
+            .err(),
+            Some(TextureError::UnexpectedEof)
+        );
+    }
+}
@@ -0,0 +1,1012 @@
+use std::collections::HashMap;
+
+use crate::atlas::AtlasBuilder;
+use crate::font::{BitmapFont, Glyph};
+use crate::image::Image;
+use crate::texture::TextureError;
+
+///Size of the atlas pages glyphs are rasterized into.
+const FONT_PAGE_SIZE: u32 = 1024;
+
+///Transparent pixels between glyphs so filtering doesn't pick up neighbouring glyphs.
+const FONT_PADDING: u32 = 2;
+
+///Deepest nesting of composite glyphs, guards against corrupt fonts referencing themselves.
+const COMPOSITE_MAX_DEPTH: usize = 8;
+
+///Printable ASCII and Latin-1 characters, rasterized by System::define_font.
+pub(crate) fn default_characters() -> impl Iterator<Item = char> {
+    (' '..='~').chain('\u{a0}'..='\u{ff}')
+}
+
+///Outline point in font units, y grows upwards.
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    x: f32,
+    y: f32,
+    on_curve: bool,
+}
+
+///TrueType font with glyf outlines, parsed from a .ttf file. Only the tables needed to rasterize
+///glyphs are read: head, maxp, cmap, hhea, hmtx, loca, glyf and kern.
+pub struct TrueTypeFont {
+    data: Vec<u8>,
+    units_per_em: f32,
+    ascender: f32,
+    descender: f32,
+    line_gap: f32,
+    glyph_count: usize,
+    long_offsets: bool,
+    horizontal_metrics: usize,
+    cmap: usize,
+    hmtx: usize,
+    loca: usize,
+    glyf: usize,
+    kern: Option<usize>,
+}
+
+impl TrueTypeFont {
+    pub fn load(path: &str) -> Result<Self, TextureError> {
+        let data = std::fs::read(path).map_err(|error| TextureError::Io(error.kind()))?;
+        Self::parse(data)
+    }
+
+    pub fn parse(data: Vec<u8>) -> Result<Self, TextureError> {
+        match read_u32(&data, 0)? {
+            0x00010000 | 0x74727565 => {}
+            0x4f54544f => {
+                return Err(TextureError::Unsupported(String::from(
+                    "OpenType fonts with CFF outlines are not supported",
+                )))
+            }
+            0x74746366 => {
+                return Err(TextureError::Unsupported(String::from(
+                    "TrueType collections are not supported",
+                )))
+            }
+            _ => return Err(TextureError::InvalidSignature),
+        }
+
+        let mut tables = HashMap::new();
+        let table_count = read_u16(&data, 4)? as usize;
+        for i in 0..table_count {
+            let record = 12 + i * 16;
+            let tag = data
+                .get(record..record + 4)
+                .ok_or(TextureError::UnexpectedEof)?;
+            let offset = read_u32(&data, record + 8)? as usize;
+            let length = read_u32(&data, record + 12)? as usize;
+            if offset
+                .checked_add(length)
+                .is_none_or(|end| end > data.len())
+            {
+                return Err(TextureError::Malformed("TrueType table out of range"));
+            }
+            tables.insert(tag.to_vec(), offset);
+        }
+        let table = |tag: &[u8]| -> Result<usize, TextureError> {
+            match tables.get(tag) {
+                Some(offset) => Ok(*offset),
+                None => Err(TextureError::Malformed("Missing TrueType table")),
+            }
+        };
+
+        let head = table(b"head")?;
+        let maxp = table(b"maxp")?;
+        let hhea = table(b"hhea")?;
+        let units_per_em = read_u16(&data, head + 18)? as f32;
+        if units_per_em == 0.0 {
+            return Err(TextureError::Malformed("TrueType font has no units per em"));
+        }
+
+        Ok(Self {
+            units_per_em,
+            ascender: read_u16(&data, hhea + 4)? as i16 as f32,
+            descender: read_u16(&data, hhea + 6)? as i16 as f32,
+            line_gap: read_u16(&data, hhea + 8)? as i16 as f32,
+            glyph_count: read_u16(&data, maxp + 4)? as usize,
+            long_offsets: read_u16(&data, head + 50)? != 0,
+            horizontal_metrics: read_u16(&data, hhea + 34)? as usize,
+            cmap: find_cmap(&data, table(b"cmap")?)?,
+            hmtx: table(b"hmtx")?,
+            loca: table(b"loca")?,
+            glyf: table(b"glyf")?,
+            kern: tables.get(&b"kern"[..]).copied(),
+            data,
+        })
+    }
+
+    ///Rasterize characters with anti-aliasing into atlas pages. pixel_height is the height of a
+    ///line from the highest ascender to the lowest descender, like the cell height of a font
+    ///defined with System::define_font. Characters the font has no glyph for are left out, the
+    ///missing glyph of the font becomes the fallback glyph drawn in their place. Fails if a glyph
+    ///doesn't fit on an atlas page.
+    pub fn rasterize(
+        &self,
+        pixel_height: f32,
+        characters: impl IntoIterator<Item = char>,
+    ) -> Result<(BitmapFont, Vec<Image>), TextureError> {
+        let em_height = self.ascender - self.descender;
+        let scale = if em_height > 0.0 {
+            pixel_height / em_height
+        } else {
+            pixel_height / self.units_per_em
+        };
+        let base = (self.ascender * scale).round();
+
+        let mut builder = AtlasBuilder::new(FONT_PAGE_SIZE, FONT_PAGE_SIZE, FONT_PADDING, 0);
+        //Metrics keyed by character, None is the missing glyph.
+        let mut metrics = HashMap::new();
+        let mut glyph_characters: HashMap<u16, Vec<char>> = HashMap::new();
+
+        let glyphs = characters.into_iter().map(|character| {
+            let index = self.glyph_index(character)?;
+            Ok((Some(character), index))
+        });
+        for glyph in std::iter::once(Ok((None, 0))).chain(glyphs) {
+            let (character, index) = glyph?;
+            if metrics.contains_key(&character) || (character.is_some() && index == 0) {
+                continue;
+            }
+            if let Some(character) = character {
+                glyph_characters.entry(index).or_default().push(character);
+            }
+
+            let advance = (self.advance(index)? * scale).round();
+            let contours = self.outline(index, 0)?;
+            match rasterize_outline(&contours, scale)? {
+                Some((image, x_min, y_max)) => {
+                    builder.add(&atlas_name(character), image);
+                    metrics.insert(character, (x_min as f32, base - y_max as f32, advance));
+                }
+                None => {
+                    metrics.insert(character, (0.0, 0.0, advance));
+                }
+            }
+        }
+
+        let atlas = builder.build()?;
+
+        //Every page of a BitmapFont has the same size, pad them to the largest page.
+        let page_width = atlas.pages.iter().map(|page| page.width).max().unwrap_or(1);
+        let page_height = atlas
+            .pages
+            .iter()
+            .map(|page| page.height)
+            .max()
+            .unwrap_or(1);
+        let pages: Vec<Image> = atlas
+            .pages
+            .iter()
+            .map(|page| {
+                let mut pixels = vec![0; page_width as usize * page_height as usize * 4];
+                for (y, row) in page
+                    .pixels
+                    .chunks_exact(page.width as usize * 4)
+                    .enumerate()
+                {
+                    let start = y * page_width as usize * 4;
+                    pixels[start..start + row.len()].copy_from_slice(row);
+                }
+                Image::new(page_width, page_height, pixels)
+            })
+            .collect();
+
+        let mut glyphs = HashMap::with_capacity(metrics.len());
+        let mut fallback = None;
+        for (character, (x_offset, y_offset, x_advance)) in metrics {
+            let region = atlas.regions.get(&atlas_name(character));
+            let glyph = Glyph {
+                page: region.map_or(0, |region| region.page),
+                x: region.map_or(0, |region| region.x),
+                y: region.map_or(0, |region| region.y),
+                width: region.map_or(0, |region| region.width),
+                height: region.map_or(0, |region| region.height),
+                x_offset,
+                y_offset,
+                x_advance,
+            };
+            match character {
+                Some(character) => {
+                    glyphs.insert(character, glyph);
+                }
+                None => fallback = Some(glyph),
+            }
+        }
+
+        let mut kerning = HashMap::new();
+        for (left, right, value) in self.kerning_pairs()? {
+            let (Some(lefts), Some(rights)) =
+                (glyph_characters.get(&left), glyph_characters.get(&right))
+            else {
+                continue;
+            };
+            for &first in lefts {
+                for &second in rights {
+                    kerning.insert((first, second), (value * scale).round());
+                }
+            }
+        }
+
+        let font = BitmapFont {
+            line_height: ((self.ascender - self.descender + self.line_gap) * scale).round(),
+            base,
+            page_width,
+            page_height,
+            pages: (0..pages.len())
+                .map(|page| format!("page_{page}"))
+                .collect(),
+            glyphs,
+            kerning,
+            fallback,
+        };
+        Ok((font, pages))
+    }
+
+    ///Glyph index of character, 0 is the missing glyph.
+    fn glyph_index(&self, character: char) -> Result<u16, TextureError> {
+        let data = &self.data;
+        let cmap = self.cmap;
+        let code = character as u32;
+
+        match read_u16(data, cmap)? {
+            4 => {
+                if code > 0xffff {
+                    return Ok(0);
+                }
+                let segments = read_u16(data, cmap + 6)? as usize / 2;
+                let end_codes = cmap + 14;
+                let start_codes = end_codes + segments * 2 + 2;
+                let deltas = start_codes + segments * 2;
+                let range_offsets = deltas + segments * 2;
+
+                for segment in 0..segments {
+                    if code > read_u16(data, end_codes + segment * 2)? as u32 {
+                        continue;
+                    }
+                    let start = read_u16(data, start_codes + segment * 2)? as u32;
+                    if code < start {
+                        return Ok(0);
+                    }
+
+                    let delta = read_u16(data, deltas + segment * 2)?;
+                    let range_offset = read_u16(data, range_offsets + segment * 2)? as usize;
+                    if range_offset == 0 {
+                        return Ok((code as u16).wrapping_add(delta));
+                    }
+
+                    //The offset is relative to its own position in the range offset array.
+                    let position =
+                        range_offsets + segment * 2 + range_offset + (code - start) as usize * 2;
+                    let index = read_u16(data, position)?;
+                    return Ok(if index == 0 {
+                        0
+                    } else {
+                        index.wrapping_add(delta)
+                    });
+                }
+                Ok(0)
+            }
+            12 => {
+                let groups = read_u32(data, cmap + 12)? as usize;
+                for group in 0..groups {
+                    let record = cmap + 16 + group * 12;
+                    let start = read_u32(data, record)?;
+                    let end = read_u32(data, record + 4)?;
+                    if (start..=end).contains(&code) {
+                        let index = read_u32(data, record + 8)? + (code - start);
+                        return Ok(u16::try_from(index).unwrap_or(0));
+                    }
+                }
+                Ok(0)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    ///Advance width of a glyph in font units.
+    fn advance(&self, index: u16) -> Result<f32, TextureError> {
+        let metric = (index as usize).min(self.horizontal_metrics.saturating_sub(1));
+        Ok(read_u16(&self.data, self.hmtx + metric * 4)? as f32)
+    }
+
+    ///Contours of a glyph in font units, components of composite glyphs are merged.
+    fn outline(&self, index: u16, depth: usize) -> Result<Vec<Vec<Point>>, TextureError> {
+        if depth > COMPOSITE_MAX_DEPTH || index as usize >= self.glyph_count {
+            return Err(TextureError::Malformed("Invalid TrueType composite glyph"));
+        }
+
+        let data = &self.data;
+        let (start, end) = if self.long_offsets {
+            let entry = self.loca + index as usize * 4;
+            (
+                read_u32(data, entry)? as usize,
+                read_u32(data, entry + 4)? as usize,
+            )
+        } else {
+            let entry = self.loca + index as usize * 2;
+            (
+                read_u16(data, entry)? as usize * 2,
+                read_u16(data, entry + 2)? as usize * 2,
+            )
+        };
+        //Glyphs without outlines, like space, have no data.
+        if end <= start {
+            return Ok(Vec::new());
+        }
+
+        let glyph = self.glyf + start;
+        let contour_count = read_u16(data, glyph)? as i16;
+        if contour_count >= 0 {
+            simple_outline(data, glyph, contour_count as usize)
+        } else {
+            self.composite_outline(glyph, depth)
+        }
+    }
+
+    fn composite_outline(
+        &self,
+        glyph: usize,
+        depth: usize,
+    ) -> Result<Vec<Vec<Point>>, TextureError> {
+        const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const WE_HAVE_A_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+        const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+        let data = &self.data;
+        let f2dot14 = |offset: usize| -> Result<f32, TextureError> {
+            Ok(read_u16(data, offset)? as i16 as f32 / 16384.0)
+        };
+
+        let mut contours = Vec::new();
+        let mut position = glyph + 10;
+        loop {
+            let flags = read_u16(data, position)?;
+            let component = read_u16(data, position + 2)?;
+            position += 4;
+
+            let (dx, dy) = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                position += 4;
+                (
+                    read_u16(data, position - 4)? as i16 as f32,
+                    read_u16(data, position - 2)? as i16 as f32,
+                )
+            } else {
+                position += 2;
+                let bytes = data
+                    .get(position - 2..position)
+                    .ok_or(TextureError::UnexpectedEof)?;
+                (bytes[0] as i8 as f32, bytes[1] as i8 as f32)
+            };
+            //Components placed by matching points are drawn without an offset.
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 {
+                (dx, dy)
+            } else {
+                (0.0, 0.0)
+            };
+
+            let (mut a, mut b, mut c, mut d) = (1.0, 0.0, 0.0, 1.0);
+            if flags & WE_HAVE_A_SCALE != 0 {
+                a = f2dot14(position)?;
+                d = a;
+                position += 2;
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                a = f2dot14(position)?;
+                d = f2dot14(position + 2)?;
+                position += 4;
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                a = f2dot14(position)?;
+                b = f2dot14(position + 2)?;
+                c = f2dot14(position + 4)?;
+                d = f2dot14(position + 6)?;
+                position += 8;
+            }
+
+            for contour in self.outline(component, depth + 1)? {
+                contours.push(
+                    contour
+                        .into_iter()
+                        .map(|point| Point {
+                            x: a * point.x + c * point.y + dx,
+                            y: b * point.x + d * point.y + dy,
+                            on_curve: point.on_curve,
+                        })
+                        .collect(),
+                );
+            }
+
+            if flags & MORE_COMPONENTS == 0 {
+                return Ok(contours);
+            }
+        }
+    }
+
+    ///Horizontal kerning pairs of glyph indices from a version 0 kern table, in font units.
+    fn kerning_pairs(&self) -> Result<Vec<(u16, u16, f32)>, TextureError> {
+        let Some(kern) = self.kern else {
+            return Ok(Vec::new());
+        };
+        let data = &self.data;
+        //Apple's version 1 tables use a different header.
+        if read_u16(data, kern)? != 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut pairs = Vec::new();
+        let mut subtable = kern + 4;
+        for _ in 0..read_u16(data, kern + 2)? {
+            let length = read_u16(data, subtable + 2)? as usize;
+            let coverage = read_u16(data, subtable + 4)?;
+            //Format 0, horizontal, kerning values rather than minimums, not cross stream.
+            if coverage >> 8 == 0 && coverage & 0x0f == 0x01 {
+                let count = read_u16(data, subtable + 6)? as usize;
+                for pair in 0..count {
+                    let record = subtable + 14 + pair * 6;
+                    pairs.push((
+                        read_u16(data, record)?,
+                        read_u16(data, record + 2)?,
+                        read_u16(data, record + 4)? as i16 as f32,
+                    ));
+                }
+            }
+            if length == 0 {
+                break;
+            }
+            subtable += length;
+        }
+        Ok(pairs)
+    }
+}
+
+///Name of a glyph in the atlas, the missing glyph has an empty name.
+fn atlas_name(character: Option<char>) -> String {
+    character.map(String::from).unwrap_or_default()
+}
+
+///Offset of the best Unicode cmap subtable, full repertoire format 12 preferred over format 4.
+fn find_cmap(data: &[u8], cmap: usize) -> Result<usize, TextureError> {
+    let mut best = None;
+    for i in 0..read_u16(data, cmap + 2)? as usize {
+        let record = cmap + 4 + i * 8;
+        let platform = read_u16(data, record)?;
+        let encoding = read_u16(data, record + 2)?;
+        let subtable = cmap + read_u32(data, record + 4)? as usize;
+
+        let unicode = platform == 0 || (platform == 3 && matches!(encoding, 1 | 10));
+        if !unicode {
+            continue;
+        }
+        match read_u16(data, subtable)? {
+            12 => return Ok(subtable),
+            4 => best = Some(subtable),
+            _ => {}
+        }
+    }
+
+    match best {
+        Some(subtable) => Ok(subtable),
+        None => Err(TextureError::Unsupported(String::from(
+            "TrueType font has no supported Unicode cmap",
+        ))),
+    }
+}
+
+fn simple_outline(
+    data: &[u8],
+    glyph: usize,
+    contour_count: usize,
+) -> Result<Vec<Vec<Point>>, TextureError> {
+    const ON_CURVE: u8 = 0x01;
+    const X_SHORT: u8 = 0x02;
+    const Y_SHORT: u8 = 0x04;
+    const REPEAT: u8 = 0x08;
+    const X_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+    let mut end_points = Vec::with_capacity(contour_count);
+    for contour in 0..contour_count {
+        end_points.push(read_u16(data, glyph + 10 + contour * 2)? as usize);
+    }
+    let Some(&last) = end_points.last() else {
+        return Ok(Vec::new());
+    };
+    let point_count = last + 1;
+
+    let instructions = glyph + 10 + contour_count * 2;
+    let mut position = instructions + 2 + read_u16(data, instructions)? as usize;
+    let byte = |position: usize| {
+        data.get(position)
+            .copied()
+            .ok_or(TextureError::UnexpectedEof)
+    };
+
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = byte(position)?;
+        position += 1;
+        flags.push(flag);
+        if flag & REPEAT != 0 {
+            let repeat = byte(position)?;
+            position += 1;
+            for _ in 0..repeat {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(point_count);
+
+    //Coordinates are stored as deltas, x for every point first, then y.
+    let mut read_coordinates = |short: u8, same_or_positive: u8| {
+        let mut value = 0i32;
+        let mut coordinates = Vec::with_capacity(point_count);
+        for &flag in &flags {
+            if flag & short != 0 {
+                let delta = byte(position)? as i32;
+                position += 1;
+                value += if flag & same_or_positive != 0 {
+                    delta
+                } else {
+                    -delta
+                };
+            } else if flag & same_or_positive == 0 {
+                value += read_u16(data, position)? as i16 as i32;
+                position += 2;
+            }
+            coordinates.push(value as f32);
+        }
+        Ok::<_, TextureError>(coordinates)
+    };
+    let xs = read_coordinates(X_SHORT, X_SAME_OR_POSITIVE)?;
+    let ys = read_coordinates(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+    let mut contours = Vec::with_capacity(contour_count);
+    let mut start = 0;
+    for end in end_points {
+        if end < start || end >= point_count {
+            return Err(TextureError::Malformed("Invalid TrueType contour"));
+        }
+        contours.push(
+            (start..=end)
+                .map(|i| Point {
+                    x: xs[i],
+                    y: ys[i],
+                    on_curve: flags[i] & ON_CURVE != 0,
+                })
+                .collect(),
+        );
+        start = end + 1;
+    }
+    Ok(contours)
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Rasterizer
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Rasterize contours scaled from font units to pixels. Returns a white image with coverage in
+///alpha and the left and top edges of the image in pixels from the glyph origin, y growing
+///upwards. None if the glyph has no area, an error if it's larger than an atlas page.
+fn rasterize_outline(
+    contours: &[Vec<Point>],
+    scale: f32,
+) -> Result<Option<(Image, i32, i32)>, TextureError> {
+    if contours.iter().all(Vec::is_empty) {
+        return Ok(None);
+    }
+
+    let (mut x_min, mut y_min) = (f32::MAX, f32::MAX);
+    let (mut x_max, mut y_max) = (f32::MIN, f32::MIN);
+    for point in contours.iter().flatten() {
+        x_min = x_min.min(point.x * scale);
+        y_min = y_min.min(point.y * scale);
+        x_max = x_max.max(point.x * scale);
+        y_max = y_max.max(point.y * scale);
+    }
+
+    let (left, bottom) = (x_min.floor() as i32, y_min.floor() as i32);
+    let (right, top) = (x_max.ceil() as i32, y_max.ceil() as i32);
+    let width = (right as i64 - left as i64).max(0);
+    let height = (top as i64 - bottom as i64).max(0);
+    if width == 0 || height == 0 {
+        return Ok(None);
+    }
+    //Checked before the rasterizer allocates, a glyph this size couldn't be packed anyway.
+    if width > FONT_PAGE_SIZE as i64 || height > FONT_PAGE_SIZE as i64 {
+        return Err(TextureError::InvalidDimensions { width, height });
+    }
+    let (width, height) = (width as usize, height as usize);
+
+    let mut rasterizer = Rasterizer::new(width, height);
+    //Image rows go down from the top edge.
+    let to_pixels = |point: &Point| (point.x * scale - left as f32, top as f32 - point.y * scale);
+
+    for contour in contours {
+        let Some(first) = contour.first() else {
+            continue;
+        };
+
+        //Start on a point on the curve, between two control points if there is none.
+        let start_index = contour.iter().position(|point| point.on_curve);
+        let start = match start_index {
+            Some(index) => to_pixels(&contour[index]),
+            None => midpoint(to_pixels(first), to_pixels(&contour[1 % contour.len()])),
+        };
+        let offset = start_index.unwrap_or(0);
+
+        let mut current = start;
+        let mut control: Option<(f32, f32)> = None;
+        for i in 1..=contour.len() {
+            let point = &contour[(offset + i) % contour.len()];
+            let position = to_pixels(point);
+
+            match (point.on_curve, control) {
+                (true, None) => {
+                    rasterizer.line(current, position);
+                    current = position;
+                }
+                (true, Some(c)) => {
+                    rasterizer.quad(current, c, position);
+                    current = position;
+                    control = None;
+                }
+                (false, None) => control = Some(position),
+                //Two control points in a row imply a point on the curve between them.
+                (false, Some(c)) => {
+                    let middle = midpoint(c, position);
+                    rasterizer.quad(current, c, middle);
+                    current = middle;
+                    control = Some(position);
+                }
+            }
+        }
+        match control {
+            Some(c) => rasterizer.quad(current, c, start),
+            None => rasterizer.line(current, start),
+        }
+    }
+
+    let pixels = rasterizer
+        .coverage()
+        .flat_map(|alpha| [255, 255, 255, alpha])
+        .collect();
+    Ok(Some((
+        Image::new(width as u32, height as u32, pixels),
+        left,
+        top,
+    )))
+}
+
+fn midpoint(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    ((a.0 + b.0) * 0.5, (a.1 + b.1) * 0.5)
+}
+
+///Anti-aliased scanline rasterizer. Every line adds the signed area it covers to the pixels it
+///crosses and the accumulated sum along each row is the coverage of the pixel.
+struct Rasterizer {
+    width: usize,
+    height: usize,
+    areas: Vec<f32>,
+}
+
+impl Rasterizer {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            //Lines on the right edge spill one cell into the next row, the running sum of a
+            //closed outline is zero at the end of every row so this is harmless.
+            areas: vec![0.0; width * height + 2],
+        }
+    }
+
+    fn line(&mut self, from: (f32, f32), to: (f32, f32)) {
+        if from.1 == to.1 {
+            return;
+        }
+        let (direction, top, bottom) = if from.1 < to.1 {
+            (1.0, from, to)
+        } else {
+            (-1.0, to, from)
+        };
+        let dxdy = (bottom.0 - top.0) / (bottom.1 - top.1);
+
+        let first_row = top.1.max(0.0) as usize;
+        let last_row = (bottom.1.ceil() as usize).min(self.height);
+        let mut x = top.0 + ((first_row as f32).max(top.1) - top.1) * dxdy;
+
+        for row in first_row..last_row {
+            let row_start = row * self.width;
+            let dy = ((row + 1) as f32).min(bottom.1) - (row as f32).max(top.1);
+            let x_next = x + dxdy * dy;
+            let area = dy * direction;
+
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = x0.floor();
+            let x1_ceil = x1.ceil();
+            let (x0_cell, x1_cell) = (x0_floor.max(0.0) as usize, x1_ceil.max(0.0) as usize);
+
+            if x1_cell <= x0_cell + 1 {
+                //The line stays within one pixel of this row.
+                let middle = 0.5 * (x + x_next) - x0_floor;
+                self.add(row_start + x0_cell, area * (1.0 - middle));
+                self.add(row_start + x0_cell + 1, area * middle);
+            } else {
+                let inverse_width = (x1 - x0).recip();
+                let x0_fraction = x0 - x0_floor;
+                let first = 0.5 * inverse_width * (1.0 - x0_fraction) * (1.0 - x0_fraction);
+                let x1_fraction = x1 - x1_ceil + 1.0;
+                let last = 0.5 * inverse_width * x1_fraction * x1_fraction;
+
+                self.add(row_start + x0_cell, area * first);
+                if x1_cell == x0_cell + 2 {
+                    self.add(row_start + x0_cell + 1, area * (1.0 - first - last));
+                } else {
+                    let second = inverse_width * (1.5 - x0_fraction);
+                    self.add(row_start + x0_cell + 1, area * (second - first));
+                    for cell in x0_cell + 2..x1_cell - 1 {
+                        self.add(row_start + cell, area * inverse_width);
+                    }
+                    let before_last = second + (x1_cell - x0_cell - 3) as f32 * inverse_width;
+                    self.add(row_start + x1_cell - 1, area * (1.0 - before_last - last));
+                }
+                self.add(row_start + x1_cell, area * last);
+            }
+            x = x_next;
+        }
+    }
+
+    ///Flatten a quadratic curve into lines, more of them the further the control point bends it.
+    fn quad(&mut self, from: (f32, f32), control: (f32, f32), to: (f32, f32)) {
+        let deviation_x = from.0 - 2.0 * control.0 + to.0;
+        let deviation_y = from.1 - 2.0 * control.1 + to.1;
+        let deviation = deviation_x * deviation_x + deviation_y * deviation_y;
+        if deviation < 0.333 {
+            self.line(from, to);
+            return;
+        }
+
+        let segments = 1 + (3.0 * deviation).sqrt().sqrt().floor() as usize;
+        let mut previous = from;
+        for segment in 1..=segments {
+            let t = segment as f32 / segments as f32;
+            let u = 1.0 - t;
+            let point = (
+                u * u * from.0 + 2.0 * u * t * control.0 + t * t * to.0,
+                u * u * from.1 + 2.0 * u * t * control.1 + t * t * to.1,
+            );
+            self.line(previous, point);
+            previous = point;
+        }
+    }
+
+    fn add(&mut self, index: usize, area: f32) {
+        if let Some(cell) = self.areas.get_mut(index) {
+            *cell += area;
+        }
+    }
+
+    ///Coverage of every pixel from 0 to 255, row by row from the top.
+    fn coverage(&self) -> impl Iterator<Item = u8> + '_ {
+        self.areas[..self.width * self.height]
+            .iter()
+            .scan(0.0, |sum, area| {
+                *sum += area;
+                Some((sum.abs().min(1.0) * 255.0).round() as u8)
+            })
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, TextureError> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(TextureError::UnexpectedEof),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, TextureError> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes(bytes.try_into().unwrap())),
+        None => Err(TextureError::UnexpectedEof),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLYPH_SPACE: u16 = 2;
+    const GLYPH_SQUARE: u16 = 1;
+    const GLYPH_COMPOSITE: u16 = 3;
+    const GLYPH_SELF_COMPOSITE: u16 = 4;
+
+    ///Closed square outline of four on curve points.
+    fn square_glyph(x_min: i16, y_min: i16, x_max: i16, y_max: i16) -> Vec<u8> {
+        let mut glyph = Vec::new();
+        for value in [1, x_min, y_min, x_max, y_max, 3, 0] {
+            glyph.extend_from_slice(&value.to_be_bytes());
+        }
+        glyph.extend_from_slice(&[0x01; 4]);
+        for delta in [
+            x_min,
+            0,
+            x_max - x_min,
+            0,
+            y_min,
+            y_max - y_min,
+            0,
+            y_min - y_max,
+        ] {
+            glyph.extend_from_slice(&delta.to_be_bytes());
+        }
+        glyph
+    }
+
+    fn composite_glyph(component: u16, dx: i16) -> Vec<u8> {
+        let mut glyph = Vec::new();
+        //ARG_1_AND_2_ARE_WORDS | ARGS_ARE_XY_VALUES
+        for value in [-1, 0, 0, 0, 0, 0x0003, component as i16, dx, 0] {
+            glyph.extend_from_slice(&value.to_be_bytes());
+        }
+        glyph
+    }
+
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect()
+    }
+
+    ///Font with 1000 units per em: a square missing glyph, a square 'A', an empty space, a 'B'
+    ///made of 'A' moved 50 units right and a 'C' made of itself. 'A' 'B' is a kerning pair.
+    fn test_font() -> Vec<u8> {
+        let glyphs = [
+            square_glyph(0, 0, 500, 500),
+            square_glyph(100, 0, 900, 700),
+            Vec::new(),
+            composite_glyph(GLYPH_SQUARE, 50),
+            composite_glyph(GLYPH_SELF_COMPOSITE, 0),
+        ];
+        let mut glyf = Vec::new();
+        let mut loca = vec![0];
+        for glyph in &glyphs {
+            glyf.extend_from_slice(glyph);
+            loca.push(glyf.len() as u16 / 2);
+        }
+
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut hhea = vec![0; 36];
+        hhea[4..10].copy_from_slice(&u16s(&[800, -200i16 as u16, 0]));
+        hhea[34..36].copy_from_slice(&(glyphs.len() as u16).to_be_bytes());
+        let maxp = u16s(&[0, 0, glyphs.len() as u16]);
+        let hmtx = u16s(&[500, 0, 1000, 0, 250, 0, 1000, 0, 1000, 0]);
+
+        //Format 4 segments for ' ', 'A' and 'B' to 'C', then the closing 0xffff segment.
+        let segments = [
+            (32, 32, GLYPH_SPACE),
+            (65, 65, GLYPH_SQUARE),
+            (66, 67, GLYPH_COMPOSITE),
+        ];
+        let mut subtable = u16s(&[4, 0, 0, (segments.len() as u16 + 1) * 2, 0, 0, 0]);
+        subtable.extend(u16s(&segments.map(|(_, end, _)| end)));
+        subtable.extend(u16s(&[0xffff, 0]));
+        subtable.extend(u16s(&segments.map(|(start, _, _)| start)));
+        subtable.extend(u16s(&[0xffff]));
+        subtable.extend(u16s(
+            &segments.map(|(start, _, glyph)| glyph.wrapping_sub(start)),
+        ));
+        subtable.extend(u16s(&[1, 0, 0, 0, 0]));
+        let mut cmap = u16s(&[0, 1, 3, 1, 0, 12]);
+        cmap.extend(subtable);
+
+        let mut kern = u16s(&[0, 1, 0, 20, 0x0001, 1, 0, 0, 0]);
+        kern.extend(u16s(&[GLYPH_SQUARE, GLYPH_COMPOSITE, -100i16 as u16]));
+
+        let tables: [(&[u8; 4], Vec<u8>); 8] = [
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"kern", kern),
+            (b"loca", u16s(&loca)),
+            (b"maxp", maxp),
+        ];
+        let mut file = u16s(&[1, 0, tables.len() as u16, 0, 0, 0]);
+        let mut offset = file.len() + tables.len() * 16;
+        for (tag, table) in &tables {
+            file.extend_from_slice(*tag);
+            file.extend_from_slice(&[0; 4]);
+            file.extend_from_slice(&(offset as u32).to_be_bytes());
+            file.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            offset += table.len();
+        }
+        for (_, table) in tables {
+            file.extend(table);
+        }
+        file
+    }
+
+    fn glyph_alpha(page: &Image, glyph: &Glyph, x: u32, y: u32) -> u8 {
+        page.pixels[(((glyph.y + y) * page.width + glyph.x + x) * 4 + 3) as usize]
+    }
+
+    #[test]
+    fn looks_up_characters_in_the_cmap() {
+        let font = TrueTypeFont::parse(test_font()).unwrap();
+
+        assert_eq!(font.glyph_index(' '), Ok(GLYPH_SPACE));
+        assert_eq!(font.glyph_index('A'), Ok(GLYPH_SQUARE));
+        assert_eq!(font.glyph_index('B'), Ok(GLYPH_COMPOSITE));
+        assert_eq!(font.glyph_index('C'), Ok(GLYPH_SELF_COMPOSITE));
+        assert_eq!(font.glyph_index('@'), Ok(0));
+        assert_eq!(font.glyph_index('D'), Ok(0));
+        assert_eq!(font.glyph_index('\u{1f600}'), Ok(0));
+    }
+
+    #[test]
+    fn rasterizes_glyphs_with_metrics_and_kerning() {
+        let font = TrueTypeFont::parse(test_font()).unwrap();
+        let (bitmap, pages) = font.rasterize(10.0, "A B?".chars()).unwrap();
+
+        assert_eq!((bitmap.line_height, bitmap.base), (10.0, 8.0));
+        assert_eq!(bitmap.kerning, HashMap::from([(('A', 'B'), -1.0)]));
+        assert!(bitmap.fallback.is_some());
+        assert!(!bitmap.glyphs.contains_key(&'?'));
+
+        let space = &bitmap.glyphs[&' '];
+        assert_eq!((space.width, space.x_advance), (0, 3.0));
+
+        //The square covers whole pixels from (1, 0) to (9, 7) above the baseline.
+        let a = &bitmap.glyphs[&'A'];
+        assert_eq!((a.width, a.height), (8, 7));
+        assert_eq!((a.x_offset, a.y_offset, a.x_advance), (1.0, 1.0, 10.0));
+        let page = &pages[a.page];
+        for (x, y) in [(0, 0), (7, 0), (3, 3), (0, 6), (7, 6)] {
+            assert_eq!(glyph_alpha(page, a, x, y), 255);
+        }
+
+        //Moved half a pixel right, the edge columns are half covered.
+        let b = &bitmap.glyphs[&'B'];
+        assert_eq!((b.width, b.height, b.x_offset), (9, 7, 1.0));
+        let page = &pages[b.page];
+        assert_eq!(glyph_alpha(page, b, 0, 3), 128);
+        assert_eq!(glyph_alpha(page, b, 4, 3), 255);
+        assert_eq!(glyph_alpha(page, b, 8, 3), 128);
+    }
+
+    #[test]
+    fn rejects_hostile_glyphs() {
+        let font = TrueTypeFont::parse(test_font()).unwrap();
+        assert_eq!(
+            font.rasterize(10.0, "C".chars()).err(),
+            Some(TextureError::Malformed("Invalid TrueType composite glyph"))
+        );
+
+        //Glyphs larger than a page fail before the rasterizer allocates anything.
+        assert!(matches!(
+            font.rasterize(100_000.0, "A".chars()),
+            Err(TextureError::InvalidDimensions { .. })
+        ));
+        let huge = [[(0.0, 0.0), (1e9, 0.0), (1e9, 1e9)]
+            .map(|(x, y)| Point {
+                x,
+                y,
+                on_curve: true,
+            })
+            .to_vec()];
+        assert!(rasterize_outline(&huge, 1.0).is_err());
+
+        let file = test_font();
+        for length in [0, 4, 12, 40, 100] {
+            assert!(
+                TrueTypeFont::parse(file[..length].to_vec()).is_err(),
+                "length {length}"
+            );
+        }
+    }
+}
@@ -0,0 +1,1618 @@
+use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2, PI};
+use std::time::Duration;
+
+use crate::mixer::{AudioError, Sound};
+use crate::ogg::OggReader;
+
+const MAX_CHANNELS: usize = 8;
+
+///Codewords up to this length are decoded with a single table lookup.
+const FAST_BITS: u32 = 10;
+
+///Range of the floor 1 amplitudes by multiplier.
+const FLOOR1_RANGES: [i32; 4] = [256, 128, 86, 64];
+
+///Most points a floor 1 curve can have.
+const FLOOR1_MAX_POINTS: usize = 65;
+
+///Most values a codebook can unpack, guards against corrupt files asking for gigabytes.
+const CODEBOOK_MAX_VALUES: usize = 1 << 24;
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Decoding
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Decode an Ogg Vorbis file completely. Files with more than two channels are mixed down to
+///stereo, use a VorbisStream to decode long music a piece at a time instead.
+pub fn decode_ogg(file: &[u8]) -> Result<Sound, AudioError> {
+    let mut stream = VorbisStream::new(file.to_vec())?;
+    let channels = stream.channels() as usize;
+    let mut samples = Vec::new();
+    let mut chunk = vec![0.0; 4096 * channels];
+
+    loop {
+        let frames = stream.read(&mut chunk)?;
+        if frames == 0 {
+            break;
+        }
+        samples.extend_from_slice(&chunk[..frames * channels]);
+    }
+
+    Ok(Sound {
+        sample_rate: stream.sample_rate(),
+        channels: channels as u16,
+        samples,
+    })
+}
+
+///Ogg Vorbis file decoded on demand. The compressed file is kept in memory and every read()
+///decodes only as many packets as it needs, so an hour of music costs a few megabytes instead of
+///hundreds. Files with more than two channels are mixed down to stereo.
+#[derive(Debug, Clone)]
+pub struct VorbisStream {
+    file: Vec<u8>,
+    reader: OggReader,
+    sample_rate: u32,
+    ///Channels coded in the file.
+    channels: usize,
+    blocksizes: [usize; 2],
+    setup: Setup,
+    imdct: [Imdct; 2],
+    ///Rising half of the short and the long window.
+    slopes: [Vec<f32>; 2],
+    downmix: Option<Vec<(f32, f32)>>,
+    ///Right half of the last block of every channel, overlapped with the next block.
+    overlap: Vec<Vec<f32>>,
+    previous_blocksize: Option<usize>,
+    ///Decoded interleaved frames that haven't been read yet.
+    pending: Vec<f32>,
+    pending_position: usize,
+    ///Frame after the last decoded one, unknown after seeking until a page ends.
+    decoded_frames: Option<u64>,
+    ///Frames before it are decoded but dropped, set by seeking.
+    skip_until: u64,
+    ///Granule position of every page that has one and the offset of the page after it.
+    pages: Vec<(u64, usize)>,
+    finished: bool,
+}
+
+impl VorbisStream {
+    pub fn open(path: &str) -> Result<Self, AudioError> {
+        match std::fs::read(path) {
+            Ok(file) => VorbisStream::new(file),
+            Err(error) => Err(AudioError::Io(error.kind())),
+        }
+    }
+
+    ///Read the headers of an Ogg Vorbis file, no audio is decoded until read() is called.
+    pub fn new(file: Vec<u8>) -> Result<Self, AudioError> {
+        let mut reader = OggReader::new();
+        let next_header = |reader: &mut OggReader| match reader.next_packet(&file)? {
+            Some(packet) => Ok(packet.data),
+            None => Err(AudioError::UnexpectedEof),
+        };
+
+        let identification = next_header(&mut reader)?;
+        let mut bits = BitReader::new(&identification);
+        read_header_type(&mut bits, 1)?;
+        let version = bits.bits(32)?;
+        let channels = bits.bits(8)? as usize;
+        let sample_rate = bits.bits(32)?;
+        bits.bits(32 * 3)?;
+        let blocksizes = [1 << bits.bits(4)?, 1 << bits.bits(4)?];
+        let framing = bits.bits(1)?;
+
+        if version != 0 {
+            return Err(AudioError::Unsupported(format!(
+                "Unsupported Vorbis version {version}"
+            )));
+        }
+        if channels == 0 || channels > MAX_CHANNELS {
+            return Err(AudioError::Unsupported(format!(
+                "Unsupported Vorbis channel count {channels}"
+            )));
+        }
+        if sample_rate == 0 {
+            return Err(AudioError::Malformed("Vorbis sample rate is 0"));
+        }
+        if blocksizes[0] < 64 || blocksizes[1] > 8192 || blocksizes[0] > blocksizes[1] {
+            return Err(AudioError::Malformed("Invalid Vorbis block sizes"));
+        }
+        if framing != 1 {
+            return Err(AudioError::Malformed("Vorbis header framing bit not set"));
+        }
+
+        //Comments aren't used but the packet has to be there.
+        let comments = next_header(&mut reader)?;
+        read_header_type(&mut BitReader::new(&comments), 3)?;
+
+        let setup = next_header(&mut reader)?;
+        let setup = read_setup(&setup, channels)?;
+
+        let downmix = (channels > 2).then(|| downmix_gains(channels));
+        let pages = reader.granule_positions(&file)?;
+
+        Ok(Self {
+            file,
+            reader,
+            sample_rate,
+            channels,
+            blocksizes,
+            setup,
+            imdct: [Imdct::new(blocksizes[0]), Imdct::new(blocksizes[1])],
+            slopes: [
+                window_slope(blocksizes[0] / 2),
+                window_slope(blocksizes[1] / 2),
+            ],
+            downmix,
+            overlap: vec![Vec::new(); channels],
+            previous_blocksize: None,
+            pending: Vec::new(),
+            pending_position: 0,
+            decoded_frames: Some(0),
+            skip_until: 0,
+            pages,
+            finished: false,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    ///Channels of the decoded frames, 1 for mono or 2 for stereo.
+    pub fn channels(&self) -> u16 {
+        self.channels.min(2) as u16
+    }
+
+    ///Length of the stream in frames, from the granule position of its last page.
+    pub fn frames(&self) -> u64 {
+        self.pages
+            .last()
+            .map_or(0, |&(granule_position, _)| granule_position)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate as f64)
+    }
+
+    ///Frame the next read() starts at.
+    pub fn position(&self) -> u64 {
+        let channels = self.channels() as usize;
+        let unread = ((self.pending.len() - self.pending_position) / channels) as u64;
+        match self.decoded_frames {
+            Some(decoded_frames) => (decoded_frames - unread).max(self.skip_until),
+            None => self.skip_until,
+        }
+    }
+
+    ///Fill out with interleaved frames. Returns the number of frames read, less than fit in out
+    ///only at the end of the stream and 0 once it's over.
+    pub fn read(&mut self, out: &mut [f32]) -> Result<usize, AudioError> {
+        let channels = self.channels() as usize;
+        let capacity = out.len() / channels * channels;
+        let mut written = 0;
+
+        while written < capacity {
+            if self.pending_position == self.pending.len() {
+                self.pending.clear();
+                self.pending_position = 0;
+                if !self.decode_next()? {
+                    break;
+                }
+                continue;
+            }
+
+            let count = (capacity - written).min(self.pending.len() - self.pending_position);
+            out[written..written + count].copy_from_slice(
+                &self.pending[self.pending_position..self.pending_position + count],
+            );
+            written += count;
+            self.pending_position += count;
+        }
+        Ok(written / channels)
+    }
+
+    ///Start over from the beginning of the file.
+    pub fn rewind(&mut self) {
+        //The headers are skipped again, they were valid the first time.
+        self.reader.rewind();
+        for _ in 0..3 {
+            let _ = self.reader.next_packet(&self.file);
+        }
+        self.reset(Some(0), 0);
+    }
+
+    ///Continue reading at frame, positions past the end end the stream. Decoding restarts two
+    ///pages before the frame and the frames up to it are decoded and dropped, so the next read()
+    ///starts at exactly that frame.
+    pub fn seek(&mut self, frame: u64) {
+        //First page that ends past the frame.
+        let page = self
+            .pages
+            .partition_point(|&(granule_position, _)| granule_position <= frame);
+
+        if page == self.pages.len() {
+            self.reset(Some(self.frames()), self.frames());
+            self.finished = true;
+            return;
+        }
+
+        //The first packet after a seek only primes the overlap, decoding starts a page early so
+        //the frames it would have produced come from the page before. The granule position of that
+        //page then tells where the decoded frames are, it's never the last page which may cut its
+        //last block short. Pages at granule position 0 hold the headers, those start over.
+        if page < 2 || self.pages[page - 2].0 == 0 {
+            self.rewind();
+            self.skip_until = frame;
+        } else {
+            self.reader.seek_to_page(self.pages[page - 2].1);
+            self.reset(None, frame);
+        }
+    }
+
+    fn reset(&mut self, decoded_frames: Option<u64>, skip_until: u64) {
+        for overlap in &mut self.overlap {
+            overlap.clear();
+        }
+        self.previous_blocksize = None;
+        self.pending.clear();
+        self.pending_position = 0;
+        self.decoded_frames = decoded_frames;
+        self.skip_until = skip_until;
+        self.finished = false;
+    }
+
+    ///Decode packets until one produces frames, false at the end of the stream.
+    fn decode_next(&mut self) -> Result<bool, AudioError> {
+        while !self.finished {
+            let Some(packet) = self.reader.next_packet(&self.file)? else {
+                self.finished = true;
+                break;
+            };
+            if packet.last {
+                self.finished = true;
+            }
+
+            //Packets that aren't audio or are cut short are skipped.
+            let Some(blocks) = self.decode_packet(&packet.data) else {
+                continue;
+            };
+            let channels = self.channels() as usize;
+            let start = self.pending.len();
+            let frames = self.overlap_add(blocks) as u64;
+
+            //Frame and sample index of the first frame whose position is now known.
+            let (first, start) = match self.decoded_frames {
+                Some(decoded_frames) => {
+                    //The granule position of the last page ends the stream part way into its
+                    //last block.
+                    let mut frames = frames;
+                    if let (true, Some(granule_position)) = (packet.last, packet.granule_position) {
+                        frames = frames.min(granule_position.saturating_sub(decoded_frames));
+                    }
+                    self.pending.truncate(start + frames as usize * channels);
+                    self.decoded_frames = Some(decoded_frames + frames);
+                    (decoded_frames, start)
+                }
+                //After seeking frames are kept until a granule position tells where they are.
+                None => match packet.granule_position {
+                    Some(granule_position) => {
+                        self.decoded_frames = Some(granule_position);
+                        let kept = (self.pending.len() / channels) as u64;
+                        (granule_position.saturating_sub(kept), 0)
+                    }
+                    None => continue,
+                },
+            };
+            let skip = (self.skip_until.saturating_sub(first) as usize)
+                .min((self.pending.len() - start) / channels);
+            self.pending.drain(start..start + skip * channels);
+
+            if !self.pending.is_empty() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    ///Decode an audio packet into windowed blocks of every channel.
+    fn decode_packet(&self, packet: &[u8]) -> Option<Vec<Vec<f32>>> {
+        let setup = &self.setup;
+        let mut bits = BitReader::new(packet);
+
+        if bits.read(1)? != 0 {
+            return None;
+        }
+        let mode_bits = ilog(setup.modes.len() as u32 - 1);
+        let mode = setup.modes.get(bits.read(mode_bits)? as usize)?;
+        let long = mode.blockflag as usize;
+        let n = self.blocksizes[long];
+        let (previous_long, next_long) = match mode.blockflag {
+            true => (bits.read(1)? == 1, bits.read(1)? == 1),
+            false => (false, false),
+        };
+
+        let mapping = &setup.mappings[mode.mapping];
+        let half = n / 2;
+
+        //Floors, running out of packet silences the whole block.
+        let mut floors = vec![vec![0.0; half]; self.channels];
+        let mut used = vec![false; self.channels];
+        let mut silent = false;
+        for (channel, floor) in floors.iter_mut().enumerate() {
+            let submap = &mapping.submaps[mapping.mux[channel]];
+            let decoded = match &setup.floors[submap.floor] {
+                Floor::Zero(floor0) => floor0.decode(&mut bits, &setup.codebooks, floor),
+                Floor::One(floor1) => floor1.decode(&mut bits, &setup.codebooks, floor),
+            };
+            match decoded {
+                Some(floor_used) => used[channel] = floor_used,
+                None => {
+                    silent = true;
+                    break;
+                }
+            }
+        }
+
+        let mut residues = vec![vec![0.0; half]; self.channels];
+        let mut no_residue: Vec<bool> = used.iter().map(|&used| !used).collect();
+        if !silent {
+            //Coupled channels are decoded if either of them is.
+            for &(magnitude, angle) in &mapping.coupling {
+                if !no_residue[magnitude] || !no_residue[angle] {
+                    no_residue[magnitude] = false;
+                    no_residue[angle] = false;
+                }
+            }
+
+            for (index, submap) in mapping.submaps.iter().enumerate() {
+                let channels: Vec<usize> = (0..self.channels)
+                    .filter(|&channel| mapping.mux[channel] == index)
+                    .collect();
+                setup.residues[submap.residue].decode(
+                    &mut bits,
+                    &setup.codebooks,
+                    &mut residues,
+                    &channels,
+                    &no_residue,
+                );
+            }
+
+            for &(magnitude, angle) in mapping.coupling.iter().rev() {
+                let mut angles = std::mem::take(&mut residues[angle]);
+                for (m, a) in residues[magnitude].iter_mut().zip(&mut angles) {
+                    (*m, *a) = match (*m > 0.0, *a > 0.0) {
+                        (true, true) => (*m, *m - *a),
+                        (true, false) => (*m + *a, *m),
+                        (false, true) => (*m, *m + *a),
+                        (false, false) => (*m - *a, *m),
+                    };
+                }
+                residues[angle] = angles;
+            }
+        }
+
+        let mut blocks = Vec::with_capacity(self.channels);
+        for (channel, residue) in residues.iter_mut().enumerate() {
+            let mut block = vec![0.0; n];
+            //Channels without a floor are silent, whatever their residue.
+            if !silent && used[channel] {
+                for (value, floor) in residue.iter_mut().zip(&floors[channel]) {
+                    *value *= floor;
+                }
+                self.imdct[long].inverse(residue, &mut block);
+                self.apply_window(&mut block, long == 1, previous_long, next_long);
+            }
+            blocks.push(block);
+        }
+        Some(blocks)
+    }
+
+    fn apply_window(&self, block: &mut [f32], long: bool, previous_long: bool, next_long: bool) {
+        let n = block.len();
+        let short_quarter = self.blocksizes[0] / 4;
+
+        let (left_start, left_slope) = match long && !previous_long {
+            true => (n / 4 - short_quarter, &self.slopes[0]),
+            false => (0, &self.slopes[long as usize]),
+        };
+        let (right_start, right_slope) = match long && !next_long {
+            true => (n * 3 / 4 - short_quarter, &self.slopes[0]),
+            false => (n / 2, &self.slopes[long as usize]),
+        };
+
+        block[..left_start].fill(0.0);
+        for (value, slope) in block[left_start..].iter_mut().zip(left_slope) {
+            *value *= slope;
+        }
+        for (value, slope) in block[right_start..]
+            .iter_mut()
+            .zip(right_slope.iter().rev())
+        {
+            *value *= slope;
+        }
+        block[right_start + right_slope.len()..].fill(0.0);
+    }
+
+    ///Overlap the blocks with the right half of the previous ones and queue the finished frames.
+    ///Returns the number of frames, the first block of a stream only primes the overlap.
+    fn overlap_add(&mut self, blocks: Vec<Vec<f32>>) -> usize {
+        let n = blocks[0].len();
+        let frames = match self.previous_blocksize {
+            Some(previous) => previous / 4 + n / 4,
+            None => 0,
+        };
+        //Where the current block starts relative to the first frame.
+        let offset = self.previous_blocksize.unwrap_or(0) / 4;
+        let offset = offset as isize - n as isize / 4;
+
+        let start = self.pending.len();
+        self.pending
+            .resize(start + frames * self.channels() as usize, 0.0);
+
+        for frame in 0..frames {
+            let mut left = 0.0;
+            let mut right = 0.0;
+            for (channel, block) in blocks.iter().enumerate() {
+                let overlap = self.overlap[channel].get(frame).copied().unwrap_or(0.0);
+                let current = frame as isize - offset;
+                let current = match current >= 0 {
+                    true => block[current as usize],
+                    false => 0.0,
+                };
+                let value = overlap + current;
+
+                match &self.downmix {
+                    Some(gains) => {
+                        left += value * gains[channel].0;
+                        right += value * gains[channel].1;
+                    }
+                    None if channel == 0 => left = value,
+                    None => right = value,
+                }
+            }
+
+            match self.channels() {
+                1 => self.pending[start + frame] = left,
+                _ => {
+                    self.pending[start + frame * 2] = left;
+                    self.pending[start + frame * 2 + 1] = right;
+                }
+            }
+        }
+
+        for (overlap, block) in self.overlap.iter_mut().zip(blocks) {
+            overlap.clear();
+            overlap.extend_from_slice(&block[n / 2..]);
+        }
+        self.previous_blocksize = Some(n);
+        frames
+    }
+}
+
+///Check the type and the "vorbis" signature that start every header packet.
+fn read_header_type(bits: &mut BitReader, header_type: u32) -> Result<(), AudioError> {
+    if bits.bits(8)? != header_type {
+        return Err(AudioError::Malformed("Unexpected Vorbis header"));
+    }
+    for &byte in b"vorbis" {
+        if bits.bits(8)? != byte as u32 {
+            return Err(AudioError::InvalidSignature);
+        }
+    }
+    Ok(())
+}
+
+///Left and right gain of every channel in the Vorbis channel order. Left and right speakers go to
+///their side, the low frequency channel is dropped and centers are shared by both sides.
+fn downmix_gains(channels: usize) -> Vec<(f32, f32)> {
+    let layout = match channels {
+        3 => "LCR",
+        4 => "LRLR",
+        5 => "LCRLR",
+        6 => "LCRLRE",
+        7 => "LCRLRCE",
+        _ => "LCRLRLRE",
+    };
+    layout
+        .chars()
+        .map(|speaker| match speaker {
+            'L' => (1.0, 0.0),
+            'R' => (0.0, 1.0),
+            'C' => (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            _ => (0.0, 0.0),
+        })
+        .collect()
+}
+
+///Rising half of the Vorbis window over n samples, the falling half is the same reversed.
+fn window_slope(n: usize) -> Vec<f32> {
+    (0..n)
+        .map(|i| {
+            let x = (i as f32 + 0.5) / n as f32 * FRAC_PI_2;
+            (FRAC_PI_2 * x.sin() * x.sin()).sin()
+        })
+        .collect()
+}
+
+///Bits needed to store value.
+fn ilog(value: u32) -> u32 {
+    32 - value.leading_zeros()
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Setup
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Codebooks and configuration of the setup header, shared by every audio packet.
+#[derive(Debug, Clone)]
+struct Setup {
+    codebooks: Vec<Codebook>,
+    floors: Vec<Floor>,
+    residues: Vec<Residue>,
+    mappings: Vec<Mapping>,
+    modes: Vec<Mode>,
+}
+
+#[derive(Debug, Clone)]
+enum Floor {
+    Zero(Floor0),
+    One(Floor1),
+}
+
+#[derive(Debug, Clone)]
+struct Mapping {
+    ///Magnitude and angle channel of every coupling step.
+    coupling: Vec<(usize, usize)>,
+    ///Submap of every channel.
+    mux: Vec<usize>,
+    submaps: Vec<Submap>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Submap {
+    floor: usize,
+    residue: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mode {
+    blockflag: bool,
+    mapping: usize,
+}
+
+fn read_setup(packet: &[u8], channels: usize) -> Result<Setup, AudioError> {
+    let mut bits = BitReader::new(packet);
+    read_header_type(&mut bits, 5)?;
+
+    let count = bits.bits(8)? + 1;
+    let mut codebooks = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        codebooks.push(Codebook::read(&mut bits)?);
+    }
+    let book = |index: u32| -> Result<usize, AudioError> {
+        match (index as usize) < codebooks.len() {
+            true => Ok(index as usize),
+            false => Err(AudioError::Malformed("Vorbis codebook doesn't exist")),
+        }
+    };
+
+    //Time domain transforms are placeholders that must be 0.
+    for _ in 0..bits.bits(6)? + 1 {
+        if bits.bits(16)? != 0 {
+            return Err(AudioError::Malformed(
+                "Invalid Vorbis time domain transform",
+            ));
+        }
+    }
+
+    let mut floors = Vec::new();
+    for _ in 0..bits.bits(6)? + 1 {
+        floors.push(match bits.bits(16)? {
+            0 => Floor::Zero(Floor0::read(&mut bits, book)?),
+            1 => Floor::One(Floor1::read(&mut bits, book)?),
+            _ => return Err(AudioError::Malformed("Invalid Vorbis floor type")),
+        });
+    }
+
+    let mut residues = Vec::new();
+    for _ in 0..bits.bits(6)? + 1 {
+        let residue = Residue::read(&mut bits, book)?;
+        let mut books = residue.books.iter().flatten().flatten();
+        if books.any(|&book| codebooks[book].vectors.is_none()) {
+            return Err(AudioError::Malformed("Vorbis residue book has no vectors"));
+        }
+        residues.push(residue);
+    }
+
+    let mut mappings = Vec::new();
+    let channel_bits = ilog(channels as u32 - 1);
+    for _ in 0..bits.bits(6)? + 1 {
+        if bits.bits(16)? != 0 {
+            return Err(AudioError::Malformed("Invalid Vorbis mapping type"));
+        }
+        let submap_count = match bits.bits(1)? {
+            1 => bits.bits(4)? as usize + 1,
+            _ => 1,
+        };
+
+        let mut coupling = Vec::new();
+        if bits.bits(1)? == 1 {
+            for _ in 0..bits.bits(8)? + 1 {
+                let magnitude = bits.bits(channel_bits)? as usize;
+                let angle = bits.bits(channel_bits)? as usize;
+                if magnitude == angle || magnitude >= channels || angle >= channels {
+                    return Err(AudioError::Malformed("Invalid Vorbis channel coupling"));
+                }
+                coupling.push((magnitude, angle));
+            }
+        }
+        if bits.bits(2)? != 0 {
+            return Err(AudioError::Malformed("Vorbis mapping reserved bits set"));
+        }
+
+        let mut mux = vec![0; channels];
+        if submap_count > 1 {
+            for submap in &mut mux {
+                *submap = bits.bits(4)? as usize;
+                if *submap >= submap_count {
+                    return Err(AudioError::Malformed("Vorbis submap doesn't exist"));
+                }
+            }
+        }
+
+        let mut submaps = Vec::with_capacity(submap_count);
+        for _ in 0..submap_count {
+            bits.bits(8)?;
+            let floor = bits.bits(8)? as usize;
+            let residue = bits.bits(8)? as usize;
+            if floor >= floors.len() || residue >= residues.len() {
+                return Err(AudioError::Malformed(
+                    "Vorbis floor or residue doesn't exist",
+                ));
+            }
+            submaps.push(Submap { floor, residue });
+        }
+
+        mappings.push(Mapping {
+            coupling,
+            mux,
+            submaps,
+        });
+    }
+
+    let mut modes = Vec::new();
+    for _ in 0..bits.bits(6)? + 1 {
+        let blockflag = bits.bits(1)? == 1;
+        let window_type = bits.bits(16)?;
+        let transform_type = bits.bits(16)?;
+        let mapping = bits.bits(8)? as usize;
+        if window_type != 0 || transform_type != 0 || mapping >= mappings.len() {
+            return Err(AudioError::Malformed("Invalid Vorbis mode"));
+        }
+        modes.push(Mode { blockflag, mapping });
+    }
+
+    if bits.bits(1)? != 1 {
+        return Err(AudioError::Malformed("Vorbis header framing bit not set"));
+    }
+
+    Ok(Setup {
+        codebooks,
+        floors,
+        residues,
+        mappings,
+        modes,
+    })
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Codebooks
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Huffman code of entries, optionally mapping every entry to a vector of values.
+#[derive(Debug, Clone)]
+struct Codebook {
+    dimensions: usize,
+    ///Entry and codeword length of the next FAST_BITS bits, length 0 if the codeword is longer.
+    fast: Vec<(u32, u8)>,
+    ///Codewords longer than FAST_BITS with their length and entry, least significant bit first.
+    long: Vec<(u32, u8, u32)>,
+    ///Values of every entry one after the other, None for scalar only books.
+    vectors: Option<Vec<f32>>,
+}
+
+impl Codebook {
+    fn read(bits: &mut BitReader) -> Result<Self, AudioError> {
+        if bits.bits(24)? != 0x564342 {
+            return Err(AudioError::Malformed(
+                "Invalid Vorbis codebook sync pattern",
+            ));
+        }
+        let dimensions = bits.bits(16)? as usize;
+        let entries = bits.bits(24)? as usize;
+
+        let mut lengths = vec![0u8; entries];
+        if bits.bits(1)? == 0 {
+            let sparse = bits.bits(1)? == 1;
+            for length in &mut lengths {
+                if !sparse || bits.bits(1)? == 1 {
+                    *length = bits.bits(5)? as u8 + 1;
+                }
+            }
+        } else {
+            let mut entry = 0;
+            let mut length = bits.bits(5)? + 1;
+            while entry < entries {
+                let count = bits.bits(ilog((entries - entry) as u32))? as usize;
+                if entry + count > entries || length > 32 {
+                    return Err(AudioError::Malformed("Invalid Vorbis codebook lengths"));
+                }
+                lengths[entry..entry + count].fill(length as u8);
+                entry += count;
+                length += 1;
+            }
+        }
+
+        let vectors = match bits.bits(4)? {
+            0 => None,
+            lookup_type @ (1 | 2) => {
+                let minimum = float32_unpack(bits.bits(32)?);
+                let delta = float32_unpack(bits.bits(32)?);
+                let value_bits = bits.bits(4)? + 1;
+                let sequence = bits.bits(1)? == 1;
+                if entries.saturating_mul(dimensions) > CODEBOOK_MAX_VALUES {
+                    return Err(AudioError::Malformed("Vorbis codebook is too large"));
+                }
+                let lookup_values = match lookup_type {
+                    1 => lookup1_values(entries, dimensions),
+                    _ => entries * dimensions,
+                };
+                let mut multiplicands = Vec::with_capacity(lookup_values);
+                for _ in 0..lookup_values {
+                    multiplicands.push(bits.bits(value_bits)? as f32);
+                }
+
+                let mut vectors = Vec::with_capacity(entries * dimensions);
+                for entry in 0..entries {
+                    let mut last = 0.0;
+                    let mut divisor = 1;
+                    for dimension in 0..dimensions {
+                        let offset = match lookup_type {
+                            1 => entry / divisor % lookup_values.max(1),
+                            _ => entry * dimensions + dimension,
+                        };
+                        let value = multiplicands.get(offset).copied().unwrap_or(0.0);
+                        let value = value * delta + minimum + last;
+                        if sequence {
+                            last = value;
+                        }
+                        vectors.push(value);
+                        divisor *= lookup_values;
+                    }
+                }
+                Some(vectors)
+            }
+            _ => return Err(AudioError::Malformed("Invalid Vorbis codebook lookup type")),
+        };
+
+        let (fast, long) = build_codewords(&lengths)?;
+        Ok(Self {
+            dimensions,
+            fast,
+            long,
+            vectors,
+        })
+    }
+
+    ///Read the next entry, None at the end of the packet or on an invalid codeword.
+    fn decode(&self, bits: &mut BitReader) -> Option<u32> {
+        let (entry, length) = self.fast[bits.peek(FAST_BITS) as usize];
+        if length > 0 {
+            bits.read(length as u32)?;
+            return Some(entry);
+        }
+
+        let next = bits.peek(32);
+        for &(codeword, length, entry) in &self.long {
+            if next & mask(length as u32) == codeword {
+                bits.read(length as u32)?;
+                return Some(entry);
+            }
+        }
+        bits.skip_to_end();
+        None
+    }
+
+    ///Read the next entry and return its vector.
+    fn decode_vector(&self, bits: &mut BitReader) -> Option<&[f32]> {
+        let entry = self.decode(bits)? as usize;
+        let start = entry * self.dimensions;
+        self.vectors.as_ref()?.get(start..start + self.dimensions)
+    }
+}
+
+///Assign codewords in entry order, every entry takes the lowest free codeword of its length.
+#[allow(clippy::type_complexity)]
+fn build_codewords(lengths: &[u8]) -> Result<(Vec<(u32, u8)>, Vec<(u32, u8, u32)>), AudioError> {
+    let mut fast = vec![(0, 0); 1 << FAST_BITS];
+    let mut long = Vec::new();
+    //Lowest free codeword of every length, most significant bit aligned.
+    let mut available = [0u32; 33];
+    let mut first = true;
+
+    for (entry, &length) in lengths.iter().enumerate() {
+        if length == 0 {
+            continue;
+        }
+        let length = length as usize;
+
+        let codeword = if first {
+            first = false;
+            for (i, free) in available.iter_mut().enumerate().take(length + 1).skip(1) {
+                *free = 1 << (32 - i);
+            }
+            0
+        } else {
+            //Longest free prefix that's no longer than the codeword, split down to its length.
+            let Some(free) = (1..=length).rev().find(|&i| available[i] != 0) else {
+                return Err(AudioError::Malformed("Vorbis codebook is overspecified"));
+            };
+            let codeword = available[free];
+            available[free] = 0;
+            for (i, split) in available
+                .iter_mut()
+                .enumerate()
+                .take(length + 1)
+                .skip(free + 1)
+            {
+                *split = codeword + (1 << (32 - i));
+            }
+            codeword
+        };
+
+        //Codewords are read a bit at a time starting with the most significant one.
+        let reversed = codeword.reverse_bits();
+        if length as u32 <= FAST_BITS {
+            for high in 0..1 << (FAST_BITS - length as u32) {
+                fast[(reversed | high << length) as usize] = (entry as u32, length as u8);
+            }
+        } else {
+            long.push((reversed, length as u8, entry as u32));
+        }
+    }
+    Ok((fast, long))
+}
+
+fn mask(bits: u32) -> u32 {
+    match bits {
+        32 => u32::MAX,
+        _ => (1 << bits) - 1,
+    }
+}
+
+fn float32_unpack(value: u32) -> f32 {
+    let mantissa = (value & 0x1fffff) as f64;
+    let exponent = ((value & 0x7fe00000) >> 21) as i32;
+    let mantissa = match value & 0x8000_0000 {
+        0 => mantissa,
+        _ => -mantissa,
+    };
+    (mantissa * 2f64.powi(exponent - 788)) as f32
+}
+
+///Largest count whose power of dimensions doesn't exceed entries.
+fn lookup1_values(entries: usize, dimensions: usize) -> usize {
+    let fits = |count: usize| {
+        (count as u64)
+            .checked_pow(dimensions as u32)
+            .is_some_and(|power| power <= entries as u64)
+    };
+    let mut count = (entries as f64).powf(1.0 / dimensions.max(1) as f64) as usize;
+    while fits(count + 1) {
+        count += 1;
+    }
+    while count > 0 && !fits(count) {
+        count -= 1;
+    }
+    count
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Floors
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Spectral envelope from line spectral pairs, used by early encoders.
+#[derive(Debug, Clone)]
+struct Floor0 {
+    order: usize,
+    rate: u32,
+    bark_map_size: u32,
+    amplitude_bits: u32,
+    amplitude_offset: u32,
+    books: Vec<usize>,
+}
+
+impl Floor0 {
+    fn read(
+        bits: &mut BitReader,
+        book: impl Fn(u32) -> Result<usize, AudioError>,
+    ) -> Result<Self, AudioError> {
+        let order = bits.bits(8)? as usize;
+        let rate = bits.bits(16)?;
+        let bark_map_size = bits.bits(16)?;
+        let amplitude_bits = bits.bits(6)?;
+        let amplitude_offset = bits.bits(8)?;
+        let mut books = Vec::new();
+        for _ in 0..bits.bits(4)? + 1 {
+            books.push(book(bits.bits(8)?)?);
+        }
+
+        if order == 0 || rate == 0 || bark_map_size == 0 {
+            return Err(AudioError::Malformed("Invalid Vorbis floor 0"));
+        }
+        Ok(Self {
+            order,
+            rate,
+            bark_map_size,
+            amplitude_bits,
+            amplitude_offset,
+            books,
+        })
+    }
+
+    ///Decode the curve into out, false if the channel is unused.
+    fn decode(
+        &self,
+        bits: &mut BitReader,
+        codebooks: &[Codebook],
+        out: &mut [f32],
+    ) -> Option<bool> {
+        let amplitude = bits.read(self.amplitude_bits)?;
+        if amplitude == 0 {
+            return Some(false);
+        }
+        let book = bits.read(ilog(self.books.len() as u32))? as usize;
+        let codebook = &codebooks[*self.books.get(book)?];
+
+        let mut coefficients = Vec::with_capacity(self.order);
+        let mut last = 0.0;
+        while coefficients.len() < self.order {
+            let vector = codebook.decode_vector(bits)?;
+            if vector.is_empty() {
+                return None;
+            }
+            coefficients.extend(vector.iter().map(|value| (value + last).cos()));
+            last += vector[vector.len() - 1];
+        }
+        coefficients.truncate(self.order);
+
+        let n = out.len();
+        let bark =
+            |x: f32| 13.1 * (0.00074 * x).atan() + 2.24 * (1.85e-8 * x * x).atan() + 1e-4 * x;
+        let bark_map_size = self.bark_map_size as f32;
+        let nyquist = bark(0.5 * self.rate as f32);
+        let map = |i: usize| {
+            let bark = bark(self.rate as f32 * i as f32 / (2.0 * n as f32));
+            ((bark * bark_map_size / nyquist).floor() as u32).min(self.bark_map_size - 1)
+        };
+        let amplitude_max = ((1u64 << self.amplitude_bits) - 1) as f32;
+
+        let mut i = 0;
+        while i < n {
+            let bin = map(i);
+            let cos_omega = (PI * bin as f32 / bark_map_size).cos();
+            let product = |start: usize| {
+                coefficients
+                    .iter()
+                    .skip(start)
+                    .step_by(2)
+                    .map(|c| 4.0 * (c - cos_omega) * (c - cos_omega))
+                    .product::<f32>()
+            };
+            let (p, q) = match self.order % 2 {
+                1 => (
+                    (1.0 - cos_omega * cos_omega) * product(1),
+                    0.25 * product(0),
+                ),
+                _ => (
+                    (1.0 - cos_omega) / 2.0 * product(1),
+                    (1.0 + cos_omega) / 2.0 * product(0),
+                ),
+            };
+            let value = amplitude as f32 * self.amplitude_offset as f32
+                / (amplitude_max * (p + q).sqrt())
+                - self.amplitude_offset as f32;
+            let value = (0.11512925 * value).exp();
+
+            while i < n && map(i) == bin {
+                out[i] = value;
+                i += 1;
+            }
+        }
+        Some(true)
+    }
+}
+
+///Spectral envelope from line segments between points, used by every current encoder.
+#[derive(Debug, Clone)]
+struct Floor1 {
+    partition_classes: Vec<usize>,
+    classes: Vec<Floor1Class>,
+    multiplier: i32,
+    x_list: Vec<i32>,
+    ///Indices of x_list in ascending order of x.
+    sorted: Vec<usize>,
+    ///Closest lower and higher point before every point.
+    neighbors: Vec<(usize, usize)>,
+    ///Linear amplitude of every floor value.
+    inverse_db: Vec<f32>,
+}
+
+#[derive(Debug, Clone)]
+struct Floor1Class {
+    dimensions: usize,
+    subclass_bits: u32,
+    masterbook: usize,
+    subclass_books: Vec<Option<usize>>,
+}
+
+impl Floor1 {
+    fn read(
+        bits: &mut BitReader,
+        book: impl Fn(u32) -> Result<usize, AudioError>,
+    ) -> Result<Self, AudioError> {
+        let mut partition_classes = Vec::new();
+        for _ in 0..bits.bits(5)? {
+            partition_classes.push(bits.bits(4)? as usize);
+        }
+        let class_count = partition_classes.iter().max().map_or(0, |max| max + 1);
+
+        let mut classes = Vec::with_capacity(class_count);
+        for _ in 0..class_count {
+            let dimensions = bits.bits(3)? as usize + 1;
+            let subclass_bits = bits.bits(2)?;
+            let masterbook = match subclass_bits {
+                0 => 0,
+                _ => book(bits.bits(8)?)?,
+            };
+            let mut subclass_books = Vec::new();
+            for _ in 0..1 << subclass_bits {
+                subclass_books.push(match bits.bits(8)? {
+                    0 => None,
+                    index => Some(book(index - 1)?),
+                });
+            }
+            classes.push(Floor1Class {
+                dimensions,
+                subclass_bits,
+                masterbook,
+                subclass_books,
+            });
+        }
+
+        let multiplier = bits.bits(2)? as i32 + 1;
+        let range_bits = bits.bits(4)?;
+        let mut x_list = vec![0, 1 << range_bits];
+        for &class in &partition_classes {
+            for _ in 0..classes[class].dimensions {
+                x_list.push(bits.bits(range_bits)? as i32);
+            }
+        }
+
+        let mut sorted: Vec<usize> = (0..x_list.len()).collect();
+        sorted.sort_by_key(|&i| x_list[i]);
+        let duplicate = sorted.windows(2).any(|w| x_list[w[0]] == x_list[w[1]]);
+        if x_list.len() > FLOOR1_MAX_POINTS || duplicate {
+            return Err(AudioError::Malformed("Invalid Vorbis floor 1 points"));
+        }
+
+        let neighbors = (0..x_list.len())
+            .map(|i| {
+                let x = x_list[i];
+                let before = || x_list[..i].iter().enumerate();
+                let low = before().filter(|&(_, &v)| v < x).max_by_key(|&(_, &v)| v);
+                let high = before().filter(|&(_, &v)| v > x).min_by_key(|&(_, &v)| v);
+                (low.map_or(0, |(j, _)| j), high.map_or(0, |(j, _)| j))
+            })
+            .collect();
+
+        let inverse_db = (0..256)
+            .map(|i| 10f64.powf(7.0 * (i as f64 - 255.0) / 256.0) as f32)
+            .collect();
+
+        Ok(Self {
+            partition_classes,
+            classes,
+            multiplier,
+            x_list,
+            sorted,
+            neighbors,
+            inverse_db,
+        })
+    }
+
+    ///Decode the curve into out, false if the channel is unused.
+    fn decode(
+        &self,
+        bits: &mut BitReader,
+        codebooks: &[Codebook],
+        out: &mut [f32],
+    ) -> Option<bool> {
+        if bits.read(1)? == 0 {
+            return Some(false);
+        }
+
+        let range = FLOOR1_RANGES[self.multiplier as usize - 1];
+        let range_bits = ilog(range as u32 - 1);
+        let mut y = [0i32; FLOOR1_MAX_POINTS];
+        y[0] = bits.read(range_bits)? as i32;
+        y[1] = bits.read(range_bits)? as i32;
+
+        let mut offset = 2;
+        for &class in &self.partition_classes {
+            let class = &self.classes[class];
+            let mut subclass = match class.subclass_bits {
+                0 => 0,
+                _ => codebooks[class.masterbook].decode(bits)?,
+            };
+            for value in &mut y[offset..offset + class.dimensions] {
+                let book = class.subclass_books[(subclass & mask(class.subclass_bits)) as usize];
+                subclass >>= class.subclass_bits;
+                *value = match book {
+                    Some(book) => codebooks[book].decode(bits)? as i32,
+                    None => 0,
+                };
+            }
+            offset += class.dimensions;
+        }
+
+        //Points are coded as offsets from the line through their neighbors.
+        let mut used = [false; FLOOR1_MAX_POINTS];
+        used[0] = true;
+        used[1] = true;
+        for i in 2..self.x_list.len() {
+            let (low, high) = self.neighbors[i];
+            let predicted = render_point(
+                self.x_list[low],
+                y[low],
+                self.x_list[high],
+                y[high],
+                self.x_list[i],
+            );
+            let value = y[i];
+            let high_room = range - predicted;
+            let low_room = predicted;
+            let room = high_room.min(low_room) * 2;
+
+            if value == 0 {
+                y[i] = predicted;
+                continue;
+            }
+            used[low] = true;
+            used[high] = true;
+            used[i] = true;
+            y[i] = if value >= room {
+                match high_room > low_room {
+                    true => value - low_room + predicted,
+                    false => predicted - value + high_room - 1,
+                }
+            } else if value % 2 == 1 {
+                predicted - (value + 1) / 2
+            } else {
+                predicted + value / 2
+            };
+        }
+
+        let n = out.len() as i32;
+        let mut x0 = 0;
+        let mut y0 = y[self.sorted[0]] * self.multiplier;
+        let (mut x1, mut y1) = (0, 0);
+        for &i in &self.sorted[1..] {
+            if used[i] {
+                x1 = self.x_list[i];
+                y1 = y[i] * self.multiplier;
+                self.render_line(x0, y0, x1, y1, out);
+                x0 = x1;
+                y0 = y1;
+            }
+        }
+        if x1 < n {
+            self.render_line(x1, y1, n, y1, out);
+        }
+        Some(true)
+    }
+
+    ///Bresenham line from x0 up to but not including x1, clipped to out.
+    fn render_line(&self, x0: i32, y0: i32, x1: i32, y1: i32, out: &mut [f32]) {
+        let dy = y1 - y0;
+        let adx = x1 - x0;
+        if adx <= 0 {
+            return;
+        }
+        let base = dy / adx;
+        let step = if dy < 0 { base - 1 } else { base + 1 };
+        let ady = dy.abs() - base.abs() * adx;
+
+        let mut y = y0;
+        let mut error = 0;
+        for x in x0..x1.min(out.len() as i32) {
+            if x > x0 {
+                error += ady;
+                if error >= adx {
+                    error -= adx;
+                    y += step;
+                } else {
+                    y += base;
+                }
+            }
+            out[x as usize] = self.inverse_db[y.clamp(0, 255) as usize];
+        }
+    }
+}
+
+fn render_point(x0: i32, y0: i32, x1: i32, y1: i32, x: i32) -> i32 {
+    let dy = y1 - y0;
+    let adx = (x1 - x0).max(1);
+    let offset = dy.abs() * (x - x0) / adx;
+    match dy < 0 {
+        true => y0 - offset,
+        false => y0 + offset,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Residues
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Fine spectral detail coded in partitions with up to 8 passes of vector codebooks.
+#[derive(Debug, Clone)]
+struct Residue {
+    residue_type: u32,
+    begin: usize,
+    end: usize,
+    partition_size: usize,
+    classifications: usize,
+    classbook: usize,
+    ///Book of every pass of every classification.
+    books: Vec<[Option<usize>; 8]>,
+}
+
+impl Residue {
+    fn read(
+        bits: &mut BitReader,
+        book: impl Fn(u32) -> Result<usize, AudioError>,
+    ) -> Result<Self, AudioError> {
+        let residue_type = bits.bits(16)?;
+        if residue_type > 2 {
+            return Err(AudioError::Malformed("Invalid Vorbis residue type"));
+        }
+        let begin = bits.bits(24)? as usize;
+        let end = bits.bits(24)? as usize;
+        let partition_size = bits.bits(24)? as usize + 1;
+        let classifications = bits.bits(6)? as usize + 1;
+        let classbook = book(bits.bits(8)?)?;
+
+        let mut cascades = Vec::with_capacity(classifications);
+        for _ in 0..classifications {
+            let low = bits.bits(3)?;
+            let high = match bits.bits(1)? {
+                1 => bits.bits(5)?,
+                _ => 0,
+            };
+            cascades.push(high << 3 | low);
+        }
+        let mut books = Vec::with_capacity(classifications);
+        for cascade in cascades {
+            let mut passes = [None; 8];
+            for (pass, slot) in passes.iter_mut().enumerate() {
+                if cascade & 1 << pass != 0 {
+                    *slot = Some(book(bits.bits(8)?)?);
+                }
+            }
+            books.push(passes);
+        }
+
+        Ok(Self {
+            residue_type,
+            begin,
+            end,
+            partition_size,
+            classifications,
+            classbook,
+            books,
+        })
+    }
+
+    ///Decode the residue of channels into vectors, channels without a floor are skipped. Running
+    ///out of packet keeps what was decoded so far.
+    fn decode(
+        &self,
+        bits: &mut BitReader,
+        codebooks: &[Codebook],
+        vectors: &mut [Vec<f32>],
+        channels: &[usize],
+        no_residue: &[bool],
+    ) {
+        if self.residue_type == 2 {
+            //Format 2 codes all channels as one interleaved vector.
+            if channels.iter().all(|&channel| no_residue[channel]) {
+                return;
+            }
+            let half = vectors[0].len();
+            let mut interleaved = vec![0.0; half * channels.len()];
+            self.decode_partitions(bits, codebooks, &mut [&mut interleaved]);
+            for (i, frame) in interleaved.chunks_exact(channels.len()).enumerate() {
+                for (&channel, &value) in channels.iter().zip(frame) {
+                    vectors[channel][i] = value;
+                }
+            }
+        } else {
+            let mut decoded: Vec<&mut [f32]> = vectors
+                .iter_mut()
+                .enumerate()
+                .filter(|(channel, _)| channels.contains(channel) && !no_residue[*channel])
+                .map(|(_, vector)| vector.as_mut_slice())
+                .collect();
+            if !decoded.is_empty() {
+                self.decode_partitions(bits, codebooks, &mut decoded);
+            }
+        }
+    }
+
+    fn decode_partitions(
+        &self,
+        bits: &mut BitReader,
+        codebooks: &[Codebook],
+        vectors: &mut [&mut [f32]],
+    ) -> Option<()> {
+        let size = vectors[0].len();
+        let begin = self.begin.min(size);
+        let end = self.end.min(size);
+        let partitions = end.saturating_sub(begin) / self.partition_size;
+        if partitions == 0 {
+            return Some(());
+        }
+
+        let classbook = &codebooks[self.classbook];
+        let per_codeword = classbook.dimensions.max(1);
+        let stride = partitions + per_codeword;
+        let mut classes = vec![0; vectors.len() * stride];
+
+        for pass in 0..8 {
+            let mut partition = 0;
+            while partition < partitions {
+                if pass == 0 {
+                    for channel in 0..vectors.len() {
+                        let mut word = classbook.decode(bits)? as usize;
+                        for i in (0..per_codeword).rev() {
+                            classes[channel * stride + partition + i] = word % self.classifications;
+                            word /= self.classifications;
+                        }
+                    }
+                }
+
+                for _ in 0..per_codeword {
+                    if partition >= partitions {
+                        break;
+                    }
+                    for (channel, vector) in vectors.iter_mut().enumerate() {
+                        let class = classes[channel * stride + partition];
+                        let Some(book) = self.books[class][pass] else {
+                            continue;
+                        };
+                        let book = &codebooks[book];
+                        let start = begin + partition * self.partition_size;
+                        let out = &mut vector[start..start + self.partition_size];
+                        self.decode_partition(bits, book, out)?;
+                    }
+                    partition += 1;
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn decode_partition(
+        &self,
+        bits: &mut BitReader,
+        book: &Codebook,
+        out: &mut [f32],
+    ) -> Option<()> {
+        if self.residue_type == 0 {
+            //Format 0 spreads every vector across the partition.
+            let step = out.len() / book.dimensions.max(1);
+            for i in 0..step {
+                let vector = book.decode_vector(bits)?;
+                for (j, value) in vector.iter().enumerate() {
+                    out[i + j * step] += value;
+                }
+            }
+        } else {
+            let mut i = 0;
+            while i < out.len() {
+                let vector = book.decode_vector(bits)?;
+                if vector.is_empty() {
+                    return None;
+                }
+                for (out, value) in out[i..].iter_mut().zip(vector) {
+                    *out += value;
+                }
+                i += vector.len();
+            }
+        }
+        Some(())
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Transform
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn from_angle(angle: f32) -> Self {
+        Self {
+            re: angle.cos(),
+            im: angle.sin(),
+        }
+    }
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+}
+
+///Inverse MDCT of one block size, computed as a DCT-IV through a complex FFT of a quarter of the
+///block size.
+#[derive(Debug, Clone)]
+struct Imdct {
+    n: usize,
+    pre_twiddles: Vec<Complex>,
+    post_twiddles: Vec<Complex>,
+    fft_twiddles: Vec<Complex>,
+    bit_reverse: Vec<usize>,
+}
+
+impl Imdct {
+    fn new(n: usize) -> Self {
+        let m = n / 2;
+        let quarter = n / 4;
+        let fft_bits = quarter.trailing_zeros();
+
+        Self {
+            n,
+            pre_twiddles: (0..quarter)
+                .map(|j| Complex::from_angle(-PI * j as f32 / m as f32))
+                .collect(),
+            post_twiddles: (0..quarter)
+                .map(|k| Complex::from_angle(-PI * (4 * k + 1) as f32 / (4 * m) as f32))
+                .collect(),
+            fft_twiddles: (0..quarter / 2)
+                .map(|k| Complex::from_angle(-2.0 * PI * k as f32 / quarter as f32))
+                .collect(),
+            bit_reverse: (0..quarter)
+                .map(|i| i.reverse_bits() >> (usize::BITS - fft_bits))
+                .collect(),
+        }
+    }
+
+    ///Transform n / 2 coefficients into n samples.
+    fn inverse(&self, input: &[f32], out: &mut [f32]) {
+        let m = self.n / 2;
+        let quarter = self.n / 4;
+
+        let mut data = vec![Complex::default(); quarter];
+        for (j, twiddle) in self.pre_twiddles.iter().enumerate() {
+            let value = Complex {
+                re: input[2 * j],
+                im: input[m - 1 - 2 * j],
+            };
+            data[self.bit_reverse[j]] = value.mul(*twiddle);
+        }
+
+        let mut size = 2;
+        while size <= quarter {
+            let half = size / 2;
+            let step = quarter / size;
+            for start in (0..quarter).step_by(size) {
+                for k in 0..half {
+                    let a = data[start + k];
+                    let b = data[start + k + half].mul(self.fft_twiddles[k * step]);
+                    data[start + k] = Complex {
+                        re: a.re + b.re,
+                        im: a.im + b.im,
+                    };
+                    data[start + k + half] = Complex {
+                        re: a.re - b.re,
+                        im: a.im - b.im,
+                    };
+                }
+            }
+            size *= 2;
+        }
+
+        //DCT-IV of the coefficients, the IMDCT is that extended by its symmetries.
+        let mut dct = vec![0.0; m];
+        for (k, twiddle) in self.post_twiddles.iter().enumerate() {
+            let value = data[k].mul(*twiddle);
+            dct[2 * k] = value.re;
+            dct[m - 1 - 2 * k] = -value.im;
+        }
+
+        for (i, out) in out.iter_mut().enumerate() {
+            let index = i + m / 2;
+            *out = if index < m {
+                dct[index]
+            } else if index < 2 * m {
+                -dct[2 * m - 1 - index]
+            } else {
+                -dct[index - 2 * m]
+            };
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Bit reading
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Reads values packed least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    ///Next bits without consuming them, zeros past the end.
+    fn peek(&self, bits: u32) -> u32 {
+        let byte = self.position / 8;
+        let mut value = 0u64;
+        for i in 0..5 {
+            if let Some(&next) = self.data.get(byte + i) {
+                value |= (next as u64) << (8 * i);
+            }
+        }
+        (value >> (self.position % 8)) as u32 & mask(bits)
+    }
+
+    ///Read up to 32 bits, None past the end of the packet.
+    fn read(&mut self, bits: u32) -> Option<u32> {
+        if bits > 32 {
+            for _ in 0..bits / 32 {
+                self.read(32)?;
+            }
+            return self.read(bits % 32);
+        }
+        let value = self.peek(bits);
+        if self.position + bits as usize > self.data.len() * 8 {
+            self.skip_to_end();
+            return None;
+        }
+        self.position += bits as usize;
+        Some(value)
+    }
+
+    ///Read while parsing headers, where running out of bits is an error.
+    fn bits(&mut self, bits: u32) -> Result<u32, AudioError> {
+        self.read(bits)
+            .ok_or(AudioError::Malformed("Vorbis header is truncated"))
+    }
+
+    fn skip_to_end(&mut self) {
+        self.position = self.data.len() * 8;
+    }
+}
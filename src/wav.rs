@@ -0,0 +1,308 @@
+use crate::mixer::{AudioError, Sound};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+const MAX_CHANNELS: u16 = 8;
+
+///Speaker positions of a channel mask, channels are stored in the order of the set bits.
+const SPEAKER_FRONT_LEFT: u32 = 0x1;
+const SPEAKER_FRONT_RIGHT: u32 = 0x2;
+const SPEAKER_LOW_FREQUENCY: u32 = 0x8;
+const SPEAKER_BACK_LEFT: u32 = 0x10;
+const SPEAKER_BACK_RIGHT: u32 = 0x20;
+const SPEAKER_FRONT_LEFT_OF_CENTER: u32 = 0x40;
+const SPEAKER_FRONT_RIGHT_OF_CENTER: u32 = 0x80;
+const SPEAKER_SIDE_LEFT: u32 = 0x200;
+const SPEAKER_SIDE_RIGHT: u32 = 0x400;
+
+const LEFT_SPEAKERS: u32 =
+    SPEAKER_FRONT_LEFT | SPEAKER_BACK_LEFT | SPEAKER_FRONT_LEFT_OF_CENTER | SPEAKER_SIDE_LEFT;
+const RIGHT_SPEAKERS: u32 =
+    SPEAKER_FRONT_RIGHT | SPEAKER_BACK_RIGHT | SPEAKER_FRONT_RIGHT_OF_CENTER | SPEAKER_SIDE_RIGHT;
+
+///Channel masks of files that don't store one, by channel count.
+const DEFAULT_CHANNEL_MASKS: [u32; MAX_CHANNELS as usize + 1] =
+    [0, 0x4, 0x3, 0x7, 0x33, 0x37, 0x3f, 0x13f, 0x63f];
+
+///How a sample is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SampleFormat {
+    Pcm,
+    Float,
+}
+
+///Contents of the fmt chunk.
+#[derive(Debug, Clone, Copy)]
+struct WavFormat {
+    sample_format: SampleFormat,
+    channels: u16,
+    sample_rate: u32,
+    block_align: usize,
+    bits_per_sample: u16,
+    channel_mask: u32,
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Decoding
+///////////////////////////////////////////////////////////////////////////////////////////////////
+
+///Decode a RIFF WAVE file with 8, 16, 24 or 32bit PCM or 32 or 64bit IEEE float samples, 1 to 8
+///channels and any sample rate. Chunks other than fmt and data, like LIST, fact and cue, are
+///skipped. Files with more than two channels are mixed down to stereo.
+pub fn decode_wav(file: &[u8]) -> Result<Sound, AudioError> {
+    if file.len() < 12 || &file[0..4] != b"RIFF" || &file[8..12] != b"WAVE" {
+        return Err(AudioError::InvalidSignature);
+    }
+
+    let mut format = None;
+    let mut position = 12;
+
+    while position + 8 <= file.len() {
+        let id = &file[position..position + 4];
+        let size = read_u32(file, position + 4)? as usize;
+        let start = position + 8;
+
+        match id {
+            b"fmt " => {
+                let Some(chunk) = file.get(start..start.saturating_add(size)) else {
+                    return Err(AudioError::UnexpectedEof);
+                };
+                format = Some(read_format(chunk)?);
+            }
+            b"data" => {
+                let Some(format) = format else {
+                    return Err(AudioError::Malformed("WAV data chunk before fmt chunk"));
+                };
+                //Writers that stream to disk often leave the size unset, read what's there.
+                let end = start.saturating_add(size).min(file.len());
+                return Ok(convert(&file[start..end], &format));
+            }
+            _ => {}
+        }
+
+        //Chunks are padded to an even size.
+        position = start.saturating_add(size).saturating_add(size & 1);
+    }
+
+    Err(AudioError::Malformed("WAV file has no data chunk"))
+}
+
+fn read_format(chunk: &[u8]) -> Result<WavFormat, AudioError> {
+    let mut tag = read_u16(chunk, 0)?;
+    let channels = read_u16(chunk, 2)?;
+    let sample_rate = read_u32(chunk, 4)?;
+    let block_align = read_u16(chunk, 12)? as usize;
+    let bits_per_sample = read_u16(chunk, 14)?;
+    let mut channel_mask = 0;
+
+    if tag == WAVE_FORMAT_EXTENSIBLE {
+        channel_mask = read_u32(chunk, 20)?;
+        //The first two bytes of the sub format GUID hold the actual format tag.
+        tag = read_u16(chunk, 24)?;
+    }
+
+    let sample_format = match (tag, bits_per_sample) {
+        (WAVE_FORMAT_PCM, 8 | 16 | 24 | 32) => SampleFormat::Pcm,
+        (WAVE_FORMAT_IEEE_FLOAT, 32 | 64) => SampleFormat::Float,
+        (WAVE_FORMAT_PCM | WAVE_FORMAT_IEEE_FLOAT, _) => {
+            return Err(AudioError::Unsupported(format!(
+                "Unsupported WAV bit depth {bits_per_sample}"
+            )))
+        }
+        _ => {
+            return Err(AudioError::Unsupported(format!(
+                "Unsupported WAV format tag {tag:#06x}"
+            )))
+        }
+    };
+
+    if channels == 0 || channels > MAX_CHANNELS {
+        return Err(AudioError::Unsupported(format!(
+            "Unsupported WAV channel count {channels}"
+        )));
+    }
+    if sample_rate == 0 {
+        return Err(AudioError::Malformed("WAV sample rate is 0"));
+    }
+    if block_align != channels as usize * bits_per_sample as usize / 8 {
+        return Err(AudioError::Malformed(
+            "WAV block align doesn't match format",
+        ));
+    }
+
+    //Masks that don't name a speaker for every channel are ignored.
+    if channel_mask.count_ones() != channels as u32 {
+        channel_mask = DEFAULT_CHANNEL_MASKS[channels as usize];
+    }
+
+    Ok(WavFormat {
+        sample_format,
+        channels,
+        sample_rate,
+        block_align,
+        bits_per_sample,
+        channel_mask,
+    })
+}
+
+///Convert samples to f32, mono and stereo files keep their channels.
+fn convert(data: &[u8], format: &WavFormat) -> Sound {
+    let bytes_per_sample = format.bits_per_sample as usize / 8;
+    //A trailing partial frame is dropped.
+    let frames = data.chunks_exact(format.block_align);
+
+    let sample = |bytes: &[u8]| -> f32 {
+        match (format.sample_format, bytes_per_sample) {
+            (SampleFormat::Pcm, 1) => (bytes[0] as f32 - 128.0) / 128.0,
+            (SampleFormat::Pcm, 2) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            (SampleFormat::Pcm, 3) => {
+                i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) as f32 / 2147483648.0
+            }
+            (SampleFormat::Pcm, _) => {
+                i32::from_le_bytes(bytes.try_into().unwrap()) as f32 / 2147483648.0
+            }
+            (SampleFormat::Float, 4) => f32::from_le_bytes(bytes.try_into().unwrap()),
+            (SampleFormat::Float, _) => f64::from_le_bytes(bytes.try_into().unwrap()) as f32,
+        }
+    };
+
+    let samples = if format.channels <= 2 {
+        data[..frames.len() * format.block_align]
+            .chunks_exact(bytes_per_sample)
+            .map(sample)
+            .collect()
+    } else {
+        let gains = downmix_gains(format.channel_mask);
+        let mut samples = Vec::with_capacity(frames.len() * 2);
+        for frame in frames {
+            let (mut left, mut right) = (0.0, 0.0);
+            let values = frame.chunks_exact(bytes_per_sample).map(sample);
+            for (value, (left_gain, right_gain)) in values.zip(&gains) {
+                left += value * left_gain;
+                right += value * right_gain;
+            }
+            samples.push(left);
+            samples.push(right);
+        }
+        samples
+    };
+
+    Sound {
+        sample_rate: format.sample_rate,
+        channels: format.channels.min(2),
+        samples,
+    }
+}
+
+///Left and right gain of every channel in mask order. Left and right speakers go to their side,
+///the low frequency channel is dropped and everything else is shared by both sides.
+fn downmix_gains(channel_mask: u32) -> Vec<(f32, f32)> {
+    let center = std::f32::consts::FRAC_1_SQRT_2;
+    (0..32)
+        .map(|bit| 1 << bit)
+        .filter(|speaker| channel_mask & speaker != 0)
+        .map(|speaker| {
+            if speaker & LEFT_SPEAKERS != 0 {
+                (1.0, 0.0)
+            } else if speaker & RIGHT_SPEAKERS != 0 {
+                (0.0, 1.0)
+            } else if speaker == SPEAKER_LOW_FREQUENCY {
+                (0.0, 0.0)
+            } else {
+                (center, center)
+            }
+        })
+        .collect()
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, AudioError> {
+    match bytes.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(AudioError::UnexpectedEof),
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, AudioError> {
+    match bytes.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes(bytes.try_into().unwrap())),
+        None => Err(AudioError::UnexpectedEof),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///RIFF WAVE file with a PCM fmt chunk, a LIST chunk to skip and data.
+    fn wav(channels: u16, bits_per_sample: u16, data: &[u8]) -> Vec<u8> {
+        let block_align = channels * bits_per_sample / 8;
+        let mut fmt = Vec::new();
+        fmt.extend(WAVE_FORMAT_PCM.to_le_bytes());
+        fmt.extend(channels.to_le_bytes());
+        fmt.extend(8000u32.to_le_bytes());
+        fmt.extend((8000 * block_align as u32).to_le_bytes());
+        fmt.extend(block_align.to_le_bytes());
+        fmt.extend(bits_per_sample.to_le_bytes());
+
+        let mut chunks = Vec::new();
+        for (id, chunk) in [
+            (b"fmt ", &fmt[..]),
+            (b"LIST", &[1, 2, 3][..]),
+            (b"data", data),
+        ] {
+            chunks.extend(id);
+            chunks.extend((chunk.len() as u32).to_le_bytes());
+            chunks.extend(chunk);
+            if chunk.len() % 2 == 1 {
+                chunks.push(0);
+            }
+        }
+
+        let mut file = Vec::from(*b"RIFF");
+        file.extend((chunks.len() as u32 + 4).to_le_bytes());
+        file.extend(b"WAVE");
+        file.extend(chunks);
+        file
+    }
+
+    #[test]
+    fn decodes_pcm_depths_to_float() {
+        let sound = decode_wav(&wav(2, 16, &[0x00, 0x40, 0x00, 0xc0])).unwrap();
+        assert_eq!((sound.sample_rate, sound.channels), (8000, 2));
+        assert_eq!(sound.samples, [0.5, -0.5]);
+
+        let sound = decode_wav(&wav(1, 8, &[128, 192, 0])).unwrap();
+        assert_eq!(sound.samples, [0.0, 0.5, -1.0]);
+
+        let sound = decode_wav(&wav(1, 24, &[0x00, 0x00, 0x40])).unwrap();
+        assert_eq!(sound.samples, [0.5]);
+    }
+
+    #[test]
+    fn mixes_surround_down_to_stereo() {
+        //5.1 frame of front left, front right, center, low frequency, back left and back right.
+        let frame = [0x00, 0x40, 0, 0, 0x00, 0x40, 0xff, 0x7f, 0, 0, 0, 0];
+        let sound = decode_wav(&wav(6, 16, &frame)).unwrap();
+        assert_eq!(sound.channels, 2);
+        let center = 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(sound.samples, [0.5 + center, center]);
+    }
+
+    #[test]
+    fn reports_broken_files() {
+        assert_eq!(decode_wav(b"RIFF"), Err(AudioError::InvalidSignature));
+
+        let mut file = wav(1, 16, &[0, 0]);
+        file[20] = 0x55;
+        assert!(matches!(decode_wav(&file), Err(AudioError::Unsupported(_))));
+
+        let file = wav(1, 16, &[]);
+        let without_data = &file[..file.len() - 8];
+        assert_eq!(
+            decode_wav(without_data),
+            Err(AudioError::Malformed("WAV file has no data chunk"))
+        );
+    }
+}
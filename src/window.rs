@@ -1,5 +1,6 @@
 use crate::input::Input;
-use crate::renderer::Renderer;
+use crate::platform::{Platform, WindowDimension};
+use crate::system::gl32::*;
 use crate::system::win32::*;
 use std::alloc;
 
@@ -8,10 +9,7 @@ pub struct WindowResources {
     pub width: i32,
     pub height: i32,
     pub input: *mut Input,
-    pub renderer: *mut Renderer,
     pub running: bool,
-    pub show_cursor: bool,
-    pub hide_cursor: bool,
 }
 
 impl WindowResources {
@@ -20,20 +18,11 @@ impl WindowResources {
             width: 0,
             height: 0,
             input: std::ptr::null_mut(),
-            renderer: std::ptr::null_mut(),
             running: true,
-            show_cursor: false,
-            hide_cursor: false,
         }
     }
 }
 
-#[derive(Debug)]
-pub struct WindowDimension {
-    pub width: i32,
-    pub height: i32,
-}
-
 #[derive(Debug)]
 pub struct Window {
     pub window_handle: HWND,
@@ -102,6 +91,80 @@ impl Window {
     }
 }
 
+impl Platform for Window {
+    fn create(window_title: &str, width: i32, height: i32) -> Self {
+        Window::create_window(window_title, width, height)
+    }
+
+    fn pump_events(&mut self, input: &mut Input) -> bool {
+        unsafe {
+            let resources = &mut *self.window_resources;
+            resources.input = input;
+
+            let mut message = MSG::default();
+            while PeekMessageA(&mut message, 0, 0, 0, PM_REMOVE) > 0 {
+                if message.message == WM_QUIT {
+                    resources.running = false;
+                }
+
+                TranslateMessage(&message);
+                DispatchMessageA(&message);
+            }
+
+            resources.running
+        }
+    }
+
+    fn poll_input(&mut self, input: &mut Input) {
+        for controller_index in 0..XUSER_MAX_COUNT {
+            let controller_state = XINPUT_STATE::default();
+
+            unsafe {
+                if XInputGetState(controller_index, &controller_state) == ERROR_SUCCESS {
+                    input.controllers[controller_index as usize].process_input(&controller_state);
+                }
+            }
+        }
+
+        for controller_index in 0..XUSER_MAX_COUNT {
+            unsafe {
+                XInputSetState(
+                    controller_index,
+                    input.controllers[controller_index as usize].get_vibration(),
+                );
+            }
+        }
+
+        let position = POINT::default();
+        unsafe {
+            GetCursorPos(&position);
+            ScreenToClient(self.window_handle, &position);
+        }
+        input.set_mouse_position(position.x, position.y);
+    }
+
+    fn set_cursor_visible(&mut self, visible: bool) {
+        unsafe {
+            if visible {
+                while ShowCursor(1) < 0 {}
+            } else {
+                while ShowCursor(0) >= 0 {}
+            }
+        }
+    }
+
+    fn present(&mut self) {
+        unsafe {
+            SwapBuffers(self.device_context);
+            DwmFlush();
+        }
+    }
+
+    fn dimensions(&self) -> WindowDimension {
+        self.dimensions
+    }
+}
+
 extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
     unsafe {
         let resources = GetWindowLongPtrA(window, GWLP_USERDATA) as *mut WindowResources;
@@ -146,6 +209,9 @@ extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: L
             WM_LBUTTONDOWN | WM_LBUTTONUP | WM_RBUTTONDOWN | WM_RBUTTONUP | WM_MBUTTONDOWN
             | WM_MBUTTONUP | WM_XBUTTONDOWN | WM_XBUTTONUP | WM_MOUSEHWHEEL | WM_MOUSEWHEEL
             | WM_SYSKEYDOWN | WM_SYSKEYUP | WM_KEYDOWN | WM_KEYUP => {
+                if (*resources).input.is_null() {
+                    return 0;
+                }
                 (*(*resources).input).process_messages(message, wparam)
             }
             WM_CLOSE => {